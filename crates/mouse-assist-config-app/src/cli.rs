@@ -0,0 +1,262 @@
+use clap::{Args, Parser, Subcommand};
+use mouse_assist_core::{
+    load_config, parse_key_name, save_config, Action, Binding, Config, MouseButton, Profile,
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+#[command(name = "mouse-assist-config-app")]
+#[command(about = "Edit mouse-assist bindings; opens the GUI when no command is given")]
+pub(crate) struct Cli {
+    /// Path to a config.toml (defaults to the standard config location).
+    #[arg(long, global = true)]
+    pub(crate) config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Print the bindings, including those inside profiles.
+    List,
+    /// Bind a button, replacing any existing binding for it.
+    Add {
+        #[command(flatten)]
+        target: BindingTarget,
+        #[command(flatten)]
+        action: ActionArgs,
+    },
+    /// Remove the bindings for a button.
+    Remove {
+        #[command(flatten)]
+        target: BindingTarget,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct BindingTarget {
+    /// The button, as named in config files (e.g. BTN_SIDE, WHEEL_TILT_LEFT).
+    #[arg(long)]
+    button: MouseButton,
+    /// Edit this profile's bindings instead of the top-level ones.
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub(crate) struct ActionArgs {
+    /// Inject these keys together (e.g. `--key-combo KEY_LEFTCTRL KEY_T` or `--key-combo ctrl t`).
+    #[arg(long, num_args = 1.., value_name = "KEY")]
+    key_combo: Vec<String>,
+    /// Run this program with the remaining arguments (e.g. `--command notify-send hi`).
+    #[arg(long, num_args = 1.., allow_hyphen_values = true, value_name = "ARG")]
+    command: Vec<String>,
+    /// Hold this profile while the button is down.
+    #[arg(long, value_name = "NAME")]
+    hold_profile: Option<String>,
+}
+
+impl ActionArgs {
+    fn into_action(self) -> Action {
+        if !self.key_combo.is_empty() {
+            Action::KeyCombo {
+                keys: self
+                    .key_combo
+                    .iter()
+                    .map(|key| parse_key_name(key))
+                    .collect(),
+                target: None,
+            }
+        } else if let Some(name) = self.hold_profile {
+            Action::HoldProfile { name }
+        } else {
+            Action::Command { argv: self.command }
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum CliError {
+    #[error(transparent)]
+    Config(#[from] mouse_assist_core::ConfigError),
+    #[error("{} has no binding for {button}", profile_label(.profile.as_deref()))]
+    NotBound {
+        profile: Option<String>,
+        button: &'static str,
+    },
+    #[error("no profile named {0:?} in the config")]
+    UnknownProfile(String),
+    #[error("not saving an invalid config")]
+    Invalid,
+}
+
+fn profile_label(profile: Option<&str>) -> String {
+    match profile {
+        Some(name) => format!("profile {name:?}"),
+        None => "the config".into(),
+    }
+}
+
+/// Runs a non-interactive command against the config at `path`.
+pub(crate) fn run(command: Command, path: &Path) -> Result<(), CliError> {
+    let mut config = load_or_default(path)?;
+    let mut replaced = None;
+    match command {
+        Command::List => {
+            print!("{}", list(&config)?);
+            return Ok(());
+        }
+        Command::Add { target, action } => {
+            let binding = Binding::new(target.button, action.into_action());
+            if add_binding(&mut config, target.profile.as_deref(), binding) {
+                replaced = Some(target.button);
+            }
+        }
+        Command::Remove { target } => {
+            let bindings = match target.profile.as_deref() {
+                Some(name) => bindings_mut(&mut config, name)
+                    .ok_or_else(|| CliError::UnknownProfile(name.to_owned()))?,
+                None => &mut config.bindings,
+            };
+            let before = bindings.len();
+            bindings.retain(|b| b.button != target.button);
+            if bindings.len() == before {
+                return Err(CliError::NotBound {
+                    profile: target.profile,
+                    button: target.button.toml_name(),
+                });
+            }
+        }
+    }
+
+    // Same checks as `mouse-assist-daemon validate`; warnings don't block saving.
+    if let Err(errors) = config.validate() {
+        for err in errors {
+            eprintln!("error: {err}");
+        }
+        return Err(CliError::Invalid);
+    }
+    for warning in config.warnings() {
+        eprintln!("warning: {warning}");
+    }
+    save_config(path, &config)?;
+    if let Some(button) = replaced {
+        println!("replaced the binding for {}", button.toml_name());
+    }
+    println!("saved {}", path.display());
+    Ok(())
+}
+
+/// A missing file counts as the default config, as in the GUI.
+fn load_or_default(path: &Path) -> Result<Config, mouse_assist_core::ConfigError> {
+    match load_config(path) {
+        Err(mouse_assist_core::ConfigError::NotFound { .. }) => Ok(Config::default()),
+        result => result,
+    }
+}
+
+fn bindings_mut<'a>(config: &'a mut Config, profile: &str) -> Option<&'a mut Vec<Binding>> {
+    config
+        .profiles
+        .iter_mut()
+        .find(|p| p.name == profile)
+        .map(|p| &mut p.bindings)
+}
+
+/// Puts `binding` in place of the first existing binding for its button (dropping any later
+/// duplicates), or appends it. Adding to a profile that doesn't exist creates it. Returns
+/// whether an existing binding was replaced.
+fn add_binding(config: &mut Config, profile: Option<&str>, binding: Binding) -> bool {
+    let bindings = match profile {
+        Some(name) => {
+            if config.profile(name).is_none() {
+                config.profiles.push(Profile {
+                    name: name.to_owned(),
+                    inherits: None,
+                    device: None,
+                    bindings: Vec::new(),
+                });
+            }
+            bindings_mut(config, name).expect("profile exists")
+        }
+        None => &mut config.bindings,
+    };
+    match bindings.iter().position(|b| b.button == binding.button) {
+        Some(index) => {
+            let button = binding.button;
+            bindings[index] = binding;
+            let mut seen = 0;
+            bindings.retain(|b| {
+                seen += usize::from(b.button == button);
+                b.button != button || seen == 1
+            });
+            true
+        }
+        None => {
+            bindings.push(binding);
+            false
+        }
+    }
+}
+
+fn list(config: &Config) -> Result<String, mouse_assist_core::ConfigError> {
+    let mut out = String::new();
+    for (profile, bindings) in config.binding_sets() {
+        if let Some(name) = profile {
+            out.push_str(&format!("\nprofile {name:?}:\n"));
+        }
+        if bindings.is_empty() {
+            out.push_str("  (no bindings)\n");
+        }
+        for binding in bindings {
+            out.push_str(&format!(
+                "  {:<16} {}\n",
+                binding.button.toml_name(),
+                binding.action.inline_toml()?
+            ));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_combo(key: &str) -> Action {
+        Action::KeyCombo {
+            keys: vec![key.into()],
+            target: None,
+        }
+    }
+
+    #[test]
+    fn add_replaces_bindings_for_the_same_button() {
+        let mut config = Config::default();
+        let count = config.bindings.len();
+
+        let replaced = add_binding(
+            &mut config,
+            None,
+            Binding::new(MouseButton::BtnSide, key_combo("KEY_MUTE")),
+        );
+        assert!(replaced);
+        assert_eq!(config.bindings.len(), count);
+        assert_eq!(config.bindings[0].action, key_combo("KEY_MUTE"));
+
+        let replaced = add_binding(
+            &mut config,
+            Some("media"),
+            Binding::new(MouseButton::BtnSide, key_combo("KEY_PLAYPAUSE")),
+        );
+        assert!(!replaced);
+        assert_eq!(config.profile("media").unwrap().bindings.len(), 1);
+
+        let listed = list(&config).unwrap();
+        assert!(
+            listed.contains("  BTN_SIDE         { type = \"key_combo\", keys = [\"KEY_MUTE\"] }\n")
+        );
+        assert!(listed.contains("\nprofile \"media\":\n"));
+    }
+}
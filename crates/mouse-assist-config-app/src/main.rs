@@ -1,51 +1,326 @@
+use clap::Parser as _;
 use eframe::egui;
 use mouse_assist_core::{
-    default_config_path, load_config, save_config, Action, Binding, Config, MouseButton,
+    default_config_path, is_modifier_only_combo, load_config, parse_key_name, save_config, Action,
+    Binding, Config, ConfigError, DesktopSetting, MonitorDirection, MouseButton, ScrollAxis,
+    Selection, Step, SystemOp, TiltGesture, Trigger, DEFAULT_DOUBLE_PRESS_MS,
+    DEFAULT_LONG_PRESS_MS, DEFAULT_TILT_HOLD_MS,
 };
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const THEME_STORAGE_KEY: &str = "theme";
+const FONT_SIZES_STORAGE_KEY: &str = "font_sizes";
+/// The range the text size controls allow, in points.
+const FONT_SIZE_RANGE: std::ops::RangeInclusive<f32> = 8.0..=32.0;
+/// How often the desktop's dark/light preference is re-read while following it.
+const SYSTEM_THEME_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a binding row stays highlighted after its button is pressed over the window.
+const PRESS_HIGHLIGHT_DURATION: Duration = Duration::from_millis(300);
+
+mod cli;
 
 fn main() -> eframe::Result<()> {
+    let cli = cli::Cli::parse();
+    let config_path = cli
+        .config
+        .unwrap_or_else(|| default_config_path().unwrap_or_else(|_| PathBuf::from("config.toml")));
+    if let Some(command) = cli.command {
+        if let Err(err) = cli::run(command, &config_path) {
+            eprintln!("error: {err}");
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions::default();
     eframe::run_native(
         "mouse-assist",
         options,
-        Box::new(|_cc| Ok(Box::new(App::new()))),
+        Box::new(|cc| Ok(Box::new(App::new(cc, config_path)))),
     )
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ThemeChoice {
+    Dark,
+    Light,
+    #[default]
+    System,
+}
+
+impl ThemeChoice {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Dark => "Dark",
+            Self::Light => "Light",
+            Self::System => "System",
+        }
+    }
+}
+
+/// Text sizes in points: `body` scales every proportional text style with it, `monospace` is
+/// used as-is for the key editors and the TOML snippet.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct FontSizes {
+    body: f32,
+    monospace: f32,
+}
+
+impl Default for FontSizes {
+    fn default() -> Self {
+        let style = egui::Style::default();
+        Self {
+            body: style.text_styles[&egui::TextStyle::Body].size,
+            monospace: style.text_styles[&egui::TextStyle::Monospace].size,
+        }
+    }
+}
+
 struct App {
     config_path: PathBuf,
     config: Config,
     selected_binding: Option<usize>,
     status: String,
+    theme: ThemeChoice,
+    applied_theme: Option<egui::Theme>,
+    system_theme: egui::Theme,
+    system_theme_checked_at: Option<Instant>,
+    font_sizes: FontSizes,
+    applied_font_sizes: Option<FontSizes>,
+    /// The buttons the one last pressed over the window may be, for highlighting matching
+    /// bindings.
+    pressed: Option<(&'static [MouseButton], Instant)>,
 }
 
 impl App {
-    fn new() -> Self {
-        let config_path = default_config_path().unwrap_or_else(|_| PathBuf::from("config.toml"));
-        let config = load_config(&config_path).unwrap_or_default();
+    fn new(cc: &eframe::CreationContext<'_>, config_path: PathBuf) -> Self {
+        let (config, status) = match load_config(&config_path) {
+            Ok(config) => (config, String::new()),
+            Err(ConfigError::NotFound { .. }) => (
+                Config::default(),
+                "No config yet; saving will create one.".to_owned(),
+            ),
+            Err(err) => (
+                Config::default(),
+                format!("Failed to load config (showing defaults): {err}"),
+            ),
+        };
         let selected_binding = (!config.bindings.is_empty()).then_some(0);
+        let theme = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, THEME_STORAGE_KEY))
+            .unwrap_or_default();
+        let font_sizes = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, FONT_SIZES_STORAGE_KEY))
+            .unwrap_or_default();
         Self {
             config_path,
             config,
             selected_binding,
-            status: String::new(),
+            status,
+            theme,
+            applied_theme: None,
+            system_theme: egui::Theme::Dark,
+            system_theme_checked_at: None,
+            font_sizes,
+            applied_font_sizes: None,
+            pressed: None,
         }
     }
+
+    /// Records presses of remappable buttons over the window (middle and the two side buttons;
+    /// left/right are left alone so clicking around the UI doesn't flash rows).
+    fn track_button_presses(&mut self, ctx: &egui::Context) {
+        let pressed = ctx.input(|i| {
+            i.events.iter().rev().find_map(|event| match event {
+                egui::Event::PointerButton {
+                    button,
+                    pressed: true,
+                    ..
+                } => Some(config_buttons(*button)),
+                _ => None,
+            })
+        });
+        if let Some(buttons) = pressed {
+            self.pressed = Some((buttons, Instant::now()));
+        }
+        if let Some((_, at)) = self.pressed {
+            match PRESS_HIGHLIGHT_DURATION.checked_sub(at.elapsed()) {
+                Some(remaining) => ctx.request_repaint_after(remaining),
+                None => self.pressed = None,
+            }
+        }
+    }
+
+    fn apply_theme(&mut self, ctx: &egui::Context) {
+        let theme = match self.theme {
+            ThemeChoice::Dark => egui::Theme::Dark,
+            ThemeChoice::Light => egui::Theme::Light,
+            ThemeChoice::System => {
+                let due = self
+                    .system_theme_checked_at
+                    .is_none_or(|at| at.elapsed() >= SYSTEM_THEME_POLL_INTERVAL);
+                if due {
+                    self.system_theme = detect_system_theme();
+                    self.system_theme_checked_at = Some(Instant::now());
+                }
+                ctx.request_repaint_after(SYSTEM_THEME_POLL_INTERVAL);
+                self.system_theme
+            }
+        };
+
+        if self.applied_theme != Some(theme) {
+            ctx.set_theme(theme);
+            ctx.set_visuals(theme.default_visuals());
+            self.applied_theme = Some(theme);
+        }
+    }
+
+    fn apply_font_sizes(&mut self, ctx: &egui::Context) {
+        if self.applied_font_sizes == Some(self.font_sizes) {
+            return;
+        }
+        let defaults = egui::Style::default().text_styles;
+        let scale = self.font_sizes.body / defaults[&egui::TextStyle::Body].size;
+        // Both the dark and the light style, so switching themes keeps the sizes.
+        ctx.all_styles_mut(|style| {
+            for (text_style, font) in &mut style.text_styles {
+                font.size = match text_style {
+                    egui::TextStyle::Monospace => self.font_sizes.monospace,
+                    _ => defaults
+                        .get(text_style)
+                        .map_or(font.size, |d| d.size * scale),
+                };
+            }
+        });
+        self.applied_font_sizes = Some(self.font_sizes);
+    }
+}
+
+/// The config buttons a button egui reports may be. Like X11, egui has one "back" and one
+/// "forward" button, which mice report as either `BTN_SIDE`/`BTN_EXTRA` or
+/// `BTN_BACK`/`BTN_FORWARD`.
+fn config_buttons(button: egui::PointerButton) -> &'static [MouseButton] {
+    match button {
+        egui::PointerButton::Primary => &[MouseButton::BtnLeft],
+        egui::PointerButton::Secondary => &[MouseButton::BtnRight],
+        egui::PointerButton::Middle => &[MouseButton::BtnMiddle],
+        egui::PointerButton::Extra1 => &[MouseButton::BtnSide, MouseButton::BtnBack],
+        egui::PointerButton::Extra2 => &[MouseButton::BtnExtra, MouseButton::BtnForward],
+    }
+}
+
+/// The `long_press` command binding that goes with the plain-press binding at `tap`: same
+/// button, same conditions. The editor shows the two as one binding's "On tap" and "On hold".
+fn hold_command(bindings: &[Binding], tap: usize) -> Option<usize> {
+    let tap_binding = &bindings[tap];
+    if !tap_binding.trigger.is_press() {
+        return None;
+    }
+    bindings.iter().position(|hold| {
+        matches!(hold.trigger, Trigger::LongPress { .. })
+            && matches!(hold.action, Action::Command { .. })
+            && hold.button == tap_binding.button
+            && hold.modifier_button == tap_binding.modifier_button
+            && hold.window_class == tap_binding.window_class
+            && hold.active_hours == tap_binding.active_hours
+    })
+}
+
+/// Sets the "On hold" command of the binding at `tap`, adding its `long_press` binding if
+/// there's none yet, or removing it when `argv` is empty. Returns the index it was removed from.
+fn set_hold_command(bindings: &mut Vec<Binding>, tap: usize, argv: Vec<String>) -> Option<usize> {
+    match (hold_command(bindings, tap), argv.is_empty()) {
+        (Some(hold), true) => {
+            bindings.remove(hold);
+            Some(hold)
+        }
+        (Some(hold), false) => {
+            bindings[hold].action = Action::Command { argv };
+            None
+        }
+        (None, true) => None,
+        (None, false) => {
+            let tap_binding = &bindings[tap];
+            let hold = Binding {
+                modifier_button: tap_binding.modifier_button,
+                trigger: Trigger::LongPress {
+                    after_ms: DEFAULT_LONG_PRESS_MS,
+                },
+                window_class: tap_binding.window_class.clone(),
+                active_hours: tap_binding.active_hours.clone(),
+                ..Binding::new(tap_binding.button, Action::Command { argv })
+            };
+            bindings.push(hold);
+            None
+        }
+    }
+}
+
+/// Reads the desktop's dark/light preference (XDG portal, with DE-specific fallbacks).
+fn detect_system_theme() -> egui::Theme {
+    match dark_light::detect() {
+        dark_light::Mode::Light => egui::Theme::Light,
+        dark_light::Mode::Dark | dark_light::Mode::Default => egui::Theme::Dark,
+    }
 }
 
 impl eframe::App for App {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, THEME_STORAGE_KEY, &self.theme);
+        eframe::set_value(storage, FONT_SIZES_STORAGE_KEY, &self.font_sizes);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.apply_theme(ctx);
+        self.apply_font_sizes(ctx);
+        self.track_button_presses(ctx);
+
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.label(format!("Config: {}", self.config_path.display()));
+                ui.separator();
+
+                ui.label("Theme:");
+                for choice in [ThemeChoice::Dark, ThemeChoice::Light, ThemeChoice::System] {
+                    ui.selectable_value(&mut self.theme, choice, choice.label());
+                }
+                ui.separator();
+
+                ui.label("Text:");
+                ui.add(
+                    egui::DragValue::new(&mut self.font_sizes.body)
+                        .range(FONT_SIZE_RANGE)
+                        .speed(0.25)
+                        .suffix(" pt"),
+                );
+                ui.label("Code:");
+                ui.add(
+                    egui::DragValue::new(&mut self.font_sizes.monospace)
+                        .range(FONT_SIZE_RANGE)
+                        .speed(0.25)
+                        .suffix(" pt"),
+                );
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     if ui.button("Save").clicked() {
-                        match save_config(&self.config_path, &self.config) {
-                            Ok(_) => self.status = "Saved".into(),
-                            Err(e) => self.status = format!("Save failed: {e}"),
-                        }
+                        self.status = match self.config.validate() {
+                            Err(errors) => format!(
+                                "Not saved: {}",
+                                errors
+                                    .iter()
+                                    .map(ToString::to_string)
+                                    .collect::<Vec<_>>()
+                                    .join("; ")
+                            ),
+                            Ok(()) => match save_config(&self.config_path, &self.config) {
+                                Ok(_) => "Saved".into(),
+                                Err(e) => format!("Save failed: {e}"),
+                            },
+                        };
                     }
                     if !self.status.is_empty() {
                         ui.separator();
@@ -61,12 +336,31 @@ impl eframe::App for App {
         egui::SidePanel::left("bindings_list")
             .default_width(520.0)
             .min_width(360.0)
-            .frame(panel_frame.clone())
+            .frame(panel_frame)
             .show(ctx, |ui| {
                 ui.heading("Bindings");
+                ui.label(
+                    egui::RichText::new(
+                        "Press a mouse button over this window to flash its bindings. The \
+                         window sees BTN_SIDE and BTN_BACK as one button, and BTN_EXTRA and \
+                         BTN_FORWARD as another, so both flash; it can't see BTN_TASK or the \
+                         wheel.",
+                    )
+                    .weak(),
+                );
                 ui.add_space(8.0);
 
                 let mut remove_index: Option<usize> = None;
+                let mut hold_edit: Option<(usize, Vec<String>)> = None;
+                let hold_commands: Vec<Option<String>> = (0..self.config.bindings.len())
+                    .map(|idx| {
+                        let hold = hold_command(&self.config.bindings, idx)?;
+                        match &self.config.bindings[hold].action {
+                            Action::Command { argv } => Some(argv.join(" ")),
+                            _ => None,
+                        }
+                    })
+                    .collect();
 
                 let bottom_tile_height = 44.0;
                 let max_scroll_height =
@@ -75,11 +369,15 @@ impl eframe::App for App {
                 egui::ScrollArea::vertical()
                     .max_height(max_scroll_height)
                     .show(ui, |ui| {
+                        let pressed_buttons = self.pressed.map_or(&[][..], |(buttons, _)| buttons);
                         for (idx, binding) in self.config.bindings.iter_mut().enumerate() {
                             let is_selected = self.selected_binding == Some(idx);
+                            let is_pressed = pressed_buttons.contains(&binding.button);
                             let visuals = ui.visuals();
                             let selected_stroke = visuals.selection.stroke;
-                            let frame = egui::Frame::group(ui.style()).stroke(if is_selected {
+                            let frame = egui::Frame::group(ui.style()).stroke(if is_pressed {
+                                egui::Stroke::new(2.0, visuals.warn_fg_color)
+                            } else if is_selected {
                                 selected_stroke
                             } else {
                                 visuals.widgets.noninteractive.bg_stroke
@@ -103,18 +401,7 @@ impl eframe::App for App {
                                         egui::ComboBox::from_id_salt(format!("button-{idx}"))
                                             .selected_text(format!("{:?}", binding.button))
                                             .show_ui(ui, |ui| {
-                                                for b in [
-                                                    MouseButton::BtnLeft,
-                                                    MouseButton::BtnRight,
-                                                    MouseButton::BtnMiddle,
-                                                    MouseButton::BtnSide,
-                                                    MouseButton::BtnExtra,
-                                                    MouseButton::BtnForward,
-                                                    MouseButton::BtnBack,
-                                                    MouseButton::BtnTask,
-                                                    MouseButton::WheelTiltLeft,
-                                                    MouseButton::WheelTiltRight,
-                                                ] {
+                                                for b in MouseButton::ALL {
                                                     ui.selectable_value(
                                                         &mut binding.button,
                                                         b,
@@ -153,35 +440,108 @@ impl eframe::App for App {
                                 match &mut binding.action {
                                     Action::Command { argv } => {
                                         let mut switch = false;
+                                        let mut switch_to_open = false;
                                         ui.horizontal(|ui| {
                                             ui.label("Action:");
                                             ui.label("command");
                                             if ui.button("Switch to key_combo").clicked() {
                                                 switch = true;
                                             }
+                                            if ui.button("Switch to open").clicked() {
+                                                switch_to_open = true;
+                                            }
                                         });
                                         let mut text = argv.join(" ");
+                                        let label = match binding.trigger {
+                                            Trigger::Press => "On tap:",
+                                            Trigger::DoublePress { .. } => "On double press:",
+                                            Trigger::LongPress { .. } => "On hold:",
+                                        };
+                                        ui.horizontal(|ui| {
+                                            ui.label(label);
+                                            if ui
+                                                .add(
+                                                    egui::TextEdit::singleline(&mut text)
+                                                        .hint_text("argv (space-separated)"),
+                                                )
+                                                .changed()
+                                            {
+                                                self.selected_binding = Some(idx);
+                                                *argv = text
+                                                    .split_whitespace()
+                                                    .map(|s| s.to_string())
+                                                    .collect();
+                                            }
+                                        });
+                                        // A plain press can share its button with a long press,
+                                        // which is kept as a binding of its own.
+                                        if binding.trigger.is_press() && !binding.button.is_wheel()
+                                        {
+                                            let mut text =
+                                                hold_commands[idx].clone().unwrap_or_default();
+                                            ui.horizontal(|ui| {
+                                                ui.label("On hold:");
+                                                let hint = format!(
+                                                    "argv, after {DEFAULT_LONG_PRESS_MS} ms (empty: none)"
+                                                );
+                                                if ui
+                                                    .add(
+                                                        egui::TextEdit::singleline(&mut text)
+                                                            .hint_text(hint),
+                                                    )
+                                                    .changed()
+                                                {
+                                                    self.selected_binding = Some(idx);
+                                                    hold_edit = Some((
+                                                        idx,
+                                                        text.split_whitespace()
+                                                            .map(|s| s.to_string())
+                                                            .collect(),
+                                                    ));
+                                                }
+                                            });
+                                        }
+                                        if switch {
+                                            self.selected_binding = Some(idx);
+                                            replacement_action = Some(Action::KeyCombo {
+                                                keys: vec!["KEY_BACK".into()],
+                                                target: None,
+                                            });
+                                        }
+                                        if switch_to_open {
+                                            self.selected_binding = Some(idx);
+                                            replacement_action = Some(Action::Open {
+                                                target: "https://".into(),
+                                            });
+                                        }
+                                    }
+                                    Action::Open { target } => {
+                                        let mut switch = false;
+                                        ui.horizontal(|ui| {
+                                            ui.label("Action:");
+                                            ui.label("open");
+                                            if ui.button("Switch to key_combo").clicked() {
+                                                switch = true;
+                                            }
+                                        });
                                         if ui
                                             .add(
-                                                egui::TextEdit::singleline(&mut text)
-                                                    .hint_text("argv (space-separated)"),
+                                                egui::TextEdit::singleline(target)
+                                                    .hint_text("URL or file path"),
                                             )
                                             .changed()
                                         {
                                             self.selected_binding = Some(idx);
-                                            *argv = text
-                                                .split_whitespace()
-                                                .map(|s| s.to_string())
-                                                .collect();
                                         }
                                         if switch {
                                             self.selected_binding = Some(idx);
                                             replacement_action = Some(Action::KeyCombo {
                                                 keys: vec!["KEY_BACK".into()],
+                                                target: None,
                                             });
                                         }
                                     }
-                                    Action::KeyCombo { keys } => {
+                                    Action::KeyCombo { keys, .. } => {
                                         let mut switch = false;
                                         ui.horizontal(|ui| {
                                             ui.label("Action:");
@@ -191,18 +551,27 @@ impl eframe::App for App {
                                             }
                                         });
                                         let mut text = keys.join(" ");
-                                        if ui
-                                            .add(egui::TextEdit::singleline(&mut text).hint_text(
-                                                "keys (space-separated, e.g. KEY_LEFTMETA KEY_L)",
-                                            ))
-                                            .changed()
-                                        {
+                                        let response = ui.add(
+                                            egui::TextEdit::singleline(&mut text)
+                                                .font(egui::TextStyle::Monospace)
+                                                .hint_text(
+                                                    "keys (space-separated, e.g. KEY_LEFTMETA KEY_L)",
+                                                ),
+                                        );
+                                        if response.changed() {
                                             self.selected_binding = Some(idx);
                                             *keys = text
                                                 .split_whitespace()
                                                 .map(|s| s.to_string())
                                                 .collect();
                                         }
+                                        // Typed names like `ctrl` or `VolumeUp` are stored
+                                        // as `KEY_*` once editing is done, not mid-word.
+                                        if response.lost_focus() {
+                                            for key in keys.iter_mut() {
+                                                *key = parse_key_name(key);
+                                            }
+                                        }
                                         if switch {
                                             self.selected_binding = Some(idx);
                                             replacement_action = Some(Action::Command {
@@ -214,6 +583,27 @@ impl eframe::App for App {
                                             });
                                         }
                                     }
+                                    other => {
+                                        let mut switch = false;
+                                        ui.horizontal(|ui| {
+                                            ui.label("Action:");
+                                            ui.label(other.kind());
+                                            if ui.button("Switch to key_combo").clicked() {
+                                                switch = true;
+                                            }
+                                        });
+                                        ui.label(
+                                            egui::RichText::new("Edit this action in config.toml.")
+                                                .weak(),
+                                        );
+                                        if switch {
+                                            self.selected_binding = Some(idx);
+                                            replacement_action = Some(Action::KeyCombo {
+                                                keys: vec!["KEY_BACK".into()],
+                                                target: None,
+                                            });
+                                        }
+                                    }
                                 }
                                 if let Some(action) = replacement_action {
                                     binding.action = action;
@@ -237,15 +627,24 @@ impl eframe::App for App {
                         .inner
                 };
                 if add_clicked {
-                    self.config.bindings.push(Binding {
-                        button: MouseButton::BtnSide,
-                        action: Action::KeyCombo {
+                    self.config.bindings.push(Binding::new(
+                        MouseButton::BtnSide,
+                        Action::KeyCombo {
                             keys: vec!["KEY_BACK".into()],
+                            target: None,
                         },
-                    });
+                    ));
                     self.selected_binding = Some(self.config.bindings.len().saturating_sub(1));
                 }
 
+                if let Some((idx, argv)) = hold_edit {
+                    if let Some(removed) = set_hold_command(&mut self.config.bindings, idx, argv) {
+                        self.selected_binding = self
+                            .selected_binding
+                            .map(|selected| selected - usize::from(selected > removed));
+                    }
+                }
+
                 if let Some(idx) = remove_index {
                     if idx < self.config.bindings.len() {
                         self.config.bindings.remove(idx);
@@ -284,13 +683,46 @@ impl eframe::App for App {
                 ui.add_space(8.0);
 
                 match &binding.action {
-                    Action::KeyCombo { keys } => {
+                    Action::KeyCombo { keys, target } => {
                         ui.label("key_combo:");
                         ui.label("- Keys are Linux evdev key names like KEY_BACK.");
                         ui.label("- Presses all keys, then releases them (chord).");
                         if keys.is_empty() {
                             ui.label("- (No keys configured)");
                         }
+                        if is_modifier_only_combo(keys) {
+                            ui.label(
+                                "- Only modifiers: a tap of Ctrl/Shift/Alt on its own usually \
+                                 does nothing.",
+                            );
+                        }
+                        if target.is_some() {
+                            ui.label("- Sent to a target window (X11 only).");
+                        }
+                    }
+                    Action::Hold { keys } => {
+                        ui.label("hold:");
+                        ui.label("- Presses the keys while the button is down (push-to-talk).");
+                        ui.label("- Keys another held button also holds stay down.");
+                        if keys.is_empty() {
+                            ui.label("- (No keys configured)");
+                        }
+                    }
+                    Action::KeyChords { keys, target } => {
+                        ui.label("key_combo (chords):");
+                        ui.label(format!(
+                            "- Presses and releases {} chords, one after another.",
+                            keys.len()
+                        ));
+                        if keys.iter().any(|chord| is_modifier_only_combo(chord)) {
+                            ui.label(
+                                "- Some chords are only modifiers, which usually does nothing \
+                                 on its own.",
+                            );
+                        }
+                        if target.is_some() {
+                            ui.label("- Sent to a target window (X11 only).");
+                        }
                     }
                     Action::Command { argv } => {
                         ui.label("command:");
@@ -299,6 +731,357 @@ impl eframe::App for App {
                             ui.label("- (No argv configured)");
                         }
                     }
+                    Action::CommandNotify { argv } => {
+                        ui.label("command_notify:");
+                        ui.label("- Runs argv and shows its output as a notification.");
+                        ui.label("- Killed after 5 s; long output is cut short.");
+                        if argv.is_empty() {
+                            ui.label("- (No argv configured)");
+                        }
+                    }
+                    Action::Script { source } => {
+                        ui.label("script:");
+                        ui.label("- Runs an embedded rhai script (daemon `script` feature).");
+                        ui.label("- Stopped after 1 s; only its requests reach the desktop.");
+                        if source.trim().is_empty() {
+                            ui.label("- (No source configured)");
+                        }
+                    }
+                    Action::RepeatLast => {
+                        ui.label("repeat_last:");
+                        ui.label("- Runs the last action any button ran again.");
+                        ui.label("- Profile switches aren't repeated; forgotten on restart.");
+                    }
+                    Action::Open { target } => {
+                        ui.label("open:");
+                        ui.label("- Opens the URL or file with its default app (xdg-open).");
+                        if target.trim().is_empty() {
+                            ui.label("- (No target configured)");
+                        }
+                    }
+                    Action::Parallel { actions } => {
+                        ui.label("parallel:");
+                        ui.label("- Fires every sub-action at once (no ordering or waiting).");
+                        if actions.is_empty() {
+                            ui.label("- (No sub-actions configured)");
+                        }
+                    }
+                    Action::Sequence { steps } => {
+                        ui.label("sequence:");
+                        for step in steps {
+                            ui.label(match step {
+                                Step::Action(action) => format!("- Runs {}.", action.kind()),
+                                Step::Delay { ms } => format!("- Waits {ms} ms."),
+                            });
+                        }
+                        if steps.is_empty() {
+                            ui.label("- (No steps configured)");
+                        }
+                    }
+                    Action::SwitchLayout { group } => {
+                        ui.label("switch_layout:");
+                        match group {
+                            Some(group) => ui.label(format!("- Switches to layout #{group}.")),
+                            None => ui.label("- Cycles to the next keyboard layout."),
+                        };
+                        ui.label("- X11 only.");
+                    }
+                    Action::Screenshot {
+                        mode,
+                        to_clipboard,
+                        save_path,
+                    } => {
+                        ui.label("screenshot:");
+                        ui.label(format!("- Captures: {mode:?}."));
+                        ui.label("- Uses grim/slurp on Wayland, maim or scrot on X11.");
+                        match save_path {
+                            Some(path) => ui.label(format!("- Saves to {}.", path.display())),
+                            None if *to_clipboard => ui.label("- Not saved to a file."),
+                            None => ui.label("- Saves to ~/Pictures."),
+                        };
+                        if *to_clipboard {
+                            ui.label("- Copies the image to the clipboard (wl-copy/xclip).");
+                        }
+                    }
+                    Action::SetClipboard { text, selection } => {
+                        ui.label("set_clipboard:");
+                        ui.label(format!(
+                            "- Puts {} character(s) on the {selection:?} selection.",
+                            text.chars().count()
+                        ));
+                        ui.label("- Uses wl-copy on Wayland, xclip or xsel on X11.");
+                    }
+                    Action::LaunchOrFocus { argv, match_class } => {
+                        ui.label("launch_or_focus:");
+                        ui.label(format!(
+                            "- Focuses a window of class \"{match_class}\" if one is open."
+                        ));
+                        ui.label("- Otherwise executes argv directly (no shell).");
+                        ui.label("- X11 only; elsewhere it always launches.");
+                        if argv.is_empty() {
+                            ui.label("- (No argv configured)");
+                        }
+                    }
+                    Action::PerProfile { map, default } => {
+                        ui.label("per_profile:");
+                        for (name, action) in map {
+                            ui.label(format!("- In profile \"{name}\": {}.", action.kind()));
+                            if self.config.profile(name).is_none() {
+                                ui.label(format!("- (No profile named \"{name}\" in the config)"));
+                            }
+                        }
+                        match default {
+                            Some(action) => ui.label(format!("- Otherwise: {}.", action.kind())),
+                            None => ui.label("- Otherwise: nothing."),
+                        };
+                        ui.label("- At the top level, also applies in profiles that don't bind this button.");
+                    }
+                    Action::ClipboardRoute {
+                        selection,
+                        rules,
+                        default,
+                    } => {
+                        ui.label("clipboard_route:");
+                        ui.label(match selection {
+                            Selection::Clipboard => "- Reads the clipboard, then runs:",
+                            Selection::Primary => "- Reads the primary selection, then runs:",
+                        });
+                        for rule in rules {
+                            ui.label(format!(
+                                "- If it matches /{}/: {}.",
+                                rule.pattern,
+                                rule.action.kind()
+                            ));
+                        }
+                        match default {
+                            Some(action) => ui.label(format!("- Otherwise: {}.", action.kind())),
+                            None => ui.label("- Otherwise: nothing."),
+                        };
+                    }
+                    Action::System { op } => {
+                        ui.label("system:");
+                        ui.label(match op {
+                            SystemOp::Lock => "- Locks the screen.",
+                            SystemOp::Suspend => "- Suspends the computer.",
+                            SystemOp::Hibernate => "- Hibernates the computer.",
+                            SystemOp::LogOut => "- Ends the session (unsaved work is lost).",
+                        });
+                        ui.label(
+                            "- Asks logind over D-Bus; it may refuse or prompt for a password.",
+                        );
+                    }
+                    Action::SetCursor { name } => {
+                        ui.label("set_cursor:");
+                        ui.label(format!(
+                            "- Shows the \"{name}\" cursor over the desktop background (X11)."
+                        ));
+                        ui.label("- Windows keep their own cursors; use left_ptr to go back.");
+                    }
+                    Action::WmCommand { command } => {
+                        ui.label("wm_command:");
+                        ui.label(format!("- Sends \"{command}\" to the window manager."));
+                        ui.label("- Sway, i3 and Hyprland; does nothing elsewhere.");
+                    }
+                    Action::ToggleSetting { setting } => {
+                        ui.label("toggle_setting:");
+                        ui.label(match setting {
+                            DesktopSetting::DoNotDisturb => "- Turns do-not-disturb on or off.",
+                            DesktopSetting::NightLight => "- Turns night light on or off.",
+                        });
+                        ui.label("- GNOME and KDE; do-not-disturb also where notifications can be inhibited.");
+                    }
+                    Action::SetLogLevel { level } => {
+                        ui.label("set_log_level:");
+                        if level.eq_ignore_ascii_case("default") {
+                            ui.label("- Puts the daemon's logging back to how it started.");
+                        } else {
+                            ui.label(format!("- Makes the daemon log at level \"{level}\"."));
+                        }
+                        ui.label("- Applies to the whole daemon until changed again.");
+                    }
+                    Action::Brightness {
+                        delta_percent,
+                        device,
+                    } => {
+                        ui.label("brightness:");
+                        ui.label(format!(
+                            "- {} the backlight by {}% of its range.",
+                            if *delta_percent < 0 { "Dims" } else { "Brightens" },
+                            delta_percent.unsigned_abs()
+                        ));
+                        match device {
+                            Some(device) => ui.label(format!("- Backlight: {device}.")),
+                            None => ui.label("- Backlight: the first under /sys/class/backlight."),
+                        };
+                        ui.label("- Writes sysfs directly, or asks logind when that's not allowed.");
+                    }
+                    Action::RawEvents { events } => {
+                        ui.label("raw_events:");
+                        ui.label(format!(
+                            "- Emits {} raw event(s) on a virtual device, then SYN_REPORT.",
+                            events.len()
+                        ));
+                        ui.label("- Sent verbatim: a press without its release stays pressed.");
+                        ui.label("- evdev backend only.");
+                    }
+                    Action::Scroll { axis, amount } => {
+                        ui.label("scroll:");
+                        let direction = match (axis, *amount > 0) {
+                            (ScrollAxis::Vertical, true) => "up",
+                            (ScrollAxis::Vertical, false) => "down",
+                            (ScrollAxis::Horizontal, true) => "right",
+                            (ScrollAxis::Horizontal, false) => "left",
+                        };
+                        ui.label(format!(
+                            "- Scrolls {direction} by {} notch(es).",
+                            amount.unsigned_abs()
+                        ));
+                        ui.label("- evdev: a virtual mouse wheel; X11: wheel button clicks.");
+                    }
+                    Action::MoveWindowToMonitor { direction } => {
+                        ui.label("move_window_to_monitor:");
+                        ui.label(match direction {
+                            MonitorDirection::Next => {
+                                "- Moves the focused window to the next monitor to the right."
+                                    .to_owned()
+                            }
+                            MonitorDirection::Prev => {
+                                "- Moves the focused window to the next monitor to the left."
+                                    .to_owned()
+                            }
+                            MonitorDirection::Index(index) => format!(
+                                "- Moves the focused window to monitor #{index}, counting from \
+                                 the left."
+                            ),
+                        });
+                        ui.label("- Keeps its relative place and maximized state.");
+                        ui.label("- X11 only; needs RandR and an EWMH window manager.");
+                    }
+                    Action::CycleWindows { same_class } => {
+                        ui.label("cycle_windows:");
+                        if *same_class {
+                            ui.label(
+                                "- Focuses the next window of the focused app, wrapping around.",
+                            );
+                        } else {
+                            ui.label("- Focuses the next window, wrapping around.");
+                        }
+                        ui.label("- X11 only; needs an EWMH window manager.");
+                    }
+                    Action::Webhook { url, method, .. } => {
+                        ui.label("webhook:");
+                        ui.label(format!(
+                            "- Sends {} {url}.",
+                            method.as_deref().unwrap_or("POST")
+                        ));
+                        ui.label("- Waits up to 5 s for a response; actions queue meanwhile.");
+                        if url.starts_with("https://") {
+                            ui.label("- HTTPS needs the daemon built with the `tls` feature.");
+                        }
+                    }
+                    Action::Macro { name } => {
+                        ui.label("macro:");
+                        ui.label(format!(
+                            "- Runs the action defined as \"{name}\" under [macros]."
+                        ));
+                        if !self.config.macros.contains_key(name) {
+                            ui.label("- (No macro with this name in the config)");
+                        }
+                    }
+                    Action::HoldProfile { name } => {
+                        ui.label("hold_profile:");
+                        ui.label(format!(
+                            "- While held, only the bindings of profile \"{name}\" are active."
+                        ));
+                        ui.label("- Releasing the button switches back.");
+                        if self.config.profile(name).is_none() {
+                            ui.label("- (No profile with this name in the config)");
+                        }
+                    }
+                    Action::SwitchProfile { name } => {
+                        ui.label("switch_profile:");
+                        match name {
+                            Some(name) => {
+                                ui.label(format!(
+                                    "- Makes profile \"{name}\" active until the next switch."
+                                ));
+                                if self.config.profile(name).is_none() {
+                                    ui.label("- (No profile with this name in the config)");
+                                }
+                            }
+                            None => {
+                                ui.label("- Switches back to the top-level bindings.");
+                            }
+                        }
+                        if self.config.remember_profile {
+                            ui.label("- The choice survives restarts (remember_profile).");
+                        }
+                    }
+                    Action::ToggleProfile { name } => {
+                        ui.label("toggle_profile:");
+                        ui.label(format!(
+                            "- First press makes profile \"{name}\" active; the next one switches back."
+                        ));
+                        ui.label("- Held profiles still win while held.");
+                        if self.config.profile(name).is_none() {
+                            ui.label("- (No profile with this name in the config)");
+                        }
+                    }
+                }
+                if !binding.window_class.is_empty() {
+                    ui.add_space(8.0);
+                    ui.label(format!(
+                        "Only while the focused window's class matches {} (X11); wins over global bindings there.",
+                        binding.window_class.join(", ")
+                    ));
+                }
+                if let Some(tilt) = binding.tilt {
+                    ui.add_space(8.0);
+                    ui.label(match tilt {
+                        TiltGesture::Flick => format!(
+                            "Fires on a quick tilt (shorter than {} ms), once the tilt stops.",
+                            self.config.tilt_hold_ms.unwrap_or(DEFAULT_TILT_HOLD_MS)
+                        ),
+                        TiltGesture::Hold => format!(
+                            "Fires once a tilt has lasted {} ms.",
+                            self.config.tilt_hold_ms.unwrap_or(DEFAULT_TILT_HOLD_MS)
+                        ),
+                    });
+                }
+                match binding.trigger {
+                    Trigger::Press => {}
+                    Trigger::DoublePress { within_ms } => {
+                        ui.add_space(8.0);
+                        ui.label(format!(
+                            "Fires on a second press within {within_ms} ms of the first one's release."
+                        ));
+                    }
+                    Trigger::LongPress { after_ms } => {
+                        ui.add_space(8.0);
+                        ui.label(format!(
+                            "Fires once the button has been held for {after_ms} ms."
+                        ));
+                    }
+                }
+                if let Some(double_click) = &binding.double_click {
+                    ui.add_space(8.0);
+                    ui.label(format!(
+                        "double_click: {} on a second press within {DEFAULT_DOUBLE_PRESS_MS} ms.",
+                        double_click.kind()
+                    ));
+                }
+                if let Some(modifier) = binding.modifier_button {
+                    ui.add_space(8.0);
+                    ui.label(format!(
+                        "Fires per scroll notch while holding {}; that button's own action waits for its release.",
+                        modifier.toml_name()
+                    ));
+                }
+                if let Some((start, end)) = &binding.active_hours {
+                    ui.add_space(8.0);
+                    ui.label(format!(
+                        "Only active from {start} to {end} (local time); ignored otherwise."
+                    ));
                 }
 
                 ui.add_space(12.0);
@@ -316,3 +1099,66 @@ impl eframe::App for App {
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_button_egui_reports_flashes_some_binding() {
+        let seen: Vec<MouseButton> = [
+            egui::PointerButton::Primary,
+            egui::PointerButton::Secondary,
+            egui::PointerButton::Middle,
+            egui::PointerButton::Extra1,
+            egui::PointerButton::Extra2,
+        ]
+        .into_iter()
+        .flat_map(config_buttons)
+        .copied()
+        .collect();
+        let unseen: Vec<MouseButton> = MouseButton::ALL
+            .into_iter()
+            .filter(|button| !button.is_wheel() && !seen.contains(button))
+            .collect();
+        assert_eq!(unseen, [MouseButton::BtnTask]);
+    }
+
+    #[test]
+    fn a_hold_command_is_a_long_press_binding_of_its_own() {
+        let mut bindings = vec![
+            Binding::new(
+                MouseButton::BtnSide,
+                Action::Command {
+                    argv: vec!["a".into()],
+                },
+            ),
+            Binding::new(
+                MouseButton::BtnExtra,
+                Action::Command {
+                    argv: vec!["b".into()],
+                },
+            ),
+        ];
+        assert_eq!(hold_command(&bindings, 0), None);
+
+        assert_eq!(set_hold_command(&mut bindings, 0, vec!["c".into()]), None);
+        assert_eq!(hold_command(&bindings, 0), Some(2));
+        assert_eq!(hold_command(&bindings, 1), None);
+        assert_eq!(bindings[2].button, MouseButton::BtnSide);
+        assert_eq!(
+            bindings[2].trigger,
+            Trigger::LongPress {
+                after_ms: DEFAULT_LONG_PRESS_MS
+            }
+        );
+        let config = Config {
+            bindings: bindings.clone(),
+            ..Config::default()
+        };
+        assert!(config.validate().is_ok());
+
+        assert_eq!(set_hold_command(&mut bindings, 0, vec![]), Some(2));
+        assert_eq!(bindings.len(), 2);
+    }
+}
@@ -1,301 +1,4291 @@
 use directories::BaseDirs;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use thiserror::Error;
 
 pub const APP_NAME: &str = "mouse-assist";
 pub const CONFIG_FILE_NAME: &str = "config.toml";
+/// Admin-managed restrictions that apply to every user's config.
+pub const SYSTEM_POLICY_PATH: &str = "/etc/mouse-assist/policy.toml";
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("failed to determine config directory")]
     NoConfigDir,
+    #[error("config not found: {}", path.display())]
+    NotFound { path: PathBuf },
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
     #[error("toml deserialize error: {0}")]
     TomlDe(#[from] toml::de::Error),
     #[error("toml serialize error: {0}")]
     TomlSer(#[from] toml::ser::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{binding}: command argv is empty")]
+    EmptyCommand { binding: BindingRef },
+    #[error("{binding}: set_cursor has no cursor name")]
+    EmptyCursorName { binding: BindingRef },
+    #[error("{binding}: wm_command has no command")]
+    EmptyWmCommand { binding: BindingRef },
+    #[error("{binding}: open has no target")]
+    EmptyOpenTarget { binding: BindingRef },
+    #[error("{binding}: clipboard_route has no rules and no default")]
+    EmptyClipboardRoute { binding: BindingRef },
+    #[error("{binding}: script has no source")]
+    EmptyScript { binding: BindingRef },
+    #[error("{binding}: unknown key name {key:?}")]
+    UnknownKey { binding: BindingRef, key: String },
+    #[error(
+        "{binding}: brightness delta_percent {delta} has to be between -100 and 100, and not 0"
+    )]
+    InvalidBrightnessDelta { binding: BindingRef, delta: i32 },
+    #[error("{binding}: scroll amount {amount} has to be between -100 and 100, and not 0")]
+    InvalidScrollAmount { binding: BindingRef, amount: i32 },
+    #[error(
+        "{binding}: sequence delays add up to {total_ms} ms; the most is {MAX_SEQUENCE_DELAY_MS}"
+    )]
+    SequenceTooLong { binding: BindingRef, total_ms: u64 },
+    #[error(
+        "{binding}: unknown log level {level:?} (use off, error, warn, info, debug, trace or \
+         default)"
+    )]
+    UnknownLogLevel { binding: BindingRef, level: String },
+    #[error("{binding}: key_combo has no keys")]
+    EmptyKeyCombo { binding: BindingRef },
+    #[error("{binding}: hold has no keys")]
+    EmptyHold { binding: BindingRef },
+    #[error("{binding}: unknown profile {name:?}")]
+    UnknownProfile { binding: BindingRef, name: String },
+    #[error("{binding}: webhook url {url:?} must start with http:// or https://")]
+    InvalidWebhookUrl { binding: BindingRef, url: String },
+    #[error("{binding}: raw_events has no events")]
+    EmptyRawEvents { binding: BindingRef },
+    #[error(
+        "{binding}: raw event #{index} (type {event_type}, code {code}) can't be sent; only EV_SYN, EV_KEY and EV_REL codes are supported"
+    )]
+    UnsupportedRawEvent {
+        binding: BindingRef,
+        index: usize,
+        event_type: u16,
+        code: u16,
+    },
+    #[error("unknown button {0:?}")]
+    UnknownButton(String),
+    #[error("queue.depth must be at least 1")]
+    ZeroQueueDepth,
+    #[error("{binding}: repeat.interval_ms must be at least 1")]
+    ZeroRepeatInterval { binding: BindingRef },
+    #[error("{binding}: {button} only works with a modifier_button held")]
+    WheelWithoutModifier {
+        binding: BindingRef,
+        button: &'static str,
+    },
+    #[error("{binding}: modifier_button only works with WHEEL_UP and WHEEL_DOWN")]
+    ModifierOnNonWheel { binding: BindingRef },
+    #[error("{binding}: modifier_button {button} can't be held; use a mouse button")]
+    UnholdableModifier {
+        binding: BindingRef,
+        button: &'static str,
+    },
+    #[error("{binding}: active_hours time {value:?} isn't HH:MM (00:00 to 23:59)")]
+    InvalidActiveHours { binding: BindingRef, value: String },
+    #[error("{binding}: active_hours starts and ends at {at}, which leaves no time active")]
+    EmptyActiveHours { binding: BindingRef, at: String },
+    #[error(
+        "{binding}: window_class pattern {pattern:?} is empty or uses [ ]; only * and ? are \
+         wildcards"
+    )]
+    InvalidWindowClass {
+        binding: BindingRef,
+        pattern: String,
+    },
+    #[error("{binding}: tilt only works with WHEEL_TILT_LEFT and WHEEL_TILT_RIGHT")]
+    TiltGestureOnNonTilt { binding: BindingRef },
+    #[error("tilt_hold_ms must be at least 1")]
+    ZeroTiltHold,
+    #[error("{binding}: {button} has no release, so it only fires on a plain press")]
+    TriggerOnWheel {
+        binding: BindingRef,
+        button: &'static str,
+    },
+    #[error(
+        "{binding}: double_click only goes with the press trigger; bind a double_press instead"
+    )]
+    DoubleClickWithTrigger { binding: BindingRef },
+    #[error("{binding}: trigger time {ms} ms has to be between 1 and {MAX_TRIGGER_MS}")]
+    InvalidTriggerTime { binding: BindingRef, ms: u64 },
+    #[error(
+        "evdev.virtual_device_name {name:?} has to be 1 to {MAX_VIRTUAL_DEVICE_NAME} bytes, \
+         without NUL"
+    )]
+    InvalidVirtualDeviceName { name: String },
+    #[error("{binding}: layout group {group} is out of range (XKB has at most 4 groups, 0-3)")]
+    InvalidLayoutGroup { binding: BindingRef, group: u8 },
+    #[error("{binding}: unknown macro {name:?}")]
+    UnknownMacro { binding: BindingRef, name: String },
+    #[error("profile {profile:?} inherits unknown profile {parent:?}")]
+    UnknownParentProfile { profile: String, parent: String },
+    #[error("profile {profile:?}: device has neither a path nor a name to match")]
+    EmptyDeviceMatch { profile: String },
+    #[error("profiles inherit from each other in a loop ({})", cycle.join(" -> "))]
+    CyclicInheritance { cycle: Vec<String> },
+    #[error("{binding}: macros refer to each other in a loop ({})", cycle.join(" -> "))]
+    CyclicMacro {
+        binding: BindingRef,
+        cycle: Vec<String>,
+    },
+}
+
+/// Problems that don't stop a config from loading but probably aren't what the user meant.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigWarning {
+    #[error("{binding}: {button} is already bound by binding #{first}, so this one never fires")]
+    DuplicateButton {
+        binding: BindingRef,
+        first: usize,
+        button: &'static str,
+    },
+    #[error("{binding}: parallel action has no sub-actions")]
+    EmptyParallel { binding: BindingRef },
+    #[error("{binding}: sequence has no actions to run")]
+    EmptySequence { binding: BindingRef },
+    #[error("profile {name:?} is defined more than once; only the first is used")]
+    DuplicateProfile { name: String },
+    #[error("{binding}: repeat has no effect on a {action} action, which already acts while held")]
+    RepeatOnHoldAction {
+        binding: BindingRef,
+        action: &'static str,
+    },
+    #[error(
+        "{binding}: key_combo {keys:?} is only modifiers, and tapping modifiers on their own \
+         usually does nothing (to change keyboard layouts, use switch_layout)"
+    )]
+    ModifierOnlyCombo {
+        binding: BindingRef,
+        keys: Vec<String>,
+    },
+}
+
+/// Identifies an action's owner in error messages: a binding (top-level, or inside a named
+/// profile) or the startup action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingRef {
+    Binding {
+        profile: Option<String>,
+        index: usize,
+    },
+    StartupAction,
+}
+
+impl fmt::Display for BindingRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Binding {
+                profile: Some(profile),
+                index,
+            } => write!(f, "profile {profile:?} binding #{index}"),
+            Self::Binding {
+                profile: None,
+                index,
+            } => write!(f, "binding #{index}"),
+            Self::StartupAction => f.write_str("startup_action"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Config {
     #[serde(default)]
     pub device_by_path: Option<String>,
+    /// Regex matched against device names; only matching devices are used when no single
+    /// device is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    /// A keymap file overriding how the X11 backend turns `KEY_*` names into keysyms; relative
+    /// paths are relative to the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keymap: Option<PathBuf>,
+    /// Key names that actions may inject; anything else is skipped. `None` allows every key.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "optional_key_names"
+    )]
+    pub allowed_keys: Option<Vec<String>>,
+    /// Keep the profile chosen with [`Action::SwitchProfile`] across daemon restarts.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub remember_profile: bool,
+    /// Swap `WHEEL_TILT_LEFT` and `WHEEL_TILT_RIGHT`, for devices that report horizontal wheel
+    /// movement the other way round.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub invert_wheel_tilt: bool,
+    /// Write bindings in button order when saving, so edits made in the config app don't
+    /// reshuffle a version-controlled file. Bindings for the same button keep their relative
+    /// order, which is the only order the daemon looks at.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sort_bindings: bool,
+    /// The order a `key_combo`'s keys are released in, on every backend.
+    #[serde(default, skip_serializing_if = "KeyReleaseOrder::is_default")]
+    pub key_release_order: KeyReleaseOrder,
+    /// How long a wheel tilt has to last to count as a hold rather than a flick, for bindings
+    /// with [`Binding::tilt`]. `None` is [`DEFAULT_TILT_HOLD_MS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tilt_hold_ms: Option<u64>,
+    /// Ignore a middle-button press this soon after a wheel notch, as a wheel pushed while
+    /// scrolling. `None` is [`DEFAULT_MIDDLE_CLICK_SCROLL_GUARD_MS`]; 0 turns the guard off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub middle_click_scroll_guard_ms: Option<u64>,
+    /// Wait this long after `run` starts before opening devices or connecting to X, so a
+    /// daemon started at login doesn't race the session coming up. `None` doesn't wait.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_delay_ms: Option<u64>,
     #[serde(default)]
     pub bindings: Vec<Binding>,
+    /// Named alternative binding sets (see [`Action::HoldProfile`] and [`Action::SwitchProfile`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<Profile>,
+    #[serde(default, skip_serializing_if = "QueueConfig::is_default")]
+    pub queue: QueueConfig,
+    #[serde(default, skip_serializing_if = "X11Config::is_default")]
+    pub x11: X11Config,
+    #[serde(default, skip_serializing_if = "EvdevConfig::is_default")]
+    pub evdev: EvdevConfig,
+    /// Named actions that bindings can refer to with [`Action::Macro`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub macros: BTreeMap<String, Action>,
+    /// Run once when the daemon has opened its devices, before it handles any input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_action: Option<Action>,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            device_by_path: None,
-            bindings: vec![
-                Binding {
-                    button: MouseButton::BtnSide,
-                    action: Action::KeyCombo {
-                        keys: vec!["KEY_BACK".into()],
-                    },
-                },
-                Binding {
-                    button: MouseButton::BtnExtra,
-                    action: Action::KeyCombo {
-                        keys: vec!["KEY_FORWARD".into()],
-                    },
-                },
-                Binding {
-                    button: MouseButton::BtnForward,
-                    action: Action::KeyCombo {
-                        keys: vec!["KEY_VOLUMEUP".into()],
-                    },
-                },
-                Binding {
-                    button: MouseButton::BtnBack,
-                    action: Action::KeyCombo {
-                        keys: vec!["KEY_VOLUMEDOWN".into()],
-                    },
-                },
-            ],
-        }
+/// Settings that only apply to the X11 backend.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct X11Config {
+    #[serde(default)]
+    pub tilt_source: TiltSource,
+    /// Ignore all bindings (and release any grabs) while the focused window is fullscreen.
+    #[serde(default)]
+    pub pause_in_fullscreen: bool,
+    /// How `key_combo` actions without a `target` reach the focused window.
+    #[serde(default)]
+    pub key_method: X11KeyMethod,
+    /// Add the modifiers physically held on the keyboard (Shift, Ctrl, ...) to keys sent with
+    /// `SendEvent`, so holding Shift while pressing the button gives a capital letter. XTEST
+    /// key presses already combine with held modifiers.
+    #[serde(default)]
+    pub compose_held_modifiers: bool,
+}
+
+/// How the X11 backend injects keys.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum X11KeyMethod {
+    /// XTEST, which the server treats like real key presses.
+    #[default]
+    #[serde(rename = "xtest")]
+    XTest,
+    /// `SendEvent` to the focused window, with modifiers as the events' state. Marked as
+    /// synthetic, so many applications ignore it.
+    SendEvent,
+}
+
+impl X11Config {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-pub struct Binding {
-    pub button: MouseButton,
-    pub action: Action,
+/// Settings that only apply to the evdev backend.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct EvdevConfig {
+    /// Send every key press and release of a combo as its own input report (`SYN_REPORT`),
+    /// for applications that drop keys arriving together in one report.
+    #[serde(default)]
+    pub syn_per_key: bool,
+    /// Extra time allowed between events from a device that looks wireless (a Bluetooth bus, or
+    /// "Bluetooth" in its name), whose events can arrive late and bunched up. Lengthens the
+    /// timing thresholds, such as how long a wheel tilt may pause before it counts as released.
+    /// Detection is a heuristic; unset, every device is timed alike.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_latency_compensation_ms: Option<u64>,
+    /// When several devices are read, a button or wheel event that another device reported
+    /// this recently counts as the same one, for receivers that expose one mouse as several
+    /// devices. `None` is [`DEFAULT_CROSS_DEVICE_COALESCE_MS`]; 0 turns it off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cross_device_coalesce_ms: Option<u64>,
+    /// Also use devices that look like keyboards (20 or more letter keys) when picking devices
+    /// by their buttons. A device given with `--device` or `device_by_path` is always used.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_keyboards: bool,
+    /// Also use touchpads (absolute position with finger tracking) when picking devices.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_touchpads: bool,
+    /// The name the virtual keyboard that injects keys shows up under, instead of
+    /// `mouse-assist-virtual-keyboard`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtual_device_name: Option<String>,
+    /// The USB ids the virtual devices report, for capture software that only picks up devices
+    /// it recognizes. `None` keeps uinput's placeholder ids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtual_device_ids: Option<VirtualDeviceIds>,
 }
 
+/// A device's `input_id`, minus the bus (always USB).
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-pub enum MouseButton {
-    BtnLeft,
-    BtnRight,
-    BtnMiddle,
-    BtnSide,
-    BtnExtra,
-    BtnForward,
-    BtnBack,
-    BtnTask,
-    WheelTiltLeft,
-    WheelTiltRight,
+pub struct VirtualDeviceIds {
+    pub vendor: u16,
+    pub product: u16,
+    #[serde(default)]
+    pub version: u16,
 }
 
-impl MouseButton {
-    pub fn toml_name(self) -> &'static str {
-        match self {
-            Self::BtnLeft => "BTN_LEFT",
-            Self::BtnRight => "BTN_RIGHT",
-            Self::BtnMiddle => "BTN_MIDDLE",
-            Self::BtnSide => "BTN_SIDE",
-            Self::BtnExtra => "BTN_EXTRA",
-            Self::BtnForward => "BTN_FORWARD",
-            Self::BtnBack => "BTN_BACK",
-            Self::BtnTask => "BTN_TASK",
-            Self::WheelTiltLeft => "WHEEL_TILT_LEFT",
-            Self::WheelTiltRight => "WHEEL_TILT_RIGHT",
-        }
-    }
-
-    pub fn linux_key_code(self) -> Option<u16> {
-        match self {
-            Self::BtnLeft => Some(0x110),
-            Self::BtnRight => Some(0x111),
-            Self::BtnMiddle => Some(0x112),
-            Self::BtnSide => Some(0x113),
-            Self::BtnExtra => Some(0x114),
-            Self::BtnForward => Some(0x115),
-            Self::BtnBack => Some(0x116),
-            Self::BtnTask => Some(0x117),
-            Self::WheelTiltLeft | Self::WheelTiltRight => None,
-        }
-    }
+/// The longest name uinput accepts for a device, in bytes.
+pub const MAX_VIRTUAL_DEVICE_NAME: usize = 79;
 
-    pub fn x11_button_number(self) -> Option<u32> {
-        match self {
-            Self::BtnLeft => Some(1),
-            Self::BtnMiddle => Some(2),
-            Self::BtnRight => Some(3),
-            Self::BtnSide | Self::BtnBack => Some(8),
-            Self::BtnExtra | Self::BtnForward => Some(9),
-            Self::BtnTask => None,
-            Self::WheelTiltLeft => Some(6),
-            Self::WheelTiltRight => Some(7),
-        }
+impl EvdevConfig {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum Action {
-    Command { argv: Vec<String> },
-    KeyCombo { keys: Vec<String> },
+/// Where the X11 backend looks for wheel tilt.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TiltSource {
+    /// Legacy buttons 6/7, which most drivers emulate for horizontal scrolling.
+    #[default]
+    Buttons,
+    /// Horizontal scroll valuators (XInput 2.1 smooth scrolling), for devices whose tilt never
+    /// shows up as buttons 6/7.
+    Valuators,
 }
 
-pub fn default_config_path() -> Result<PathBuf, ConfigError> {
-    let dirs = BaseDirs::new().ok_or(ConfigError::NoConfigDir)?;
-    Ok(dirs.config_dir().join(APP_NAME).join(CONFIG_FILE_NAME))
+/// Which kind of wheel tilt a binding answers to, telling a quick flick from a sustained tilt.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TiltGesture {
+    /// A tilt that ends before `tilt_hold_ms`. Fires once the tilt's events stop.
+    Flick,
+    /// A tilt still going at `tilt_hold_ms`. Fires once, at that point.
+    Hold,
 }
 
-pub fn load_config(path: &Path) -> Result<Config, ConfigError> {
-    let raw = fs::read_to_string(path)?;
-    Ok(toml::from_str(&raw)?)
+/// [`Config::tilt_hold_ms`] when it isn't set.
+pub const DEFAULT_TILT_HOLD_MS: u64 = 400;
+
+/// What a binding's button has to do for it to fire.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// Any press. Fires right away, unless the button also has a `double_press` or
+    /// `long_press` binding, which makes it wait until the press can't be one of those.
+    #[default]
+    Press,
+    /// A second press within `within_ms` of the first one's release.
+    DoublePress {
+        #[serde(default = "default_double_press_ms")]
+        within_ms: u64,
+    },
+    /// A press still held after `after_ms`. Fires once, at that point.
+    LongPress {
+        #[serde(default = "default_long_press_ms")]
+        after_ms: u64,
+    },
 }
 
-pub fn save_config(path: &Path, config: &Config) -> Result<(), ConfigError> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+impl Trigger {
+    pub fn is_press(&self) -> bool {
+        *self == Self::Press
     }
-    let raw = config_to_toml_string(config)?;
-    fs::write(path, raw)?;
-    Ok(())
 }
 
-pub fn binding_to_toml_string(binding: &Binding) -> Result<String, ConfigError> {
-    let mut out = String::new();
-
-    out.push_str("[[bindings]]\n");
+/// [`Trigger::DoublePress`]'s `within_ms` when it isn't set, and the window for
+/// [`Binding::double_click`].
+pub const DEFAULT_DOUBLE_PRESS_MS: u64 = 300;
 
-    let button = toml::Value::try_from(&binding.button)?;
-    out.push_str("button = ");
-    out.push_str(&button.to_string());
-    out.push('\n');
+/// [`Trigger::LongPress`]'s `after_ms` when it isn't set.
+pub const DEFAULT_LONG_PRESS_MS: u64 = 500;
 
-    out.push_str("action = ");
-    out.push_str(&action_inline(&binding.action));
-    out.push('\n');
+/// The longest a trigger may wait. A button's plain presses wait as long as its double press
+/// window, so a long one makes every click feel stuck.
+pub const MAX_TRIGGER_MS: u64 = 5000;
 
-    Ok(out)
+fn default_double_press_ms() -> u64 {
+    DEFAULT_DOUBLE_PRESS_MS
 }
 
-fn toml_string(value: &str) -> String {
-    toml::Value::String(value.to_owned()).to_string()
+fn default_long_press_ms() -> u64 {
+    DEFAULT_LONG_PRESS_MS
 }
 
-fn toml_array_of_strings(values: &[String]) -> String {
-    toml::Value::Array(values.iter().cloned().map(toml::Value::String).collect()).to_string()
+/// [`EvdevConfig::cross_device_coalesce_ms`] when it isn't set.
+pub const DEFAULT_CROSS_DEVICE_COALESCE_MS: u64 = 5;
+
+/// [`Config::middle_click_scroll_guard_ms`] when it isn't set.
+pub const DEFAULT_MIDDLE_CLICK_SCROLL_GUARD_MS: u64 = 150;
+
+/// The order a combo's keys are released in after they've all been pressed, modifiers first and
+/// then the other keys, each as listed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyReleaseOrder {
+    /// Last pressed, first released, so modifiers stay down around the other keys.
+    #[default]
+    Reverse,
+    /// In the order pressed, for applications that expect it.
+    Forward,
 }
 
-fn action_inline(action: &Action) -> String {
-    match action {
-        Action::Command { argv } => format!(
-            "{{ type = {}, argv = {} }}",
-            toml_string("command"),
-            toml_array_of_strings(argv)
-        ),
-        Action::KeyCombo { keys } => format!(
-            "{{ type = {}, keys = {} }}",
-            toml_string("key_combo"),
-            toml_array_of_strings(keys)
-        ),
+impl KeyReleaseOrder {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `pressed`, in the order the keys are to be released.
+    pub fn releases<T: Copy>(self, pressed: &[T]) -> Vec<T> {
+        match self {
+            Self::Reverse => pressed.iter().rev().copied().collect(),
+            Self::Forward => pressed.to_vec(),
+        }
     }
 }
 
-fn config_to_toml_string(config: &Config) -> Result<String, ConfigError> {
-    let mut out = String::new();
+/// Bounds on the daemon's queue of actions waiting for the action worker.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// How many actions may wait at once (not counting the one running).
+    #[serde(default = "QueueConfig::default_depth")]
+    pub depth: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// Drop an action identical to the one dispatched just before it, from any binding, if it
+    /// comes less than this many milliseconds later. Catches switch chatter and accidental
+    /// double fires; 0 turns it off.
+    #[serde(default, skip_serializing_if = "QueueConfig::is_zero")]
+    pub min_action_interval_ms: u64,
+}
 
-    if let Some(device_by_path) = &config.device_by_path {
-        out.push_str("device_by_path = ");
-        out.push_str(&toml_string(device_by_path));
-        out.push('\n');
-        out.push('\n');
+impl QueueConfig {
+    fn default_depth() -> usize {
+        32
     }
 
-    for (idx, binding) in config.bindings.iter().enumerate() {
-        if idx != 0 {
-            out.push('\n');
+    fn is_zero(ms: &u64) -> bool {
+        *ms == 0
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            depth: Self::default_depth(),
+            overflow: OverflowPolicy::default(),
+            min_action_interval_ms: 0,
         }
-        out.push_str(&binding_to_toml_string(binding)?);
     }
+}
 
-    Ok(out)
+/// What to do with a new action when the queue is already full.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Discard the new action.
+    DropNewest,
+    /// Discard the longest-waiting action to make room.
+    #[default]
+    DropOldest,
+    /// Stall the input loop until the worker catches up.
+    Block,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// A named set of bindings that can replace the top-level `bindings`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    /// Another profile whose bindings this one starts from; see [`Config::resolve_inheritance`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherits: Option<String>,
+    /// Use this profile instead of the top-level bindings for input from a matching device, when
+    /// the evdev backend reads several (e.g. a mouse and a trackball).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceMatch>,
+    #[serde(default)]
+    pub bindings: Vec<Binding>,
+}
 
-    #[test]
-    fn config_round_trip_toml() {
-        let cfg = Config::default();
-        let raw = config_to_toml_string(&cfg).unwrap();
-        let decoded: Config = toml::from_str(&raw).unwrap();
-        assert_eq!(decoded, cfg);
+/// Picks an input device by its path and/or name; every field that's set has to match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct DeviceMatch {
+    /// A device node, or a link to one such as `/dev/input/by-id/...-event-mouse`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// A substring of the device name, compared case-insensitively.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl DeviceMatch {
+    /// Whether the device at `path` (a node under `/dev/input`) called `name` matches. A
+    /// `path` is compared by the node it leads to, so it's looked up on disk.
+    pub fn matches(&self, path: &Path, name: &str) -> bool {
+        let path_matches = self.path.as_deref().is_none_or(|want| {
+            Path::new(want) == path || fs::canonicalize(want).is_ok_and(|want| want == path)
+        });
+        let name_matches = self
+            .name
+            .as_deref()
+            .is_none_or(|want| name.to_lowercase().contains(&want.to_lowercase()));
+        path_matches && name_matches
     }
+}
 
-    #[test]
-    fn binding_snippet_round_trip_toml() {
-        #[derive(serde::Deserialize)]
-        struct Wrapper {
-            bindings: Vec<Binding>,
+impl Config {
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// The first profile whose `device` matches the device at `path` called `name`.
+    pub fn device_profile(&self, path: &Path, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| {
+            p.device
+                .as_ref()
+                .is_some_and(|device| device.matches(path, name))
+        })
+    }
+
+    /// Every binding in the config, including those inside profiles.
+    pub fn all_bindings(&self) -> impl Iterator<Item = &Binding> {
+        self.binding_sets().flat_map(|(_, bindings)| bindings)
+    }
+
+    /// Every binding set in the config: the top-level list first, then each profile's.
+    pub fn binding_sets(&self) -> impl Iterator<Item = (Option<&str>, &[Binding])> {
+        std::iter::once((None, self.bindings.as_slice())).chain(
+            self.profiles
+                .iter()
+                .map(|p| (Some(p.name.as_str()), p.bindings.as_slice())),
+        )
+    }
+
+    /// Checks for hard errors: bindings whose actions can never do anything.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        if self.queue.depth == 0 {
+            errors.push(ConfigError::ZeroQueueDepth);
+        }
+        if self.tilt_hold_ms == Some(0) {
+            errors.push(ConfigError::ZeroTiltHold);
         }
+        for profile in &self.profiles {
+            match self.inherited_bindings(profile, &mut vec![profile.name.clone()]) {
+                // Report problems once, not again from every profile that inherits them: a
+                // missing parent from the profile naming it, a loop from its first name.
+                Err(ConfigError::UnknownParentProfile { profile: child, .. })
+                    if child != profile.name => {}
+                Err(ConfigError::CyclicInheritance { cycle })
+                    if cycle.iter().min() != Some(&profile.name) => {}
+                Err(err) => errors.push(err),
+                Ok(_) => {}
+            }
+            if profile
+                .device
+                .as_ref()
+                .is_some_and(|device| device.path.is_none() && device.name.is_none())
+            {
+                errors.push(ConfigError::EmptyDeviceMatch {
+                    profile: profile.name.clone(),
+                });
+            }
+        }
+        if let Some(name) = &self.evdev.virtual_device_name {
+            if name.trim().is_empty() || name.len() > MAX_VIRTUAL_DEVICE_NAME || name.contains('\0')
+            {
+                errors.push(ConfigError::InvalidVirtualDeviceName { name: name.clone() });
+            }
+        }
+        for (profile, bindings) in self.binding_sets() {
+            for (index, binding) in bindings.iter().enumerate() {
+                let binding_ref = BindingRef::Binding {
+                    profile: profile.map(str::to_owned),
+                    index,
+                };
+                if binding.repeat.is_some_and(|repeat| repeat.interval_ms == 0) {
+                    errors.push(ConfigError::ZeroRepeatInterval {
+                        binding: binding_ref.clone(),
+                    });
+                }
+                match (binding.button, binding.modifier_button) {
+                    (MouseButton::WheelUp | MouseButton::WheelDown, None) => {
+                        errors.push(ConfigError::WheelWithoutModifier {
+                            binding: binding_ref.clone(),
+                            button: binding.button.toml_name(),
+                        });
+                    }
+                    (MouseButton::WheelUp | MouseButton::WheelDown, Some(modifier))
+                        if modifier.is_wheel() =>
+                    {
+                        errors.push(ConfigError::UnholdableModifier {
+                            binding: binding_ref.clone(),
+                            button: modifier.toml_name(),
+                        });
+                    }
+                    (MouseButton::WheelUp | MouseButton::WheelDown, Some(_)) | (_, None) => {}
+                    (_, Some(_)) => errors.push(ConfigError::ModifierOnNonWheel {
+                        binding: binding_ref.clone(),
+                    }),
+                }
+                if binding.button.is_wheel()
+                    && (!binding.trigger.is_press() || binding.double_click.is_some())
+                {
+                    errors.push(ConfigError::TriggerOnWheel {
+                        binding: binding_ref.clone(),
+                        button: binding.button.toml_name(),
+                    });
+                }
+                if !binding.trigger.is_press() && binding.double_click.is_some() {
+                    errors.push(ConfigError::DoubleClickWithTrigger {
+                        binding: binding_ref.clone(),
+                    });
+                }
+                if let Trigger::DoublePress { within_ms: ms }
+                | Trigger::LongPress { after_ms: ms } = binding.trigger
+                {
+                    if !(1..=MAX_TRIGGER_MS).contains(&ms) {
+                        errors.push(ConfigError::InvalidTriggerTime {
+                            binding: binding_ref.clone(),
+                            ms,
+                        });
+                    }
+                }
+                if binding.tilt.is_some()
+                    && !matches!(
+                        binding.button,
+                        MouseButton::WheelTiltLeft | MouseButton::WheelTiltRight
+                    )
+                {
+                    errors.push(ConfigError::TiltGestureOnNonTilt {
+                        binding: binding_ref.clone(),
+                    });
+                }
+                for pattern in &binding.window_class {
+                    if pattern.trim().is_empty() || pattern.contains(['[', ']']) {
+                        errors.push(ConfigError::InvalidWindowClass {
+                            binding: binding_ref.clone(),
+                            pattern: pattern.clone(),
+                        });
+                    }
+                }
+                if let Some((start, end)) = &binding.active_hours {
+                    for value in [start, end] {
+                        if minute_of_day(value).is_none() {
+                            errors.push(ConfigError::InvalidActiveHours {
+                                binding: binding_ref.clone(),
+                                value: value.clone(),
+                            });
+                        }
+                    }
+                    if minute_of_day(start).is_some() && minute_of_day(start) == minute_of_day(end)
+                    {
+                        errors.push(ConfigError::EmptyActiveHours {
+                            binding: binding_ref.clone(),
+                            at: start.clone(),
+                        });
+                    }
+                }
+                self.validate_action(&binding_ref, &binding.action, &mut errors);
+                if let Some(action) = &binding.double_click {
+                    self.validate_action(&binding_ref, action, &mut errors);
+                }
+            }
+        }
+        if let Some(action) = &self.startup_action {
+            self.validate_action(&BindingRef::StartupAction, action, &mut errors);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 
-        let binding = Binding {
-            button: MouseButton::BtnForward,
-            action: Action::KeyCombo {
-                keys: vec!["KEY_VOLUMEUP".into()],
+    fn validate_action(
+        &self,
+        binding: &BindingRef,
+        action: &Action,
+        errors: &mut Vec<ConfigError>,
+    ) {
+        match action {
+            Action::Command { argv }
+            | Action::CommandNotify { argv }
+            | Action::LaunchOrFocus { argv, .. }
+                if argv.is_empty() =>
+            {
+                errors.push(ConfigError::EmptyCommand {
+                    binding: binding.clone(),
+                });
+            }
+            Action::Open { target } if target.trim().is_empty() => {
+                errors.push(ConfigError::EmptyOpenTarget {
+                    binding: binding.clone(),
+                });
+            }
+            Action::Script { source } if source.trim().is_empty() => {
+                errors.push(ConfigError::EmptyScript {
+                    binding: binding.clone(),
+                });
+            }
+            Action::Brightness { delta_percent, .. }
+                if *delta_percent == 0 || !(-100..=100).contains(delta_percent) =>
+            {
+                errors.push(ConfigError::InvalidBrightnessDelta {
+                    binding: binding.clone(),
+                    delta: *delta_percent,
+                });
+            }
+            Action::Scroll { amount, .. } if *amount == 0 || !(-100..=100).contains(amount) => {
+                errors.push(ConfigError::InvalidScrollAmount {
+                    binding: binding.clone(),
+                    amount: *amount,
+                });
+            }
+            Action::SetCursor { name } if name.trim().is_empty() => {
+                errors.push(ConfigError::EmptyCursorName {
+                    binding: binding.clone(),
+                });
+            }
+            Action::WmCommand { command } if command.trim().is_empty() => {
+                errors.push(ConfigError::EmptyWmCommand {
+                    binding: binding.clone(),
+                });
+            }
+            Action::SetLogLevel { level } if !is_log_level(level) => {
+                errors.push(ConfigError::UnknownLogLevel {
+                    binding: binding.clone(),
+                    level: level.clone(),
+                });
+            }
+            Action::KeyCombo { keys, .. } if keys.is_empty() => {
+                errors.push(ConfigError::EmptyKeyCombo {
+                    binding: binding.clone(),
+                });
+            }
+            Action::KeyChords { keys, .. } if keys.is_empty() || keys.iter().any(Vec::is_empty) => {
+                errors.push(ConfigError::EmptyKeyCombo {
+                    binding: binding.clone(),
+                });
+            }
+            Action::Hold { keys } if keys.is_empty() => {
+                errors.push(ConfigError::EmptyHold {
+                    binding: binding.clone(),
+                });
+            }
+            Action::KeyCombo { keys, .. } | Action::Hold { keys } => {
+                unknown_keys(binding, keys, errors)
+            }
+            Action::KeyChords { keys, .. } => {
+                for chord in keys {
+                    unknown_keys(binding, chord, errors);
+                }
+            }
+            Action::Parallel { actions } => {
+                for action in actions {
+                    self.validate_action(binding, action, errors);
+                }
+            }
+            Action::Sequence { steps } => {
+                let total_ms = Step::total_delay(steps);
+                if total_ms > MAX_SEQUENCE_DELAY_MS {
+                    errors.push(ConfigError::SequenceTooLong {
+                        binding: binding.clone(),
+                        total_ms,
+                    });
+                }
+                for action in Step::actions(steps) {
+                    self.validate_action(binding, action, errors);
+                }
+            }
+            Action::ClipboardRoute { rules, default, .. } => {
+                if rules.is_empty() && default.is_none() {
+                    errors.push(ConfigError::EmptyClipboardRoute {
+                        binding: binding.clone(),
+                    });
+                }
+                for action in rules
+                    .iter()
+                    .map(|rule| &rule.action)
+                    .chain(default.as_deref())
+                {
+                    self.validate_action(binding, action, errors);
+                }
+            }
+            Action::PerProfile { map, default } => {
+                for (name, action) in map {
+                    if self.profile(name).is_none() {
+                        errors.push(ConfigError::UnknownProfile {
+                            binding: binding.clone(),
+                            name: name.clone(),
+                        });
+                    }
+                    self.validate_action(binding, action, errors);
+                }
+                if let Some(action) = default {
+                    self.validate_action(binding, action, errors);
+                }
+            }
+            Action::SwitchLayout { group: Some(group) } if *group > 3 => {
+                errors.push(ConfigError::InvalidLayoutGroup {
+                    binding: binding.clone(),
+                    group: *group,
+                });
+            }
+            Action::HoldProfile { name }
+            | Action::ToggleProfile { name }
+            | Action::SwitchProfile {
+                name: Some(name), ..
+            } if self.profile(name).is_none() => {
+                errors.push(ConfigError::UnknownProfile {
+                    binding: binding.clone(),
+                    name: name.clone(),
+                });
+            }
+            Action::Webhook { url, .. }
+                if !(url.starts_with("http://") || url.starts_with("https://")) =>
+            {
+                errors.push(ConfigError::InvalidWebhookUrl {
+                    binding: binding.clone(),
+                    url: url.clone(),
+                });
+            }
+            Action::RawEvents { events } if events.is_empty() => {
+                errors.push(ConfigError::EmptyRawEvents {
+                    binding: binding.clone(),
+                });
+            }
+            Action::RawEvents { events } => {
+                for (index, event) in events.iter().enumerate() {
+                    if !event.is_supported() {
+                        errors.push(ConfigError::UnsupportedRawEvent {
+                            binding: binding.clone(),
+                            index,
+                            event_type: event.event_type,
+                            code: event.code,
+                        });
+                    }
+                }
+            }
+            Action::Macro { .. } => match self.expand_action(binding, action, &mut Vec::new()) {
+                Ok(expanded) => self.validate_action(binding, &expanded, errors),
+                Err(err) => errors.push(err),
             },
-        };
-        let raw = binding_to_toml_string(&binding).unwrap();
-        let wrapper: Wrapper = toml::from_str(&raw).unwrap();
-        assert_eq!(wrapper.bindings, vec![binding]);
+            _ => {}
+        }
     }
 
-    #[test]
-    fn config_parses_inline_action_table() {
-        let raw = r#"
-[[bindings]]
-button = "BTN_SIDE"
-action = { type = "key_combo", keys = ["KEY_BACK"] }
-"#;
-        let decoded: Config = toml::from_str(raw).unwrap();
-        assert_eq!(decoded.bindings.len(), 1);
-        assert_eq!(decoded.bindings[0].button, MouseButton::BtnSide);
-        assert_eq!(
-            decoded.bindings[0].action,
-            Action::KeyCombo {
-                keys: vec!["KEY_BACK".into()]
-            }
-        );
+    /// The config as `save_config` would write it.
+    pub fn to_toml_string(&self) -> Result<String, ConfigError> {
+        config_to_toml_string(self, TomlStyle::default())
     }
 
-    #[test]
-    fn config_parses_expanded_action_subtable() {
-        let raw = r#"
-[[bindings]]
-button = "BTN_SIDE"
+    /// The config as pretty-printed JSON, with the same field names as the TOML.
+    pub fn to_json_string(&self) -> Result<String, ConfigError> {
+        let mut out = serde_json::to_string_pretty(self)?;
+        out.push('\n');
+        Ok(out)
+    }
 
-[bindings.action]
-type = "key_combo"
-keys = ["KEY_BACK"]
-"#;
-        let decoded: Config = toml::from_str(raw).unwrap();
-        assert_eq!(decoded.bindings.len(), 1);
-        assert_eq!(decoded.bindings[0].button, MouseButton::BtnSide);
-        assert_eq!(
-            decoded.bindings[0].action,
-            Action::KeyCombo {
-                keys: vec!["KEY_BACK".into()]
-            }
-        );
+    /// A copy of the config where every profile with `inherits` holds its full set of bindings:
+    /// the parent's (itself resolved first), minus those for a button the profile binds itself,
+    /// followed by the profile's own. A chord counts as its own button, so overriding one chord
+    /// keeps the parent's others.
+    pub fn resolve_inheritance(&self) -> Result<Config, ConfigError> {
+        let mut resolved = self.clone();
+        for profile in &mut resolved.profiles {
+            profile.bindings = self.inherited_bindings(profile, &mut vec![profile.name.clone()])?;
+            profile.inherits = None;
+        }
+        Ok(resolved)
     }
 
-    #[test]
-    fn config_serializes_actions_inline() {
-        let cfg = Config {
-            device_by_path: None,
-            bindings: vec![Binding {
-                button: MouseButton::WheelTiltRight,
-                action: Action::KeyCombo {
-                    keys: vec!["KEY_FORWARD".into()],
-                },
-            }],
+    /// `stack` holds the profiles being resolved, innermost last, to catch loops.
+    fn inherited_bindings(
+        &self,
+        profile: &Profile,
+        stack: &mut Vec<String>,
+    ) -> Result<Vec<Binding>, ConfigError> {
+        let Some(parent_name) = &profile.inherits else {
+            return Ok(profile.bindings.clone());
         };
-        let raw = config_to_toml_string(&cfg).unwrap();
+        if let Some(start) = stack.iter().position(|n| n == parent_name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(parent_name.clone());
+            return Err(ConfigError::CyclicInheritance { cycle });
+        }
+        let parent =
+            self.profile(parent_name)
+                .ok_or_else(|| ConfigError::UnknownParentProfile {
+                    profile: profile.name.clone(),
+                    parent: parent_name.clone(),
+                })?;
+        stack.push(parent_name.clone());
+        let inherited = self.inherited_bindings(parent, stack);
+        stack.pop();
+        let mut bindings: Vec<Binding> = inherited?
+            .into_iter()
+            .filter(|b| {
+                !profile
+                    .bindings
+                    .iter()
+                    .any(|own| own.button == b.button && own.modifier_button == b.modifier_button)
+            })
+            .collect();
+        bindings.extend(profile.bindings.iter().cloned());
+        Ok(bindings)
+    }
+
+    /// A copy of the config with `WHEEL_TILT_LEFT` and `WHEEL_TILT_RIGHT` swapped in every
+    /// binding when [`Config::invert_wheel_tilt`] is set, and the flag cleared, so the backends
+    /// can go by the direction the device reports.
+    pub fn resolve_wheel_tilt(&self) -> Config {
+        let mut resolved = self.clone();
+        if !resolved.invert_wheel_tilt {
+            return resolved;
+        }
+        resolved.invert_wheel_tilt = false;
+        let sets = std::iter::once(&mut resolved.bindings)
+            .chain(resolved.profiles.iter_mut().map(|p| &mut p.bindings));
+        for binding in sets.flatten() {
+            binding.button = binding.button.tilt_mirrored();
+            binding.modifier_button = binding.modifier_button.map(MouseButton::tilt_mirrored);
+        }
+        resolved
+    }
+
+    /// A copy of the config with every [`Action::Macro`] replaced by the action it names.
+    pub fn expand_macros(&self) -> Result<Config, ConfigError> {
+        let mut expanded = self.clone();
+        let sets = std::iter::once((None, &mut expanded.bindings)).chain(
+            expanded
+                .profiles
+                .iter_mut()
+                .map(|p| (Some(p.name.clone()), &mut p.bindings)),
+        );
+        for (profile, bindings) in sets {
+            for (index, binding) in bindings.iter_mut().enumerate() {
+                let binding_ref = BindingRef::Binding {
+                    profile: profile.clone(),
+                    index,
+                };
+                binding.action =
+                    self.expand_action(&binding_ref, &binding.action, &mut Vec::new())?;
+                if let Some(action) = &mut binding.double_click {
+                    *action = self.expand_action(&binding_ref, action, &mut Vec::new())?;
+                }
+            }
+        }
+        if let Some(action) = &mut expanded.startup_action {
+            *action = self.expand_action(&BindingRef::StartupAction, action, &mut Vec::new())?;
+        }
+        Ok(expanded)
+    }
+
+    /// `stack` holds the macros being expanded, outermost first, to catch loops.
+    fn expand_action(
+        &self,
+        binding: &BindingRef,
+        action: &Action,
+        stack: &mut Vec<String>,
+    ) -> Result<Action, ConfigError> {
+        match action {
+            Action::Macro { name } => {
+                if let Some(start) = stack.iter().position(|n| n == name) {
+                    let mut cycle = stack[start..].to_vec();
+                    cycle.push(name.clone());
+                    return Err(ConfigError::CyclicMacro {
+                        binding: binding.clone(),
+                        cycle,
+                    });
+                }
+                let body = self
+                    .macros
+                    .get(name)
+                    .ok_or_else(|| ConfigError::UnknownMacro {
+                        binding: binding.clone(),
+                        name: name.clone(),
+                    })?;
+                stack.push(name.clone());
+                let expanded = self.expand_action(binding, body, stack);
+                stack.pop();
+                expanded
+            }
+            Action::Parallel { actions } => Ok(Action::Parallel {
+                actions: actions
+                    .iter()
+                    .map(|action| self.expand_action(binding, action, stack))
+                    .collect::<Result<_, _>>()?,
+            }),
+            Action::Sequence { steps } => Ok(Action::Sequence {
+                steps: steps
+                    .iter()
+                    .map(|step| match step {
+                        Step::Action(action) => {
+                            self.expand_action(binding, action, stack).map(Step::Action)
+                        }
+                        Step::Delay { ms } => Ok(Step::Delay { ms: *ms }),
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            Action::ClipboardRoute {
+                selection,
+                rules,
+                default,
+            } => Ok(Action::ClipboardRoute {
+                selection: *selection,
+                rules: rules
+                    .iter()
+                    .map(|rule| {
+                        Ok(ClipboardRule {
+                            pattern: rule.pattern.clone(),
+                            action: self.expand_action(binding, &rule.action, stack)?,
+                        })
+                    })
+                    .collect::<Result<_, ConfigError>>()?,
+                default: default
+                    .as_deref()
+                    .map(|action| self.expand_action(binding, action, stack).map(Box::new))
+                    .transpose()?,
+            }),
+            Action::PerProfile { map, default } => Ok(Action::PerProfile {
+                map: map
+                    .iter()
+                    .map(|(name, action)| {
+                        Ok((name.clone(), self.expand_action(binding, action, stack)?))
+                    })
+                    .collect::<Result<_, ConfigError>>()?,
+                default: default
+                    .as_deref()
+                    .map(|action| self.expand_action(binding, action, stack).map(Box::new))
+                    .transpose()?,
+            }),
+            action => Ok(action.clone()),
+        }
+    }
+
+    /// Collects soft problems that [`Config::validate`] lets through.
+    pub fn warnings(&self) -> Vec<ConfigWarning> {
+        let mut warnings = Vec::new();
+        for (idx, profile) in self.profiles.iter().enumerate() {
+            if self.profiles[..idx].iter().any(|p| p.name == profile.name) {
+                warnings.push(ConfigWarning::DuplicateProfile {
+                    name: profile.name.clone(),
+                });
+            }
+        }
+        for (profile, bindings) in self.binding_sets() {
+            for (index, binding) in bindings.iter().enumerate() {
+                let binding_ref = BindingRef::Binding {
+                    profile: profile.map(str::to_owned),
+                    index,
+                };
+                // A binding with `window_class` wins over one without, whatever the order.
+                if let Some(first) = bindings[..index].iter().position(|b| {
+                    b.button == binding.button
+                        && b.modifier_button == binding.modifier_button
+                        && b.window_class == binding.window_class
+                        && b.tilt == binding.tilt
+                        && std::mem::discriminant(&b.trigger)
+                            == std::mem::discriminant(&binding.trigger)
+                }) {
+                    warnings.push(ConfigWarning::DuplicateButton {
+                        binding: binding_ref.clone(),
+                        first,
+                        button: binding.button.toml_name(),
+                    });
+                }
+                if binding.repeat.is_some()
+                    && matches!(
+                        binding.action,
+                        Action::HoldProfile { .. } | Action::Hold { .. }
+                    )
+                {
+                    warnings.push(ConfigWarning::RepeatOnHoldAction {
+                        binding: binding_ref.clone(),
+                        action: binding.action.kind(),
+                    });
+                }
+                action_warnings(&binding_ref, &binding.action, &mut warnings);
+                if let Some(action) = &binding.double_click {
+                    action_warnings(&binding_ref, action, &mut warnings);
+                }
+            }
+        }
+        if let Some(action) = &self.startup_action {
+            action_warnings(&BindingRef::StartupAction, action, &mut warnings);
+        }
+        warnings
+    }
+}
+
+fn action_warnings(binding: &BindingRef, action: &Action, warnings: &mut Vec<ConfigWarning>) {
+    match action {
+        Action::Parallel { actions } => {
+            if actions.is_empty() {
+                warnings.push(ConfigWarning::EmptyParallel {
+                    binding: binding.clone(),
+                });
+            }
+            for action in actions {
+                action_warnings(binding, action, warnings);
+            }
+        }
+        Action::Sequence { steps } => {
+            if Step::actions(steps).next().is_none() {
+                warnings.push(ConfigWarning::EmptySequence {
+                    binding: binding.clone(),
+                });
+            }
+            for action in Step::actions(steps) {
+                action_warnings(binding, action, warnings);
+            }
+        }
+        Action::PerProfile { map, default } => {
+            for action in map.values().chain(default.as_deref()) {
+                action_warnings(binding, action, warnings);
+            }
+        }
+        Action::ClipboardRoute { rules, default, .. } => {
+            for action in rules
+                .iter()
+                .map(|rule| &rule.action)
+                .chain(default.as_deref())
+            {
+                action_warnings(binding, action, warnings);
+            }
+        }
+        Action::KeyCombo { keys, .. } if is_modifier_only_combo(keys) => {
+            warnings.push(ConfigWarning::ModifierOnlyCombo {
+                binding: binding.clone(),
+                keys: keys.clone(),
+            });
+        }
+        Action::KeyChords { keys, .. } => {
+            for chord in keys.iter().filter(|chord| is_modifier_only_combo(chord)) {
+                warnings.push(ConfigWarning::ModifierOnlyCombo {
+                    binding: binding.clone(),
+                    keys: chord.clone(),
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Ctrl, Shift and Alt; the Meta (Super) keys are left out because a lone tap of those is a
+/// common shortcut for the desktop's app menu.
+const TAP_USELESS_MODIFIERS: [&str; 6] = [
+    "KEY_LEFTCTRL",
+    "KEY_RIGHTCTRL",
+    "KEY_LEFTSHIFT",
+    "KEY_RIGHTSHIFT",
+    "KEY_LEFTALT",
+    "KEY_RIGHTALT",
+];
+
+/// Whether a chord is nothing but modifiers whose tap has no effect on its own, which usually
+/// means the author expected it to hold the modifier down.
+pub fn is_modifier_only_combo(keys: &[String]) -> bool {
+    !keys.is_empty()
+        && keys
+            .iter()
+            .all(|key| TAP_USELESS_MODIFIERS.contains(&key.as_str()))
+}
+
+/// Everyday names for keys whose `KEY_*` name can't be guessed by adding the prefix, keyed by
+/// the name uppercased with spaces, `-` and `_` taken out.
+const KEY_ALIASES: [(&str, &str); 23] = [
+    ("CTRL", "KEY_LEFTCTRL"),
+    ("CONTROL", "KEY_LEFTCTRL"),
+    ("SHIFT", "KEY_LEFTSHIFT"),
+    ("ALT", "KEY_LEFTALT"),
+    ("ALTGR", "KEY_RIGHTALT"),
+    ("SUPER", "KEY_LEFTMETA"),
+    ("META", "KEY_LEFTMETA"),
+    ("WIN", "KEY_LEFTMETA"),
+    ("WINDOWS", "KEY_LEFTMETA"),
+    ("ESCAPE", "KEY_ESC"),
+    ("RETURN", "KEY_ENTER"),
+    ("DEL", "KEY_DELETE"),
+    ("INS", "KEY_INSERT"),
+    ("PAGEUP", "KEY_PAGEUP"),
+    ("PGUP", "KEY_PAGEUP"),
+    ("PAGEDOWN", "KEY_PAGEDOWN"),
+    ("PGDN", "KEY_PAGEDOWN"),
+    ("PRINTSCREEN", "KEY_SYSRQ"),
+    ("VOLUP", "KEY_VOLUMEUP"),
+    ("VOLDOWN", "KEY_VOLUMEDOWN"),
+    ("NEXTTRACK", "KEY_NEXTSONG"),
+    ("PREVTRACK", "KEY_PREVIOUSSONG"),
+    ("PREVIOUSTRACK", "KEY_PREVIOUSSONG"),
+];
+
+/// The canonical `KEY_*` (or `BTN_*`) name for a key as people tend to write it: `KEY_A`,
+/// `key_a`, `a` and `A` are all `KEY_A`, `VolumeUp` is `KEY_VOLUMEUP`, and a few everyday names
+/// such as `Ctrl` or `Escape` have aliases. Whether the result names a real key is up to the
+/// backend.
+pub fn parse_key_name(name: &str) -> String {
+    let upper = name.trim().to_ascii_uppercase().replace([' ', '-'], "_");
+    if upper.starts_with("KEY_") || upper.starts_with("BTN_") {
+        return upper;
+    }
+    let squashed = upper.replace('_', "");
+    match KEY_ALIASES.iter().find(|(alias, _)| *alias == squashed) {
+        Some((_, canonical)) => (*canonical).to_owned(),
+        None => format!("KEY_{upper}"),
+    }
+}
+
+/// Reports each of `keys` the kernel has no code for, e.g. a typo like `KEY_ENTR`.
+fn unknown_keys(binding: &BindingRef, keys: &[String], errors: &mut Vec<ConfigError>) {
+    for key in keys {
+        if evdev::KeyCode::from_str(key).is_err() {
+            errors.push(ConfigError::UnknownKey {
+                binding: binding.clone(),
+                key: key.clone(),
+            });
+        }
+    }
+}
+
+fn key_names<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let keys = Vec::<String>::deserialize(deserializer)?;
+    Ok(keys.iter().map(|key| parse_key_name(key)).collect())
+}
+
+fn key_chord_names<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Vec<String>>, D::Error> {
+    let chords = Vec::<Vec<String>>::deserialize(deserializer)?;
+    Ok(chords
+        .iter()
+        .map(|chord| chord.iter().map(|key| parse_key_name(key)).collect())
+        .collect())
+}
+
+fn optional_key_names<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<String>>, D::Error> {
+    let keys = Option::<Vec<String>>::deserialize(deserializer)?;
+    Ok(keys.map(|keys| keys.iter().map(|key| parse_key_name(key)).collect()))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            device_by_path: None,
+            bindings: vec![
+                Binding::new(
+                    MouseButton::BtnSide,
+                    Action::KeyCombo {
+                        keys: vec!["KEY_BACK".into()],
+                        target: None,
+                    },
+                ),
+                Binding::new(
+                    MouseButton::BtnExtra,
+                    Action::KeyCombo {
+                        keys: vec!["KEY_FORWARD".into()],
+                        target: None,
+                    },
+                ),
+                Binding::new(
+                    MouseButton::BtnForward,
+                    Action::KeyCombo {
+                        keys: vec!["KEY_VOLUMEUP".into()],
+                        target: None,
+                    },
+                ),
+                Binding::new(
+                    MouseButton::BtnBack,
+                    Action::KeyCombo {
+                        keys: vec!["KEY_VOLUMEDOWN".into()],
+                        target: None,
+                    },
+                ),
+            ],
+            device_name: None,
+            keymap: None,
+            allowed_keys: None,
+            remember_profile: false,
+            invert_wheel_tilt: false,
+            sort_bindings: false,
+            key_release_order: KeyReleaseOrder::default(),
+            tilt_hold_ms: None,
+            middle_click_scroll_guard_ms: None,
+            startup_delay_ms: None,
+            profiles: Vec::new(),
+            queue: QueueConfig::default(),
+            x11: X11Config::default(),
+            evdev: EvdevConfig::default(),
+            macros: BTreeMap::new(),
+            startup_action: None,
+        }
+    }
+}
+
+/// The starter configs `write-default-config --template` knows, by name; see
+/// [`Config::template`].
+pub const TEMPLATES: [&str; 4] = ["default", "browser-nav", "media", "minimal"];
+
+impl Config {
+    /// The starter config called `name` (one of [`TEMPLATES`]).
+    pub fn template(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::default()),
+            "browser-nav" => Some(Self::browser_nav()),
+            "media" => Some(Self::media()),
+            "minimal" => Some(Self::minimal()),
+            _ => None,
+        }
+    }
+
+    /// Side buttons go back and forward; wheel tilt switches tabs, and the task button reopens
+    /// the last closed one.
+    pub fn browser_nav() -> Self {
+        Self::with_key_bindings(&[
+            (MouseButton::BtnSide, &["KEY_BACK"]),
+            (MouseButton::BtnExtra, &["KEY_FORWARD"]),
+            (MouseButton::WheelTiltLeft, &["KEY_LEFTCTRL", "KEY_PAGEUP"]),
+            (
+                MouseButton::WheelTiltRight,
+                &["KEY_LEFTCTRL", "KEY_PAGEDOWN"],
+            ),
+            (
+                MouseButton::BtnTask,
+                &["KEY_LEFTCTRL", "KEY_LEFTSHIFT", "KEY_T"],
+            ),
+        ])
+    }
+
+    /// Side buttons skip tracks, wheel tilt changes the volume, and the task button plays or
+    /// pauses.
+    pub fn media() -> Self {
+        Self::with_key_bindings(&[
+            (MouseButton::BtnSide, &["KEY_PREVIOUSSONG"]),
+            (MouseButton::BtnExtra, &["KEY_NEXTSONG"]),
+            (MouseButton::WheelTiltLeft, &["KEY_VOLUMEDOWN"]),
+            (MouseButton::WheelTiltRight, &["KEY_VOLUMEUP"]),
+            (MouseButton::BtnTask, &["KEY_PLAYPAUSE"]),
+        ])
+    }
+
+    /// Just back and forward on the two side buttons most mice have.
+    pub fn minimal() -> Self {
+        Self::with_key_bindings(&[
+            (MouseButton::BtnSide, &["KEY_BACK"]),
+            (MouseButton::BtnExtra, &["KEY_FORWARD"]),
+        ])
+    }
+
+    /// The default settings with only these `key_combo` bindings.
+    fn with_key_bindings(bindings: &[(MouseButton, &[&str])]) -> Self {
+        Self {
+            bindings: bindings
+                .iter()
+                .map(|(button, keys)| {
+                    Binding::new(
+                        *button,
+                        Action::KeyCombo {
+                            keys: keys.iter().map(|key| (*key).to_owned()).collect(),
+                            target: None,
+                        },
+                    )
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub button: MouseButton,
+    /// A button that has to be held for this binding to fire; only for `WHEEL_UP` and
+    /// `WHEEL_DOWN`, making a "hold and scroll" chord.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modifier_button: Option<MouseButton>,
+    /// A plain press when left out.
+    #[serde(default, skip_serializing_if = "Trigger::is_press")]
+    pub trigger: Trigger,
+    pub action: Action,
+    /// What a quick second click does instead, as if it were a `double_press` binding with the
+    /// default window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_click: Option<Action>,
+    /// Re-run the action while the button is held.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<RepeatSpec>,
+    /// With `--grab`, still deliver the original button to applications before running the
+    /// action. Without `--grab` the original always gets through.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub passthrough: bool,
+    /// Local `HH:MM` times between which the binding works: from the first, up to but not
+    /// including the second, wrapping past midnight when the second is earlier. `None` means
+    /// always.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_hours: Option<(String, String)>,
+    /// Glob patterns (`*` and `?`, case-insensitive) for the focused window's `WM_CLASS`
+    /// (instance or class name). When any matches, this binding wins over ones without
+    /// `window_class`; when none does, it's skipped. A single string is accepted too. Empty
+    /// means every window.
+    #[serde(
+        default,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub window_class: Vec<String>,
+    /// Only for wheel tilt: answer just to a flick or just to a hold (see
+    /// [`Config::tilt_hold_ms`]) instead of to every tilt event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tilt: Option<TiltGesture>,
+}
+
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+impl Binding {
+    pub fn new(button: MouseButton, action: Action) -> Self {
+        Self {
+            button,
+            modifier_button: None,
+            trigger: Trigger::Press,
+            action,
+            double_click: None,
+            repeat: None,
+            passthrough: false,
+            active_hours: None,
+            window_class: Vec::new(),
+            tilt: None,
+        }
+    }
+
+    /// Whether one of the `window_class` patterns matches `wm_class` (the focused window's
+    /// instance and class names).
+    pub fn matches_window(&self, wm_class: &[&str]) -> bool {
+        self.window_class
+            .iter()
+            .any(|pattern| wm_class.iter().any(|name| glob_matches(pattern, name)))
+    }
+
+    /// Whether the binding works at `minute` minutes past local midnight. Times that don't
+    /// parse (which [`Config::validate`] reports) leave it always active.
+    pub fn is_active_at(&self, minute: u16) -> bool {
+        let Some((start, end)) = &self.active_hours else {
+            return true;
+        };
+        let (Some(start), Some(end)) = (minute_of_day(start), minute_of_day(end)) else {
+            return true;
+        };
+        if start <= end {
+            (start..end).contains(&minute)
+        } else {
+            minute >= start || minute < end
+        }
+    }
+}
+
+/// Matches `text` against a glob `pattern`, ignoring case: `*` stands for any run of
+/// characters and `?` for exactly one.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let (mut p, mut t) = (0, 0);
+    // The last `*` seen, and where in `text` its match currently ends.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the `*` take one more character and try again.
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The levels [`Action::SetLogLevel`] accepts besides `default`, least verbose first.
+pub const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Whether [`Action::SetLogLevel`] accepts `level` (case-insensitively).
+pub fn is_log_level(level: &str) -> bool {
+    level.eq_ignore_ascii_case("default")
+        || LOG_LEVELS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(level))
+}
+
+/// Parses `HH:MM` (24-hour; a one-digit hour is fine) into minutes past midnight.
+pub fn minute_of_day(time: &str) -> Option<u16> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(hours) || hours.len() > 2 || !all_digits(minutes) || minutes.len() != 2 {
+        return None;
+    }
+    let (hours, minutes): (u16, u16) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Auto-repeat timing: the action fires on press, again after `delay_ms`, then every
+/// `interval_ms` until release.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatSpec {
+    pub delay_ms: u64,
+    pub interval_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MouseButton {
+    BtnLeft,
+    BtnRight,
+    BtnMiddle,
+    BtnSide,
+    BtnExtra,
+    BtnForward,
+    BtnBack,
+    BtnTask,
+    WheelTiltLeft,
+    WheelTiltRight,
+    /// A notch of the scroll wheel; only bindable together with a held `modifier_button`.
+    WheelUp,
+    WheelDown,
+}
+
+impl MouseButton {
+    pub const ALL: [Self; 12] = [
+        Self::BtnLeft,
+        Self::BtnRight,
+        Self::BtnMiddle,
+        Self::BtnSide,
+        Self::BtnExtra,
+        Self::BtnForward,
+        Self::BtnBack,
+        Self::BtnTask,
+        Self::WheelTiltLeft,
+        Self::WheelTiltRight,
+        Self::WheelUp,
+        Self::WheelDown,
+    ];
+
+    pub fn toml_name(self) -> &'static str {
+        match self {
+            Self::BtnLeft => "BTN_LEFT",
+            Self::BtnRight => "BTN_RIGHT",
+            Self::BtnMiddle => "BTN_MIDDLE",
+            Self::BtnSide => "BTN_SIDE",
+            Self::BtnExtra => "BTN_EXTRA",
+            Self::BtnForward => "BTN_FORWARD",
+            Self::BtnBack => "BTN_BACK",
+            Self::BtnTask => "BTN_TASK",
+            Self::WheelTiltLeft => "WHEEL_TILT_LEFT",
+            Self::WheelTiltRight => "WHEEL_TILT_RIGHT",
+            Self::WheelUp => "WHEEL_UP",
+            Self::WheelDown => "WHEEL_DOWN",
+        }
+    }
+
+    /// Wheel tilt and scroll, which come in notches rather than press and release.
+    pub fn is_wheel(self) -> bool {
+        matches!(
+            self,
+            Self::WheelTiltLeft | Self::WheelTiltRight | Self::WheelUp | Self::WheelDown
+        )
+    }
+
+    pub fn linux_key_code(self) -> Option<u16> {
+        match self {
+            Self::BtnLeft => Some(0x110),
+            Self::BtnRight => Some(0x111),
+            Self::BtnMiddle => Some(0x112),
+            Self::BtnSide => Some(0x113),
+            Self::BtnExtra => Some(0x114),
+            Self::BtnForward => Some(0x115),
+            Self::BtnBack => Some(0x116),
+            Self::BtnTask => Some(0x117),
+            Self::WheelTiltLeft | Self::WheelTiltRight | Self::WheelUp | Self::WheelDown => None,
+        }
+    }
+
+    /// The other wheel tilt direction for a tilt, the button itself for anything else.
+    pub fn tilt_mirrored(self) -> Self {
+        match self {
+            Self::WheelTiltLeft => Self::WheelTiltRight,
+            Self::WheelTiltRight => Self::WheelTiltLeft,
+            other => other,
+        }
+    }
+
+    pub fn x11_button_number(self) -> Option<u32> {
+        match self {
+            Self::BtnLeft => Some(1),
+            Self::BtnMiddle => Some(2),
+            Self::BtnRight => Some(3),
+            Self::BtnSide | Self::BtnBack => Some(8),
+            Self::BtnExtra | Self::BtnForward => Some(9),
+            Self::BtnTask => None,
+            Self::WheelTiltLeft => Some(6),
+            Self::WheelTiltRight => Some(7),
+            Self::WheelUp => Some(4),
+            Self::WheelDown => Some(5),
+        }
+    }
+}
+
+impl std::str::FromStr for MouseButton {
+    type Err = ConfigError;
+
+    /// Parses the name used in config files, e.g. `BTN_SIDE`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|button| button.toml_name() == name)
+            .ok_or_else(|| ConfigError::UnknownButton(name.to_owned()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(remote = "Self", tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    Command {
+        argv: Vec<String>,
+    },
+    /// Run `argv` and show what it prints as a desktop notification, e.g. the battery level.
+    /// A command that fails shows its stderr or exit status instead.
+    CommandNotify {
+        argv: Vec<String>,
+    },
+    KeyCombo {
+        #[serde(deserialize_with = "key_names")]
+        keys: Vec<String>,
+        /// Send the keys to this window instead of the focused one (X11 only).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<KeyTarget>,
+    },
+    /// Several chords pressed one after another, e.g. Ctrl+C then Ctrl+V.
+    ///
+    /// Written as a `key_combo` whose `keys` is an array of arrays.
+    #[serde(rename(serialize = "key_combo", deserialize = "key_chords"))]
+    KeyChords {
+        #[serde(deserialize_with = "key_chord_names")]
+        keys: Vec<Vec<String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<KeyTarget>,
+    },
+    /// Press `keys` when the button goes down and release them when it comes up, e.g. for
+    /// push-to-talk. Only works as a binding's own action.
+    Hold {
+        #[serde(deserialize_with = "key_names")]
+        keys: Vec<String>,
+    },
+    /// Fire every sub-action at once, without waiting on each other or preserving order.
+    Parallel {
+        actions: Vec<Action>,
+    },
+    /// Run `steps` one after another, pausing at each `delay`, e.g. press a key, wait, then
+    /// launch a command. The delays add up to at most [`MAX_SEQUENCE_DELAY_MS`].
+    Sequence {
+        steps: Vec<Step>,
+    },
+    /// While the button is held, use the named profile's bindings instead of the top-level ones.
+    ///
+    /// This swaps the whole binding set: buttons the profile doesn't bind do nothing until the
+    /// button is released.
+    HoldProfile {
+        name: String,
+    },
+    /// Make the named profile's bindings the active ones until the next switch; without `name`,
+    /// go back to the top-level bindings.
+    SwitchProfile {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    /// Latch the named profile on, or, if it's the one latched, back off to the profile that
+    /// was active before. A top-level one also works from inside the profile it toggles.
+    ToggleProfile {
+        name: String,
+    },
+    /// Switch the keyboard layout (XKB group): to `group` (0-based) if set, else to the next one.
+    ///
+    /// X11 only.
+    SwitchLayout {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<u8>,
+    },
+    /// Take a screenshot with whatever tool is installed (`grim`/`slurp` on Wayland, `maim` or
+    /// `scrot` on X11), then save it and/or copy it to the clipboard.
+    ///
+    /// Without `save_path` (a file, or a directory for timestamped files) the image goes to
+    /// `~/Pictures`, unless it's only copied to the clipboard.
+    Screenshot {
+        #[serde(default)]
+        mode: ScreenshotMode,
+        #[serde(default)]
+        to_clipboard: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        save_path: Option<PathBuf>,
+    },
+    /// Put `text` on the clipboard (or the primary selection) through a helper tool:
+    /// `wl-copy` on Wayland, `xclip` or `xsel` on X11.
+    SetClipboard {
+        text: String,
+        #[serde(default)]
+        selection: Selection,
+    },
+    /// Run the action defined under `name` in the `[macros]` table.
+    Macro {
+        name: String,
+    },
+    /// Focus the window whose `WM_CLASS` matches `match_class` (case-insensitively), or run
+    /// `argv` when there's none. X11 only; elsewhere `argv` always runs.
+    LaunchOrFocus {
+        argv: Vec<String>,
+        match_class: String,
+    },
+    /// Send an HTTP request, e.g. to a Home Assistant webhook. `method` defaults to POST.
+    Webhook {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        method: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        body: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        headers: Option<BTreeMap<String, String>>,
+    },
+    /// Focus the next window in the window manager's client list, wrapping around; with
+    /// `same_class`, only among windows with the focused window's `WM_CLASS`. X11 only.
+    CycleWindows {
+        #[serde(default)]
+        same_class: bool,
+    },
+    /// Emit `events` verbatim on a virtual device, followed by a `SYN_REPORT`. evdev only.
+    RawEvents {
+        events: Vec<RawEvent>,
+    },
+    /// Ask logind to lock, suspend, hibernate or end the session.
+    System {
+        op: SystemOp,
+    },
+    /// Change the mouse cursor shown over the desktop background (the root window) to `name`
+    /// from the cursor theme, e.g. `crosshair`; `left_ptr` puts the usual arrow back. X11 only.
+    SetCursor {
+        name: String,
+    },
+    /// Send `command` to the running window manager over its IPC socket: a `swaymsg`/`i3-msg`
+    /// command on Sway and i3, or a `hyprctl` command (e.g. `dispatch workspace +1`) on
+    /// Hyprland.
+    WmCommand {
+        command: String,
+    },
+    /// Turn a desktop setting on if it's off and off if it's on, through whatever the running
+    /// desktop offers for it.
+    ToggleSetting {
+        setting: DesktopSetting,
+    },
+    /// Change how much the daemon logs, for the whole process, until changed again: one of
+    /// [`LOG_LEVELS`], or `default` for what it started with.
+    SetLogLevel {
+        level: String,
+    },
+    /// Change the backlight by `delta_percent` of its range, through sysfs or, when that isn't
+    /// writable, logind. `device` names one under `/sys/class/backlight`; by default the first.
+    Brightness {
+        delta_percent: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device: Option<String>,
+    },
+    /// Run the action `map` has for the profile in effect when the button is pressed, or
+    /// `default` for any other (including the top-level bindings).
+    PerProfile {
+        map: BTreeMap<String, Action>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        default: Option<Box<Action>>,
+    },
+    /// Open a URL or file with the desktop's default handler (`xdg-open`). Surrounding
+    /// whitespace is ignored.
+    Open {
+        target: String,
+    },
+    /// Read the clipboard (or the primary selection) and run the action of the first rule
+    /// whose regex `pattern` matches it, or `default` when none does. `{clipboard}` in the
+    /// chosen action's arguments, target, text, url or body is replaced by what was read, and
+    /// `{clipboard_url}` by the same, percent-encoded.
+    ClipboardRoute {
+        #[serde(default)]
+        selection: Selection,
+        rules: Vec<ClipboardRule>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        default: Option<Box<Action>>,
+    },
+    /// Run again the last action any binding ran, except `repeat_last` itself and profile
+    /// switches. Only remembered while the daemon runs.
+    RepeatLast,
+    /// Move the focused window to another monitor, keeping its place on the screen and its
+    /// maximized or fullscreen state. X11 only.
+    MoveWindowToMonitor {
+        direction: MonitorDirection,
+    },
+    /// Run a small [rhai](https://rhai.rs) script that decides what to do, e.g. send different
+    /// keys depending on the focused window. Needs a daemon built with the `script` feature.
+    Script {
+        source: String,
+    },
+    /// Turn the scroll wheel by `amount` notches: up or right when positive, down or left when
+    /// negative, the way the kernel counts `REL_WHEEL`/`REL_HWHEEL`.
+    Scroll {
+        axis: ScrollAxis,
+        amount: i32,
+    },
+}
+
+/// The longest the delays of one [`Action::Sequence`] may add up to.
+pub const MAX_SEQUENCE_DELAY_MS: u64 = 10_000;
+
+/// One step of an [`Action::Sequence`]: an action, or `{ type = "delay", ms = 200 }` to wait
+/// before the next one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Action(Action),
+    Delay { ms: u64 },
+}
+
+/// How a [`Step::Delay`] is written.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DelayStep {
+    Delay { ms: u64 },
+}
+
+impl Serialize for Step {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Action(action) => action.serialize(serializer),
+            Self::Delay { ms } => DelayStep::Delay { ms: *ms }.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = toml::Value::deserialize(deserializer)?;
+        if value.get("type").and_then(toml::Value::as_str) == Some("delay") {
+            let DelayStep::Delay { ms } =
+                DelayStep::deserialize(value).map_err(serde::de::Error::custom)?;
+            Ok(Self::Delay { ms })
+        } else {
+            Action::deserialize(value)
+                .map(Self::Action)
+                .map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+impl Step {
+    /// The actions among `steps`, leaving out the delays.
+    pub fn actions(steps: &[Step]) -> impl Iterator<Item = &Action> {
+        steps.iter().filter_map(|step| match step {
+            Self::Action(action) => Some(action),
+            Self::Delay { .. } => None,
+        })
+    }
+
+    /// How long the delays among `steps` add up to, in milliseconds.
+    pub fn total_delay(steps: &[Step]) -> u64 {
+        steps
+            .iter()
+            .map(|step| match step {
+                Self::Action(_) => 0,
+                Self::Delay { ms } => *ms,
+            })
+            .fold(0, u64::saturating_add)
+    }
+}
+
+/// An input event as the kernel sees it (`struct input_event` without the timestamp), e.g.
+/// `{ type = 2, code = 8, value = -1 }` for one notch of the scroll wheel.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawEvent {
+    #[serde(rename = "type")]
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl RawEvent {
+    pub const EV_SYN: u16 = 0x00;
+    pub const EV_KEY: u16 = 0x01;
+    pub const EV_REL: u16 = 0x02;
+
+    /// Whether the virtual device can declare this event: a `SYN_*`, `KEY_*`/`BTN_*` or `REL_*`
+    /// code within the kernel's range for its type.
+    pub fn is_supported(&self) -> bool {
+        let max_code = match self.event_type {
+            Self::EV_SYN => 0x0f,
+            Self::EV_KEY => 0x2ff,
+            Self::EV_REL => 0x0f,
+            _ => return false,
+        };
+        self.code <= max_code
+    }
+}
+
+/// Where a key action sends its keys instead of the focused window: a window picked by
+/// [`WindowTarget`], or one named by a [`NamedTarget`] string.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum KeyTarget {
+    Named(NamedTarget),
+    Window(WindowTarget),
+}
+
+/// A key target written as a plain string.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NamedTarget {
+    /// The window under the pointer, which isn't necessarily the focused one.
+    UnderCursor,
+}
+
+/// One rule of [`Action::ClipboardRoute`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardRule {
+    /// A regex, matched anywhere in the text unless anchored with `^`/`$`.
+    pub pattern: String,
+    pub action: Action,
+}
+
+/// Picks a window by its `WM_CLASS` and/or title; every field that's set has to match.
+///
+/// Used by key actions to send keys to a window other than the focused one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct WindowTarget {
+    /// Either part of `WM_CLASS` (instance or class name), compared case-insensitively.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// A substring of the window title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+impl WindowTarget {
+    /// `wm_class` holds the `WM_CLASS` strings (instance, class).
+    pub fn matches(&self, wm_class: &[&str], title: &str) -> bool {
+        let class_ok = self
+            .class
+            .as_ref()
+            .is_none_or(|class| wm_class.iter().any(|part| part.eq_ignore_ascii_case(class)));
+        let title_ok = self
+            .title
+            .as_ref()
+            .is_none_or(|wanted| title.contains(wanted.as_str()));
+        class_ok && title_ok
+    }
+}
+
+/// What [`Action::Screenshot`] captures.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenshotMode {
+    /// Every monitor.
+    #[default]
+    Full,
+    /// A rectangle picked with the mouse.
+    Region,
+    /// The focused window.
+    Window,
+}
+
+/// Which selection [`Action::SetClipboard`] sets.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    /// The one pasted with Ctrl+V.
+    #[default]
+    Clipboard,
+    /// The one pasted with a middle click.
+    Primary,
+}
+
+/// Where [`Action::MoveWindowToMonitor`] sends the window. Monitors are counted left to right
+/// (top to bottom where they're stacked), from 0.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitorDirection {
+    /// The monitor to the right, wrapping around to the first.
+    Next,
+    /// The monitor to the left, wrapping around to the last.
+    Prev,
+    Index(usize),
+}
+
+/// Which wheel [`Action::Scroll`] turns.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollAxis {
+    Vertical,
+    Horizontal,
+}
+
+/// What [`Action::System`] asks logind for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemOp {
+    /// Lock the screen of the current session.
+    Lock,
+    Suspend,
+    Hibernate,
+    /// End the current session.
+    LogOut,
+}
+
+impl SystemOp {
+    /// The name used in config files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lock => "lock",
+            Self::Suspend => "suspend",
+            Self::Hibernate => "hibernate",
+            Self::LogOut => "log_out",
+        }
+    }
+}
+
+/// What [`Action::ToggleSetting`] toggles.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DesktopSetting {
+    /// Hold back notification banners.
+    DoNotDisturb,
+    /// Warmer screen colors (GNOME's Night Light, KDE's Night Color).
+    NightLight,
+}
+
+impl DesktopSetting {
+    /// The name used in config files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DoNotDisturb => "do_not_disturb",
+            Self::NightLight => "night_light",
+        }
+    }
+}
+
+impl Serialize for Action {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Action::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = toml::Value::deserialize(deserializer)?;
+        // A `key_combo` whose keys are arrays is a sequence of chords.
+        if let Some(table) = value.as_table_mut() {
+            let nested = table.get("type").and_then(toml::Value::as_str) == Some("key_combo")
+                && table
+                    .get("keys")
+                    .and_then(toml::Value::as_array)
+                    .is_some_and(|keys| keys.iter().any(toml::Value::is_array));
+            if nested {
+                table.insert("type".into(), "key_chords".into());
+            }
+        }
+        Action::deserialize(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Action {
+    /// The action as the inline table config files use, e.g.
+    /// `{ type = "key_combo", keys = ["KEY_BACK"] }`.
+    pub fn inline_toml(&self) -> Result<String, ConfigError> {
+        action_inline(self)
+    }
+
+    /// The `type` tag used for this action in TOML.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Command { .. } => "command",
+            Self::CommandNotify { .. } => "command_notify",
+            Self::KeyCombo { .. } | Self::KeyChords { .. } => "key_combo",
+            Self::Hold { .. } => "hold",
+            Self::Parallel { .. } => "parallel",
+            Self::Sequence { .. } => "sequence",
+            Self::HoldProfile { .. } => "hold_profile",
+            Self::SwitchProfile { .. } => "switch_profile",
+            Self::ToggleProfile { .. } => "toggle_profile",
+            Self::SwitchLayout { .. } => "switch_layout",
+            Self::Screenshot { .. } => "screenshot",
+            Self::SetClipboard { .. } => "set_clipboard",
+            Self::Macro { .. } => "macro",
+            Self::Webhook { .. } => "webhook",
+            Self::LaunchOrFocus { .. } => "launch_or_focus",
+            Self::CycleWindows { .. } => "cycle_windows",
+            Self::RawEvents { .. } => "raw_events",
+            Self::System { .. } => "system",
+            Self::Brightness { .. } => "brightness",
+            Self::SetLogLevel { .. } => "set_log_level",
+            Self::WmCommand { .. } => "wm_command",
+            Self::ToggleSetting { .. } => "toggle_setting",
+            Self::SetCursor { .. } => "set_cursor",
+            Self::PerProfile { .. } => "per_profile",
+            Self::Open { .. } => "open",
+            Self::ClipboardRoute { .. } => "clipboard_route",
+            Self::RepeatLast => "repeat_last",
+            Self::MoveWindowToMonitor { .. } => "move_window_to_monitor",
+            Self::Script { .. } => "script",
+            Self::Scroll { .. } => "scroll",
+        }
+    }
+
+    /// The action to run while `profile` is in effect (`None` for the top-level bindings), with
+    /// every `per_profile`, including those inside `parallel` or `sequence`, replaced by its pick.
+    /// `None` when a `per_profile` has nothing for the profile.
+    pub fn for_profile(&self, profile: Option<&str>) -> Option<Action> {
+        match self {
+            Self::PerProfile { map, default } => profile
+                .and_then(|name| map.get(name))
+                .or(default.as_deref())?
+                .for_profile(profile),
+            Self::Parallel { actions } => Some(Self::Parallel {
+                actions: actions
+                    .iter()
+                    .filter_map(|action| action.for_profile(profile))
+                    .collect(),
+            }),
+            // A step left with nothing is dropped; the delays around it stay.
+            Self::Sequence { steps } => Some(Self::Sequence {
+                steps: steps
+                    .iter()
+                    .filter_map(|step| match step {
+                        Step::Action(action) => action.for_profile(profile).map(Step::Action),
+                        Step::Delay { ms } => Some(Step::Delay { ms: *ms }),
+                    })
+                    .collect(),
+            }),
+            // A rule left with nothing still wins its match, so later rules don't take over.
+            Self::ClipboardRoute {
+                selection,
+                rules,
+                default,
+            } => Some(Self::ClipboardRoute {
+                selection: *selection,
+                rules: rules
+                    .iter()
+                    .map(|rule| ClipboardRule {
+                        pattern: rule.pattern.clone(),
+                        action: rule.action.for_profile(profile).unwrap_or(Self::Parallel {
+                            actions: Vec::new(),
+                        }),
+                    })
+                    .collect(),
+                default: default
+                    .as_deref()
+                    .and_then(|action| action.for_profile(profile))
+                    .map(Box::new),
+            }),
+            action => Some(action.clone()),
+        }
+    }
+}
+
+pub fn default_config_path() -> Result<PathBuf, ConfigError> {
+    let dirs = BaseDirs::new().ok_or(ConfigError::NoConfigDir)?;
+    Ok(dirs.config_dir().join(APP_NAME).join(CONFIG_FILE_NAME))
+}
+
+/// State kept across reboots (the remembered profile): `$XDG_STATE_HOME/mouse-assist`, or the
+/// local data directory where there's no state directory.
+pub fn state_dir() -> Result<PathBuf, ConfigError> {
+    let dirs = BaseDirs::new().ok_or(ConfigError::NoConfigDir)?;
+    let base = dirs.state_dir().unwrap_or_else(|| dirs.data_local_dir());
+    Ok(base.join(APP_NAME))
+}
+
+/// Per-session state (sockets, recent launches): `$XDG_RUNTIME_DIR/mouse-assist`, or the cache
+/// directory when there's no runtime directory.
+pub fn runtime_dir() -> Result<PathBuf, ConfigError> {
+    let dirs = BaseDirs::new().ok_or(ConfigError::NoConfigDir)?;
+    let base = dirs.runtime_dir().unwrap_or_else(|| dirs.cache_dir());
+    Ok(base.join(APP_NAME))
+}
+
+/// Reads and parses the config at `path`; a missing file is [`ConfigError::NotFound`].
+pub fn load_config(path: &Path) -> Result<Config, ConfigError> {
+    let raw = fs::read_to_string(path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => ConfigError::NotFound {
+            path: path.to_path_buf(),
+        },
+        _ => ConfigError::Io(err),
+    })?;
+    parse_config(&raw, ConfigFormat::Toml)
+}
+
+/// A format a config can be written in. Config files are TOML; JSON is for exporting to
+/// tools such as `jq` and importing what they produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// The format a file's extension names: `.toml` or `.json`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a config written in `format`.
+pub fn parse_config(raw: &str, format: ConfigFormat) -> Result<Config, ConfigError> {
+    Ok(match format {
+        ConfigFormat::Toml => toml::from_str(raw)?,
+        ConfigFormat::Json => {
+            let mut value = serde_json::from_str(raw)?;
+            drop_nulls(&mut value);
+            serde_json::from_value(value)?
+        }
+    })
+}
+
+/// Removes the `null` fields of JSON objects, so they read as left out. Actions are read through
+/// `toml::Value`, which has no null, so `"target": null` would otherwise be rejected.
+fn drop_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            fields.retain(|_, field| !field.is_null());
+            fields.values_mut().for_each(drop_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(drop_nulls),
+        _ => {}
+    }
+}
+
+/// The contents of [`SYSTEM_POLICY_PATH`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SystemPolicy {
+    /// Key names that any user's actions may inject. `None` allows every key.
+    #[serde(default, deserialize_with = "optional_key_names")]
+    pub allowed_keys: Option<Vec<String>>,
+}
+
+/// Loads the system policy, treating a missing file as "no restrictions".
+pub fn load_system_policy(path: &Path) -> Result<SystemPolicy, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(raw) => Ok(toml::from_str(&raw)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(SystemPolicy::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Which keys actions may inject, after combining a config's `allowed_keys` with the system
+/// policy. A key has to pass both lists.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyPolicy {
+    allowed: Option<BTreeSet<String>>,
+}
+
+impl KeyPolicy {
+    pub fn new(config: &Config, system: &SystemPolicy) -> Self {
+        let allowed = match (&config.allowed_keys, &system.allowed_keys) {
+            (None, None) => None,
+            (Some(keys), None) | (None, Some(keys)) => Some(keys.iter().cloned().collect()),
+            (Some(user), Some(system)) => Some(
+                user.iter()
+                    .filter(|key| system.contains(key))
+                    .cloned()
+                    .collect(),
+            ),
+        };
+        Self { allowed }
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.allowed.is_some()
+    }
+
+    /// The effective allowlist, sorted; `None` when every key is allowed.
+    pub fn allowed_keys(&self) -> Option<Vec<String>> {
+        self.allowed
+            .as_ref()
+            .map(|allowed| allowed.iter().cloned().collect())
+    }
+
+    pub fn allows(&self, key: &str) -> bool {
+        self.allowed
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(key))
+    }
+}
+
+/// An X11 keysym in a keymap file: a number, or a name such as `XF86Back` or `XK_F13`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum KeysymSpec {
+    Number(u32),
+    Name(String),
+}
+
+/// Loads a keymap file: a table from `KEY_*` names to [`KeysymSpec`]s.
+pub fn load_keymap(path: &Path) -> Result<BTreeMap<String, KeysymSpec>, ConfigError> {
+    let raw = fs::read_to_string(path)?;
+    Ok(toml::from_str(&raw)?)
+}
+
+/// How bindings are laid out when a config is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TomlStyle {
+    /// One `[[bindings]]` table per binding.
+    #[default]
+    Tables,
+    /// A single `bindings = [...]` array with one inline table per line.
+    Compact,
+}
+
+pub fn save_config(path: &Path, config: &Config) -> Result<(), ConfigError> {
+    save_config_with_style(path, config, TomlStyle::default())
+}
+
+pub fn save_config_with_style(
+    path: &Path,
+    config: &Config,
+    style: TomlStyle,
+) -> Result<(), ConfigError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let raw = config_to_toml_string(config, style)?;
+    fs::write(path, raw)?;
+    Ok(())
+}
+
+pub fn binding_to_toml_string(binding: &Binding) -> Result<String, ConfigError> {
+    binding_table_to_toml_string("bindings", binding)
+}
+
+fn binding_table_to_toml_string(header: &str, binding: &Binding) -> Result<String, ConfigError> {
+    let mut out = String::new();
+
+    out.push_str("[[");
+    out.push_str(header);
+    out.push_str("]]\n");
+
+    let button = toml::Value::try_from(binding.button)?;
+    out.push_str("button = ");
+    out.push_str(&button.to_string());
+    out.push('\n');
+
+    if let Some(modifier_button) = binding.modifier_button {
+        out.push_str("modifier_button = ");
+        out.push_str(&toml::Value::try_from(modifier_button)?.to_string());
+        out.push('\n');
+    }
+
+    if !binding.trigger.is_press() {
+        out.push_str("trigger = ");
+        out.push_str(&inline_value(&toml::Value::try_from(binding.trigger)?));
+        out.push('\n');
+    }
+
+    out.push_str("action = ");
+    out.push_str(&action_inline(&binding.action)?);
+    out.push('\n');
+
+    if let Some(double_click) = &binding.double_click {
+        out.push_str("double_click = ");
+        out.push_str(&action_inline(double_click)?);
+        out.push('\n');
+    }
+
+    if let Some(repeat) = &binding.repeat {
+        out.push_str("repeat = ");
+        out.push_str(&inline_value(&toml::Value::try_from(repeat)?));
+        out.push('\n');
+    }
+
+    if binding.passthrough {
+        out.push_str("passthrough = true\n");
+    }
+
+    if let Some(active_hours) = &binding.active_hours {
+        out.push_str("active_hours = ");
+        out.push_str(&inline_value(&toml::Value::try_from(active_hours)?));
+        out.push('\n');
+    }
+
+    if !binding.window_class.is_empty() {
+        out.push_str("window_class = ");
+        out.push_str(&inline_value(&toml::Value::try_from(
+            &binding.window_class,
+        )?));
+        out.push('\n');
+    }
+
+    if let Some(tilt) = binding.tilt {
+        out.push_str("tilt = ");
+        out.push_str(&toml::Value::try_from(tilt)?.to_string());
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_owned()).to_string()
+}
+
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_owned()
+    } else {
+        toml_string(key)
+    }
+}
+
+/// Renders `table` inline, with the `leading` keys (when present) first and in that order.
+fn inline_table(table: &toml::Table, leading: &[&str]) -> String {
+    let first = leading.iter().filter_map(|key| table.get_key_value(*key));
+    let rest = table
+        .iter()
+        .filter(|(key, _)| !leading.contains(&key.as_str()));
+    let fields: Vec<String> = first
+        .chain(rest)
+        .map(|(key, value)| format!("{} = {}", toml_key(key), inline_value(value)))
+        .collect();
+    if fields.is_empty() {
+        "{}".to_owned()
+    } else {
+        format!("{{ {} }}", fields.join(", "))
+    }
+}
+
+/// Renders a value on a single line, expanding nested tables as inline tables.
+///
+/// Inside a table the `type` key (if any) is emitted first so actions read as
+/// `{ type = "...", ... }` at every nesting level.
+fn inline_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(table) => inline_table(table, &["type"]),
+        toml::Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(inline_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Renders a settings struct as a `[name]` table with one `key = value` line per field.
+fn section_to_toml_string<T: Serialize>(name: &str, section: &T) -> Result<String, ConfigError> {
+    let mut out = format!("[{name}]\n");
+    if let toml::Value::Table(fields) = toml::Value::try_from(section)? {
+        for (key, value) in &fields {
+            out.push_str(&format!("{} = {}\n", toml_key(key), inline_value(value)));
+        }
+    }
+    Ok(out)
+}
+
+fn action_inline(action: &Action) -> Result<String, ConfigError> {
+    Ok(inline_value(&toml::Value::try_from(action)?))
+}
+
+/// `key = [...]` with one inline binding table per line.
+fn bindings_array_to_toml_string(key: &str, bindings: &[&Binding]) -> Result<String, ConfigError> {
+    if bindings.is_empty() {
+        return Ok(format!("{key} = []\n"));
+    }
+    let mut out = format!("{key} = [\n");
+    for binding in bindings {
+        let value = toml::Value::try_from(binding)?;
+        let table = value.as_table().expect("bindings serialize as tables");
+        out.push_str("  ");
+        out.push_str(&inline_table(
+            table,
+            &[
+                "button",
+                "modifier_button",
+                "trigger",
+                "action",
+                "double_click",
+            ],
+        ));
+        out.push_str(",\n");
+    }
+    out.push_str("]\n");
+    Ok(out)
+}
+
+/// The order `bindings` are written in: as they are, or with [`Config::sort_bindings`], stably
+/// sorted by button.
+fn saved_order<'a>(config: &Config, bindings: &'a [Binding]) -> Vec<&'a Binding> {
+    let mut ordered: Vec<&Binding> = bindings.iter().collect();
+    if config.sort_bindings {
+        ordered.sort_by_key(|b| {
+            MouseButton::ALL
+                .iter()
+                .position(|&button| button == b.button)
+        });
+    }
+    ordered
+}
+
+fn config_to_toml_string(config: &Config, style: TomlStyle) -> Result<String, ConfigError> {
+    let mut out = String::new();
+
+    if let Some(device_by_path) = &config.device_by_path {
+        out.push_str("device_by_path = ");
+        out.push_str(&toml_string(device_by_path));
+        out.push('\n');
+        out.push('\n');
+    }
+
+    if let Some(device_name) = &config.device_name {
+        out.push_str("device_name = ");
+        out.push_str(&toml_string(device_name));
+        out.push('\n');
+        out.push('\n');
+    }
+
+    if let Some(keymap) = &config.keymap {
+        out.push_str("keymap = ");
+        out.push_str(&toml_string(&keymap.to_string_lossy()));
+        out.push('\n');
+        out.push('\n');
+    }
+
+    if let Some(allowed_keys) = &config.allowed_keys {
+        out.push_str("allowed_keys = ");
+        out.push_str(&inline_value(&toml::Value::try_from(allowed_keys)?));
+        out.push('\n');
+        out.push('\n');
+    }
+
+    if config.remember_profile {
+        out.push_str("remember_profile = true\n\n");
+    }
+
+    if config.invert_wheel_tilt {
+        out.push_str("invert_wheel_tilt = true\n\n");
+    }
+
+    if config.sort_bindings {
+        out.push_str("sort_bindings = true\n\n");
+    }
+
+    if let Some(tilt_hold_ms) = config.tilt_hold_ms {
+        out.push_str(&format!("tilt_hold_ms = {tilt_hold_ms}\n\n"));
+    }
+
+    if let Some(guard_ms) = config.middle_click_scroll_guard_ms {
+        out.push_str(&format!("middle_click_scroll_guard_ms = {guard_ms}\n\n"));
+    }
+
+    if let Some(delay_ms) = config.startup_delay_ms {
+        out.push_str(&format!("startup_delay_ms = {delay_ms}\n\n"));
+    }
+
+    if !config.key_release_order.is_default() {
+        out.push_str("key_release_order = ");
+        out.push_str(&toml::Value::try_from(config.key_release_order)?.to_string());
+        out.push('\n');
+        out.push('\n');
+    }
+
+    if let Some(action) = &config.startup_action {
+        out.push_str("startup_action = ");
+        out.push_str(&action_inline(action)?);
+        out.push('\n');
+        out.push('\n');
+    }
+
+    // An inline array is a plain key, so it has to come before any `[table]` header.
+    if style == TomlStyle::Compact {
+        out.push_str(&bindings_array_to_toml_string(
+            "bindings",
+            &saved_order(config, &config.bindings),
+        )?);
+        out.push('\n');
+    }
+
+    if !config.queue.is_default() {
+        out.push_str(&section_to_toml_string("queue", &config.queue)?);
+        out.push('\n');
+    }
+    if !config.x11.is_default() {
+        out.push_str(&section_to_toml_string("x11", &config.x11)?);
+        out.push('\n');
+    }
+    if !config.evdev.is_default() {
+        out.push_str(&section_to_toml_string("evdev", &config.evdev)?);
+        out.push('\n');
+    }
+    if !config.macros.is_empty() {
+        out.push_str(&section_to_toml_string("macros", &config.macros)?);
+        out.push('\n');
+    }
+
+    if style == TomlStyle::Tables {
+        for (idx, binding) in saved_order(config, &config.bindings)
+            .into_iter()
+            .enumerate()
+        {
+            if idx != 0 {
+                out.push('\n');
+            }
+            out.push_str(&binding_to_toml_string(binding)?);
+        }
+    }
+
+    for profile in &config.profiles {
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        out.push_str("[[profiles]]\n");
+        out.push_str("name = ");
+        out.push_str(&toml_string(&profile.name));
+        out.push('\n');
+        if let Some(inherits) = &profile.inherits {
+            out.push_str("inherits = ");
+            out.push_str(&toml_string(inherits));
+            out.push('\n');
+        }
+        if let Some(device) = &profile.device {
+            out.push_str("device = ");
+            out.push_str(&inline_value(&toml::Value::try_from(device)?));
+            out.push('\n');
+        }
+        match style {
+            TomlStyle::Tables => {
+                for binding in saved_order(config, &profile.bindings) {
+                    out.push('\n');
+                    out.push_str(&binding_table_to_toml_string("profiles.bindings", binding)?);
+                }
+            }
+            TomlStyle::Compact => {
+                out.push_str(&bindings_array_to_toml_string(
+                    "bindings",
+                    &saved_order(config, &profile.bindings),
+                )?);
+            }
+        }
+    }
+
+    while out.ends_with("\n\n") {
+        out.pop();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trip_toml() {
+        let cfg = Config::default();
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+    }
+
+    #[test]
+    fn binding_snippet_round_trip_toml() {
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            bindings: Vec<Binding>,
+        }
+
+        let binding = Binding::new(
+            MouseButton::BtnForward,
+            Action::KeyCombo {
+                keys: vec!["KEY_VOLUMEUP".into()],
+                target: None,
+            },
+        );
+        let raw = binding_to_toml_string(&binding).unwrap();
+        let wrapper: Wrapper = toml::from_str(&raw).unwrap();
+        assert_eq!(wrapper.bindings, vec![binding]);
+    }
+
+    #[test]
+    fn config_parses_inline_action_table() {
+        let raw = r#"
+[[bindings]]
+button = "BTN_SIDE"
+action = { type = "key_combo", keys = ["KEY_BACK"] }
+"#;
+        let decoded: Config = toml::from_str(raw).unwrap();
+        assert_eq!(decoded.bindings.len(), 1);
+        assert_eq!(decoded.bindings[0].button, MouseButton::BtnSide);
+        assert_eq!(
+            decoded.bindings[0].action,
+            Action::KeyCombo {
+                keys: vec!["KEY_BACK".into()],
+                target: None,
+            }
+        );
+    }
+
+    #[test]
+    fn config_parses_expanded_action_subtable() {
+        let raw = r#"
+[[bindings]]
+button = "BTN_SIDE"
+
+[bindings.action]
+type = "key_combo"
+keys = ["KEY_BACK"]
+"#;
+        let decoded: Config = toml::from_str(raw).unwrap();
+        assert_eq!(decoded.bindings.len(), 1);
+        assert_eq!(decoded.bindings[0].button, MouseButton::BtnSide);
+        assert_eq!(
+            decoded.bindings[0].action,
+            Action::KeyCombo {
+                keys: vec!["KEY_BACK".into()],
+                target: None,
+            }
+        );
+    }
+
+    #[test]
+    fn config_serializes_actions_inline() {
+        let cfg = Config {
+            bindings: vec![Binding::new(
+                MouseButton::WheelTiltRight,
+                Action::KeyCombo {
+                    keys: vec!["KEY_FORWARD".into()],
+                    target: None,
+                },
+            )],
+            ..Config::default()
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
         assert!(raw.contains("action = {"));
         assert!(!raw.contains("[bindings.action]"));
         let decoded: Config = toml::from_str(&raw).unwrap();
         assert_eq!(decoded, cfg);
     }
+
+    #[test]
+    fn validate_reports_empty_actions_per_binding() {
+        let cfg = Config {
+            bindings: vec![
+                Binding::new(
+                    MouseButton::BtnSide,
+                    Action::KeyCombo {
+                        keys: vec!["KEY_BACK".into()],
+                        target: None,
+                    },
+                ),
+                Binding::new(
+                    MouseButton::BtnExtra,
+                    Action::Parallel {
+                        actions: vec![Action::Command { argv: vec![] }],
+                    },
+                ),
+                Binding::new(
+                    MouseButton::BtnTask,
+                    Action::LaunchOrFocus {
+                        argv: vec![],
+                        match_class: "kitty".into(),
+                    },
+                ),
+                Binding::new(MouseButton::BtnBack, Action::CommandNotify { argv: vec![] }),
+            ],
+            ..Config::default()
+        };
+        let errors = cfg.validate().unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].to_string(), "binding #1: command argv is empty");
+        assert_eq!(errors[1].to_string(), "binding #2: command argv is empty");
+        assert_eq!(errors[2].to_string(), "binding #3: command argv is empty");
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn warnings_flag_duplicate_buttons() {
+        let mut cfg = Config::default();
+        cfg.bindings.push(Binding::new(
+            MouseButton::BtnSide,
+            Action::KeyCombo {
+                keys: vec!["KEY_MUTE".into()],
+                target: None,
+            },
+        ));
+        assert_eq!(
+            cfg.warnings(),
+            vec![ConfigWarning::DuplicateButton {
+                binding: BindingRef::Binding {
+                    profile: None,
+                    index: 4,
+                },
+                first: 0,
+                button: "BTN_SIDE",
+            }]
+        );
+        assert!(Config::default().warnings().is_empty());
+    }
+
+    #[test]
+    fn warnings_flag_modifier_only_combos() {
+        let mut cfg = Config::default();
+        let keys = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect::<Vec<_>>();
+        cfg.bindings[0].action = Action::KeyCombo {
+            keys: keys(&["KEY_LEFTCTRL", "KEY_LEFTSHIFT"]),
+            target: None,
+        };
+        cfg.bindings[1].action = Action::KeyChords {
+            keys: vec![keys(&["KEY_LEFTCTRL", "KEY_C"]), keys(&["KEY_RIGHTALT"])],
+            target: None,
+        };
+        // A lone Super tap opens the app menu on most desktops.
+        cfg.bindings[2].action = Action::KeyCombo {
+            keys: keys(&["KEY_LEFTMETA"]),
+            target: None,
+        };
+        let warned: Vec<Vec<String>> = cfg
+            .warnings()
+            .into_iter()
+            .map(|warning| match warning {
+                ConfigWarning::ModifierOnlyCombo { keys, .. } => keys,
+                other => panic!("unexpected warning: {other}"),
+            })
+            .collect();
+        assert_eq!(
+            warned,
+            vec![
+                keys(&["KEY_LEFTCTRL", "KEY_LEFTSHIFT"]),
+                keys(&["KEY_RIGHTALT"])
+            ]
+        );
+    }
+
+    #[test]
+    fn parallel_action_round_trips_inline() {
+        let cfg = Config {
+            bindings: vec![Binding::new(
+                MouseButton::BtnTask,
+                Action::Parallel {
+                    actions: vec![
+                        Action::Command {
+                            argv: vec!["firefox".into()],
+                        },
+                        Action::Command {
+                            argv: vec!["notify-send".into(), "launched".into()],
+                        },
+                    ],
+                },
+            )],
+            ..Config::default()
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains(
+            r#"action = { type = "parallel", actions = [{ type = "command", argv = ["firefox"] }"#
+        ));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+    }
+
+    fn media_profile_config() -> Config {
+        let mut cfg = Config::default();
+        cfg.bindings.push(Binding::new(
+            MouseButton::BtnTask,
+            Action::HoldProfile {
+                name: "media".into(),
+            },
+        ));
+        cfg.profiles.push(Profile {
+            name: "media".into(),
+            inherits: None,
+            device: None,
+            bindings: vec![Binding::new(
+                MouseButton::BtnSide,
+                Action::KeyCombo {
+                    keys: vec!["KEY_PLAYPAUSE".into()],
+                    target: None,
+                },
+            )],
+        });
+        cfg
+    }
+
+    #[test]
+    fn sort_bindings_writes_the_same_bytes_for_any_button_order() {
+        let mut cfg = media_profile_config();
+        cfg.sort_bindings = true;
+        // Two bindings for one button: their order decides which one the daemon uses.
+        cfg.bindings.push(Binding::new(
+            MouseButton::BtnSide,
+            Action::Command {
+                argv: vec!["true".into()],
+            },
+        ));
+        let position = |b: &Binding| MouseButton::ALL.iter().position(|&x| x == b.button);
+        let mut shuffled = cfg.clone();
+        shuffled
+            .bindings
+            .sort_by_key(|b| std::cmp::Reverse(position(b)));
+        assert_ne!(shuffled.bindings, cfg.bindings);
+
+        for style in [TomlStyle::Tables, TomlStyle::Compact] {
+            let raw = config_to_toml_string(&cfg, style).unwrap();
+            assert_eq!(raw, config_to_toml_string(&shuffled, style).unwrap());
+            let decoded: Config = toml::from_str(&raw).unwrap();
+            let mut expected = cfg.bindings.clone();
+            expected.sort_by_key(position);
+            assert_eq!(decoded.bindings, expected);
+            assert!(decoded.sort_bindings);
+        }
+
+        // Without the option, bindings are written as they are.
+        shuffled.sort_bindings = false;
+        let decoded: Config =
+            toml::from_str(&config_to_toml_string(&shuffled, TomlStyle::Tables).unwrap()).unwrap();
+        assert_eq!(decoded.bindings, shuffled.bindings);
+    }
+
+    #[test]
+    fn double_click_round_trips_next_to_the_action() {
+        let mut cfg = Config::default();
+        cfg.bindings[0].double_click = Some(Action::Parallel {
+            actions: vec![
+                Action::KeyCombo {
+                    keys: vec!["KEY_LEFTCTRL".into(), "KEY_H".into()],
+                    target: None,
+                },
+                Action::Command {
+                    argv: vec!["notify-send".into(), "history".into()],
+                },
+            ],
+        });
+
+        for style in [TomlStyle::Tables, TomlStyle::Compact] {
+            let raw = config_to_toml_string(&cfg, style).unwrap();
+            let decoded: Config = toml::from_str(&raw).unwrap();
+            assert_eq!(decoded, cfg);
+        }
+        let table = binding_to_toml_string(&cfg.bindings[0]).unwrap();
+        assert!(table.contains(
+            "action = { type = \"key_combo\", keys = [\"KEY_BACK\"] }\ndouble_click = { type = \"parallel\""
+        ));
+        assert!(cfg.warnings().is_empty());
+    }
+
+    #[test]
+    fn triggers_default_to_press_and_need_a_button_that_releases() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [[bindings]]
+            button = "BTN_SIDE"
+            action = { type = "key_combo", keys = ["KEY_BACK"] }
+
+            [[bindings]]
+            button = "BTN_SIDE"
+            trigger = { type = "long_press" }
+            action = { type = "key_combo", keys = ["KEY_LEFTCTRL", "KEY_H"] }
+
+            [[bindings]]
+            button = "BTN_EXTRA"
+            trigger = { type = "double_press", within_ms = 250 }
+            action = { type = "key_combo", keys = ["KEY_REFRESH"] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.bindings[0].trigger, Trigger::Press);
+        assert_eq!(
+            cfg.bindings[1].trigger,
+            Trigger::LongPress {
+                after_ms: DEFAULT_LONG_PRESS_MS
+            }
+        );
+        assert!(cfg.validate().is_ok());
+        assert!(cfg.warnings().is_empty(), "{:?}", cfg.warnings());
+        for style in [TomlStyle::Tables, TomlStyle::Compact] {
+            let decoded: Config =
+                toml::from_str(&config_to_toml_string(&cfg, style).unwrap()).unwrap();
+            assert_eq!(decoded, cfg);
+        }
+        assert!(binding_to_toml_string(&cfg.bindings[2])
+            .unwrap()
+            .contains("trigger = { type = \"double_press\", within_ms = 250 }\n"));
+
+        let mut bad = cfg.clone();
+        bad.bindings[0].button = MouseButton::WheelTiltLeft;
+        bad.bindings[0].trigger = Trigger::LongPress { after_ms: 500 };
+        bad.bindings[1].double_click = Some(Action::SwitchProfile { name: None });
+        bad.bindings[2].trigger = Trigger::DoublePress { within_ms: 0 };
+        let errors = bad.validate().unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [
+                ConfigError::TriggerOnWheel { .. },
+                ConfigError::DoubleClickWithTrigger { .. },
+                ConfigError::InvalidTriggerTime { ms: 0, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn tilt_gestures_round_trip_and_only_fit_tilt_buttons() {
+        let mut cfg = Config {
+            tilt_hold_ms: Some(350),
+            ..Config::default()
+        };
+        let key = |name: &str| Action::KeyCombo {
+            keys: vec![name.into()],
+            target: None,
+        };
+        let mut flick = Binding::new(MouseButton::WheelTiltRight, key("KEY_NEXTSONG"));
+        flick.tilt = Some(TiltGesture::Flick);
+        let mut hold = Binding::new(MouseButton::WheelTiltRight, key("KEY_VOLUMEUP"));
+        hold.tilt = Some(TiltGesture::Hold);
+        cfg.bindings.extend([flick, hold]);
+
+        for style in [TomlStyle::Tables, TomlStyle::Compact] {
+            let raw = config_to_toml_string(&cfg, style).unwrap();
+            assert!(raw.starts_with("tilt_hold_ms = 350\n"));
+            assert!(!raw.contains("middle_click_scroll_guard_ms"));
+            let decoded: Config = toml::from_str(&raw).unwrap();
+            assert_eq!(decoded, cfg);
+        }
+        // A flick and a hold on the same tilt are two different bindings.
+        assert!(cfg.validate().is_ok());
+        assert!(cfg.warnings().is_empty());
+
+        cfg.bindings[0].tilt = Some(TiltGesture::Hold);
+        cfg.tilt_hold_ms = Some(0);
+        let errors = cfg.validate().unwrap_err();
+        assert!(errors
+            .iter()
+            .any(|e| matches!(e, ConfigError::ZeroTiltHold)));
+        assert!(errors
+            .iter()
+            .any(|e| matches!(e, ConfigError::TiltGestureOnNonTilt { .. })));
+    }
+
+    #[test]
+    fn virtual_device_identity_round_trips_and_checks_the_name() {
+        let mut cfg = Config::default();
+        cfg.evdev.virtual_device_name = Some("Logitech K120".into());
+        cfg.evdev.virtual_device_ids = Some(VirtualDeviceIds {
+            vendor: 0x046d,
+            product: 0xc31c,
+            version: 0x0110,
+        });
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+        assert!(cfg.validate().is_ok());
+
+        let hex: Config =
+            toml::from_str("[evdev]\nvirtual_device_ids = { vendor = 0x046d, product = 0xc31c }\n")
+                .unwrap();
+        assert_eq!(hex.evdev.virtual_device_ids.unwrap().version, 0);
+
+        cfg.evdev.virtual_device_name = Some("x".repeat(MAX_VIRTUAL_DEVICE_NAME + 1));
+        assert!(matches!(
+            cfg.validate().unwrap_err()[..],
+            [ConfigError::InvalidVirtualDeviceName { .. }]
+        ));
+    }
+
+    #[test]
+    fn profiles_round_trip_toml() {
+        let cfg = media_profile_config();
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("[[profiles]]\nname = \"media\"\n\n[[profiles.bindings]]\n"));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+    }
+
+    #[test]
+    fn inherited_profiles_override_per_button_and_reject_loops() {
+        let raw = r#"
+            [[profiles]]
+            name = "base"
+            bindings = [
+              { button = "BTN_SIDE", action = { type = "key_combo", keys = ["KEY_BACK"] } },
+              { button = "BTN_EXTRA", action = { type = "key_combo", keys = ["KEY_FORWARD"] } },
+              { button = "WHEEL_UP", modifier_button = "BTN_SIDE", action = { type = "key_combo", keys = ["KEY_VOLUMEUP"] } },
+            ]
+
+            [[profiles]]
+            name = "gaming"
+            inherits = "base"
+            bindings = [
+              { button = "BTN_SIDE", action = { type = "key_combo", keys = ["KEY_R"] } },
+            ]
+
+            [[profiles]]
+            name = "streaming"
+            inherits = "gaming"
+            bindings = [
+              { button = "BTN_EXTRA", action = { type = "key_combo", keys = ["KEY_F9"] } },
+            ]
+        "#;
+        let mut cfg: Config = toml::from_str(raw).unwrap();
+        assert!(cfg.validate().is_ok());
+        let reparsed: Config =
+            toml::from_str(&config_to_toml_string(&cfg, TomlStyle::Tables).unwrap()).unwrap();
+        assert_eq!(reparsed, cfg);
+
+        let resolved = cfg.resolve_inheritance().unwrap();
+        let keys = |name: &str| -> Vec<(MouseButton, Vec<String>)> {
+            resolved
+                .profile(name)
+                .unwrap()
+                .bindings
+                .iter()
+                .map(|b| match &b.action {
+                    Action::KeyCombo { keys, .. } => (b.button, keys.clone()),
+                    other => panic!("unexpected {other:?}"),
+                })
+                .collect()
+        };
+        // The child's own binding replaces the parent's for that button; the chord stays.
+        assert_eq!(
+            keys("gaming"),
+            [
+                (MouseButton::BtnExtra, vec!["KEY_FORWARD".to_owned()]),
+                (MouseButton::WheelUp, vec!["KEY_VOLUMEUP".to_owned()]),
+                (MouseButton::BtnSide, vec!["KEY_R".to_owned()]),
+            ]
+        );
+        // Grandchildren see the resolved parent.
+        assert_eq!(
+            keys("streaming"),
+            [
+                (MouseButton::WheelUp, vec!["KEY_VOLUMEUP".to_owned()]),
+                (MouseButton::BtnSide, vec!["KEY_R".to_owned()]),
+                (MouseButton::BtnExtra, vec!["KEY_F9".to_owned()]),
+            ]
+        );
+        assert!(resolved.profiles.iter().all(|p| p.inherits.is_none()));
+
+        cfg.profiles[0].inherits = Some("streaming".into());
+        let errors = cfg.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "profiles inherit from each other in a loop (base -> streaming -> gaming -> base)"
+        );
+        assert!(cfg.resolve_inheritance().is_err());
+
+        cfg.profiles[0].inherits = Some("missing".into());
+        assert!(matches!(
+            cfg.validate().unwrap_err()[..],
+            [ConfigError::UnknownParentProfile { .. }]
+        ));
+    }
+
+    #[test]
+    fn device_profiles_match_by_path_or_name_substring() {
+        let raw = r#"
+[[profiles]]
+name = "trackball"
+device = { name = "trackball" }
+
+[[profiles.bindings]]
+button = "BTN_SIDE"
+action = { type = "key_combo", keys = ["KEY_COPY"] }
+
+[[profiles]]
+name = "wired"
+device = { path = "/dev/input/event7" }
+"#;
+        let mut cfg: Config = toml::from_str(raw).unwrap();
+        assert!(cfg.validate().is_ok());
+        let name = |path: &str, device: &str| {
+            cfg.device_profile(Path::new(path), device)
+                .map(|p| p.name.as_str())
+        };
+        assert_eq!(
+            name("/dev/input/event3", "Kensington Expert Trackball"),
+            Some("trackball")
+        );
+        assert_eq!(name("/dev/input/event7", "Logitech G502"), Some("wired"));
+        assert_eq!(name("/dev/input/event4", "Logitech G502"), None);
+
+        let decoded: Config =
+            toml::from_str(&config_to_toml_string(&cfg, TomlStyle::Tables).unwrap()).unwrap();
+        assert_eq!(decoded, cfg);
+
+        cfg.profiles[1].device = Some(DeviceMatch::default());
+        assert!(matches!(
+            &cfg.validate().unwrap_err()[..],
+            [ConfigError::EmptyDeviceMatch { profile }] if profile == "wired"
+        ));
+    }
+
+    #[test]
+    fn queue_settings_round_trip_and_default_when_omitted() {
+        let mut cfg = Config::default();
+        assert!(!config_to_toml_string(&cfg, TomlStyle::Tables)
+            .unwrap()
+            .contains("[queue]"));
+
+        cfg.queue = QueueConfig {
+            depth: 4,
+            overflow: OverflowPolicy::Block,
+            ..QueueConfig::default()
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("[queue]\ndepth = 4\noverflow = \"block\"\n"));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+
+        let partial: Config = toml::from_str("[queue]\ndepth = 8\n").unwrap();
+        assert_eq!(partial.queue.overflow, OverflowPolicy::DropOldest);
+    }
+
+    #[test]
+    fn repeat_round_trips_and_conflicts_with_hold_profile() {
+        let mut cfg = media_profile_config();
+        cfg.bindings[4].repeat = Some(RepeatSpec {
+            delay_ms: 300,
+            interval_ms: 50,
+        });
+        cfg.bindings[0].passthrough = true;
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("repeat = { delay_ms = 300, interval_ms = 50 }\n"));
+        assert_eq!(raw.matches("passthrough = true\n").count(), 1);
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+
+        assert_eq!(
+            cfg.warnings(),
+            vec![ConfigWarning::RepeatOnHoldAction {
+                binding: BindingRef::Binding {
+                    profile: None,
+                    index: 4,
+                },
+                action: "hold_profile",
+            }]
+        );
+    }
+
+    #[test]
+    fn key_combo_accepts_flat_and_nested_keys() {
+        let raw = r#"
+[[bindings]]
+button = "BTN_SIDE"
+action = { type = "key_combo", keys = ["KEY_LEFTCTRL", "KEY_C"] }
+
+[[bindings]]
+button = "BTN_EXTRA"
+action = { type = "key_combo", keys = [["KEY_LEFTCTRL", "KEY_C"], ["KEY_LEFTCTRL", "KEY_V"]] }
+"#;
+        let cfg: Config = toml::from_str(raw).unwrap();
+        assert_eq!(
+            cfg.bindings[0].action,
+            Action::KeyCombo {
+                keys: vec!["KEY_LEFTCTRL".into(), "KEY_C".into()],
+                target: None,
+            }
+        );
+        assert_eq!(
+            cfg.bindings[1].action,
+            Action::KeyChords {
+                keys: vec![
+                    vec!["KEY_LEFTCTRL".into(), "KEY_C".into()],
+                    vec!["KEY_LEFTCTRL".into(), "KEY_V".into()],
+                ],
+                target: None,
+            }
+        );
+
+        let saved = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(saved.contains(
+            r#"action = { type = "key_combo", keys = [["KEY_LEFTCTRL", "KEY_C"], ["KEY_LEFTCTRL", "KEY_V"]] }"#
+        ));
+        let decoded: Config = toml::from_str(&saved).unwrap();
+        assert_eq!(decoded, cfg);
+    }
+
+    #[test]
+    fn compact_style_emits_inline_binding_arrays_that_round_trip() {
+        let mut cfg = media_profile_config();
+        cfg.bindings[0].passthrough = true;
+        cfg.queue.depth = 8;
+        let raw = config_to_toml_string(&cfg, TomlStyle::Compact).unwrap();
+        assert!(raw.starts_with(
+            "bindings = [\n  { button = \"BTN_SIDE\", action = { type = \"key_combo\", keys = [\"KEY_BACK\"] }, passthrough = true },\n"
+        ));
+        assert!(!raw.contains("[[bindings]]"));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+
+        let one_line: Config = toml::from_str(
+            r#"bindings = [{ button = "BTN_SIDE", action = { type = "key_combo", keys = ["KEY_BACK"] } }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            one_line.bindings,
+            cfg.bindings[..1]
+                .iter()
+                .map(|b| Binding {
+                    passthrough: false,
+                    ..b.clone()
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn key_policy_requires_both_allowlists() {
+        let keys = |names: &[&str]| Some(names.iter().map(|&k| k.to_owned()).collect());
+        let mut cfg = Config::default();
+        let open = KeyPolicy::new(&cfg, &SystemPolicy::default());
+        assert!(!open.is_restricted());
+        assert!(open.allows("KEY_F1"));
+
+        let system = SystemPolicy {
+            allowed_keys: keys(&["KEY_BACK", "KEY_FORWARD"]),
+        };
+        assert!(!KeyPolicy::new(&cfg, &system).allows("KEY_F1"));
+
+        cfg.allowed_keys = keys(&["KEY_BACK", "KEY_F1"]);
+        let both = KeyPolicy::new(&cfg, &system);
+        assert!(both.allows("KEY_BACK"));
+        assert!(!both.allows("KEY_FORWARD"));
+        assert!(!both.allows("KEY_F1"));
+
+        let decoded: Config =
+            toml::from_str(&config_to_toml_string(&cfg, TomlStyle::Tables).unwrap()).unwrap();
+        assert_eq!(decoded, cfg);
+    }
+
+    #[test]
+    fn set_clipboard_defaults_to_the_clipboard_selection() {
+        let action: Action =
+            toml::from_str("type = \"set_clipboard\"\ntext = \"Kind regards\"").unwrap();
+        assert_eq!(
+            action,
+            Action::SetClipboard {
+                text: "Kind regards".into(),
+                selection: Selection::Clipboard,
+            }
+        );
+        let action: Action =
+            toml::from_str("type = \"set_clipboard\"\ntext = \"x\"\nselection = \"primary\"")
+                .unwrap();
+        assert!(matches!(
+            action,
+            Action::SetClipboard {
+                selection: Selection::Primary,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn screenshot_fields_are_optional() {
+        let action: Action = toml::from_str(r#"type = "screenshot""#).unwrap();
+        assert_eq!(
+            action,
+            Action::Screenshot {
+                mode: ScreenshotMode::Full,
+                to_clipboard: false,
+                save_path: None,
+            }
+        );
+        let action: Action =
+            toml::from_str("type = \"screenshot\"\nmode = \"region\"\nto_clipboard = true")
+                .unwrap();
+        assert_eq!(
+            action,
+            Action::Screenshot {
+                mode: ScreenshotMode::Region,
+                to_clipboard: true,
+                save_path: None,
+            }
+        );
+    }
+
+    #[test]
+    fn webhook_round_trips_and_needs_an_http_url() {
+        let mut cfg = Config::default();
+        cfg.bindings[0].action = Action::Webhook {
+            url: "http://homeassistant.local:8123/api/webhook/desk-lamp".into(),
+            method: None,
+            body: Some(r#"{"on":true}"#.into()),
+            headers: Some(BTreeMap::from([(
+                "Content-Type".into(),
+                "application/json".into(),
+            )])),
+        };
+        let raw = cfg.to_toml_string().unwrap();
+        assert!(raw.contains(
+            r#"action = { type = "webhook", body = '{"on":true}', headers = { Content-Type = "application/json" }, url = "http://homeassistant.local:8123/api/webhook/desk-lamp" }"#
+        ), "{raw}");
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg);
+        assert!(cfg.validate().is_ok());
+
+        cfg.bindings[0].action = Action::Webhook {
+            url: "homeassistant.local/api/webhook/desk-lamp".into(),
+            method: None,
+            body: None,
+            headers: None,
+        };
+        assert!(matches!(
+            cfg.validate().unwrap_err().as_slice(),
+            [ConfigError::InvalidWebhookUrl { .. }]
+        ));
+    }
+
+    fn macro_config() -> Config {
+        let mut cfg = Config::default();
+        cfg.macros.insert(
+            "copy paste".into(),
+            Action::KeyChords {
+                keys: vec![
+                    vec!["KEY_LEFTCTRL".into(), "KEY_C".into()],
+                    vec!["KEY_LEFTCTRL".into(), "KEY_V".into()],
+                ],
+                target: None,
+            },
+        );
+        cfg.macros.insert(
+            "duplicate".into(),
+            Action::Parallel {
+                actions: vec![Action::Macro {
+                    name: "copy paste".into(),
+                }],
+            },
+        );
+        cfg.bindings[0].action = Action::Macro {
+            name: "duplicate".into(),
+        };
+        cfg
+    }
+
+    #[test]
+    fn macros_round_trip_and_expand() {
+        let cfg = macro_config();
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("[macros]\n\"copy paste\" = { type = \"key_combo\""));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+        assert!(cfg.validate().is_ok());
+
+        let expanded = cfg.expand_macros().unwrap();
+        assert_eq!(
+            expanded.bindings[0].action,
+            Action::Parallel {
+                actions: vec![cfg.macros["copy paste"].clone()]
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_and_cyclic_macros_are_errors() {
+        let mut cfg = macro_config();
+        cfg.macros.insert(
+            "copy paste".into(),
+            Action::Macro {
+                name: "duplicate".into(),
+            },
+        );
+        cfg.bindings[1].action = Action::Macro {
+            name: "missing".into(),
+        };
+        let binding = |index| BindingRef::Binding {
+            profile: None,
+            index,
+        };
+        let errors = cfg.validate().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            &errors[0],
+            ConfigError::CyclicMacro { binding: b, cycle }
+                if *b == binding(0) && cycle == &["duplicate", "copy paste", "duplicate"]
+        ));
+        assert_eq!(
+            errors[0].to_string(),
+            "binding #0: macros refer to each other in a loop (duplicate -> copy paste -> duplicate)"
+        );
+        assert!(matches!(
+            &errors[1],
+            ConfigError::UnknownMacro { binding: b, name } if *b == binding(1) && name == "missing"
+        ));
+        assert!(cfg.expand_macros().is_err());
+    }
+
+    #[test]
+    fn keymap_entries_are_numbers_or_names() {
+        let keymap: BTreeMap<String, KeysymSpec> =
+            toml::from_str("KEY_F13 = 0xffca\nKEY_BACK = \"XF86Back\"\n").unwrap();
+        assert_eq!(keymap["KEY_F13"], KeysymSpec::Number(0xffca));
+        assert_eq!(keymap["KEY_BACK"], KeysymSpec::Name("XF86Back".into()));
+        assert!(toml::from_str::<BTreeMap<String, KeysymSpec>>("KEY_F13 = true").is_err());
+    }
+
+    #[test]
+    fn key_combo_target_matches_class_and_title() {
+        let action: Action = toml::from_str(
+            "type = \"key_combo\"\nkeys = [\"KEY_SPACE\"]\ntarget = { class = \"mpv\" }",
+        )
+        .unwrap();
+        let Action::KeyCombo {
+            target: Some(KeyTarget::Window(target)),
+            ..
+        } = &action
+        else {
+            panic!("expected a targeted key_combo, got {action:?}");
+        };
+        assert!(target.matches(&["gl", "MPV"], "video.mkv - mpv"));
+        assert!(!target.matches(&["firefox", "Firefox"], "mpv - Search"));
+
+        let both = WindowTarget {
+            class: Some("firefox".into()),
+            title: Some("Docs".into()),
+        };
+        assert!(both.matches(&["Navigator", "firefox"], "Docs - Mozilla Firefox"));
+        assert!(!both.matches(&["Navigator", "firefox"], "Mail - Mozilla Firefox"));
+
+        let mut cfg = Config::default();
+        cfg.bindings[0].action = Action::KeyCombo {
+            keys: vec!["KEY_SPACE".into()],
+            target: Some(KeyTarget::Named(NamedTarget::UnderCursor)),
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("target = \"under_cursor\""));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+    }
+
+    #[test]
+    fn per_profile_picks_by_profile_and_validates_names() {
+        let mut cfg = media_profile_config();
+        let per_profile = Action::PerProfile {
+            map: [(
+                "media".to_owned(),
+                Action::Command {
+                    argv: vec!["mpv".into()],
+                },
+            )]
+            .into(),
+            default: None,
+        };
+        let parallel = Action::Parallel {
+            actions: vec![per_profile.clone(), Action::SwitchLayout { group: None }],
+        };
+        assert_eq!(
+            per_profile.for_profile(Some("media")),
+            Some(Action::Command {
+                argv: vec!["mpv".into()]
+            })
+        );
+        assert_eq!(per_profile.for_profile(None), None);
+        assert_eq!(
+            parallel.for_profile(None),
+            Some(Action::Parallel {
+                actions: vec![Action::SwitchLayout { group: None }]
+            })
+        );
+
+        cfg.bindings[0].action = per_profile;
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains(
+            "action = { type = \"per_profile\", map = { media = { type = \"command\", argv = [\"mpv\"] } } }\n"
+        ));
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg);
+        assert!(cfg.validate().is_ok());
+
+        let Action::PerProfile { map, .. } = &mut cfg.bindings[0].action else {
+            unreachable!();
+        };
+        map.insert("gaming".into(), Action::SwitchLayout { group: None });
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(errors, [r#"binding #0: unknown profile "gaming""#]);
+    }
+
+    #[test]
+    fn every_template_is_valid_and_round_trips() {
+        for name in TEMPLATES {
+            let cfg = Config::template(name).unwrap();
+            assert!(cfg.validate().is_ok(), "{name}");
+            for style in [TomlStyle::Tables, TomlStyle::Compact] {
+                let raw = config_to_toml_string(&cfg, style).unwrap();
+                assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg, "{name}");
+            }
+        }
+        assert_eq!(Config::template("default"), Some(Config::default()));
+        assert_eq!(Config::template("gaming"), None);
+    }
+
+    #[test]
+    fn startup_action_round_trips_and_is_validated() {
+        let mut cfg = Config {
+            startup_delay_ms: Some(2000),
+            startup_action: Some(Action::Command {
+                argv: vec!["notify-send".into(), "mouse-assist active".into()],
+            }),
+            ..Config::default()
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.starts_with(
+            "startup_delay_ms = 2000\n\nstartup_action = { type = \"command\", argv = [\"notify-send\", \"mouse-assist active\"] }\n\n[[bindings]]\n"
+        ));
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg);
+
+        cfg.startup_action = Some(Action::Command { argv: vec![] });
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(errors, ["startup_action: command argv is empty"]);
+    }
+
+    #[test]
+    fn window_class_globs_match_either_wm_class_name() {
+        assert!(glob_matches("firefox*", "Firefox-esr"));
+        assert!(glob_matches("chrom*", "chromium-browser"));
+        assert!(glob_matches("*term*", "XTerm"));
+        assert!(glob_matches("k?tty", "kitty"));
+        assert!(!glob_matches("k?tty", "ktty"));
+        assert!(!glob_matches("firefox", "firefox-esr"));
+
+        let cfg: Config = toml::from_str(
+            r#"
+[[bindings]]
+button = "BTN_SIDE"
+window_class = "navigator"
+action = { type = "key_combo", keys = ["KEY_BACK"] }
+
+[[bindings]]
+button = "BTN_SIDE"
+window_class = ["firefox*", "chrom*"]
+action = { type = "key_combo", keys = ["KEY_BACK"] }
+"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.bindings[0].window_class, vec!["navigator".to_owned()]);
+        assert!(cfg.bindings[0].matches_window(&["Navigator", "firefox"]));
+        assert!(cfg.bindings[1].matches_window(&["chromium", "Chromium"]));
+        assert!(!cfg.bindings[1].matches_window(&["kitty", "kitty"]));
+        // Bindings for different windows don't shadow each other.
+        assert!(cfg.warnings().is_empty());
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("window_class = [\"firefox*\", \"chrom*\"]"));
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg);
+
+        let mut bad = cfg;
+        bad.bindings[1].window_class = vec![" ".into(), "[Ff]irefox".into()];
+        assert_eq!(bad.validate().unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn active_hours_wrap_past_midnight_and_are_validated() {
+        let at = |hhmm| minute_of_day(hhmm).unwrap();
+        let mut binding = Binding::new(MouseButton::BtnSide, Action::SwitchLayout { group: None });
+        assert!(binding.is_active_at(at("03:00")));
+
+        binding.active_hours = Some(("9:00".into(), "17:30".into()));
+        assert!(!binding.is_active_at(at("08:59")));
+        assert!(binding.is_active_at(at("09:00")));
+        assert!(binding.is_active_at(at("17:29")));
+        assert!(!binding.is_active_at(at("17:30")));
+
+        binding.active_hours = Some(("22:00".into(), "06:00".into()));
+        assert!(binding.is_active_at(at("23:15")));
+        assert!(binding.is_active_at(at("00:00")));
+        assert!(!binding.is_active_at(at("06:00")));
+        assert!(!binding.is_active_at(at("12:00")));
+
+        let mut cfg = Config {
+            bindings: vec![binding],
+            ..Config::default()
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("active_hours = [\"22:00\", \"06:00\"]\n"));
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg);
+        assert!(cfg.validate().is_ok());
+
+        cfg.bindings[0].active_hours = Some(("08:00".into(), "8:00".into()));
+        cfg.bindings.push(cfg.bindings[0].clone());
+        cfg.bindings[0].active_hours = Some(("24:00".into(), "7:5".into()));
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                r#"binding #0: active_hours time "24:00" isn't HH:MM (00:00 to 23:59)"#,
+                r#"binding #0: active_hours time "7:5" isn't HH:MM (00:00 to 23:59)"#,
+                r#"binding #1: active_hours starts and ends at 08:00, which leaves no time active"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn monitor_directions_are_names_or_an_index() {
+        let raw = r#"
+[[bindings]]
+button = "BTN_SIDE"
+action = { type = "move_window_to_monitor", direction = "next" }
+
+[[bindings]]
+button = "BTN_EXTRA"
+action = { type = "move_window_to_monitor", direction = { index = 1 } }
+"#;
+        let cfg: Config = toml::from_str(raw).unwrap();
+        assert_eq!(
+            cfg.bindings[0].action,
+            Action::MoveWindowToMonitor {
+                direction: MonitorDirection::Next
+            }
+        );
+        assert_eq!(
+            cfg.bindings[1].action,
+            Action::MoveWindowToMonitor {
+                direction: MonitorDirection::Index(1)
+            }
+        );
+        let saved = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(saved.contains(
+            "action = { type = \"move_window_to_monitor\", direction = { index = 1 } }\n"
+        ));
+        assert_eq!(toml::from_str::<Config>(&saved).unwrap(), cfg);
+    }
+
+    #[test]
+    fn scroll_chords_need_a_holdable_modifier() {
+        let raw = r#"
+[[bindings]]
+button = "WHEEL_UP"
+modifier_button = "BTN_SIDE"
+action = { type = "key_combo", keys = ["KEY_LEFTCTRL", "KEY_EQUAL"] }
+
+[[bindings]]
+button = "WHEEL_DOWN"
+modifier_button = "BTN_SIDE"
+action = { type = "key_combo", keys = ["KEY_LEFTCTRL", "KEY_MINUS"] }
+"#;
+        let mut cfg: Config = toml::from_str(raw).unwrap();
+        assert_eq!(cfg.bindings[0].modifier_button, Some(MouseButton::BtnSide));
+        assert!(cfg.validate().is_ok());
+        assert!(cfg.warnings().is_empty());
+        let saved = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(saved.starts_with(
+            "[[bindings]]\nbutton = \"WHEEL_UP\"\nmodifier_button = \"BTN_SIDE\"\naction = "
+        ));
+        assert_eq!(toml::from_str::<Config>(&saved).unwrap(), cfg);
+        let compact = config_to_toml_string(&cfg, TomlStyle::Compact).unwrap();
+        assert!(
+            compact.contains("{ button = \"WHEEL_UP\", modifier_button = \"BTN_SIDE\", action = ")
+        );
+
+        cfg.bindings[0].modifier_button = None;
+        cfg.bindings[1].modifier_button = Some(MouseButton::WheelTiltLeft);
+        cfg.bindings.push(Binding {
+            modifier_button: Some(MouseButton::BtnSide),
+            ..Binding::new(MouseButton::BtnExtra, Action::SwitchLayout { group: None })
+        });
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "binding #0: WHEEL_UP only works with a modifier_button held",
+                "binding #1: modifier_button WHEEL_TILT_LEFT can't be held; use a mouse button",
+                "binding #2: modifier_button only works with WHEEL_UP and WHEEL_DOWN",
+            ]
+        );
+    }
+
+    #[test]
+    fn open_needs_a_target() {
+        let mut cfg = media_profile_config();
+        cfg.bindings[0].action = Action::Open {
+            target: "https://example.com/dashboard".into(),
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains(
+            "action = { type = \"open\", target = \"https://example.com/dashboard\" }\n"
+        ));
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg);
+        assert!(cfg.validate().is_ok());
+
+        cfg.bindings[0].action = Action::Open {
+            target: "  ".into(),
+        };
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(errors, ["binding #0: open has no target"]);
+    }
+
+    #[test]
+    fn unknown_key_names_are_reported_one_by_one() {
+        let raw = r#"
+            [[bindings]]
+            button = "BTN_SIDE"
+            action = { type = "key_combo", keys = ["ctrl", "shift", "entr"] }
+
+            [[bindings]]
+            button = "BTN_EXTRA"
+            action = { type = "key_combo", keys = [["ctrl", "c"], ["KEY_PASTE", "BTN_LEFT", "KEY_FOO"]] }
+        "#;
+        let cfg: Config = toml::from_str(raw).unwrap();
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "binding #0: unknown key name \"KEY_ENTR\"",
+                "binding #1: unknown key name \"KEY_FOO\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn scripts_round_trip_and_need_a_source() {
+        let mut cfg = media_profile_config();
+        cfg.bindings[0].action = Action::Parallel {
+            actions: vec![Action::Script {
+                source: "send_keys(\"ctrl+w\");".into(),
+            }],
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        let parsed = toml::from_str::<Config>(&raw).unwrap();
+        assert_eq!(parsed.bindings[0].action, cfg.bindings[0].action);
+
+        cfg.bindings[0].action = Action::Script { source: " ".into() };
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(errors, ["binding #0: script has no source"]);
+    }
+
+    #[test]
+    fn brightness_needs_a_nonzero_percentage() {
+        let action: Action = toml::from_str("type = \"brightness\"\ndelta_percent = -10").unwrap();
+        assert_eq!(
+            action,
+            Action::Brightness {
+                delta_percent: -10,
+                device: None,
+            }
+        );
+
+        let mut cfg = Config::default();
+        cfg.bindings[0].action = action;
+        assert!(cfg.validate().is_ok());
+        for delta_percent in [0, 101, -150] {
+            cfg.bindings[0].action = Action::Brightness {
+                delta_percent,
+                device: None,
+            };
+            assert!(matches!(
+                cfg.validate().unwrap_err()[..],
+                [ConfigError::InvalidBrightnessDelta { delta, .. }] if delta == delta_percent
+            ));
+        }
+    }
+
+    #[test]
+    fn scroll_takes_a_nonzero_amount_either_way() {
+        let action: Action =
+            toml::from_str("type = \"scroll\"\naxis = \"horizontal\"\namount = -3").unwrap();
+        assert_eq!(
+            action,
+            Action::Scroll {
+                axis: ScrollAxis::Horizontal,
+                amount: -3,
+            }
+        );
+
+        let mut cfg = Config::default();
+        cfg.bindings[0].action = action;
+        assert!(cfg.validate().is_ok());
+        for amount in [0, 101, -500] {
+            cfg.bindings[0].action = Action::Scroll {
+                axis: ScrollAxis::Vertical,
+                amount,
+            };
+            assert!(matches!(
+                cfg.validate().unwrap_err()[..],
+                [ConfigError::InvalidScrollAmount { amount: got, .. }] if got == amount
+            ));
+        }
+    }
+
+    #[test]
+    fn sequences_mix_actions_and_delays_up_to_a_limit() {
+        let mut cfg: Config = toml::from_str(
+            r#"
+            [[bindings]]
+            button = "BTN_SIDE"
+            action = { type = "sequence", steps = [
+                { type = "key_combo", keys = ["KEY_LEFTCTRL", "KEY_L"] },
+                { type = "delay", ms = 200 },
+                { type = "command", argv = ["notify-send", "done"] },
+            ] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.bindings[0].action,
+            Action::Sequence {
+                steps: vec![
+                    Step::Action(Action::KeyCombo {
+                        keys: vec!["KEY_LEFTCTRL".into(), "KEY_L".into()],
+                        target: None,
+                    }),
+                    Step::Delay { ms: 200 },
+                    Step::Action(Action::Command {
+                        argv: vec!["notify-send".into(), "done".into()],
+                    }),
+                ],
+            }
+        );
+        assert!(cfg.validate().is_ok());
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("{ type = \"delay\", ms = 200 }"), "{raw}");
+        assert_eq!(toml::from_str::<Config>(&raw).unwrap(), cfg);
+
+        cfg.bindings[0].action = Action::Sequence {
+            steps: vec![Step::Delay { ms: 6000 }, Step::Delay { ms: 6000 }],
+        };
+        assert!(matches!(
+            cfg.validate().unwrap_err()[..],
+            [ConfigError::SequenceTooLong {
+                total_ms: 12_000,
+                ..
+            }]
+        ));
+        assert!(cfg
+            .warnings()
+            .iter()
+            .any(|w| matches!(w, ConfigWarning::EmptySequence { .. })));
+    }
+
+    #[test]
+    fn json_nulls_read_as_left_out_fields() {
+        let cfg = parse_config(
+            r#"{
+                "device_name": null,
+                "bindings": [{
+                    "button": "BTN_SIDE",
+                    "tilt": null,
+                    "action": { "type": "key_combo", "keys": ["KEY_BACK"], "target": null }
+                }, {
+                    "button": "BTN_EXTRA",
+                    "action": { "type": "sequence", "steps": [
+                        { "type": "switch_profile", "name": null }
+                    ] }
+                }]
+            }"#,
+            ConfigFormat::Json,
+        )
+        .unwrap();
+        assert_eq!(cfg.device_name, None);
+        assert_eq!(
+            cfg.bindings[0].action,
+            Action::KeyCombo {
+                keys: vec!["KEY_BACK".into()],
+                target: None,
+            }
+        );
+        assert_eq!(
+            cfg.bindings[1].action,
+            Action::Sequence {
+                steps: vec![Step::Action(Action::SwitchProfile { name: None })],
+            }
+        );
+    }
+
+    #[test]
+    fn configs_survive_a_trip_through_json() {
+        let cfg: Config = toml::from_str(
+            r#"
+            device_name = "Logitech"
+
+            [[bindings]]
+            button = "BTN_SIDE"
+            trigger = { type = "long_press", after_ms = 800 }
+            action = { type = "sequence", steps = [
+                { type = "key_combo", keys = ["KEY_LEFTCTRL", "KEY_L"] },
+                { type = "delay", ms = 200 },
+                { type = "command", argv = ["notify-send", "done"] },
+            ] }
+
+            [[bindings]]
+            button = "WHEEL_UP"
+            modifier_button = "BTN_EXTRA"
+            action = { type = "key_combo", keys = ["KEY_VOLUMEUP"] }
+
+            [[bindings]]
+            button = "BTN_EXTRA"
+            action = { type = "hold_profile", name = "editing" }
+
+            [[profiles]]
+            name = "editing"
+
+            [[profiles.bindings]]
+            button = "BTN_SIDE"
+            window_class = ["code"]
+            action = { type = "key_combo", keys = ["KEY_LEFTCTRL", "KEY_Z"] }
+            "#,
+        )
+        .unwrap();
+        let json = cfg.to_json_string().unwrap();
+        assert!(json.contains(r#""type": "sequence""#), "{json}");
+        assert_eq!(parse_config(&json, ConfigFormat::Json).unwrap(), cfg);
+        for name in TEMPLATES {
+            let cfg = Config::template(name).unwrap();
+            let json = cfg.to_json_string().unwrap();
+            assert_eq!(
+                parse_config(&json, ConfigFormat::Json).unwrap(),
+                cfg,
+                "{name}"
+            );
+        }
+
+        assert!(matches!(
+            parse_config("{ \"bindings\": 1 }", ConfigFormat::Json),
+            Err(ConfigError::Json(_))
+        ));
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("dotfiles/mouse.json")),
+            Some(ConfigFormat::Json)
+        );
+        assert_eq!(ConfigFormat::from_path(Path::new("config")), None);
+    }
+
+    #[test]
+    fn set_log_level_takes_a_level_name() {
+        let mut cfg = Config::default();
+        for level in ["debug", "WARN", "default"] {
+            cfg.bindings[0].action = Action::SetLogLevel {
+                level: level.into(),
+            };
+            assert!(cfg.validate().is_ok(), "{level}");
+        }
+        cfg.bindings[0].action = Action::SetLogLevel {
+            level: "verbose".into(),
+        };
+        assert!(matches!(
+            cfg.validate().unwrap_err()[..],
+            [ConfigError::UnknownLogLevel { .. }]
+        ));
+    }
+
+    #[test]
+    fn system_ops_use_snake_case_names() {
+        let mut cfg = Config::default();
+        cfg.bindings[0].action = Action::System {
+            op: SystemOp::LogOut,
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("action = { type = \"system\", op = \"log_out\" }\n"));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+        assert_eq!(SystemOp::LogOut.name(), "log_out");
+    }
+
+    #[test]
+    fn key_names_accept_bare_lowercase_and_alias_forms() {
+        for (written, canonical) in [
+            ("KEY_A", "KEY_A"),
+            ("key_a", "KEY_A"),
+            ("a", "KEY_A"),
+            ("A", "KEY_A"),
+            ("VolumeUp", "KEY_VOLUMEUP"),
+            ("PlayPause", "KEY_PLAYPAUSE"),
+            ("Ctrl", "KEY_LEFTCTRL"),
+            ("escape", "KEY_ESC"),
+            ("Page Up", "KEY_PAGEUP"),
+            ("f13", "KEY_F13"),
+            ("BTN_left", "BTN_LEFT"),
+        ] {
+            assert_eq!(parse_key_name(written), canonical, "{written}");
+        }
+
+        let raw = "allowed_keys = [\"ctrl\", \"c\"]\n\n[[bindings]]\nbutton = \"BTN_SIDE\"\n\
+                   action = { type = \"key_combo\", keys = [[\"Ctrl\", \"c\"], [\"ctrl\", \"v\"]] }\n";
+        let cfg: Config = toml::from_str(raw).unwrap();
+        assert_eq!(
+            cfg.allowed_keys,
+            Some(vec!["KEY_LEFTCTRL".to_owned(), "KEY_C".to_owned()])
+        );
+        assert_eq!(
+            cfg.bindings[0].action,
+            Action::KeyChords {
+                keys: vec![
+                    vec!["KEY_LEFTCTRL".into(), "KEY_C".into()],
+                    vec!["KEY_LEFTCTRL".into(), "KEY_V".into()],
+                ],
+                target: None,
+            }
+        );
+    }
+
+    #[test]
+    fn x11_key_method_defaults_to_xtest_and_round_trips() {
+        let mut cfg = Config::default();
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(!raw.contains("key_method"));
+        cfg.x11.key_method = X11KeyMethod::SendEvent;
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains("key_method = \"send_event\""));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+        let xtest: X11Config = toml::from_str("key_method = \"xtest\"").unwrap();
+        assert_eq!(xtest.key_method, X11KeyMethod::XTest);
+        assert!(!xtest.compose_held_modifiers);
+    }
+
+    #[test]
+    fn raw_events_round_trip_and_validate() {
+        let mut cfg = Config::default();
+        cfg.bindings[0].action = Action::RawEvents {
+            events: vec![
+                RawEvent {
+                    event_type: RawEvent::EV_REL,
+                    code: 8,
+                    value: -1,
+                },
+                RawEvent {
+                    event_type: 3,
+                    code: 0,
+                    value: 100,
+                },
+            ],
+        };
+        let raw = config_to_toml_string(&cfg, TomlStyle::Tables).unwrap();
+        assert!(raw.contains(
+            "action = { type = \"raw_events\", events = [{ type = 2, code = 8, value = -1 }, { type = 3, code = 0, value = 100 }] }\n"
+        ));
+        let decoded: Config = toml::from_str(&raw).unwrap();
+        assert_eq!(decoded, cfg);
+
+        let errors: Vec<String> = cfg
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            errors,
+            ["binding #0: raw event #1 (type 3, code 0) can't be sent; only EV_SYN, EV_KEY and EV_REL codes are supported"]
+        );
+
+        cfg.bindings[0].action = Action::RawEvents { events: Vec::new() };
+        assert!(matches!(
+            cfg.validate().unwrap_err()[..],
+            [ConfigError::EmptyRawEvents { .. }]
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_unknown_hold_profile() {
+        let mut cfg = media_profile_config();
+        assert!(cfg.validate().is_ok());
+
+        cfg.profiles[0].bindings[0].action = Action::HoldProfile {
+            name: "gaming".into(),
+        };
+        cfg.bindings[1].action = Action::SwitchProfile {
+            name: Some("work".into()),
+        };
+        let errors = cfg.validate().unwrap_err();
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                r#"binding #1: unknown profile "work""#,
+                r#"profile "media" binding #0: unknown profile "gaming""#,
+            ]
+        );
+
+        // Switching back to the top-level bindings needs no profile.
+        cfg.bindings[1].action = Action::SwitchProfile { name: None };
+        assert_eq!(cfg.validate().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn load_config_reports_a_missing_file_as_not_found() {
+        let path = std::env::temp_dir().join(format!(
+            "mouse-assist-missing-{}/config.toml",
+            std::process::id()
+        ));
+        match load_config(&path) {
+            Err(ConfigError::NotFound { path: missing }) => assert_eq!(missing, path),
+            other => panic!("expected NotFound, got {other:?}"),
+        }
+    }
 }
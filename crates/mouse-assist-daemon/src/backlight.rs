@@ -0,0 +1,164 @@
+use crate::logind;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+const BACKLIGHT_CLASS: &str = "/sys/class/backlight";
+
+#[derive(thiserror::Error, Debug)]
+enum BacklightError {
+    #[error("no backlight under {BACKLIGHT_CLASS}")]
+    NoDevice,
+    #[error("no backlight named {0:?} under {BACKLIGHT_CLASS}")]
+    UnknownDevice(String),
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("{}: not a number: {value:?}", path.display())]
+    Parse { path: PathBuf, value: String },
+    #[error(
+        "{} isn't writable and logind refused too ({logind}); check that the daemon runs in \
+         your login session, or add a udev rule that lets the video group write brightness",
+        path.display()
+    )]
+    Denied {
+        path: PathBuf,
+        logind: logind::LogindError,
+    },
+}
+
+/// Runs a `brightness` action, logging the new level or why it couldn't be set.
+pub(crate) fn adjust(delta_percent: i32, device: Option<&str>) {
+    match adjust_in(Path::new(BACKLIGHT_CLASS), delta_percent, device) {
+        Ok((name, value, max)) => info!("brightness of {name}: {value}/{max}"),
+        Err(err) => error!("brightness failed: {err}"),
+    }
+}
+
+/// Does the work of [`adjust`] against `class` (normally [`BACKLIGHT_CLASS`]), returning the
+/// device's name, its new brightness and its maximum.
+fn adjust_in(
+    class: &Path,
+    delta_percent: i32,
+    device: Option<&str>,
+) -> Result<(String, u32, u32), BacklightError> {
+    let dir = find_device(class, device)?;
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let max = read_number(&dir.join("max_brightness"))?;
+    let current = read_number(&dir.join("brightness"))?;
+    let value = stepped(current, max, delta_percent);
+
+    let path = dir.join("brightness");
+    match fs::write(&path, value.to_string()) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            logind::set_brightness("backlight", &name, value)
+                .map_err(|logind| BacklightError::Denied { path, logind })?;
+        }
+        Err(source) => return Err(BacklightError::Io { path, source }),
+    }
+    Ok((name, value, max))
+}
+
+/// The backlight called `name`, or the first one by name.
+fn find_device(class: &Path, name: Option<&str>) -> Result<PathBuf, BacklightError> {
+    if let Some(name) = name {
+        let dir = class.join(name);
+        return if dir.join("brightness").exists() {
+            Ok(dir)
+        } else {
+            Err(BacklightError::UnknownDevice(name.to_owned()))
+        };
+    }
+    let entries = fs::read_dir(class).map_err(|source| match source.kind() {
+        io::ErrorKind::NotFound => BacklightError::NoDevice,
+        _ => BacklightError::Io {
+            path: class.to_owned(),
+            source,
+        },
+    })?;
+    let mut devices: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|dir| dir.join("brightness").exists())
+        .collect();
+    devices.sort();
+    devices.into_iter().next().ok_or(BacklightError::NoDevice)
+}
+
+fn read_number(path: &Path) -> Result<u32, BacklightError> {
+    let raw = fs::read_to_string(path).map_err(|source| BacklightError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    raw.trim().parse().map_err(|_| BacklightError::Parse {
+        path: path.to_owned(),
+        value: raw.trim().to_owned(),
+    })
+}
+
+/// `current` moved by `delta_percent` of `max`, by at least one step, and kept within 0..=max.
+fn stepped(current: u32, max: u32, delta_percent: i32) -> u32 {
+    let step = (i64::from(max) * i64::from(delta_percent) / 100)
+        .abs()
+        .max(1);
+    let target = if delta_percent < 0 {
+        i64::from(current) - step
+    } else {
+        i64::from(current) + step
+    };
+    target.clamp(0, i64::from(max)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_steps_by_percent_within_range() {
+        assert_eq!(stepped(500, 1000, 10), 600);
+        assert_eq!(stepped(500, 1000, -10), 400);
+        assert_eq!(stepped(950, 1000, 10), 1000);
+        assert_eq!(stepped(30, 1000, -10), 0);
+        // Coarse backlights still move.
+        assert_eq!(stepped(3, 7, 5), 4);
+
+        let class =
+            std::env::temp_dir().join(format!("mouse-assist-backlight-{}", std::process::id()));
+        for (name, brightness) in [("intel_backlight", "400"), ("acpi_video0", "5")] {
+            let dir = class.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("brightness"), brightness).unwrap();
+            fs::write(
+                dir.join("max_brightness"),
+                if name == "acpi_video0" {
+                    "10\n"
+                } else {
+                    "800\n"
+                },
+            )
+            .unwrap();
+        }
+        // The first by name unless one is picked.
+        assert_eq!(
+            adjust_in(&class, 20, None).unwrap(),
+            ("acpi_video0".to_owned(), 7, 10)
+        );
+        assert_eq!(
+            adjust_in(&class, -25, Some("intel_backlight")).unwrap(),
+            ("intel_backlight".to_owned(), 200, 800)
+        );
+        assert_eq!(
+            fs::read_to_string(class.join("intel_backlight/brightness")).unwrap(),
+            "200"
+        );
+        assert!(matches!(
+            adjust_in(&class, 10, Some("nope")),
+            Err(BacklightError::UnknownDevice(_))
+        ));
+        fs::remove_dir_all(&class).unwrap();
+    }
+}
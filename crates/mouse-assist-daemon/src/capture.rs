@@ -0,0 +1,114 @@
+use crate::device_class::classify_device;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// The first line of a capture: which device it came from.
+#[derive(Serialize)]
+struct Header {
+    device: String,
+    path: String,
+    /// How the daemon classifies the device (`mouse`, `keyboard`, ...).
+    kind: String,
+    bus: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+/// Every other line: one event exactly as the kernel reported it.
+#[derive(Serialize)]
+struct Event {
+    /// Microseconds since the first captured event, from the kernel's timestamps.
+    time_us: u64,
+    #[serde(rename = "type")]
+    event_type: u16,
+    code: u16,
+    value: i32,
+}
+
+/// Writes a device's raw event stream as JSON lines, flushing each one so an interrupted
+/// capture keeps everything up to the interruption.
+pub(crate) struct Capture<W: Write> {
+    out: W,
+    start: Option<SystemTime>,
+}
+
+impl<W: Write> Capture<W> {
+    /// Starts a capture of `dev` (opened from `path`) by writing its header.
+    pub(crate) fn start(mut out: W, path: &Path, dev: &evdev::Device) -> std::io::Result<Self> {
+        let id = dev.input_id();
+        write_line(
+            &mut out,
+            &Header {
+                device: dev.name().unwrap_or("<unknown>").to_owned(),
+                path: path.display().to_string(),
+                kind: classify_device(dev).to_string(),
+                bus: id.bus_type().0,
+                vendor: id.vendor(),
+                product: id.product(),
+                version: id.version(),
+            },
+        )?;
+        Ok(Self { out, start: None })
+    }
+
+    pub(crate) fn event(&mut self, ev: &evdev::InputEvent) -> std::io::Result<()> {
+        self.write_event(ev.timestamp(), ev.event_type().0, ev.code(), ev.value())
+    }
+
+    fn write_event(
+        &mut self,
+        at: SystemTime,
+        event_type: u16,
+        code: u16,
+        value: i32,
+    ) -> std::io::Result<()> {
+        let start = *self.start.get_or_insert(at);
+        let since = at.duration_since(start).unwrap_or(Duration::ZERO);
+        write_line(
+            &mut self.out,
+            &Event {
+                time_us: u64::try_from(since.as_micros()).unwrap_or(u64::MAX),
+                event_type,
+                code,
+                value,
+            },
+        )
+    }
+}
+
+fn write_line(out: &mut impl Write, line: &impl Serialize) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *out, line).map_err(std::io::Error::from)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_timed_from_the_first_one() {
+        let mut capture = Capture {
+            out: Vec::new(),
+            start: None,
+        };
+        let first = SystemTime::now();
+        // A wheel notch down, its SYN_REPORT, and another notch 2.5ms later.
+        capture.write_event(first, 2, 8, -1).unwrap();
+        capture.write_event(first, 0, 0, 0).unwrap();
+        let later = first + Duration::from_micros(2500);
+        capture.write_event(later, 2, 8, -1).unwrap();
+        let out = String::from_utf8(capture.out).unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                r#"{"time_us":0,"type":2,"code":8,"value":-1}"#,
+                r#"{"time_us":0,"type":0,"code":0,"value":0}"#,
+                r#"{"time_us":2500,"type":2,"code":8,"value":-1}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::command_notify;
+use crate::screenshot::{argv, in_path, Session};
+use mouse_assist_core::{Action, ClipboardRule, Selection, Step};
+use regex::Regex;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tracing::{debug, error, warn};
+
+/// How long reading the selection may take. Its owner has to answer the request, and a hung
+/// one would otherwise hold up the action queue.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The command that reads text on stdin and takes over `selection` with it. Owning an X11
+/// selection means answering every paste for as long as it's held, so rather than keep that
+/// in the daemon this leaves it to a helper that stays in the background.
+fn copy_command(
+    session: Session,
+    selection: Selection,
+    installed: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let primary = selection == Selection::Primary;
+    match session {
+        Session::Wayland if installed("wl-copy") => Ok(if primary {
+            argv(&["wl-copy", "--primary"])
+        } else {
+            argv(&["wl-copy"])
+        }),
+        Session::Wayland => Err("no clipboard tool found (install wl-clipboard)".into()),
+        Session::X11 if installed("xclip") => Ok(argv(&[
+            "xclip",
+            "-selection",
+            if primary { "primary" } else { "clipboard" },
+        ])),
+        Session::X11 if installed("xsel") => Ok(argv(&[
+            "xsel",
+            if primary { "--primary" } else { "--clipboard" },
+            "--input",
+        ])),
+        Session::X11 => Err("no clipboard tool found (install xclip or xsel)".into()),
+    }
+}
+
+/// The command that prints `selection`'s text on stdout.
+fn paste_command(
+    session: Session,
+    selection: Selection,
+    installed: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let primary = selection == Selection::Primary;
+    match session {
+        Session::Wayland if installed("wl-paste") => Ok(if primary {
+            argv(&["wl-paste", "--no-newline", "--primary"])
+        } else {
+            argv(&["wl-paste", "--no-newline"])
+        }),
+        Session::Wayland => Err("no clipboard tool found (install wl-clipboard)".into()),
+        Session::X11 if installed("xclip") => Ok(argv(&[
+            "xclip",
+            "-out",
+            "-selection",
+            if primary { "primary" } else { "clipboard" },
+        ])),
+        Session::X11 if installed("xsel") => Ok(argv(&[
+            "xsel",
+            if primary { "--primary" } else { "--clipboard" },
+            "--output",
+        ])),
+        Session::X11 => Err("no clipboard tool found (install xclip or xsel)".into()),
+    }
+}
+
+/// Reads `selection`'s text through the clipboard tool. An empty selection reads as "".
+fn get(selection: Selection) -> Result<String, String> {
+    let session = Session::detect().ok_or("neither WAYLAND_DISPLAY nor DISPLAY is set")?;
+    let command = paste_command(session, selection, in_path)?;
+    let outcome = command_notify::capture(&command, PASTE_TIMEOUT)
+        .map_err(|err| format!("{}: {err}", command[0]))?;
+    match outcome.status {
+        Some(status) if status.success() => Ok(outcome.stdout),
+        // Both tools fail when nothing owns the selection (or it holds no text).
+        Some(_) => Ok(String::new()),
+        None => Err(format!(
+            "{} didn't answer within {}s",
+            command[0],
+            PASTE_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// Reads the selection for a `clipboard_route` action and returns the action to run for it,
+/// with the placeholders filled in; `None` when there's nothing to run.
+pub(crate) fn route(
+    selection: Selection,
+    rules: &[ClipboardRule],
+    default: Option<&Action>,
+) -> Option<Action> {
+    let text = match get(selection) {
+        Ok(text) => text,
+        Err(err) => {
+            warn!("clipboard_route: {err}");
+            return None;
+        }
+    };
+    let action = pick(rules, default, &text)?;
+    Some(fill(action, &text))
+}
+
+/// The first rule's action whose pattern matches `text`, else `default`. Patterns that don't
+/// compile are skipped (`validate` reports them).
+fn pick<'a>(
+    rules: &'a [ClipboardRule],
+    default: Option<&'a Action>,
+    text: &str,
+) -> Option<&'a Action> {
+    for rule in rules {
+        match Regex::new(&rule.pattern) {
+            Ok(pattern) if pattern.is_match(text) => {
+                debug!("clipboard_route: {:?} matched", rule.pattern);
+                return Some(&rule.action);
+            }
+            Ok(_) => {}
+            Err(err) => error!("clipboard_route pattern {:?}: {err}", rule.pattern),
+        }
+    }
+    default
+}
+
+/// `action` with `{clipboard}` and `{clipboard_url}` replaced in its strings.
+fn fill(action: &Action, text: &str) -> Action {
+    let encoded = percent_encode(text);
+    let fill_str = |s: &String| {
+        s.replace("{clipboard_url}", &encoded)
+            .replace("{clipboard}", text)
+    };
+    let fill_all = |argv: &Vec<String>| argv.iter().map(fill_str).collect();
+    match action {
+        Action::Command { argv } => Action::Command {
+            argv: fill_all(argv),
+        },
+        Action::CommandNotify { argv } => Action::CommandNotify {
+            argv: fill_all(argv),
+        },
+        Action::LaunchOrFocus { argv, match_class } => Action::LaunchOrFocus {
+            argv: fill_all(argv),
+            match_class: match_class.clone(),
+        },
+        Action::Open { target } => Action::Open {
+            target: fill_str(target),
+        },
+        Action::SetClipboard {
+            text: set,
+            selection,
+        } => Action::SetClipboard {
+            text: fill_str(set),
+            selection: *selection,
+        },
+        Action::Webhook {
+            url,
+            method,
+            body,
+            headers,
+        } => Action::Webhook {
+            url: fill_str(url),
+            method: method.clone(),
+            body: body.as_ref().map(fill_str),
+            headers: headers.clone(),
+        },
+        Action::Parallel { actions } => Action::Parallel {
+            actions: actions.iter().map(|action| fill(action, text)).collect(),
+        },
+        Action::Sequence { steps } => Action::Sequence {
+            steps: steps
+                .iter()
+                .map(|step| match step {
+                    Step::Action(action) => Step::Action(fill(action, text)),
+                    delay => delay.clone(),
+                })
+                .collect(),
+        },
+        action => action.clone(),
+    }
+}
+
+/// Every byte but the URL-safe ones (`A-Z a-z 0-9 - _ . ~`) as `%XX`.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Hands `text` to the clipboard tool in the background.
+pub(crate) fn set(text: &str, selection: Selection) {
+    let Some(session) = Session::detect() else {
+        warn!("set_clipboard: neither WAYLAND_DISPLAY nor DISPLAY is set");
+        return;
+    };
+    let command = match copy_command(session, selection, in_path) {
+        Ok(command) => command,
+        Err(err) => {
+            warn!("set_clipboard: {err}");
+            return;
+        }
+    };
+    let text = text.to_owned();
+    let spawned = std::thread::Builder::new()
+        .name("clipboard".into())
+        .spawn(move || {
+            if let Err(err) = run(&command, &text) {
+                error!("set_clipboard failed: {err}");
+            }
+        });
+    if let Err(err) = spawned {
+        error!("failed to start clipboard thread: {err}");
+    }
+}
+
+fn run(command: &[String], text: &str) -> Result<(), String> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        // The tool forks to keep serving the selection; an inherited stdout would stay open.
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("{}: {err}", command[0]))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|err| format!("{}: {err}", command[0]))?;
+    }
+    let status = child
+        .wait()
+        .map_err(|err| format!("{}: {err}", command[0]))?;
+    if !status.success() {
+        return Err(format!("{} exited with {status}", command[0]));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_command_prefers_xclip_and_falls_back_to_xsel() {
+        let only = |tools: &'static [&'static str]| move |tool: &str| tools.contains(&tool);
+
+        assert_eq!(
+            copy_command(Session::X11, Selection::Clipboard, only(&["xclip", "xsel"])),
+            Ok(argv(&["xclip", "-selection", "clipboard"]))
+        );
+        assert_eq!(
+            copy_command(Session::X11, Selection::Primary, only(&["xsel"])),
+            Ok(argv(&["xsel", "--primary", "--input"]))
+        );
+        assert_eq!(
+            copy_command(Session::Wayland, Selection::Primary, only(&["wl-copy"])),
+            Ok(argv(&["wl-copy", "--primary"]))
+        );
+        assert!(copy_command(Session::Wayland, Selection::Clipboard, only(&["xclip"])).is_err());
+    }
+
+    #[test]
+    fn clipboard_route_picks_the_first_matching_rule_and_fills_it_in() {
+        let open = |target: &str| Action::Open {
+            target: target.into(),
+        };
+        let rules = [
+            ClipboardRule {
+                pattern: "^https?://".into(),
+                action: open("{clipboard}"),
+            },
+            ClipboardRule {
+                pattern: "(".into(),
+                action: open("never"),
+            },
+        ];
+        let search = open("https://duckduckgo.com/?q={clipboard_url}");
+
+        let url = "https://example.org/a?b=1";
+        let picked = pick(&rules, Some(&search), url).unwrap();
+        assert_eq!(fill(picked, url), open(url));
+
+        let text = "rust & regex";
+        let picked = pick(&rules, Some(&search), text).unwrap();
+        assert_eq!(
+            fill(picked, text),
+            open("https://duckduckgo.com/?q=rust%20%26%20regex")
+        );
+        assert_eq!(pick(&rules, None, text), None);
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long events are remembered. Devices are read one after another, so another device's copy
+/// of an event can be handled well after events that came later on the first one.
+const REMEMBER: Duration = Duration::from_secs(1);
+
+/// An event some device reported, by its kernel timestamp.
+struct Seen {
+    device: PathBuf,
+    event: (u16, u16, i32),
+    at: SystemTime,
+}
+
+/// Spots button and wheel events that one mouse reported through several devices, as some
+/// wireless receivers do, so the action only fires once.
+pub(crate) struct Coalescer {
+    window: Duration,
+    seen: Vec<Seen>,
+}
+
+impl Coalescer {
+    /// Events from different devices at most `window` apart count as the same; a zero `window`
+    /// never coalesces anything.
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Vec::new(),
+        }
+    }
+
+    /// Whether `ev` from `device` repeats an event another device reported within the window.
+    /// Repeats from the same device are real (a fast double click), so they always count.
+    pub(crate) fn is_duplicate(&mut self, device: &Path, ev: &evdev::InputEvent) -> bool {
+        if self.window.is_zero() || !is_action_event(ev) {
+            return false;
+        }
+        let event = (ev.event_type().0, ev.code(), ev.value());
+        let at = ev.timestamp();
+        let apart = |other: SystemTime| {
+            at.duration_since(other)
+                .or_else(|_| other.duration_since(at))
+                .unwrap_or_default()
+        };
+        self.seen.retain(|seen| apart(seen.at) <= REMEMBER);
+        let duplicate = self.seen.iter().any(|seen| {
+            seen.device != device && seen.event == event && apart(seen.at) <= self.window
+        });
+        if !duplicate {
+            self.seen.push(Seen {
+                device: device.to_owned(),
+                event,
+                at,
+            });
+        }
+        duplicate
+    }
+}
+
+/// Button presses and releases and wheel notches, the events that can fire actions.
+fn is_action_event(ev: &evdev::InputEvent) -> bool {
+    match ev.destructure() {
+        evdev::EventSummary::Key(_, _, value) => matches!(value, 0 | 1),
+        evdev::EventSummary::RelativeAxis(_, axis, _) => matches!(
+            axis,
+            evdev::RelativeAxisCode::REL_WHEEL | evdev::RelativeAxisCode::REL_HWHEEL
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `BTN_SIDE` event stamped `at_ms` into some second.
+    fn side(value: i32, at_ms: i64) -> evdev::InputEvent {
+        evdev::InputEvent::from(libc::input_event {
+            time: libc::timeval {
+                tv_sec: 1_700_000_000,
+                tv_usec: at_ms * 1000,
+            },
+            type_: evdev::EventType::KEY.0,
+            code: evdev::KeyCode::BTN_SIDE.code(),
+            value,
+        })
+    }
+
+    #[test]
+    fn copies_from_another_device_within_the_window_are_dropped() {
+        let (receiver, keyboard_node) = (
+            Path::new("/dev/input/event5"),
+            Path::new("/dev/input/event6"),
+        );
+        let mut coalescer = Coalescer::new(Duration::from_millis(5));
+
+        assert!(!coalescer.is_duplicate(receiver, &side(1, 0)));
+        assert!(coalescer.is_duplicate(keyboard_node, &side(1, 2)));
+        assert!(!coalescer.is_duplicate(receiver, &side(0, 50)));
+        assert!(coalescer.is_duplicate(keyboard_node, &side(0, 51)));
+        // The same device pressing again is a real second press.
+        assert!(!coalescer.is_duplicate(receiver, &side(1, 53)));
+        // Another device outside the window is a separate press too.
+        assert!(!coalescer.is_duplicate(keyboard_node, &side(1, 70)));
+
+        let mut off = Coalescer::new(Duration::ZERO);
+        assert!(!off.is_duplicate(receiver, &side(1, 0)));
+        assert!(!off.is_duplicate(keyboard_node, &side(1, 0)));
+    }
+}
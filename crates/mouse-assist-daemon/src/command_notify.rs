@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+/// How long the command may run before it's killed.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Output beyond this many lines, or characters, is cut off.
+const MAX_LINES: usize = 10;
+const MAX_CHARS: usize = 400;
+
+const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+/// What became of the command.
+pub(crate) struct Outcome {
+    /// `None` when it was killed for running past the timeout.
+    pub(crate) status: Option<ExitStatus>,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+}
+
+/// Runs a `command_notify` action: runs `argv`, waits for it, then shows its output (or why
+/// it failed) as a notification.
+pub(crate) fn run(argv: &[String]) {
+    let outcome = match capture(argv, TIMEOUT) {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            error!("failed to execute {argv:?}: {err}");
+            return;
+        }
+    };
+    let (summary, body) = message(argv, &outcome);
+    info!("command_notify {argv:?}: {summary}");
+    if let Err(err) = notify(&summary, &body) {
+        error!("failed to show notification for {argv:?}: {err}");
+    }
+}
+
+/// Runs `argv` with its output piped, killing it after `timeout`.
+pub(crate) fn capture(argv: &[String], timeout: Duration) -> std::io::Result<Outcome> {
+    let Some((program, args)) = argv.split_first() else {
+        return Err(std::io::Error::other("empty argv"));
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Both pipes are drained as the command runs, so it can't stall on a full one.
+    let (tx, rx) = mpsc::channel();
+    let readers: [Option<Box<dyn Read + Send>>; 2] = [
+        child.stdout.take().map(|out| Box::new(out) as _),
+        child.stderr.take().map(|err| Box::new(err) as _),
+    ];
+    for (index, reader) in readers.into_iter().enumerate() {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut reader) = reader {
+                let _ = reader.read_to_end(&mut bytes);
+            }
+            let _ = tx.send((index, bytes));
+        });
+    }
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    // Something the command left running in the background can keep a pipe open; whatever was
+    // read by the deadline is all there is.
+    let mut output = [Vec::new(), Vec::new()];
+    for _ in 0..2 {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(wait.max(Duration::from_millis(50))) {
+            Ok((index, bytes)) => output[index] = bytes,
+            Err(_) => break,
+        }
+    }
+    let [stdout, stderr] = output.map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+    Ok(Outcome {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// The notification's summary and body.
+fn message(argv: &[String], outcome: &Outcome) -> (String, String) {
+    let command = argv.join(" ");
+    let failure = match outcome.status {
+        Some(status) if status.success() => {
+            let stdout = outcome.stdout.trim();
+            let body = if stdout.is_empty() {
+                "(no output)"
+            } else {
+                stdout
+            };
+            return (command, truncate(body));
+        }
+        Some(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => format!("exited with status {code}"),
+            (None, Some(signal)) => format!("killed by signal {signal}"),
+            (None, None) => "failed".to_owned(),
+        },
+        None => format!("timed out after {}s", TIMEOUT.as_secs()),
+    };
+    let stderr = outcome.stderr.trim();
+    let body = if stderr.is_empty() {
+        failure.clone()
+    } else {
+        truncate(stderr)
+    };
+    (format!("{command}: {failure}"), body)
+}
+
+/// At most [`MAX_LINES`] lines and [`MAX_CHARS`] characters, with `…` where it was cut.
+fn truncate(text: &str) -> String {
+    let mut lines = text.lines();
+    let mut out = lines
+        .by_ref()
+        .take(MAX_LINES)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut cut = lines.next().is_some();
+    if let Some((index, _)) = out.char_indices().nth(MAX_CHARS) {
+        out.truncate(index);
+        cut = true;
+    }
+    if cut {
+        out.push('…');
+    }
+    out
+}
+
+/// Shows a notification through the session's notification server. Bodies may be read as
+/// markup, so `&`, `<` and `>` are escaped.
+pub(crate) fn notify(summary: &str, body: &str) -> zbus::Result<()> {
+    let body = body
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let hints: HashMap<&str, Value> = HashMap::new();
+    Connection::session()?.call_method(
+        Some(NOTIFICATIONS),
+        NOTIFICATIONS_PATH,
+        Some(NOTIFICATIONS),
+        "Notify",
+        &(
+            "mouse-assist",
+            0u32,
+            "",
+            summary,
+            body,
+            Vec::<&str>::new(),
+            hints,
+            -1i32,
+        ),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".into(), "-c".into(), script.into()]
+    }
+
+    #[test]
+    fn output_or_failure_becomes_the_message() {
+        let argv = sh("echo 192.168.1.5");
+        let outcome = capture(&argv, TIMEOUT).unwrap();
+        assert_eq!(
+            message(&argv, &outcome),
+            (
+                "sh -c echo 192.168.1.5".to_owned(),
+                "192.168.1.5".to_owned()
+            )
+        );
+
+        let argv = sh("echo no battery >&2; exit 3");
+        let outcome = capture(&argv, TIMEOUT).unwrap();
+        assert_eq!(
+            message(&argv, &outcome).1,
+            "no battery",
+            "stderr is shown on failure"
+        );
+        assert!(message(&argv, &outcome)
+            .0
+            .ends_with(": exited with status 3"));
+
+        let argv = sh("sleep 5");
+        let outcome = capture(&argv, Duration::from_millis(50)).unwrap();
+        assert!(outcome.status.is_none());
+
+        let long = (1..=12).map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            truncate(&long.join("\n")),
+            format!("{}…", long[..10].join("\n"))
+        );
+        assert_eq!(truncate(&"x".repeat(500)).chars().count(), MAX_CHARS + 1);
+    }
+}
@@ -0,0 +1,182 @@
+use mouse_assist_core::DesktopSetting;
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::Mutex;
+use tracing::{error, info};
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+/// KWin's global shortcuts, which include one for toggling Night Color.
+const KGLOBALACCEL: &str = "org.kde.kglobalaccel";
+const KWIN_COMPONENT_PATH: &str = "/component/kwin";
+const KGLOBALACCEL_COMPONENT: &str = "org.kde.kglobalaccel.Component";
+
+/// Our do-not-disturb inhibition, if one is in effect. The notification server drops it when
+/// the connection that asked for it closes, so the connection is kept along with the cookie.
+static INHIBITION: Mutex<Option<(Connection, u32)>> = Mutex::new(None);
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum SettingError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Dbus(#[from] zbus::Error),
+    #[error("gsettings failed: {0}")]
+    Gsettings(String),
+    #[error("{setting} isn't supported on {desktop} (only GNOME and KDE)")]
+    Unsupported {
+        setting: &'static str,
+        desktop: String,
+    },
+}
+
+/// The desktop, from `XDG_CURRENT_DESKTOP`.
+#[derive(Debug, PartialEq, Eq)]
+enum Desktop {
+    Gnome,
+    Kde,
+    Other(String),
+}
+
+/// `XDG_CURRENT_DESKTOP` is a `:`-separated list, e.g. `ubuntu:GNOME`.
+fn detect(current_desktop: Option<&str>) -> Desktop {
+    let names = current_desktop.unwrap_or_default();
+    let has = |wanted: &str| {
+        names
+            .split(':')
+            .any(|name| name.eq_ignore_ascii_case(wanted))
+    };
+    if has("GNOME") {
+        Desktop::Gnome
+    } else if has("KDE") {
+        Desktop::Kde
+    } else if names.is_empty() {
+        Desktop::Other("an unknown desktop (XDG_CURRENT_DESKTOP isn't set)".into())
+    } else {
+        Desktop::Other(names.to_owned())
+    }
+}
+
+/// Runs a `toggle_setting` action on the desktop `XDG_CURRENT_DESKTOP` names, logging the
+/// setting's new state when the desktop tells.
+pub(crate) fn toggle(setting: DesktopSetting) {
+    let desktop = detect(std::env::var("XDG_CURRENT_DESKTOP").ok().as_deref());
+    match toggle_on(&desktop, setting) {
+        Ok(Some(on)) => info!(
+            "{} turned {}",
+            setting.name(),
+            if on { "on" } else { "off" }
+        ),
+        Ok(None) => info!("{} toggled", setting.name()),
+        Err(err) => error!("toggle_setting {} failed: {err}", setting.name()),
+    }
+}
+
+/// Returns the setting's new state, when the desktop tells.
+fn toggle_on(desktop: &Desktop, setting: DesktopSetting) -> Result<Option<bool>, SettingError> {
+    match (desktop, setting) {
+        // Do-not-disturb is "don't show banners".
+        (Desktop::Gnome, DesktopSetting::DoNotDisturb) => {
+            flip_gsetting("org.gnome.desktop.notifications", "show-banners")
+                .map(|shown| Some(!shown))
+        }
+        (Desktop::Gnome, DesktopSetting::NightLight) => flip_gsetting(
+            "org.gnome.settings-daemon.plugins.color",
+            "night-light-enabled",
+        )
+        .map(Some),
+        (Desktop::Kde, DesktopSetting::NightLight) => {
+            Connection::session()?.call_method(
+                Some(KGLOBALACCEL),
+                KWIN_COMPONENT_PATH,
+                Some(KGLOBALACCEL_COMPONENT),
+                "invokeShortcut",
+                &("Toggle Night Color",),
+            )?;
+            Ok(None)
+        }
+        // KDE's notification server, among others, takes inhibitions.
+        (_, DesktopSetting::DoNotDisturb) => toggle_inhibition().map(Some),
+        (Desktop::Other(desktop), setting) => Err(SettingError::Unsupported {
+            setting: setting.name(),
+            desktop: desktop.clone(),
+        }),
+    }
+}
+
+/// Sets a boolean GSettings key to the opposite of what it is; returns the new value.
+fn flip_gsetting(schema: &str, key: &str) -> Result<bool, SettingError> {
+    let output = Command::new("gsettings")
+        .args(["get", schema, key])
+        .output()?;
+    let current = String::from_utf8_lossy(&output.stdout);
+    let current = match (output.status.success(), current.trim()) {
+        (true, "true") => true,
+        (true, "false") => false,
+        _ => {
+            return Err(SettingError::Gsettings(
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ))
+        }
+    };
+    let output = Command::new("gsettings")
+        .args(["set", schema, key, if current { "false" } else { "true" }])
+        .output()?;
+    if !output.status.success() {
+        return Err(SettingError::Gsettings(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    Ok(!current)
+}
+
+/// Inhibits notifications, or lifts the inhibition this daemon made; returns whether
+/// notifications are now inhibited. Inhibitions made elsewhere (e.g. the panel's switch) are
+/// left alone.
+fn toggle_inhibition() -> Result<bool, SettingError> {
+    let mut inhibition = INHIBITION.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some((conn, cookie)) = inhibition.take() {
+        conn.call_method(
+            Some(NOTIFICATIONS),
+            NOTIFICATIONS_PATH,
+            Some(NOTIFICATIONS),
+            "UnInhibit",
+            &(cookie,),
+        )?;
+        return Ok(false);
+    }
+    let conn = Connection::session()?;
+    let hints: HashMap<&str, Value> = HashMap::new();
+    let cookie: u32 = conn
+        .call_method(
+            Some(NOTIFICATIONS),
+            NOTIFICATIONS_PATH,
+            Some(NOTIFICATIONS),
+            "Inhibit",
+            &("mouse-assist", "toggle_setting", hints),
+        )?
+        .body()
+        .deserialize()?;
+    *inhibition = Some((conn, cookie));
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktops_are_told_apart_from_xdg_current_desktop() {
+        assert_eq!(detect(Some("ubuntu:GNOME")), Desktop::Gnome);
+        assert_eq!(detect(Some("KDE")), Desktop::Kde);
+        assert_eq!(detect(Some("Hyprland")), Desktop::Other("Hyprland".into()));
+        assert!(matches!(detect(None), Desktop::Other(_)));
+        let err = toggle_on(&detect(Some("sway")), DesktopSetting::NightLight).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "night_light isn't supported on sway (only GNOME and KDE)"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use evdev::{AbsoluteAxisCode, AttributeSetRef, KeyCode, RelativeAxisCode};
+use std::fmt;
+
+/// What an input device looks like from its capabilities, so that matching "every device with a
+/// bound button" doesn't pick up (and, with `--grab`, take over) a keyboard or touchpad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DeviceKind {
+    Mouse,
+    Touchpad,
+    Keyboard,
+    Other,
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Mouse => "mouse",
+            Self::Touchpad => "touchpad",
+            Self::Keyboard => "keyboard",
+            Self::Other => "other",
+        })
+    }
+}
+
+/// A device with at least this many letter keys is a keyboard, whatever else it reports.
+const KEYBOARD_LETTERS: usize = 20;
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::KEY_A,
+    KeyCode::KEY_B,
+    KeyCode::KEY_C,
+    KeyCode::KEY_D,
+    KeyCode::KEY_E,
+    KeyCode::KEY_F,
+    KeyCode::KEY_G,
+    KeyCode::KEY_H,
+    KeyCode::KEY_I,
+    KeyCode::KEY_J,
+    KeyCode::KEY_K,
+    KeyCode::KEY_L,
+    KeyCode::KEY_M,
+    KeyCode::KEY_N,
+    KeyCode::KEY_O,
+    KeyCode::KEY_P,
+    KeyCode::KEY_Q,
+    KeyCode::KEY_R,
+    KeyCode::KEY_S,
+    KeyCode::KEY_T,
+    KeyCode::KEY_U,
+    KeyCode::KEY_V,
+    KeyCode::KEY_W,
+    KeyCode::KEY_X,
+    KeyCode::KEY_Y,
+    KeyCode::KEY_Z,
+];
+
+pub(crate) fn classify_device(dev: &evdev::Device) -> DeviceKind {
+    classify(
+        dev.supported_keys(),
+        dev.supported_relative_axes(),
+        dev.supported_absolute_axes(),
+    )
+}
+
+/// Keyboards first (a letter row means typing can be grabbed), then touchpads (absolute
+/// position plus finger tools), then anything that moves a pointer, scrolls or has a left
+/// button.
+fn classify(
+    keys: Option<&AttributeSetRef<KeyCode>>,
+    rel: Option<&AttributeSetRef<RelativeAxisCode>>,
+    abs: Option<&AttributeSetRef<AbsoluteAxisCode>>,
+) -> DeviceKind {
+    let has_key = |code: KeyCode| keys.is_some_and(|keys| keys.contains(code));
+    let has_rel = |axis: RelativeAxisCode| rel.is_some_and(|rel| rel.contains(axis));
+    let has_abs = |axis: AbsoluteAxisCode| abs.is_some_and(|abs| abs.contains(axis));
+
+    let letters = LETTERS.iter().filter(|&&code| has_key(code)).count();
+    if letters >= KEYBOARD_LETTERS {
+        DeviceKind::Keyboard
+    } else if has_abs(AbsoluteAxisCode::ABS_X)
+        && has_abs(AbsoluteAxisCode::ABS_Y)
+        && has_key(KeyCode::BTN_TOOL_FINGER)
+    {
+        DeviceKind::Touchpad
+    } else if (has_rel(RelativeAxisCode::REL_X) && has_rel(RelativeAxisCode::REL_Y))
+        || has_rel(RelativeAxisCode::REL_WHEEL)
+        || has_rel(RelativeAxisCode::REL_HWHEEL)
+        || has_key(KeyCode::BTN_LEFT)
+    {
+        DeviceKind::Mouse
+    } else {
+        DeviceKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::AttributeSet;
+
+    #[test]
+    fn classify_tells_mice_from_keyboards_and_touchpads() {
+        let keys = |codes: &[KeyCode]| codes.iter().copied().collect::<AttributeSet<KeyCode>>();
+        let rel = |axes: &[RelativeAxisCode]| {
+            axes.iter()
+                .copied()
+                .collect::<AttributeSet<RelativeAxisCode>>()
+        };
+        let abs = |axes: &[AbsoluteAxisCode]| {
+            axes.iter()
+                .copied()
+                .collect::<AttributeSet<AbsoluteAxisCode>>()
+        };
+        let pointer = rel(&[RelativeAxisCode::REL_X, RelativeAxisCode::REL_Y]);
+
+        let mouse = keys(&[KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT, KeyCode::BTN_SIDE]);
+        assert_eq!(
+            classify(Some(&mouse), Some(&pointer), None),
+            DeviceKind::Mouse
+        );
+
+        // A keyboard that also reports BTN_LEFT (say, for a built-in pointing stick) is still
+        // a keyboard.
+        let mut keyboard: Vec<KeyCode> = LETTERS.to_vec();
+        keyboard.push(KeyCode::BTN_LEFT);
+        assert_eq!(
+            classify(Some(&keys(&keyboard)), Some(&pointer), None),
+            DeviceKind::Keyboard
+        );
+        // A few letters (macro keys on a gaming mouse) don't make a keyboard.
+        let mut gaming = LETTERS[..5].to_vec();
+        gaming.push(KeyCode::BTN_LEFT);
+        assert_eq!(
+            classify(Some(&keys(&gaming)), Some(&pointer), None),
+            DeviceKind::Mouse
+        );
+
+        let touchpad = keys(&[KeyCode::BTN_LEFT, KeyCode::BTN_TOOL_FINGER]);
+        let position = abs(&[AbsoluteAxisCode::ABS_X, AbsoluteAxisCode::ABS_Y]);
+        assert_eq!(
+            classify(Some(&touchpad), None, Some(&position)),
+            DeviceKind::Touchpad
+        );
+
+        let power_button = keys(&[KeyCode::KEY_POWER]);
+        assert_eq!(classify(Some(&power_button), None, None), DeviceKind::Other);
+    }
+}
@@ -0,0 +1,1645 @@
+use crate::profile_state;
+use crate::status::Status;
+use crate::worker::ActionQueue;
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use mouse_assist_core::{
+    Action, Binding, Config, MouseButton, RepeatSpec, TiltGesture, Trigger,
+    DEFAULT_DOUBLE_PRESS_MS, DEFAULT_MIDDLE_CLICK_SCROLL_GUARD_MS, DEFAULT_TILT_HOLD_MS,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Minutes past midnight in the system's local time zone; `None` if that can't be worked out.
+fn local_minute_of_day() -> Option<u16> {
+    // SAFETY: `time` accepts a null pointer, and `localtime_r` only writes to `tm`, which is
+    // read only after it reports success.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+        if libc::localtime_r(&now, tm.as_mut_ptr()).is_null() {
+            return None;
+        }
+        let tm = tm.assume_init();
+        u16::try_from(tm.tm_hour * 60 + tm.tm_min).ok()
+    }
+}
+
+/// A physical input as reported by a backend, before it's matched against a binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Input {
+    /// An evdev key/button code.
+    Key(u16),
+    /// Something the backend already resolved to a config button (evdev wheel tilt).
+    Button(MouseButton),
+    /// An X11 core button number.
+    X11Button(u32),
+}
+
+impl Input {
+    fn matches(self, button: MouseButton) -> bool {
+        match self {
+            Self::Key(code) => button.linux_key_code() == Some(code),
+            Self::Button(b) => button == b,
+            Self::X11Button(n) => button.x11_button_number() == Some(n),
+        }
+    }
+
+    fn is_wheel_notch(self) -> bool {
+        self.matches(MouseButton::WheelUp) || self.matches(MouseButton::WheelDown)
+    }
+}
+
+/// Which of a button's [`Trigger`]s a press turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PressKind {
+    Single,
+    Double,
+    Long,
+}
+
+impl PressKind {
+    /// Whether `binding` fires for this kind of press. A plain binding's `double_click` makes it
+    /// answer double presses too.
+    fn fires(self, binding: &Binding) -> bool {
+        match (binding.trigger, self) {
+            (Trigger::Press, Self::Single)
+            | (Trigger::DoublePress { .. }, Self::Double)
+            | (Trigger::LongPress { .. }, Self::Long) => true,
+            (Trigger::Press, Self::Double) => binding.double_click.is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// The binding for `input` whose `tilt` is `tilt` and that fires for a `kind` press, skipping
+/// chords whose `modifier_button` isn't among `down`. One whose `window_class` matches the
+/// focused window (`WM_CLASS`, from `focused`, which is only asked when needed) wins over those
+/// without.
+fn find_binding<'a>(
+    bindings: &'a [Binding],
+    input: Input,
+    tilt: Option<TiltGesture>,
+    kind: PressKind,
+    down: &[Input],
+    focused: impl FnOnce() -> Option<Vec<String>>,
+) -> Option<&'a Binding> {
+    let applies = |b: &Binding| {
+        input.matches(b.button)
+            && b.tilt == tilt
+            && kind.fires(b)
+            && b.modifier_button
+                .is_none_or(|modifier| down.iter().any(|held| held.matches(modifier)))
+    };
+    let pick = |filter: &dyn Fn(&Binding) -> bool| {
+        let mut candidates = bindings.iter().filter(|b| applies(b) && filter(b));
+        // The X11 backend has always let the last binding for a button win.
+        if matches!(input, Input::X11Button(_)) {
+            candidates.next_back()
+        } else {
+            candidates.next()
+        }
+    };
+    if bindings
+        .iter()
+        .any(|b| !b.window_class.is_empty() && applies(b))
+    {
+        if let Some(wm_class) = focused() {
+            let wm_class: Vec<&str> = wm_class.iter().map(String::as_str).collect();
+            debug!("focused window class: {wm_class:?}");
+            if let Some(binding) = pick(&|b| b.matches_window(&wm_class)) {
+                return Some(binding);
+            }
+        }
+    }
+    pick(&|b| b.window_class.is_empty())
+}
+
+/// Reports the focused window's `WM_CLASS` for bindings with `window_class`.
+pub(crate) type FocusQuery = Box<dyn Fn() -> Option<Vec<String>>>;
+
+/// Re-queues an action on a timer until dropped.
+struct Repeater {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Repeater {
+    fn start(
+        queue: ActionQueue,
+        action: Action,
+        button: MouseButton,
+        spec: RepeatSpec,
+    ) -> std::io::Result<Self> {
+        let (stop, stopped) = crossbeam_channel::bounded::<()>(0);
+        let thread = std::thread::Builder::new()
+            .name("repeat".into())
+            .spawn(move || {
+                let mut wait = Duration::from_millis(spec.delay_ms);
+                // Dropping `stop` disconnects the channel, which ends the wait immediately.
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                    queue.dispatch_from(button, &action);
+                    wait = Duration::from_millis(spec.interval_ms);
+                }
+            })?;
+        Ok(Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Queues a tilt flick's action once no tilt event has arrived for a while.
+struct FlickTimer {
+    /// A new deadline (another event arrived), or `None` to cancel.
+    extend: Sender<Option<Instant>>,
+}
+
+impl FlickTimer {
+    fn start(
+        queue: ActionQueue,
+        action: Action,
+        button: MouseButton,
+        deadline: Instant,
+    ) -> std::io::Result<Self> {
+        let (extend, extended) = crossbeam_channel::unbounded::<Option<Instant>>();
+        std::thread::Builder::new()
+            .name("tilt-flick".into())
+            .spawn(move || {
+                let mut deadline = deadline;
+                loop {
+                    match extended.recv_deadline(deadline) {
+                        Ok(Some(later)) => deadline = later,
+                        Ok(None) => return,
+                        // Dropped with its run, which only happens once the events have stopped.
+                        Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                queue.dispatch_from(button, &action);
+            })?;
+        Ok(Self { extend })
+    }
+
+    fn extend(&self, deadline: Instant) {
+        let _ = self.extend.send(Some(deadline));
+    }
+
+    fn cancel(&self) {
+        let _ = self.extend.send(None);
+    }
+}
+
+/// Queues an action after a delay, unless it's cancelled first.
+struct PressTimer {
+    /// Set by whichever comes first, the timer or [`Self::cancel`].
+    claimed: Arc<AtomicBool>,
+}
+
+impl PressTimer {
+    fn start(
+        queue: ActionQueue,
+        action: Action,
+        button: MouseButton,
+        delay: Duration,
+    ) -> std::io::Result<Self> {
+        let claimed = Arc::new(AtomicBool::new(false));
+        let fire = Arc::clone(&claimed);
+        std::thread::Builder::new()
+            .name("press-timer".into())
+            .spawn(move || {
+                std::thread::sleep(delay);
+                if !fire.swap(true, Ordering::AcqRel) {
+                    queue.dispatch_from(button, &action);
+                }
+            })?;
+        Ok(Self { claimed })
+    }
+
+    /// Stops the action from being queued; `false` if it already has been.
+    fn cancel(&self) -> bool {
+        !self.claimed.swap(true, Ordering::AcqRel)
+    }
+}
+
+impl Drop for Repeater {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Turns button presses and releases into queued actions, tracking held profiles.
+pub(crate) struct Dispatcher {
+    config: Config,
+    queue: ActionQueue,
+    /// The profile chosen with [`Action::SwitchProfile`]; `None` is the top-level bindings.
+    active_profile: Option<String>,
+    /// What was active before [`Action::ToggleProfile`] latched `active_profile`, to go back to
+    /// when it's toggled off.
+    toggled_from: Option<Option<String>>,
+    /// The profile whose `device` matches the device being read, standing in for the top-level
+    /// bindings; see [`Self::set_device`].
+    device_profile: Option<String>,
+    /// The profile each device seen so far uses, by path.
+    device_profiles: HashMap<PathBuf, Option<String>>,
+    /// Profiles switched to by [`Action::HoldProfile`], innermost last, with the input holding
+    /// each one.
+    held_profiles: Vec<(Input, String)>,
+    /// Keys pressed by [`Action::Hold`] bindings whose button is still down, with the input
+    /// holding them. A key stays down while any of them holds it.
+    held_keys: Vec<(Input, Vec<String>)>,
+    /// Bindings with `repeat` whose button is still down.
+    repeating: Vec<(Input, Repeater)>,
+    /// Wheel tilts with `repeat` that are still producing events.
+    tilting: Vec<(Input, TiltRepeat)>,
+    /// Wheel tilts with `tilt` bindings that are still producing events.
+    tilt_runs: Vec<(Input, TiltRun)>,
+    /// Buttons with `double_press` or `long_press` bindings whose last press is still being
+    /// told apart.
+    press_runs: Vec<(Input, PressRun)>,
+    /// Inputs pressed and not yet released. Some devices report a hold as a stream of presses;
+    /// only the first one acts.
+    down: Vec<Input>,
+    /// Held buttons that some binding uses as its `modifier_button`, and whether a chord has
+    /// fired while they were held. Their own action waits for the release and is skipped if
+    /// one has.
+    chording: Vec<(Input, bool)>,
+    /// Told about every profile change.
+    status: Option<Arc<Status>>,
+    /// Where `window_class` bindings learn the focused window; without it they never fire.
+    focus: Option<FocusQuery>,
+    /// When the wheel last turned, for `middle_click_scroll_guard_ms`.
+    last_wheel_notch: Option<Instant>,
+}
+
+/// A binding's action with `per_profile` resolved, its repeat, and its button.
+type Resolved = (Action, Option<RepeatSpec>, MouseButton);
+
+/// A tilt counts as released once no event has arrived for this long.
+const TILT_IDLE: Duration = Duration::from_millis(150);
+
+/// Repeat state for an input without releases, driven by its own stream of events.
+struct TiltRepeat {
+    last_event: Instant,
+    next_fire: Instant,
+    /// [`TILT_IDLE`] plus the device's latency compensation.
+    idle: Duration,
+}
+
+/// A press of a button with `double_press` or `long_press` bindings, being told apart.
+enum PressRun {
+    /// The button is down. `long` fires the `long_press` binding unless the button comes up
+    /// first; `second` means this is a double press, which has already fired.
+    Down {
+        long: Option<PressTimer>,
+        second: bool,
+    },
+    /// Let go after a short press, waiting until `until` for a second one. `single` fires the
+    /// plain binding if none comes.
+    Released {
+        until: Instant,
+        single: Option<PressTimer>,
+    },
+}
+
+impl PressRun {
+    fn cancel(&self) {
+        let (Self::Down {
+            long: Some(timer), ..
+        }
+        | Self::Released {
+            single: Some(timer),
+            ..
+        }) = self
+        else {
+            return;
+        };
+        timer.cancel();
+    }
+}
+
+/// A wheel tilt being told apart as a flick or a hold, for bindings with `tilt`.
+struct TiltRun {
+    started: Instant,
+    last_event: Instant,
+    /// [`TILT_IDLE`] plus the device's latency compensation.
+    idle: Duration,
+    /// Whether it has lasted `tilt_hold_ms`, so it's a hold.
+    held: bool,
+    /// Fires the flick binding when the events stop, unless the tilt turns into a hold first.
+    flick: Option<FlickTimer>,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(config: Config, queue: ActionQueue) -> Self {
+        let active_profile = if config.remember_profile {
+            profile_state::load().filter(|name| {
+                let exists = config.profile(name).is_some();
+                if exists {
+                    info!("restored profile {name:?}");
+                } else {
+                    warn!("remembered profile {name:?} no longer exists; using the top-level bindings");
+                }
+                exists
+            })
+        } else {
+            None
+        };
+        Self {
+            config,
+            queue,
+            active_profile,
+            toggled_from: None,
+            device_profile: None,
+            device_profiles: HashMap::new(),
+            held_profiles: Vec::new(),
+            held_keys: Vec::new(),
+            repeating: Vec::new(),
+            tilting: Vec::new(),
+            tilt_runs: Vec::new(),
+            press_runs: Vec::new(),
+            down: Vec::new(),
+            chording: Vec::new(),
+            status: None,
+            focus: None,
+            last_wheel_notch: None,
+        }
+    }
+
+    /// Lets bindings with `window_class` fire, looking up the focused window with `focus`.
+    pub(crate) fn with_focus(mut self, focus: FocusQuery) -> Self {
+        self.focus = Some(focus);
+        self
+    }
+
+    /// Keeps `status` up to date with the profile in effect.
+    pub(crate) fn with_status(mut self, status: Arc<Status>) -> Self {
+        self.status = Some(status);
+        self.publish_profile();
+        self
+    }
+
+    /// Switches to a reloaded `config`, whose actions go to `queue` from now on. Held buttons
+    /// are let go first; the profile switched to stays in effect if the new config still has
+    /// it.
+    pub(crate) fn reload(&mut self, config: Config, queue: ActionQueue) {
+        self.release_all();
+        if let Some(name) = &self.active_profile {
+            if config.profile(name).is_none() {
+                warn!("profile {name:?} is gone from the reloaded config; using the top-level bindings");
+                self.active_profile = None;
+                self.toggled_from = None;
+            }
+        }
+        if let Some(Some(name)) = &self.toggled_from {
+            if config.profile(name).is_none() {
+                self.toggled_from = Some(None);
+            }
+        }
+        self.device_profile = None;
+        self.device_profiles.clear();
+        self.config = config;
+        self.queue = queue;
+        self.publish_profile();
+    }
+
+    /// Says which device the inputs that follow come from: the device at `path` called `name`.
+    /// Until a profile is switched to or held, they use the bindings of the first profile whose
+    /// `device` matches it, or the top-level ones when none does.
+    pub(crate) fn set_device(&mut self, path: &Path, name: &str) {
+        if !self.device_profiles.contains_key(path) {
+            let profile = self
+                .config
+                .device_profile(path, name)
+                .map(|p| p.name.clone());
+            if let Some(profile) = &profile {
+                info!("{} ({name}) uses profile {profile:?}", path.display());
+            }
+            self.device_profiles.insert(path.to_path_buf(), profile);
+        }
+        self.device_profile = self.device_profiles[path].clone();
+    }
+
+    /// The config in use, which changes on [`Self::reload`].
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Queues the config's `startup_action`, if any, as if a binding in the current profile had
+    /// fired it.
+    pub(crate) fn run_startup_action(&self) {
+        let Some(action) = &self.config.startup_action else {
+            return;
+        };
+        match action.for_profile(self.current_profile()) {
+            Some(action) => {
+                info!("running startup action ({})", action.kind());
+                self.queue.dispatch(&action);
+            }
+            None => debug!(
+                "startup per_profile has nothing for {:?}",
+                self.current_profile()
+            ),
+        }
+    }
+
+    /// Reports the profile switched to or held; device profiles come and go with every event,
+    /// so they're left out.
+    fn publish_profile(&self) {
+        if let Some(status) = &self.status {
+            status.set_profile(self.switched_profile());
+        }
+    }
+
+    /// The profile held or switched to: a held profile wins over the switched-to one.
+    fn switched_profile(&self) -> Option<&str> {
+        let held = self.held_profiles.last().map(|(_, name)| name);
+        held.or(self.active_profile.as_ref()).map(String::as_str)
+    }
+
+    /// The profile in effect: one held or switched to, else the device's own.
+    fn current_profile(&self) -> Option<&str> {
+        self.switched_profile().or(self.device_profile.as_deref())
+    }
+
+    fn active_bindings(&self) -> &[Binding] {
+        match self.current_profile() {
+            Some(name) => self
+                .config
+                .profile(name)
+                .map_or(&[][..], |p| p.bindings.as_slice()),
+            None => &self.config.bindings,
+        }
+    }
+
+    fn switch_profile(&mut self, name: Option<String>) {
+        match &name {
+            Some(name) => info!("switched to profile {name:?}"),
+            None => info!("switched to the top-level bindings"),
+        }
+        if self.config.remember_profile {
+            profile_state::save(name.as_deref());
+        }
+        self.active_profile = name;
+        self.toggled_from = None;
+        self.publish_profile();
+    }
+
+    fn toggle_profile(&mut self, name: String) {
+        if self.active_profile.as_ref() == Some(&name) {
+            info!("toggled profile {name:?} off");
+            let previous = self.toggled_from.take().flatten();
+            self.switch_profile(previous);
+        } else {
+            info!("toggled profile {name:?} on");
+            let previous = self.active_profile.clone();
+            self.switch_profile(Some(name));
+            self.toggled_from = Some(previous);
+        }
+    }
+
+    fn lookup(&self, input: Input) -> Option<&Binding> {
+        self.lookup_tilt(input, None)
+    }
+
+    /// [`Self::lookup`] for the bindings whose `tilt` is `tilt`.
+    fn lookup_tilt(&self, input: Input, tilt: Option<TiltGesture>) -> Option<&Binding> {
+        self.lookup_kind(input, tilt, PressKind::Single)
+    }
+
+    /// [`Self::lookup_tilt`] for the bindings that fire for a `kind` press.
+    fn lookup_kind(
+        &self,
+        input: Input,
+        tilt: Option<TiltGesture>,
+        kind: PressKind,
+    ) -> Option<&Binding> {
+        let focused = || self.focus.as_ref().and_then(|focus| focus());
+        let find = |bindings| find_binding(bindings, input, tilt, kind, &self.down, focused);
+        find(self.active_bindings()).or_else(|| {
+            // A top-level `per_profile` binding also applies in profiles that leave its button
+            // unbound; that's what it's for. So does `toggle_profile`, to toggle back off.
+            self.current_profile()?;
+            find(&self.config.bindings).filter(|b| {
+                matches!(
+                    b.action,
+                    Action::PerProfile { .. } | Action::ToggleProfile { .. }
+                )
+            })
+        })
+    }
+
+    /// The bound action for `input` with `per_profile` resolved, the binding's repeat, and the
+    /// button it's bound to.
+    fn resolve(&self, input: Input) -> Option<Resolved> {
+        self.resolve_tilt(input, None)
+    }
+
+    /// [`Self::resolve`] for the bindings whose `tilt` is `tilt`.
+    fn resolve_tilt(&self, input: Input, tilt: Option<TiltGesture>) -> Option<Resolved> {
+        self.resolve_kind(input, tilt, PressKind::Single)
+    }
+
+    /// [`Self::resolve_tilt`] for the bindings that fire for a `kind` press; a `double_click`
+    /// stands in for its binding's action.
+    fn resolve_kind(
+        &self,
+        input: Input,
+        tilt: Option<TiltGesture>,
+        kind: PressKind,
+    ) -> Option<Resolved> {
+        let binding = self.lookup_kind(input, tilt, kind)?;
+        if binding.active_hours.is_some() {
+            if let Some(minute) = local_minute_of_day() {
+                if !binding.is_active_at(minute) {
+                    debug!(
+                        "{} is outside its active_hours; ignoring",
+                        binding.button.toml_name()
+                    );
+                    return None;
+                }
+            }
+        }
+        let action = match (kind, &binding.double_click) {
+            (PressKind::Double, Some(double_click)) if binding.trigger.is_press() => double_click,
+            _ => &binding.action,
+        };
+        match action.for_profile(self.current_profile()) {
+            Some(action) => Some((action, binding.repeat, binding.button)),
+            None => {
+                debug!(
+                    "per_profile on {} has nothing for {:?}",
+                    binding.button.toml_name(),
+                    self.current_profile()
+                );
+                None
+            }
+        }
+    }
+
+    pub(crate) fn press(&mut self, input: Input) {
+        self.press_at(input, Instant::now());
+    }
+
+    fn press_at(&mut self, input: Input, now: Instant) {
+        // X11 reports wheel notches as clicks of buttons 4 and 5.
+        if input.is_wheel_notch() {
+            self.last_wheel_notch = Some(now);
+        }
+        if self.is_scroll_jitter(input, now) {
+            debug!("ignoring a middle-button press right after scrolling");
+            return;
+        }
+        // Acting on the press edge only also means a button that is holding a profile (or
+        // repeating) stays bound to that until it's released, even if the profile binds the same
+        // button to something else.
+        if self.down.contains(&input) {
+            return;
+        }
+        // X11 reports each tilt notch as a click of button 6 or 7.
+        if self.has_tilt_gestures(input) {
+            self.tilt_gesture_at(input, Instant::now(), Duration::ZERO);
+            return;
+        }
+        self.down.push(input);
+        if self.is_modifier(input) {
+            self.chording.push((input, false));
+            return;
+        }
+        self.note_chord(input);
+        if self.has_press_gestures(input) {
+            self.press_gesture_at(input, now);
+            return;
+        }
+        let Some((action, repeat, button)) = self.resolve(input) else {
+            return;
+        };
+        self.fire_press(input, action, repeat, button);
+    }
+
+    /// Runs the action of a binding whose button has just gone down, and keeps it going
+    /// (held, repeating) until the release.
+    fn fire_press(
+        &mut self,
+        input: Input,
+        action: Action,
+        repeat: Option<RepeatSpec>,
+        button: MouseButton,
+    ) {
+        match action {
+            Action::HoldProfile { name } => {
+                info!("holding profile {name:?}");
+                self.held_profiles.push((input, name));
+                self.publish_profile();
+            }
+            Action::SwitchProfile { name } => self.switch_profile(name),
+            Action::ToggleProfile { name } => self.toggle_profile(name),
+            Action::Hold { keys } => self.hold_keys(input, keys),
+            action => {
+                self.queue.dispatch_from(button, &action);
+                if let Some(spec) = repeat {
+                    match Repeater::start(self.queue.clone(), action, button, spec) {
+                        Ok(repeater) => self.repeating.push((input, repeater)),
+                        Err(err) => warn!("failed to start auto-repeat: {err}"),
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn release(&mut self, input: Input) {
+        self.down.retain(|held| *held != input);
+        self.repeating.retain(|(held, _)| *held != input);
+        if let Some(idx) = self
+            .held_profiles
+            .iter()
+            .rposition(|(held, _)| *held == input)
+        {
+            let (_, name) = self.held_profiles.remove(idx);
+            info!("released profile {name:?}");
+            self.publish_profile();
+        }
+        self.release_keys(input);
+        if let Some(idx) = self.chording.iter().position(|(held, _)| *held == input) {
+            let (_, chorded) = self.chording.remove(idx);
+            if !chorded {
+                self.click(input);
+            }
+        }
+        self.release_gesture(input, Instant::now());
+    }
+
+    /// Presses the keys of a `hold` binding, apart from those another held binding already has
+    /// down.
+    fn hold_keys(&mut self, input: Input, keys: Vec<String>) {
+        let mut press: Vec<String> = Vec::new();
+        for key in &keys {
+            if !self.is_key_held(key) && !press.contains(key) {
+                press.push(key.clone());
+            }
+        }
+        self.held_keys.push((input, keys));
+        if !press.is_empty() {
+            self.queue.hold_keys(press, true);
+        }
+    }
+
+    /// Releases the keys `input` was holding, apart from those another held binding still
+    /// holds.
+    fn release_keys(&mut self, input: Input) {
+        let Some(idx) = self.held_keys.iter().position(|(held, _)| *held == input) else {
+            return;
+        };
+        let (_, keys) = self.held_keys.remove(idx);
+        let mut release: Vec<String> = Vec::new();
+        for key in keys {
+            if !self.is_key_held(&key) && !release.contains(&key) {
+                release.push(key);
+            }
+        }
+        if !release.is_empty() {
+            self.queue.hold_keys(release, false);
+        }
+    }
+
+    fn is_key_held(&self, key: &str) -> bool {
+        self.held_keys
+            .iter()
+            .any(|(_, keys)| keys.iter().any(|held| held == key))
+    }
+
+    /// Whether `input` is a middle-button press within `middle_click_scroll_guard_ms` of a wheel
+    /// notch, which is more likely the wheel being pushed while scrolling than a click. Its
+    /// release is harmless on its own, since the press never counted as down.
+    fn is_scroll_jitter(&self, input: Input, now: Instant) -> bool {
+        let guard = Duration::from_millis(
+            self.config
+                .middle_click_scroll_guard_ms
+                .unwrap_or(DEFAULT_MIDDLE_CLICK_SCROLL_GUARD_MS),
+        );
+        input.matches(MouseButton::BtnMiddle)
+            && self
+                .last_wheel_notch
+                .is_some_and(|at| now.saturating_duration_since(at) < guard)
+    }
+
+    /// Whether a binding in effect uses `input` as its `modifier_button`.
+    fn is_modifier(&self, input: Input) -> bool {
+        self.active_bindings()
+            .iter()
+            .filter_map(|b| b.modifier_button)
+            .any(|modifier| input.matches(modifier))
+    }
+
+    /// Remembers that a chord is about to fire, so its modifier's own action won't.
+    fn note_chord(&mut self, input: Input) {
+        let Some(modifier) = self.lookup(input).and_then(|b| b.modifier_button) else {
+            return;
+        };
+        for (held, chorded) in &mut self.chording {
+            if held.matches(modifier) {
+                *chorded = true;
+            }
+        }
+    }
+
+    /// Runs the action of a button whose binding waits for the release (a modifier button
+    /// released without chording, or a short press that can't be a double one), once and
+    /// without repeat.
+    fn click(&mut self, input: Input) {
+        let Some((action, _, button)) = self.resolve(input) else {
+            return;
+        };
+        match action {
+            Action::HoldProfile { name } => {
+                warn!("ignoring hold_profile {name:?} on a button that only acts once released");
+            }
+            Action::Hold { .. } => {
+                warn!("ignoring hold on a button that only acts once released");
+            }
+            Action::SwitchProfile { name } => self.switch_profile(name),
+            Action::ToggleProfile { name } => self.toggle_profile(name),
+            action => self.queue.dispatch_from(button, &action),
+        }
+    }
+
+    /// Forgets every held button: stops repeats, drops held profiles and releases held keys.
+    pub(crate) fn release_all(&mut self) {
+        self.repeating.clear();
+        self.held_profiles.clear();
+        while let Some(&(input, _)) = self.held_keys.first() {
+            self.release_keys(input);
+        }
+        self.tilting.clear();
+        for (_, run) in self.tilt_runs.drain(..) {
+            if let Some(flick) = run.flick {
+                flick.cancel();
+            }
+        }
+        for (_, run) in self.press_runs.drain(..) {
+            run.cancel();
+        }
+        self.down.clear();
+        self.chording.clear();
+        self.publish_profile();
+    }
+
+    /// For inputs that have no release (wheel tilt notches).
+    ///
+    /// With `repeat`, a run of events counts as one hold: the action fires on the first event,
+    /// then on events at least `delay_ms` later, then at most every `interval_ms`, until the
+    /// events pause for [`TILT_IDLE`]. Bindings with `tilt` take over the input instead; see
+    /// [`Self::tilt_gesture_at`].
+    pub(crate) fn tap(&mut self, input: Input) {
+        self.tap_delayed(input, Duration::ZERO);
+    }
+
+    /// [`Self::tap`] for a device whose events may arrive up to `latency` late, which lengthens
+    /// the pause that ends a run of events.
+    pub(crate) fn tap_delayed(&mut self, input: Input, latency: Duration) {
+        self.tap_at(input, Instant::now(), latency);
+    }
+
+    fn tap_at(&mut self, input: Input, now: Instant, latency: Duration) {
+        if input.is_wheel_notch() {
+            self.last_wheel_notch = Some(now);
+        }
+        if self.has_tilt_gestures(input) {
+            self.tilt_gesture_at(input, now, latency);
+            return;
+        }
+        self.note_chord(input);
+        let Some((action, repeat, button)) = self.resolve(input) else {
+            return;
+        };
+        let action = match action {
+            Action::HoldProfile { name } => {
+                warn!("ignoring hold_profile {name:?} on an input that can't be held");
+                return;
+            }
+            Action::Hold { .. } => {
+                warn!("ignoring hold on an input that can't be held");
+                return;
+            }
+            Action::SwitchProfile { name } => {
+                self.switch_profile(name);
+                return;
+            }
+            Action::ToggleProfile { name } => {
+                self.toggle_profile(name);
+                return;
+            }
+            action => action,
+        };
+        let Some(spec) = repeat else {
+            self.queue.dispatch_from(button, &action);
+            return;
+        };
+
+        self.tilting
+            .retain(|(_, tilt)| now.duration_since(tilt.last_event) < tilt.idle);
+        match self.tilting.iter_mut().find(|(held, _)| *held == input) {
+            Some((_, tilt)) => {
+                tilt.last_event = now;
+                if now >= tilt.next_fire {
+                    tilt.next_fire = now + Duration::from_millis(spec.interval_ms);
+                    self.queue.dispatch_from(button, &action);
+                }
+            }
+            None => {
+                self.tilting.push((
+                    input,
+                    TiltRepeat {
+                        last_event: now,
+                        next_fire: now + Duration::from_millis(spec.delay_ms),
+                        idle: TILT_IDLE + latency,
+                    },
+                ));
+                self.queue.dispatch_from(button, &action);
+            }
+        }
+    }
+
+    /// Whether a binding in effect tells plain presses of `input` from double or long ones.
+    fn has_press_gestures(&self, input: Input) -> bool {
+        self.active_bindings()
+            .iter()
+            .any(|b| input.matches(b.button) && (!b.trigger.is_press() || b.double_click.is_some()))
+    }
+
+    /// How long the `kind` binding for `input` waits: a double press's window, or how long a
+    /// long press has to be held.
+    fn press_delay(&self, input: Input, kind: PressKind) -> Option<Duration> {
+        let ms = match self.lookup_kind(input, None, kind)?.trigger {
+            Trigger::Press => DEFAULT_DOUBLE_PRESS_MS,
+            Trigger::DoublePress { within_ms } => within_ms,
+            Trigger::LongPress { after_ms } => after_ms,
+        };
+        Some(Duration::from_millis(ms))
+    }
+
+    /// A press of a button with `double_press` or `long_press` bindings. A second press soon
+    /// enough after a short one fires the double press binding on the spot; otherwise a timer
+    /// fires the long press binding if the button is still held by then.
+    fn press_gesture_at(&mut self, input: Input, now: Instant) {
+        let second = match self.press_runs.iter().position(|(held, _)| *held == input) {
+            Some(idx) => match self.press_runs.remove(idx).1 {
+                PressRun::Released { until, single } => {
+                    now < until && single.as_ref().is_none_or(PressTimer::cancel)
+                }
+                PressRun::Down { .. } => false,
+            },
+            None => false,
+        };
+        if second {
+            self.press_runs.push((
+                input,
+                PressRun::Down {
+                    long: None,
+                    second: true,
+                },
+            ));
+            if let Some((action, repeat, button)) =
+                self.resolve_kind(input, None, PressKind::Double)
+            {
+                self.fire_press(input, action, repeat, button);
+            }
+            return;
+        }
+        let long = match (
+            self.resolve_kind(input, None, PressKind::Long),
+            self.press_delay(input, PressKind::Long),
+        ) {
+            (Some((action, _, button)), Some(after)) => {
+                self.press_timer(action, button, after, "long_press")
+            }
+            _ => None,
+        };
+        self.press_runs.push((
+            input,
+            PressRun::Down {
+                long,
+                second: false,
+            },
+        ));
+    }
+
+    /// The release of a button pressed in [`Self::press_gesture_at`]. A short first press
+    /// fires the plain binding: right away without a double press binding, and otherwise once
+    /// the double press window has passed without a second press.
+    fn release_gesture(&mut self, input: Input, now: Instant) {
+        let Some(idx) = self
+            .press_runs
+            .iter()
+            .position(|(held, run)| *held == input && matches!(run, PressRun::Down { .. }))
+        else {
+            return;
+        };
+        let (_, PressRun::Down { long, second }) = self.press_runs.remove(idx) else {
+            unreachable!("only a held press was picked");
+        };
+        if second || long.is_some_and(|long| !long.cancel()) {
+            return;
+        }
+        let Some(within) = self.press_delay(input, PressKind::Double) else {
+            self.click(input);
+            return;
+        };
+        let single = self
+            .resolve(input)
+            .and_then(|(action, _, button)| self.press_timer(action, button, within, "press"));
+        self.press_runs.push((
+            input,
+            PressRun::Released {
+                until: now + within,
+                single,
+            },
+        ));
+    }
+
+    /// Starts a timer that queues `action` after `delay`. Actions that change the dispatcher's
+    /// own state can't run from a timer, so those are skipped.
+    fn press_timer(
+        &self,
+        action: Action,
+        button: MouseButton,
+        delay: Duration,
+        trigger: &str,
+    ) -> Option<PressTimer> {
+        if let Action::HoldProfile { .. }
+        | Action::Hold { .. }
+        | Action::SwitchProfile { .. }
+        | Action::ToggleProfile { .. } = action
+        {
+            warn!(
+                "ignoring {} on a {trigger} binding, which fires later from a timer; profile \
+                 switches and holds need one that fires on the press itself",
+                action.kind()
+            );
+            return None;
+        }
+        PressTimer::start(self.queue.clone(), action, button, delay)
+            .map_err(|err| warn!("failed to start the {trigger} timer: {err}"))
+            .ok()
+    }
+
+    /// Whether a binding in effect tells flicks of `input` from holds.
+    fn has_tilt_gestures(&self, input: Input) -> bool {
+        self.active_bindings()
+            .iter()
+            .any(|b| b.tilt.is_some() && input.matches(b.button))
+    }
+
+    /// Treats a run of tilt events, up to a pause of [`TILT_IDLE`], as one gesture: once it has
+    /// lasted `tilt_hold_ms` the `hold` binding fires, and if it stops before that the `flick`
+    /// binding fires when it stops. Bindings without `tilt` are left out.
+    fn tilt_gesture_at(&mut self, input: Input, now: Instant, latency: Duration) {
+        self.tilt_runs
+            .retain(|(_, run)| now.duration_since(run.last_event) < run.idle);
+        let hold_after =
+            Duration::from_millis(self.config.tilt_hold_ms.unwrap_or(DEFAULT_TILT_HOLD_MS));
+        if let Some((_, run)) = self.tilt_runs.iter_mut().find(|(held, _)| *held == input) {
+            run.last_event = now;
+            if run.held || now.duration_since(run.started) < hold_after {
+                if let Some(flick) = &run.flick {
+                    flick.extend(now + run.idle);
+                }
+                return;
+            }
+            run.held = true;
+            if let Some(flick) = run.flick.take() {
+                flick.cancel();
+            }
+            let Some((action, _, button)) = self.resolve_tilt(input, Some(TiltGesture::Hold))
+            else {
+                return;
+            };
+            match action {
+                Action::HoldProfile { name } => {
+                    warn!("ignoring hold_profile {name:?} on a tilt, which can't be held");
+                }
+                Action::Hold { .. } => warn!("ignoring hold on a tilt, which can't be held"),
+                Action::SwitchProfile { name } => self.switch_profile(name),
+                Action::ToggleProfile { name } => self.toggle_profile(name),
+                action => self.queue.dispatch_from(button, &action),
+            }
+            return;
+        }
+
+        let idle = TILT_IDLE + latency;
+        let flick = match self.resolve_tilt(input, Some(TiltGesture::Flick)) {
+            Some((
+                action @ (Action::HoldProfile { .. }
+                | Action::Hold { .. }
+                | Action::SwitchProfile { .. }
+                | Action::ToggleProfile { .. }),
+                _,
+                _,
+            )) => {
+                warn!(
+                    "ignoring {} on a tilt flick; bind it to the hold",
+                    action.kind()
+                );
+                None
+            }
+            Some((action, _, button)) => {
+                FlickTimer::start(self.queue.clone(), action, button, now + idle)
+                    .map_err(|err| warn!("failed to start the tilt flick timer: {err}"))
+                    .ok()
+            }
+            None => None,
+        };
+        self.tilt_runs.push((
+            input,
+            TiltRun {
+                started: now,
+                last_event: now,
+                idle,
+                held: false,
+                flick,
+            },
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::{Job, Queued};
+    use crossbeam_channel::Receiver;
+    use mouse_assist_core::{DeviceMatch, Profile, QueueConfig};
+    use std::time::Duration;
+
+    /// Waits up to `timeout` for the next queued action.
+    fn next(rx: &Receiver<Queued>, timeout: Duration) -> Action {
+        rx.recv_timeout(timeout)
+            .expect("nothing was queued in time")
+            .action()
+    }
+
+    /// What else gets queued once the dispatcher is gone, which is when its timers have all
+    /// finished.
+    fn rest(rx: &Receiver<Queued>) -> Vec<Action> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut rest = Vec::new();
+        loop {
+            match rx.recv_deadline(deadline) {
+                Ok(queued) => rest.push(queued.action()),
+                Err(RecvTimeoutError::Disconnected) => return rest,
+                Err(RecvTimeoutError::Timeout) => panic!("a timer is still running"),
+            }
+        }
+    }
+
+    fn key_combo(key: &str) -> Action {
+        Action::KeyCombo {
+            keys: vec![key.into()],
+            target: None,
+        }
+    }
+
+    fn binding(button: MouseButton, action: Action) -> Binding {
+        Binding::new(button, action)
+    }
+
+    fn side() -> Input {
+        Input::Key(MouseButton::BtnSide.linux_key_code().unwrap())
+    }
+
+    fn task() -> Input {
+        Input::Key(MouseButton::BtnTask.linux_key_code().unwrap())
+    }
+
+    #[test]
+    fn hold_profile_swaps_bindings_until_release() {
+        let mut config = Config::default();
+        config.bindings.push(binding(
+            MouseButton::BtnTask,
+            Action::HoldProfile {
+                name: "media".into(),
+            },
+        ));
+        config.profiles.push(Profile {
+            name: "media".into(),
+            inherits: None,
+            device: None,
+            bindings: vec![
+                binding(MouseButton::BtnSide, key_combo("KEY_PLAYPAUSE")),
+                // Binding the hold button inside its own profile must not break the release.
+                binding(MouseButton::BtnTask, key_combo("KEY_MUTE")),
+            ],
+        });
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+
+        dispatcher.press(task());
+        dispatcher.press(side());
+        dispatcher.release(side());
+        // A repeated press report for the held button doesn't fire the profile's binding for it.
+        dispatcher.press(task());
+        // Not bound in the profile, so it does nothing while held.
+        dispatcher.press(Input::Key(MouseButton::BtnExtra.linux_key_code().unwrap()));
+        dispatcher.release(task());
+        dispatcher.press(side());
+        drop(dispatcher);
+
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(
+            queued,
+            vec![key_combo("KEY_PLAYPAUSE"), key_combo("KEY_BACK")]
+        );
+    }
+
+    #[test]
+    fn device_profiles_apply_to_their_own_device_only() {
+        let mut config = Config::default();
+        config.bindings.push(binding(
+            MouseButton::BtnTask,
+            Action::SwitchProfile {
+                name: Some("media".into()),
+            },
+        ));
+        config.profiles.push(Profile {
+            name: "trackball".into(),
+            inherits: None,
+            device: Some(DeviceMatch {
+                path: None,
+                name: Some("trackball".into()),
+            }),
+            bindings: vec![binding(MouseButton::BtnSide, key_combo("KEY_COPY"))],
+        });
+        config.profiles.push(Profile {
+            name: "media".into(),
+            inherits: None,
+            device: None,
+            bindings: vec![binding(MouseButton::BtnSide, key_combo("KEY_PLAYPAUSE"))],
+        });
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+        let mut click = |path: &str, name: &str, input: Input| {
+            dispatcher.set_device(Path::new(path), name);
+            dispatcher.press(input);
+            dispatcher.release(input);
+        };
+
+        click("/dev/input/event3", "Logitech G502", side());
+        click("/dev/input/event5", "Kensington Trackball", side());
+        // A profile switched to wins over the device's own.
+        click("/dev/input/event3", "Logitech G502", task());
+        click("/dev/input/event5", "Kensington Trackball", side());
+        drop(dispatcher);
+
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(
+            queued,
+            vec![
+                key_combo("KEY_BACK"),
+                key_combo("KEY_COPY"),
+                key_combo("KEY_PLAYPAUSE")
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_holds_keep_shared_keys_down_until_the_last_release() {
+        let hold = |keys: &[&str]| Action::Hold {
+            keys: keys.iter().map(|&key| key.into()).collect(),
+        };
+        let held = |keys: &[&str], down| Job::HeldKeys {
+            keys: keys.iter().map(|&key| key.into()).collect(),
+            down,
+        };
+        let config = Config {
+            bindings: vec![
+                binding(MouseButton::BtnSide, hold(&["KEY_LEFTCTRL"])),
+                binding(
+                    MouseButton::BtnTask,
+                    hold(&["KEY_LEFTCTRL", "KEY_LEFTSHIFT"]),
+                ),
+            ],
+            ..Config::default()
+        };
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+
+        dispatcher.press(side());
+        dispatcher.press(task());
+        // Ctrl is still held by the side button.
+        dispatcher.release(task());
+        dispatcher.press(task());
+        dispatcher.release(side());
+        dispatcher.release_all();
+        drop(dispatcher);
+
+        let queued: Vec<Job> = rx.iter().map(|queued| queued.job).collect();
+        assert_eq!(
+            queued,
+            [
+                held(&["KEY_LEFTCTRL"], true),
+                held(&["KEY_LEFTSHIFT"], true),
+                held(&["KEY_LEFTSHIFT"], false),
+                held(&["KEY_LEFTSHIFT"], true),
+                held(&["KEY_LEFTCTRL", "KEY_LEFTSHIFT"], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn toggle_profile_on_then_off_restores_the_base_bindings() {
+        let mut config = Config::default();
+        config.bindings.push(binding(
+            MouseButton::BtnTask,
+            Action::ToggleProfile {
+                name: "media".into(),
+            },
+        ));
+        config.profiles.push(Profile {
+            name: "media".into(),
+            inherits: None,
+            device: None,
+            bindings: vec![binding(MouseButton::BtnSide, key_combo("KEY_PLAYPAUSE"))],
+        });
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+        let click = |dispatcher: &mut Dispatcher, input| {
+            dispatcher.press(input);
+            dispatcher.release(input);
+        };
+
+        click(&mut dispatcher, task());
+        click(&mut dispatcher, side());
+        click(&mut dispatcher, side());
+        // The profile doesn't bind BTN_TASK, so the top-level toggle turns it back off.
+        click(&mut dispatcher, task());
+        assert_eq!(dispatcher.current_profile(), None);
+        click(&mut dispatcher, side());
+        drop(dispatcher);
+
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(
+            queued,
+            [
+                key_combo("KEY_PLAYPAUSE"),
+                key_combo("KEY_PLAYPAUSE"),
+                key_combo("KEY_BACK")
+            ]
+        );
+    }
+
+    #[test]
+    fn switch_profile_latches_until_switched_back() {
+        let mut config = Config::default();
+        config.bindings.push(binding(
+            MouseButton::BtnTask,
+            Action::SwitchProfile {
+                name: Some("media".into()),
+            },
+        ));
+        config.profiles.push(Profile {
+            name: "media".into(),
+            inherits: None,
+            device: None,
+            bindings: vec![
+                binding(MouseButton::BtnSide, key_combo("KEY_PLAYPAUSE")),
+                binding(MouseButton::BtnTask, Action::SwitchProfile { name: None }),
+            ],
+        });
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+
+        dispatcher.press(task());
+        dispatcher.release(task());
+        dispatcher.press(side());
+        dispatcher.release(side());
+        dispatcher.press(task());
+        dispatcher.release(task());
+        dispatcher.press(side());
+        drop(dispatcher);
+
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(
+            queued,
+            vec![key_combo("KEY_PLAYPAUSE"), key_combo("KEY_BACK")]
+        );
+    }
+
+    #[test]
+    fn repeated_presses_fire_once_until_release() {
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(Config::default(), queue);
+
+        // A device that reports a hold as repeated value-1 events.
+        for _ in 0..5 {
+            dispatcher.press(side());
+        }
+        dispatcher.release(side());
+        dispatcher.press(side());
+        dispatcher.press(side());
+        drop(dispatcher);
+
+        assert_eq!(rx.iter().count(), 2);
+    }
+
+    #[test]
+    fn releasing_a_repeating_button_stops_the_repeat() {
+        let mut config = Config::default();
+        config.bindings[0].repeat = Some(RepeatSpec {
+            delay_ms: 5,
+            interval_ms: 5,
+        });
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+
+        // The press, then at least two repeats.
+        dispatcher.press(side());
+        for _ in 0..3 {
+            assert_eq!(next(&rx, Duration::from_secs(5)), key_combo("KEY_BACK"));
+        }
+        dispatcher.release(side());
+        // Repeats queued before the release may still be waiting; none come after it.
+        let before_release = rx.try_iter().count();
+        drop(dispatcher);
+        assert_eq!(
+            rest(&rx),
+            [],
+            "after {before_release} more before the release"
+        );
+    }
+
+    #[test]
+    fn per_profile_follows_the_profile_in_effect() {
+        let mut config = Config::default();
+        config.bindings[0].action = Action::PerProfile {
+            map: [("work".to_owned(), key_combo("KEY_F1"))].into(),
+            default: Some(Box::new(key_combo("KEY_F2"))),
+        };
+        config.bindings.push(binding(
+            MouseButton::BtnTask,
+            Action::HoldProfile {
+                name: "work".into(),
+            },
+        ));
+        config.profiles.push(Profile {
+            name: "work".into(),
+            inherits: None,
+            device: None,
+            bindings: vec![binding(MouseButton::BtnExtra, key_combo("KEY_F3"))],
+        });
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+
+        dispatcher.press(side());
+        dispatcher.release(side());
+        // "work" doesn't bind BTN_SIDE, so the top-level per_profile binding still applies.
+        dispatcher.press(task());
+        dispatcher.press(side());
+        dispatcher.release(side());
+        dispatcher.release(task());
+        drop(dispatcher);
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(queued, [key_combo("KEY_F2"), key_combo("KEY_F1")]);
+    }
+
+    #[test]
+    fn scroll_chords_fire_per_notch_and_hold_back_the_modifier() {
+        let mut config = Config::default();
+        let mut chord = binding(MouseButton::WheelUp, key_combo("KEY_ZOOMIN"));
+        chord.modifier_button = Some(MouseButton::BtnSide);
+        config.bindings.push(chord);
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+        let wheel_up = Input::Button(MouseButton::WheelUp);
+
+        // Not held: plain scrolling isn't bound.
+        dispatcher.tap(wheel_up);
+        dispatcher.press(side());
+        dispatcher.tap(wheel_up);
+        dispatcher.tap(wheel_up);
+        dispatcher.release(side());
+        // A click without scrolling still does what BTN_SIDE is bound to, on release.
+        dispatcher.press(side());
+        dispatcher.release(side());
+        drop(dispatcher);
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(
+            queued,
+            [
+                key_combo("KEY_ZOOMIN"),
+                key_combo("KEY_ZOOMIN"),
+                key_combo("KEY_BACK")
+            ]
+        );
+    }
+
+    #[test]
+    fn window_class_bindings_win_for_matching_windows_only() {
+        let mut config = Config::default();
+        let mut browser = binding(MouseButton::BtnSide, key_combo("KEY_F1"));
+        browser.window_class = vec!["firefox*".into(), "chrom*".into()];
+        // Listed after the global binding, and still preferred.
+        config.bindings.push(browser);
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let focused = std::rc::Rc::new(std::cell::RefCell::new(None::<Vec<String>>));
+        let query = std::rc::Rc::clone(&focused);
+        let mut dispatcher =
+            Dispatcher::new(config, queue).with_focus(Box::new(move || query.borrow().clone()));
+        let mut click_in = |wm_class: Option<[&str; 2]>| {
+            *focused.borrow_mut() = wm_class.map(|names| names.map(str::to_owned).to_vec());
+            dispatcher.press(side());
+            dispatcher.release(side());
+        };
+
+        click_in(Some(["Navigator", "firefox-esr"]));
+        click_in(Some(["kitty", "kitty"]));
+        click_in(None);
+        drop(dispatcher);
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(
+            queued,
+            [
+                key_combo("KEY_F1"),
+                key_combo("KEY_BACK"),
+                key_combo("KEY_BACK")
+            ]
+        );
+    }
+
+    #[test]
+    fn tilt_repeat_follows_the_event_stream() {
+        let mut config = Config::default();
+        let mut tilt = binding(MouseButton::WheelTiltRight, key_combo("KEY_VOLUMEUP"));
+        tilt.repeat = Some(RepeatSpec {
+            delay_ms: 300,
+            interval_ms: 100,
+        });
+        config.bindings = vec![tilt];
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+        let input = Input::Button(MouseButton::WheelTiltRight);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // A notch every 50ms for 600ms: fires at 0, after the delay at 300, then every 100.
+        for ms in (0..=600).step_by(50) {
+            dispatcher.tap_at(input, at(ms), Duration::ZERO);
+        }
+        assert_eq!(rx.try_iter().count(), 5);
+
+        // After a pause the next notch is a new tilt and fires straight away.
+        dispatcher.tap_at(input, at(900), Duration::ZERO);
+        dispatcher.tap_at(input, at(950), Duration::ZERO);
+        assert_eq!(rx.try_iter().count(), 1);
+
+        // A 250ms gap ends a tilt, unless the device gets latency compensation.
+        dispatcher.tap_at(input, at(1200), Duration::ZERO);
+        assert_eq!(rx.try_iter().count(), 1);
+        let slow = Duration::from_millis(200);
+        dispatcher.tap_at(input, at(2000), slow);
+        dispatcher.tap_at(input, at(2250), slow);
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn tilt_flicks_and_holds_fire_their_own_bindings() {
+        let mut flick = binding(MouseButton::WheelTiltRight, key_combo("KEY_NEXTSONG"));
+        flick.tilt = Some(TiltGesture::Flick);
+        let mut hold = binding(MouseButton::WheelTiltRight, key_combo("KEY_VOLUMEUP"));
+        hold.tilt = Some(TiltGesture::Hold);
+        let config = Config {
+            tilt_hold_ms: Some(300),
+            bindings: vec![flick, hold],
+            ..Config::default()
+        };
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+        let input = Input::Button(MouseButton::WheelTiltRight);
+
+        // A short tilt is a flick, which fires once its events stop, and not before.
+        let start = Instant::now();
+        dispatcher.tap_at(input, start, Duration::ZERO);
+        dispatcher.tap_at(input, start + Duration::from_millis(30), Duration::ZERO);
+        assert_eq!(next(&rx, Duration::from_secs(5)), key_combo("KEY_NEXTSONG"));
+        assert!(start.elapsed() >= Duration::from_millis(30) + TILT_IDLE);
+
+        // A tilt still going after 300ms is a hold: it fires once, and never as a flick. Its
+        // events are dated an hour ahead, so its flick timer can't go off however slowly the
+        // test runs; only the hold cancelling it keeps it from firing once the dispatcher is
+        // dropped.
+        let start = Instant::now() + Duration::from_secs(3600);
+        for ms in (0..=500).step_by(50) {
+            dispatcher.tap_at(input, start + Duration::from_millis(ms), Duration::ZERO);
+        }
+        assert_eq!(
+            rx.try_iter()
+                .map(|queued| queued.action())
+                .collect::<Vec<_>>(),
+            vec![key_combo("KEY_VOLUMEUP")]
+        );
+        drop(dispatcher);
+        assert_eq!(rest(&rx), []);
+    }
+
+    #[test]
+    fn plain_double_and_long_presses_fire_their_own_bindings() {
+        // Generous times, so a slow test run doesn't turn quick clicks into slow ones.
+        let window = Duration::from_millis(500);
+        let mut double = binding(MouseButton::BtnSide, key_combo("KEY_REFRESH"));
+        double.trigger = Trigger::DoublePress { within_ms: 500 };
+        let mut long = binding(MouseButton::BtnSide, key_combo("KEY_HOMEPAGE"));
+        long.trigger = Trigger::LongPress { after_ms: 500 };
+        let mut extra = binding(MouseButton::BtnExtra, key_combo("KEY_FORWARD"));
+        extra.double_click = Some(key_combo("KEY_STOP"));
+        let config = Config {
+            bindings: vec![
+                binding(MouseButton::BtnSide, key_combo("KEY_BACK")),
+                double,
+                long,
+                extra,
+            ],
+            ..Config::default()
+        };
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+        let extra = Input::Key(MouseButton::BtnExtra.linux_key_code().unwrap());
+
+        // A click waits out the double press window before it counts as a plain press.
+        dispatcher.press(side());
+        let released = Instant::now();
+        dispatcher.release(side());
+        assert_eq!(next(&rx, Duration::from_secs(5)), key_combo("KEY_BACK"));
+        assert!(released.elapsed() >= window);
+
+        // Two quick clicks are a double press, and never a plain one.
+        dispatcher.press(side());
+        dispatcher.release(side());
+        dispatcher.press(side());
+        assert_eq!(
+            rx.try_iter()
+                .map(|queued| queued.action())
+                .collect::<Vec<_>>(),
+            vec![key_combo("KEY_REFRESH")]
+        );
+        dispatcher.release(side());
+
+        // Held past after_ms, it's a long press, which fires while still held.
+        let pressed = Instant::now();
+        dispatcher.press(side());
+        assert_eq!(next(&rx, Duration::from_secs(5)), key_combo("KEY_HOMEPAGE"));
+        assert!(pressed.elapsed() >= window);
+        dispatcher.release(side());
+
+        // double_click works like a double_press binding.
+        dispatcher.press(extra);
+        dispatcher.release(extra);
+        dispatcher.press(extra);
+        dispatcher.release(extra);
+        assert_eq!(
+            rx.try_iter()
+                .map(|queued| queued.action())
+                .collect::<Vec<_>>(),
+            vec![key_combo("KEY_STOP")]
+        );
+
+        // None of them also fired as a plain press once its timer ran out.
+        drop(dispatcher);
+        assert_eq!(rest(&rx), []);
+    }
+
+    #[test]
+    fn middle_presses_right_after_scrolling_are_ignored() {
+        let mut config = Config::default();
+        config
+            .bindings
+            .push(binding(MouseButton::BtnMiddle, key_combo("KEY_COPY")));
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config, queue);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let middle = Input::Key(MouseButton::BtnMiddle.linux_key_code().unwrap());
+
+        // An evdev notch, then the wheel pushed mid-scroll.
+        dispatcher.tap_at(Input::Button(MouseButton::WheelDown), at(0), Duration::ZERO);
+        dispatcher.press_at(middle, at(100));
+        dispatcher.release(middle);
+        // Past the default 150ms, it's a click.
+        dispatcher.press_at(middle, at(200));
+        dispatcher.release(middle);
+        // X11 wheel notches are button presses.
+        dispatcher.press_at(Input::X11Button(4), at(300));
+        dispatcher.release(Input::X11Button(4));
+        dispatcher.press_at(Input::X11Button(2), at(320));
+        dispatcher.press_at(Input::X11Button(2), at(600));
+        drop(dispatcher);
+
+        assert_eq!(
+            rx.iter().map(|queued| queued.action()).collect::<Vec<_>>(),
+            vec![key_combo("KEY_COPY"), key_combo("KEY_COPY")]
+        );
+    }
+}
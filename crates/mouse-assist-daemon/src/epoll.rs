@@ -0,0 +1,119 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+/// How many ready descriptors one [`Epoll::wait`] reports; the rest are reported by the next.
+const MAX_EVENTS: usize = 16;
+
+/// Lets `run` sleep until one of many devices has something to read, instead of asking each
+/// of them in turn. Descriptors are level-triggered: one with unread events stays ready.
+pub(crate) struct Epoll {
+    fd: OwnedFd,
+}
+
+impl Epoll {
+    pub(crate) fn new() -> io::Result<Self> {
+        // SAFETY: a descriptor returned by `epoll_create1` is owned by nothing else.
+        let fd = unsafe {
+            let fd = libc::epoll_create1(libc::EPOLL_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            OwnedFd::from_raw_fd(fd)
+        };
+        Ok(Self { fd })
+    }
+
+    /// Starts watching `fd`, which [`Self::wait`] reports by its number. An fd is dropped from
+    /// the set when it's closed, but [`Self::remove`] says so sooner.
+    pub(crate) fn add(&self, fd: RawFd) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: fd as u64,
+        };
+        // SAFETY: `event` is a valid, exclusively borrowed epoll_event.
+        let result =
+            unsafe { libc::epoll_ctl(self.fd.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, fd: RawFd) -> io::Result<()> {
+        // SAFETY: the event argument may be null for EPOLL_CTL_DEL.
+        let result = unsafe {
+            libc::epoll_ctl(
+                self.fd.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                fd,
+                std::ptr::null_mut(),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Waits until a watched fd has something to read (or has failed, say because its device
+    /// was unplugged) or `timeout` has passed, and returns the ones that are ready; without a
+    /// timeout it waits for as long as it takes.
+    pub(crate) fn wait(&self, timeout: Option<Duration>) -> io::Result<Vec<RawFd>> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let timeout = timeout.map_or(-1, |timeout| {
+            i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+        });
+        loop {
+            // SAFETY: `events` is a valid, exclusively borrowed array of MAX_EVENTS entries.
+            let ready = unsafe {
+                libc::epoll_wait(
+                    self.fd.as_raw_fd(),
+                    events.as_mut_ptr(),
+                    MAX_EVENTS as i32,
+                    timeout,
+                )
+            };
+            if let Ok(ready) = usize::try_from(ready) {
+                return Ok(events[..ready]
+                    .iter()
+                    .map(|event| event.u64 as RawFd)
+                    .collect());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn only_descriptors_with_something_to_read_are_ready() {
+        let epoll = Epoll::new().unwrap();
+        let (mut quiet_writer, quiet) = UnixStream::pair().unwrap();
+        let (mut busy_writer, busy) = UnixStream::pair().unwrap();
+        epoll.add(quiet.as_raw_fd()).unwrap();
+        epoll.add(busy.as_raw_fd()).unwrap();
+        assert!(epoll.wait(Some(Duration::ZERO)).unwrap().is_empty());
+
+        busy_writer.write_all(b"x").unwrap();
+        let ready = epoll.wait(Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(ready, vec![busy.as_raw_fd()]);
+        // Still unread, so still ready.
+        assert_eq!(epoll.wait(Some(Duration::ZERO)).unwrap(), ready);
+
+        epoll.remove(busy.as_raw_fd()).unwrap();
+        quiet_writer.write_all(b"x").unwrap();
+        assert_eq!(
+            epoll.wait(Some(Duration::ZERO)).unwrap(),
+            vec![quiet.as_raw_fd()]
+        );
+    }
+}
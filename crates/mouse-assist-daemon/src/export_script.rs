@@ -0,0 +1,419 @@
+use mouse_assist_core::{Action, Binding, Config, KeyTarget, Selection, Step, SystemOp, Trigger};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The tool the exported script sends keys with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tool {
+    /// X11 keysym names, e.g. `xdotool key ctrl+c`.
+    Xdotool,
+    /// Kernel key codes, e.g. `ydotool key 29:1 46:1 46:0 29:0`; works on Wayland too.
+    Ydotool,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Xdotool => "xdotool",
+            Self::Ydotool => "ydotool",
+        }
+    }
+}
+
+/// Writes `config`'s bindings (already resolved) as a shell script with one function per
+/// binding, each running a rough equivalent of the action. Actions with no equivalent are left
+/// as comments saying why.
+pub(crate) fn render(config: &Config, source: &Path, tool: Tool) -> String {
+    let mut out = format!(
+        "#!/bin/sh\n\
+         # Bindings from {}, as {} commands.\n\
+         #\n\
+         # A best-effort approximation, not a replacement for the daemon: nothing here watches\n\
+         # the mouse. Each binding is a shell function to bind in your window manager or run\n\
+         # by hand (`. ./this-script && btn_side`). Hold-to-repeat, active hours, window\n\
+         # classes and profile switching aren't reproduced, and actions with no command-line\n\
+         # equivalent are left as comments.\n",
+        source.display(),
+        tool.name()
+    );
+    let mut names = HashSet::new();
+    write_bindings(&mut out, &mut names, None, &config.bindings, tool);
+    for profile in &config.profiles {
+        out.push_str(&format!("\n# Profile {:?}\n", profile.name));
+        write_bindings(
+            &mut out,
+            &mut names,
+            Some(&profile.name),
+            &profile.bindings,
+            tool,
+        );
+    }
+    out
+}
+
+fn write_bindings(
+    out: &mut String,
+    names: &mut HashSet<String>,
+    profile: Option<&str>,
+    bindings: &[Binding],
+    tool: Tool,
+) {
+    for binding in bindings {
+        let mut trigger = binding.button.toml_name().to_owned();
+        if let Some(modifier) = binding.modifier_button {
+            trigger = format!("{} + {trigger}", modifier.toml_name());
+        }
+        let mut conditions = Vec::new();
+        if !binding.window_class.is_empty() {
+            conditions.push(format!("in {}", binding.window_class.join(", ")));
+        }
+        if let Some((from, until)) = &binding.active_hours {
+            conditions.push(format!("from {from} until {until}"));
+        }
+        if binding.repeat.is_some() {
+            conditions.push("repeats while held".to_owned());
+        }
+        match binding.trigger {
+            Trigger::Press => {}
+            Trigger::DoublePress { .. } => conditions.push("on a double press".to_owned()),
+            Trigger::LongPress { .. } => conditions.push("on a long press".to_owned()),
+        }
+        if binding.double_click.is_some() {
+            conditions.push("double click not exported".to_owned());
+        }
+        let conditions = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", conditions.join("; "))
+        };
+        out.push_str(&format!(
+            "\n# {trigger}: {}{conditions}\n{}() {{\n",
+            binding.action.kind(),
+            function_name(names, profile, binding)
+        ));
+        for line in body(&binding.action, tool) {
+            out.push_str(&format!("    {line}\n"));
+        }
+        out.push_str("}\n");
+    }
+}
+
+/// `btn_side`, `work_btn_extra_wheel_up`, ... with a number added when taken.
+fn function_name(names: &mut HashSet<String>, profile: Option<&str>, binding: &Binding) -> String {
+    let mut parts: Vec<&str> = profile.into_iter().collect();
+    parts.extend(binding.modifier_button.map(|b| b.toml_name()));
+    parts.push(binding.button.toml_name());
+    let base: String = parts
+        .join("_")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let mut name = base.clone();
+    let mut n = 2;
+    while !names.insert(name.clone()) {
+        name = format!("{base}_{n}");
+        n += 1;
+    }
+    name
+}
+
+/// The lines of a function body; never empty, since `sh` rejects an empty function.
+fn body(action: &Action, tool: Tool) -> Vec<String> {
+    match action {
+        Action::Parallel { actions } => {
+            let mut lines: Vec<String> = actions
+                .iter()
+                .map(|action| match command(action, tool) {
+                    Ok(command) => format!("{command} &"),
+                    Err(why) => format!("# {why}"),
+                })
+                .collect();
+            lines.push("wait".to_owned());
+            lines
+        }
+        Action::Sequence { steps } if !steps.is_empty() => steps
+            .iter()
+            .map(|step| match step {
+                Step::Action(action) => line(command(action, tool)),
+                Step::Delay { ms } => format!("sleep {}", *ms as f64 / 1000.0),
+            })
+            .collect(),
+        action => vec![line(command(action, tool))],
+    }
+}
+
+fn line(command: Result<String, String>) -> String {
+    command.unwrap_or_else(|why| format!(": # {why}"))
+}
+
+/// One shell command for `action`, or why there's none.
+fn command(action: &Action, tool: Tool) -> Result<String, String> {
+    let untranslatable = || {
+        let toml = action
+            .inline_toml()
+            .unwrap_or_else(|_| action.kind().to_owned());
+        Err(format!("no {} equivalent: {toml}", tool.name()))
+    };
+    Ok(match action {
+        Action::Command { argv } => quote_all(argv),
+        Action::CommandNotify { argv } => format!(
+            "notify-send {} \"$({} 2>&1)\"",
+            quote(&argv.join(" ")),
+            quote_all(argv)
+        ),
+        Action::KeyCombo { keys, target } => {
+            return key_command(tool, std::slice::from_ref(keys), target.as_ref())
+        }
+        Action::KeyChords { keys, target } => return key_command(tool, keys, target.as_ref()),
+        Action::Open { target } => format!("xdg-open {}", quote(target.trim())),
+        Action::LaunchOrFocus { argv, match_class } => match tool {
+            Tool::Xdotool => format!(
+                "xdotool search --limit 1 --class {} windowactivate 2>/dev/null || {}",
+                quote(match_class),
+                quote_all(argv)
+            ),
+            Tool::Ydotool => quote_all(argv),
+        },
+        Action::SetClipboard { text, selection } => {
+            let copy = match (tool, selection) {
+                (Tool::Xdotool, Selection::Clipboard) => "xclip -selection clipboard",
+                (Tool::Xdotool, Selection::Primary) => "xclip -selection primary",
+                (Tool::Ydotool, Selection::Clipboard) => "wl-copy",
+                (Tool::Ydotool, Selection::Primary) => "wl-copy --primary",
+            };
+            format!("printf '%s' {} | {copy}", quote(text))
+        }
+        Action::Webhook {
+            url,
+            method,
+            body,
+            headers,
+        } => {
+            let mut argv = vec![
+                "curl".to_owned(),
+                "-fsS".to_owned(),
+                "-X".to_owned(),
+                method.clone().unwrap_or_else(|| "POST".to_owned()),
+            ];
+            for (name, value) in headers.iter().flatten() {
+                argv.extend(["-H".to_owned(), format!("{name}: {value}")]);
+            }
+            if let Some(body) = body {
+                argv.extend(["--data".to_owned(), body.clone()]);
+            }
+            argv.push(url.clone());
+            quote_all(&argv)
+        }
+        Action::System { op } => match op {
+            SystemOp::Lock => "loginctl lock-session",
+            SystemOp::Suspend => "systemctl suspend",
+            SystemOp::Hibernate => "systemctl hibernate",
+            SystemOp::LogOut => "loginctl terminate-session \"$XDG_SESSION_ID\"",
+        }
+        .to_owned(),
+        _ => return untranslatable(),
+    })
+}
+
+/// Presses each chord in turn.
+fn key_command(
+    tool: Tool,
+    chords: &[Vec<String>],
+    target: Option<&KeyTarget>,
+) -> Result<String, String> {
+    match tool {
+        Tool::Xdotool => {
+            let combos = chords
+                .iter()
+                .map(|chord| {
+                    chord
+                        .iter()
+                        .map(|key| {
+                            xdotool_key_name(key)
+                                .ok_or_else(|| format!("no X11 keysym name known for {key}"))
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(|names| names.join("+"))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join(" ");
+            match target {
+                None => Ok(format!("xdotool key --clearmodifiers {combos}")),
+                Some(KeyTarget::Window(window)) => {
+                    let mut search = String::from("xdotool search --limit 1");
+                    if window.class.is_some() && window.title.is_some() {
+                        search.push_str(" --all");
+                    }
+                    if let Some(class) = &window.class {
+                        search.push_str(&format!(" --class {}", quote(class)));
+                    }
+                    if let Some(title) = &window.title {
+                        search.push_str(&format!(" --name {}", quote(title)));
+                    }
+                    Ok(format!("{search} key --window %1 {combos}"))
+                }
+                Some(KeyTarget::Named(_)) => Err(format!(
+                    "keys for the window under the cursor aren't exported: {combos}"
+                )),
+            }
+        }
+        Tool::Ydotool => {
+            if target.is_some() {
+                return Err("ydotool can't send keys to a particular window".to_owned());
+            }
+            let mut events = Vec::new();
+            for chord in chords {
+                let codes = chord
+                    .iter()
+                    .map(|key| {
+                        key.parse::<evdev::KeyCode>()
+                            .map(|code| code.code())
+                            .map_err(|_| format!("unknown key name {key}"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                events.extend(codes.iter().map(|code| format!("{code}:1")));
+                events.extend(codes.iter().rev().map(|code| format!("{code}:0")));
+            }
+            Ok(format!("ydotool key {}", events.join(" ")))
+        }
+    }
+}
+
+/// The keysym name `xdotool key` takes for a `KEY_*` name.
+fn xdotool_key_name(key: &str) -> Option<String> {
+    let name = key.strip_prefix("KEY_")?;
+    let named = match name {
+        "LEFTCTRL" => "ctrl",
+        "RIGHTCTRL" => "Control_R",
+        "LEFTSHIFT" => "shift",
+        "RIGHTSHIFT" => "Shift_R",
+        "LEFTALT" => "alt",
+        "RIGHTALT" => "Alt_R",
+        "LEFTMETA" => "super",
+        "RIGHTMETA" => "Super_R",
+        "ENTER" => "Return",
+        "ESC" => "Escape",
+        "BACKSPACE" => "BackSpace",
+        "TAB" => "Tab",
+        "SPACE" => "space",
+        "DELETE" => "Delete",
+        "INSERT" => "Insert",
+        "HOME" => "Home",
+        "END" => "End",
+        "PAGEUP" => "Prior",
+        "PAGEDOWN" => "Next",
+        "UP" => "Up",
+        "DOWN" => "Down",
+        "LEFT" => "Left",
+        "RIGHT" => "Right",
+        "MINUS" => "minus",
+        "EQUAL" => "equal",
+        "LEFTBRACE" => "bracketleft",
+        "RIGHTBRACE" => "bracketright",
+        "SEMICOLON" => "semicolon",
+        "APOSTROPHE" => "apostrophe",
+        "GRAVE" => "grave",
+        "BACKSLASH" => "backslash",
+        "COMMA" => "comma",
+        "DOT" => "period",
+        "SLASH" => "slash",
+        "CAPSLOCK" => "Caps_Lock",
+        "SYSRQ" | "PRINT" => "Print",
+        "COMPOSE" | "MENU" => "Menu",
+        "PAUSE" => "Pause",
+        "VOLUMEUP" => "XF86AudioRaiseVolume",
+        "VOLUMEDOWN" => "XF86AudioLowerVolume",
+        "MUTE" => "XF86AudioMute",
+        "PLAYPAUSE" => "XF86AudioPlay",
+        "NEXTSONG" => "XF86AudioNext",
+        "PREVIOUSSONG" => "XF86AudioPrev",
+        "STOPCD" => "XF86AudioStop",
+        "BACK" => "XF86Back",
+        "FORWARD" => "XF86Forward",
+        "REFRESH" => "XF86Reload",
+        "BRIGHTNESSUP" => "XF86MonBrightnessUp",
+        "BRIGHTNESSDOWN" => "XF86MonBrightnessDown",
+        _ => {
+            let is_function_key = name
+                .strip_prefix('F')
+                .and_then(|n| n.parse::<u8>().ok())
+                .is_some_and(|n| (1..=24).contains(&n));
+            return match name.as_bytes() {
+                [c] if c.is_ascii_alphanumeric() => Some(name.to_ascii_lowercase()),
+                _ if is_function_key => Some(name.to_owned()),
+                _ => None,
+            };
+        }
+    };
+    Some(named.to_owned())
+}
+
+fn quote_all(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `arg` as one shell word: as is when that's safe, else single-quoted.
+fn quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mouse_assist_core::MouseButton;
+
+    #[test]
+    fn bindings_become_shell_functions() {
+        let keys = ["KEY_LEFTCTRL", "KEY_LEFTSHIFT", "KEY_T"].map(String::from);
+        let mut cycle = Binding::new(
+            MouseButton::BtnExtra,
+            Action::CycleWindows { same_class: false },
+        );
+        cycle.window_class = vec!["firefox".into()];
+        let config = Config {
+            bindings: vec![
+                Binding::new(
+                    MouseButton::BtnSide,
+                    Action::KeyCombo {
+                        keys: keys.to_vec(),
+                        target: None,
+                    },
+                ),
+                Binding::new(
+                    MouseButton::BtnExtra,
+                    Action::Command {
+                        argv: vec!["notify-send".into(), "it's done".into()],
+                    },
+                ),
+                cycle,
+            ],
+            ..Config::default()
+        };
+
+        let script = render(&config, Path::new("config.toml"), Tool::Xdotool);
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains(
+            "# BTN_SIDE: key_combo\nbtn_side() {\n    xdotool key --clearmodifiers ctrl+shift+t\n}\n"
+        ));
+        assert!(script.contains("btn_extra() {\n    notify-send 'it'\\''s done'\n}\n"));
+        assert!(script.contains("# BTN_EXTRA: cycle_windows (in firefox)\nbtn_extra_2() {\n"));
+        assert!(script.contains("    : # no xdotool equivalent: { type = \"cycle_windows\""));
+
+        let script = render(&config, Path::new("config.toml"), Tool::Ydotool);
+        assert!(script.contains("    ydotool key 29:1 42:1 20:1 20:0 42:0 29:0\n"));
+    }
+}
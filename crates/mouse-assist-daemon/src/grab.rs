@@ -0,0 +1,277 @@
+use crate::panic_cleanup;
+use mouse_assist_core::{Config, MouseButton};
+use tracing::{info, warn};
+
+/// Which events from a grabbed evdev device are consumed (not forwarded to applications).
+///
+/// Only bound side buttons and wheel-tilt directions are ever consumed, along with scrolling
+/// while a chord's `modifier_button` is held; everything else the device reports is passed
+/// through unchanged. A button also passes through when every binding for it (in any profile)
+/// asks for `passthrough`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct GrabFilter {
+    keys: Vec<u16>,
+    tilt_left: bool,
+    tilt_right: bool,
+    /// The modifier buttons of `WHEEL_UP` and `WHEEL_DOWN` chords.
+    scroll_up: Vec<u16>,
+    scroll_down: Vec<u16>,
+}
+
+impl GrabFilter {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        let mut filter = Self::default();
+        for binding in config.all_bindings().filter(|b| !b.passthrough) {
+            let modifier = binding
+                .modifier_button
+                .and_then(MouseButton::linux_key_code);
+            if let Some(code) = modifier {
+                filter.keys.push(code);
+            }
+            match binding.button {
+                MouseButton::BtnSide
+                | MouseButton::BtnExtra
+                | MouseButton::BtnForward
+                | MouseButton::BtnBack => {
+                    if let Some(code) = binding.button.linux_key_code() {
+                        filter.keys.push(code);
+                    }
+                }
+                MouseButton::WheelTiltLeft => filter.tilt_left = true,
+                MouseButton::WheelTiltRight => filter.tilt_right = true,
+                MouseButton::WheelUp => filter.scroll_up.extend(modifier),
+                MouseButton::WheelDown => filter.scroll_down.extend(modifier),
+                _ => {}
+            }
+        }
+        filter.keys.sort_unstable();
+        filter.keys.dedup();
+        filter
+    }
+
+    /// Whether grabbing `dev` would consume anything it can actually report.
+    pub(crate) fn applies_to(&self, dev: &evdev::Device) -> bool {
+        let keys = dev.supported_keys().is_some_and(|keys| {
+            self.keys
+                .iter()
+                .any(|&code| keys.contains(evdev::KeyCode::new(code)))
+        });
+        let tilt = (self.tilt_left || self.tilt_right)
+            && dev.supported_relative_axes().is_some_and(|axes| {
+                axes.contains(evdev::RelativeAxisCode::REL_HWHEEL)
+                    || axes.contains(evdev::RelativeAxisCode::REL_HWHEEL_HI_RES)
+            });
+        keys || tilt
+    }
+
+    /// Whether `ev` is kept from applications, with the keys in `held` down.
+    pub(crate) fn consumes(&self, ev: &evdev::InputEvent, held: &[u16]) -> bool {
+        match ev.destructure() {
+            evdev::EventSummary::Key(_, keycode, _) => self.keys.contains(&keycode.code()),
+            evdev::EventSummary::RelativeAxis(
+                _,
+                evdev::RelativeAxisCode::REL_WHEEL | evdev::RelativeAxisCode::REL_WHEEL_HI_RES,
+                value,
+            ) => {
+                let modifiers = if value > 0 {
+                    &self.scroll_up
+                } else {
+                    &self.scroll_down
+                };
+                modifiers.iter().any(|code| held.contains(code))
+            }
+            evdev::EventSummary::RelativeAxis(_, axis, value) => {
+                matches!(
+                    axis,
+                    evdev::RelativeAxisCode::REL_HWHEEL
+                        | evdev::RelativeAxisCode::REL_HWHEEL_HI_RES
+                ) && ((value < 0 && self.tilt_left) || (value > 0 && self.tilt_right))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Exclusively grabs an evdev device and re-emits everything that isn't consumed by a binding
+/// on a virtual pointer mirroring the device's capabilities.
+pub(crate) struct Passthrough {
+    /// Declared first so they're dropped before the virtual device.
+    _panic_cleanups: [panic_cleanup::Registration; 2],
+    filter: GrabFilter,
+    device: evdev::uinput::VirtualDevice,
+    pending: Vec<evdev::InputEvent>,
+    /// Keys down on the grabbed device, for scroll chords.
+    held: Vec<u16>,
+}
+
+impl Passthrough {
+    /// Builds the virtual mirror first and only then grabs `source`, so a failure never leaves
+    /// the physical device grabbed without anything forwarding its events.
+    pub(crate) fn grab(source: &mut evdev::Device, filter: GrabFilter) -> std::io::Result<Self> {
+        let name = format!(
+            "mouse-assist passthrough ({})",
+            source.name().unwrap_or("unknown")
+        );
+        let mut builder = evdev::uinput::VirtualDevice::builder()?
+            .name(&name)
+            .input_id(source.input_id())
+            .with_properties(source.properties())?;
+        if let Some(keys) = source.supported_keys() {
+            builder = builder.with_keys(keys)?;
+        }
+        if let Some(axes) = source.supported_relative_axes() {
+            builder = builder.with_relative_axes(axes)?;
+        }
+        if source.supported_absolute_axes().is_some() {
+            for (axis, info) in source.get_absinfo()? {
+                builder = builder.with_absolute_axis(&evdev::UinputAbsSetup::new(axis, info))?;
+            }
+        }
+        if let Some(misc) = source.misc_properties() {
+            builder = builder.with_msc(misc)?;
+        }
+        let device = builder.build()?;
+
+        source.grab()?;
+        info!("grabbed device; forwarding unbound events via \"{name}\"");
+        let forwarded_keys: Vec<u16> = source
+            .supported_keys()
+            .map(|keys| keys.iter().map(|key| key.code()).collect())
+            .unwrap_or_default();
+
+        Ok(Self {
+            _panic_cleanups: [
+                panic_cleanup::ungrab_on_panic(&*source),
+                panic_cleanup::release_on_panic(&device, forwarded_keys),
+            ],
+            filter,
+            device,
+            pending: Vec::new(),
+            held: Vec::new(),
+        })
+    }
+
+    /// Forwards one event from the grabbed device, batching until its `SYN_REPORT`.
+    pub(crate) fn forward(&mut self, ev: evdev::InputEvent) {
+        // The panic hook has let go of the grab, so applications see the device directly.
+        if panic_cleanup::panicked() {
+            return;
+        }
+        if ev.event_type() == evdev::EventType::SYNCHRONIZATION {
+            if ev.code() == evdev::SynchronizationCode::SYN_REPORT.0 && !self.pending.is_empty() {
+                if let Err(err) = self.device.emit(&self.pending) {
+                    warn!("failed to forward grabbed events: {err}");
+                }
+                self.pending.clear();
+            }
+            return;
+        }
+        if let evdev::EventSummary::Key(_, keycode, value) = ev.destructure() {
+            match value {
+                0 => self.held.retain(|&code| code != keycode.code()),
+                1 if !self.held.contains(&keycode.code()) => self.held.push(keycode.code()),
+                _ => {}
+            }
+        }
+        if !self.filter.consumes(&ev, &self.held) {
+            self.pending.push(ev);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mouse_assist_core::{Action, Binding};
+
+    fn binding(button: MouseButton) -> Binding {
+        Binding::new(
+            button,
+            Action::KeyCombo {
+                keys: vec!["KEY_MUTE".into()],
+                target: None,
+            },
+        )
+    }
+
+    fn key(code: evdev::KeyCode, value: i32) -> evdev::InputEvent {
+        evdev::InputEvent::new(evdev::EventType::KEY.0, code.code(), value)
+    }
+
+    fn hwheel(value: i32) -> evdev::InputEvent {
+        evdev::InputEvent::new(
+            evdev::EventType::RELATIVE.0,
+            evdev::RelativeAxisCode::REL_HWHEEL.0,
+            value,
+        )
+    }
+
+    #[test]
+    fn grab_skips_buttons_whose_bindings_all_pass_through() {
+        let mut config = Config {
+            bindings: vec![binding(MouseButton::BtnBack), binding(MouseButton::BtnSide)],
+            ..Config::default()
+        };
+        config.bindings[0].passthrough = true;
+        config.bindings[1].passthrough = true;
+        config.profiles.push(mouse_assist_core::Profile {
+            name: "media".into(),
+            inherits: None,
+            device: None,
+            bindings: vec![binding(MouseButton::BtnSide)],
+        });
+        let filter = GrabFilter::from_config(&config);
+
+        assert!(!filter.consumes(&key(evdev::KeyCode::BTN_BACK, 1), &[]));
+        // The profile's binding for BTN_SIDE doesn't pass through, so it stays grabbed.
+        assert!(filter.consumes(&key(evdev::KeyCode::BTN_SIDE, 1), &[]));
+    }
+
+    #[test]
+    fn grab_consumes_only_bound_side_buttons_and_tilt() {
+        let config = Config {
+            bindings: vec![
+                binding(MouseButton::BtnBack),
+                binding(MouseButton::BtnMiddle),
+                binding(MouseButton::WheelTiltRight),
+            ],
+            ..Config::default()
+        };
+        let filter = GrabFilter::from_config(&config);
+
+        assert!(filter.consumes(&key(evdev::KeyCode::BTN_BACK, 1), &[]));
+        assert!(filter.consumes(&key(evdev::KeyCode::BTN_BACK, 0), &[]));
+        assert!(!filter.consumes(&key(evdev::KeyCode::BTN_FORWARD, 1), &[]));
+        // Bound, but not a side button: never consumed, so clicks keep working.
+        assert!(!filter.consumes(&key(evdev::KeyCode::BTN_MIDDLE, 1), &[]));
+        assert!(!filter.consumes(&key(evdev::KeyCode::BTN_LEFT, 1), &[]));
+
+        assert!(filter.consumes(&hwheel(1), &[]));
+        assert!(!filter.consumes(&hwheel(-1), &[]));
+    }
+
+    #[test]
+    fn grab_consumes_scrolling_only_while_a_chord_modifier_is_held() {
+        let mut chord = binding(MouseButton::WheelUp);
+        chord.modifier_button = Some(MouseButton::BtnExtra);
+        let config = Config {
+            bindings: vec![chord],
+            ..Config::default()
+        };
+        let filter = GrabFilter::from_config(&config);
+        let wheel = |axis: evdev::RelativeAxisCode, value| {
+            evdev::InputEvent::new(evdev::EventType::RELATIVE.0, axis.0, value)
+        };
+        let extra = [evdev::KeyCode::BTN_EXTRA.code()];
+
+        assert!(filter.consumes(&key(evdev::KeyCode::BTN_EXTRA, 1), &[]));
+        assert!(!filter.consumes(&wheel(evdev::RelativeAxisCode::REL_WHEEL, 1), &[]));
+        assert!(filter.consumes(&wheel(evdev::RelativeAxisCode::REL_WHEEL, 1), &extra));
+        assert!(filter.consumes(
+            &wheel(evdev::RelativeAxisCode::REL_WHEEL_HI_RES, 120),
+            &extra
+        ));
+        // Only scrolling up is bound.
+        assert!(!filter.consumes(&wheel(evdev::RelativeAxisCode::REL_WHEEL, -1), &extra));
+    }
+}
@@ -0,0 +1,130 @@
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+/// Watches the input device directory for `run`, so that a mouse plugged in (or plugged in
+/// again) while it runs is picked up. udev creates and removes the `event*` nodes there, so
+/// their comings and goings are what it announces.
+pub(crate) struct DeviceWatch {
+    changes: Receiver<Change>,
+    /// Watching stops when it's dropped.
+    _watcher: RecommendedWatcher,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Change {
+    /// An event node was created, or its permissions changed. udev often makes a new node
+    /// readable a moment after creating it, so the node may only be usable after the second.
+    Appeared(PathBuf),
+    Removed(PathBuf),
+}
+
+impl DeviceWatch {
+    pub(crate) fn watch(dir: &Path) -> notify::Result<Self> {
+        let (tx, changes) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                for path in event.paths {
+                    if let Some(change) = change(&event.kind, path) {
+                        let _ = tx.send(change);
+                    }
+                }
+            })?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            changes,
+            _watcher: watcher,
+        })
+    }
+
+    /// The changes since the last call, oldest first.
+    pub(crate) fn poll(&self) -> impl Iterator<Item = Change> + '_ {
+        self.changes.try_iter()
+    }
+}
+
+fn change(kind: &EventKind, path: PathBuf) -> Option<Change> {
+    let is_event_node = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("event"));
+    if !is_event_node {
+        return None;
+    }
+    match kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Metadata(_)) => {
+            Some(Change::Appeared(path))
+        }
+        EventKind::Remove(_) => Some(Change::Removed(path)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant, SystemTime};
+
+    /// A directory of its own, removed when the test ends, however it ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let nanos = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos();
+            let dir = std::env::temp_dir().join(format!(
+                "mouse-assist-hotplug-{}-{nanos}",
+                std::process::id()
+            ));
+            fs::create_dir(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Waits for `expected`, letting through repeats of `Appeared(node)`: one change to a
+    /// node can be reported more than once.
+    fn wait_for(watch: &DeviceWatch, node: &Path, expected: Change) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let change = watch
+                .changes
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .unwrap_or_else(|_| panic!("no {expected:?} reported"));
+            if change == expected {
+                return;
+            }
+            assert_eq!(change, Change::Appeared(node.to_path_buf()));
+        }
+    }
+
+    #[test]
+    fn event_nodes_coming_and_going_are_reported() {
+        let dir = TempDir::new();
+        let watch = DeviceWatch::watch(&dir.0).unwrap();
+
+        let node = dir.0.join("event7");
+        fs::write(dir.0.join("mouse0"), "").unwrap();
+        fs::write(&node, "").unwrap();
+        wait_for(&watch, &node, Change::Appeared(node.clone()));
+
+        fs::set_permissions(&node, fs::Permissions::from_mode(0o640)).unwrap();
+        wait_for(&watch, &node, Change::Appeared(node.clone()));
+
+        fs::remove_file(&node).unwrap();
+        wait_for(&watch, &node, Change::Removed(node.clone()));
+    }
+}
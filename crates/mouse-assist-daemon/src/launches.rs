@@ -0,0 +1,157 @@
+use crate::worker::Shared;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+/// How many launches the state file remembers.
+const CAPACITY: usize = 16;
+
+/// A process started by a `command` action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Launch {
+    pub(crate) pid: u32,
+    /// Seconds since the Unix epoch.
+    pub(crate) started: u64,
+    pub(crate) argv: Vec<String>,
+}
+
+impl Launch {
+    /// One tab-separated line: pid, start time, then the argv (tabs and newlines in arguments
+    /// become spaces; the file is for reading, not re-running).
+    fn to_line(&self) -> String {
+        let mut fields = vec![self.pid.to_string(), self.started.to_string()];
+        fields.extend(self.argv.iter().map(|arg| arg.replace(['\t', '\n'], " ")));
+        fields.join("\t")
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        Some(Self {
+            pid: fields.next()?.parse().ok()?,
+            started: fields.next()?.parse().ok()?,
+            argv: fields.map(str::to_owned).collect(),
+        })
+    }
+}
+
+/// Spawns `command` actions and remembers the most recent ones in a state file, which the
+/// `recent` subcommand reads. Clones share what they remember.
+#[derive(Clone)]
+pub(crate) struct Launcher {
+    /// The child handles are kept so finished processes get reaped instead of lingering as
+    /// zombies.
+    recent: Shared<VecDeque<(Launch, Child)>>,
+    state_file: Option<PathBuf>,
+}
+
+impl Launcher {
+    pub(crate) fn new() -> Self {
+        let state_file = match state_file() {
+            Ok(path) => Some(path),
+            Err(err) => {
+                debug!("not recording launches: {err}");
+                None
+            }
+        };
+        Self {
+            recent: Shared::new(VecDeque::with_capacity(CAPACITY)),
+            state_file,
+        }
+    }
+
+    pub(crate) fn spawn(&self, argv: &[String]) {
+        let Some((program, args)) = argv.split_first() else {
+            warn!("ignoring empty command argv");
+            return;
+        };
+        let child = match std::process::Command::new(program).args(args).spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                error!("failed to execute {:?}: {}", argv, err);
+                return;
+            }
+        };
+        info!("executed command: {:?} (pid {})", argv, child.id());
+
+        let mut recent = self.recent.lock();
+        for (_, child) in recent.iter_mut() {
+            let _ = child.try_wait();
+        }
+        if recent.len() == CAPACITY {
+            recent.pop_front();
+        }
+        let launch = Launch {
+            pid: child.id(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            argv: argv.to_vec(),
+        };
+        recent.push_back((launch, child));
+        self.save(&recent);
+    }
+
+    fn save(&self, recent: &VecDeque<(Launch, Child)>) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let mut contents = String::new();
+        for (launch, _) in recent {
+            contents.push_str(&launch.to_line());
+            contents.push('\n');
+        }
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, contents));
+        if let Err(err) = written {
+            debug!("failed to record launch in {}: {err}", path.display());
+        }
+    }
+}
+
+/// Reads the launches recorded by a running (or previous) daemon, oldest first.
+pub(crate) fn read_recent(path: &Path) -> std::io::Result<Vec<Launch>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.lines().filter_map(Launch::parse).collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether `pid` is still a live process (zombies count as finished).
+pub(crate) fn is_running(pid: u32) -> bool {
+    fs::read_to_string(format!("/proc/{pid}/stat")).is_ok_and(|stat| {
+        // The state follows the parenthesized command name, which may itself contain spaces.
+        stat.rsplit_once(") ")
+            .and_then(|(_, rest)| rest.chars().next())
+            .is_some_and(|state| state != 'Z' && state != 'X')
+    })
+}
+
+pub(crate) fn state_file() -> Result<PathBuf, mouse_assist_core::ConfigError> {
+    Ok(mouse_assist_core::runtime_dir()?.join("recent-launches"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launch_lines_round_trip() {
+        let launch = Launch {
+            pid: 4242,
+            started: 1_700_000_000,
+            argv: vec!["notify-send".into(), "two\twords".into()],
+        };
+        let line = launch.to_line();
+        assert_eq!(line, "4242\t1700000000\tnotify-send\ttwo words");
+        let parsed = Launch::parse(&line).unwrap();
+        assert_eq!(parsed.pid, 4242);
+        assert_eq!(parsed.argv, ["notify-send", "two words"]);
+        assert_eq!(Launch::parse("not a launch"), None);
+    }
+}
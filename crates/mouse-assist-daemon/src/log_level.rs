@@ -0,0 +1,35 @@
+use std::sync::OnceLock;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// Swaps the installed filter for another.
+type SetFilter = Box<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync>;
+
+/// How to change the process-wide log filter, and the directives it started with.
+static RELOAD: OnceLock<(SetFilter, String)> = OnceLock::new();
+
+/// Makes [`set`] work; `initial` is what `default` goes back to.
+pub(crate) fn install(set_filter: SetFilter, initial: String) {
+    let _ = RELOAD.set((set_filter, initial));
+}
+
+/// Runs a `set_log_level` action: a level name (see [`mouse_assist_core::LOG_LEVELS`]) or
+/// `default`. Applies to every thread, not just the one that asked.
+pub(crate) fn set(level: &str) {
+    let Some((set_filter, initial)) = RELOAD.get() else {
+        warn!("set_log_level: logging can't be reconfigured in this process; ignoring");
+        return;
+    };
+    let directives = if level.eq_ignore_ascii_case("default") {
+        initial.clone()
+    } else {
+        level.to_ascii_lowercase()
+    };
+    let result = EnvFilter::try_new(&directives)
+        .map_err(|err| err.to_string())
+        .and_then(set_filter);
+    match result {
+        Ok(()) => info!("log level is now {directives:?}"),
+        Err(err) => warn!("set_log_level {level:?} failed: {err}"),
+    }
+}
@@ -0,0 +1,86 @@
+use mouse_assist_core::SystemOp;
+use tracing::{error, info, warn};
+use zbus::blocking::Connection;
+
+const DESTINATION: &str = "org.freedesktop.login1";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+/// The caller's session, or the user's graphical session when (as under `systemd --user`) the
+/// daemon isn't part of one.
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION: &str = "org.freedesktop.login1.Session";
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum LogindError {
+    #[error(transparent)]
+    Dbus(#[from] zbus::Error),
+    #[error("logind says {0} is not allowed for this user ({1:?})")]
+    NotAllowed(&'static str, String),
+}
+
+/// Asks logind to carry out a `system` action; it may still refuse or ask for a password.
+pub(crate) fn run(op: SystemOp) {
+    match call(op) {
+        Ok(()) => info!("{}: requested from logind", op.name()),
+        Err(err) => error!("{} failed: {err}", op.name()),
+    }
+}
+
+fn call(op: SystemOp) -> Result<(), LogindError> {
+    let conn = Connection::system()?;
+    match op {
+        SystemOp::Lock => session_call(&conn, "Lock"),
+        SystemOp::LogOut => session_call(&conn, "Terminate"),
+        SystemOp::Suspend => sleep_call(&conn, "Suspend"),
+        SystemOp::Hibernate => sleep_call(&conn, "Hibernate"),
+    }
+}
+
+/// Sets `/sys/class/<subsystem>/<name>/brightness` through logind, which lets the session's
+/// user do it without write access to sysfs.
+pub(crate) fn set_brightness(subsystem: &str, name: &str, value: u32) -> Result<(), LogindError> {
+    let conn = Connection::system()?;
+    conn.call_method(
+        Some(DESTINATION),
+        SESSION_PATH,
+        Some(SESSION),
+        "SetBrightness",
+        &(subsystem, name, value),
+    )?;
+    Ok(())
+}
+
+fn session_call(conn: &Connection, method: &'static str) -> Result<(), LogindError> {
+    conn.call_method(Some(DESTINATION), SESSION_PATH, Some(SESSION), method, &())?;
+    Ok(())
+}
+
+/// Asks first with `Can<method>` so a policy refusal gets a clear message. A block inhibitor
+/// (e.g. a running update) still makes the call itself fail, and that error is passed on.
+fn sleep_call(conn: &Connection, method: &'static str) -> Result<(), LogindError> {
+    let answer: String = conn
+        .call_method(
+            Some(DESTINATION),
+            MANAGER_PATH,
+            Some(MANAGER),
+            format!("Can{method}").as_str(),
+            &(),
+        )?
+        .body()
+        .deserialize()?;
+    match answer.as_str() {
+        "yes" => {}
+        // polkit will ask for authentication.
+        "challenge" => warn!("{method} needs authentication; expect a prompt"),
+        _ => return Err(LogindError::NotAllowed(method, answer)),
+    }
+    // `interactive`: allow that polkit prompt instead of failing outright.
+    conn.call_method(
+        Some(DESTINATION),
+        MANAGER_PATH,
+        Some(MANAGER),
+        method,
+        &(true,),
+    )?;
+    Ok(())
+}
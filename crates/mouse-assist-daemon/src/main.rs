@@ -1,26 +1,97 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use coalesce::Coalescer;
+use device_class::DeviceKind;
+use dispatch::{Dispatcher, Input};
+use epoll::Epoll;
+use grab::{GrabFilter, Passthrough};
+use hotplug::{Change, DeviceWatch};
+use launches::Launcher;
 use mouse_assist_core::{
-    default_config_path, load_config, save_config, Action, Config, MouseButton,
+    default_config_path, load_config, load_system_policy, parse_config, save_config,
+    save_config_with_style, Action, Config, ConfigFormat, KeyPolicy, KeyReleaseOrder, KeyTarget,
+    MonitorDirection, MouseButton, NamedTarget, RawEvent, ScrollAxis, Step, TiltSource, TomlStyle,
+    WindowTarget, X11Config, X11KeyMethod, DEFAULT_CROSS_DEVICE_COALESCE_MS, SYSTEM_POLICY_PATH,
 };
+use regex::Regex;
+use reload::Reloader;
 use std::fs;
+use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
-use tracing::{error, info, warn};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use worker::{ActionQueue, Executor, Shared};
 use x11rb::connection::Connection as _;
 use x11rb::protocol::{xinput, xproto, Event};
 use x11rb::protocol::{
-    xinput::ConnectionExt as _, xproto::ConnectionExt as _, xtest::ConnectionExt as _,
+    xinput::ConnectionExt as _, xkb::ConnectionExt as _, xproto::ConnectionExt as _,
+    xtest::ConnectionExt as _,
 };
 
+mod backlight;
+mod capture;
+mod clipboard;
+mod coalesce;
+mod command_notify;
+mod desktop_settings;
+mod device_class;
+mod dispatch;
+mod epoll;
+mod export_script;
+mod grab;
+mod hotplug;
+mod launches;
+mod log_level;
+mod logind;
+mod panic_cleanup;
+mod priority;
+mod privs;
+mod profile_state;
+mod recording;
+mod reload;
+mod retry;
+mod screenshot;
+mod script;
+mod status;
+mod wayland_keyboard;
+mod webhook;
+mod wm_ipc;
+mod worker;
+mod x11_devices;
+mod x11_fullscreen;
+mod x11_keymap;
+mod x11_scroll;
+mod x11_windows;
+
 #[derive(Parser, Debug)]
 #[command(name = "mouse-assist-daemon")]
 #[command(about = "Remap mouse buttons to system actions", long_about = None)]
 struct Cli {
+    /// Log more: -v for info, -vv for debug, -vvv for trace. Overrides RUST_LOG.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Log at this level (off, error, warn, info, debug, trace). Overrides RUST_LOG.
+    #[arg(long, value_name = "LEVEL", global = true, conflicts_with = "verbose")]
+    log_level: Option<LevelFilter>,
     #[command(subcommand)]
     command: Command,
 }
 
+impl Cli {
+    /// The level asked for on the command line, if any.
+    fn requested_log_level(&self) -> Option<LevelFilter> {
+        self.log_level.or(match self.verbose {
+            0 => None,
+            1 => Some(LevelFilter::INFO),
+            2 => Some(LevelFilter::DEBUG),
+            _ => Some(LevelFilter::TRACE),
+        })
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Print the default config path and exit.
@@ -33,28 +104,274 @@ enum Command {
         /// Overwrite if the file already exists.
         #[arg(long)]
         force: bool,
+        /// Write bindings as a single inline `bindings = [...]` array.
+        #[arg(long)]
+        compact: bool,
+        /// Which starter bindings to write: `browser-nav` (back/forward, tab switching),
+        /// `media` (tracks and volume), `minimal` (back/forward only) or `default`.
+        #[arg(
+            long,
+            value_name = "NAME",
+            default_value = "default",
+            value_parser = clap::builder::PossibleValuesParser::new(mouse_assist_core::TEMPLATES)
+        )]
+        template: String,
     },
     /// List /dev/input/event* devices (best-effort; may require permissions).
-    ListDevices,
+    ListDevices {
+        /// List the X server's pointers instead, with the ids `run --x11-device` takes.
+        #[arg(long)]
+        x11: bool,
+    },
+    /// Show the session environment and which backend `run` would pick from it, and why.
+    Detect,
+    /// List the programs recently started by `command` actions, with their PIDs.
+    Recent,
+    /// Check config files and exit: 0 = ok, 1 = warnings only, 2 = errors (or unparseable).
+    ///
+    /// With several files, or a directory (every `*.toml` under it), each file is reported on
+    /// its own and the exit code is the worst of them.
+    Validate {
+        /// Path to a config.toml (defaults to the standard config location).
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// More config files or directories to check.
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+    /// Print the config `run` would actually use: macros expanded, paths resolved, the system
+    /// key policy applied, and settings the chosen backend ignores dropped.
+    DumpConfig {
+        /// Path to a config.toml (defaults to the standard config location).
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Backend to resolve for, as with `run --backend`.
+        #[arg(long, value_enum, default_value_t = Backend::Auto)]
+        backend: Backend,
+        /// Show the bindings that are active while this profile is held.
+        #[arg(long)]
+        profile: Option<String>,
+        /// As with `run --device`.
+        #[arg(long)]
+        device: Option<PathBuf>,
+        /// As with `run --device-name`.
+        #[arg(long, value_name = "REGEX")]
+        device_name: Option<String>,
+    },
+    /// Print the bindings as a shell script of `xdotool` (X11) or `ydotool` (evdev) commands,
+    /// one function per binding. A best-effort approximation; see the script's header.
+    ExportScript {
+        /// Path to a config.toml (defaults to the standard config location).
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Backend to write commands for, as with `run --backend`: `x11` uses xdotool, `evdev`
+        /// ydotool.
+        #[arg(long, value_enum, default_value_t = Backend::Auto)]
+        backend: Backend,
+    },
+    /// Print the config in TOML or JSON, as written (macros and inheritance left as they are).
+    ExportConfig {
+        /// Path to a config.toml (defaults to the standard config location).
+        #[arg(long)]
+        config: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = Format::Toml)]
+        format: Format,
+        /// Write to this file instead of standard output.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Replace the config with one in TOML or JSON, such as `export-config` writes. It's
+    /// checked like `validate` does first, and written as TOML.
+    ImportConfig {
+        /// The config to read, or `-` for standard input.
+        #[arg(long = "in", value_name = "PATH")]
+        input: PathBuf,
+        /// The format of the input; by default, taken from its `.toml` or `.json` extension.
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// Path to write the config.toml to (defaults to the standard config location).
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Replace the config file if there already is one.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Record mouse button and wheel tilt events (evdev) to a file, one JSON object per line,
+    /// until interrupted.
+    Record {
+        /// Where to write the recording.
+        file: PathBuf,
+        /// Record from a single /dev/input/eventX device node.
+        #[arg(long)]
+        device: Option<PathBuf>,
+        /// Only record from devices whose name matches this regex.
+        #[arg(long, value_name = "REGEX")]
+        device_name: Option<String>,
+    },
+    /// Write every raw event a device reports to a file, for attaching to bug reports.
+    ///
+    /// The first line describes the device (name, classification and input ids); each line
+    /// after that is one event as JSON (`type`, `code`, `value`, and `time_us` since the first
+    /// event). Runs until interrupted, or for `--seconds`.
+    Capture {
+        /// The /dev/input/eventX device node to capture.
+        #[arg(long)]
+        device: PathBuf,
+        /// Where to write the capture.
+        #[arg(long)]
+        out: PathBuf,
+        /// Stop after this many seconds.
+        #[arg(long, value_name = "N")]
+        seconds: Option<u64>,
+    },
+    /// Feed a recording through the bindings with its original timing, running the actions.
+    Replay {
+        /// A file written by `record`.
+        file: PathBuf,
+        /// Path to a config.toml (defaults to the standard config location).
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Which backend executes the actions, as with `run --backend`.
+        #[arg(long, value_enum, default_value_t = Backend::Auto)]
+        backend: Backend,
+    },
     /// Run the background event loop (defaults to all matching devices).
     Run {
         /// Restrict to a single /dev/input/eventX device node.
         #[arg(long)]
         device: Option<PathBuf>,
+        /// Only use devices whose name matches this regex (overrides `device_name` in the config).
+        ///
+        /// Applied on top of the usual filtering by bound buttons, so e.g. `"Logitech"` picks
+        /// every Logitech device that has one of the configured buttons.
+        #[arg(long, value_name = "REGEX")]
+        device_name: Option<String>,
+        /// With the X11 backend, only react to this pointer: its xinput id, its name, or a part
+        /// of the name only it has (see `list-devices --x11`).
+        #[arg(long, value_name = "ID|NAME")]
+        x11_device: Option<String>,
         /// Path to a config.toml (defaults to the standard config location).
         #[arg(long)]
         config: Option<PathBuf>,
-        /// On X11: grab configured mouse buttons so applications won't receive them.
+        /// Which input backend to use; `auto` decides from the session (see `detect`).
+        #[arg(long, value_enum, default_value_t = Backend::Auto)]
+        backend: Backend,
+        /// Grab configured mouse buttons so applications won't receive them.
         ///
         /// This is useful to prevent browsers from handling back/forward side buttons (8/9),
         /// avoiding "double actions" when you bind those buttons to something else.
         ///
-        /// For safety, this only grabs X11 buttons 6-9 (horizontal wheel + side buttons).
+        /// For safety, this only grabs side buttons and wheel tilt (X11 buttons 6-9). On evdev
+        /// the whole device is grabbed and every other event is re-emitted on a virtual pointer.
         #[arg(long)]
         grab: bool,
+        /// After opening (and grabbing) devices, drop root or file capabilities for the rest of
+        /// the run.
+        ///
+        /// When started as root (sudo, pkexec, setuid), switches to the invoking user; when
+        /// started with file capabilities, clears them. Already-open devices keep working.
+        #[arg(long)]
+        drop_privileges: bool,
+        /// If no device can be used yet, keep retrying for up to this many seconds.
+        ///
+        /// At login the daemon can start before udev has made the mouse's event node readable;
+        /// a few seconds of waiting avoids exiting with nothing to listen to. Ignored by the X11
+        /// backend.
+        #[arg(long, value_name = "SECS", default_value_t = 0)]
+        wait_for_device: u64,
+        /// Wait this many milliseconds before opening devices or connecting to X (overrides
+        /// `startup_delay_ms` in the config).
+        ///
+        /// Started from autostart, the daemon can come up before the X server or compositor has
+        /// settled; a short delay avoids failing or misfiring in that window. Combines with
+        /// `--wait-for-device`, which starts counting after the delay.
+        #[arg(long, value_name = "MS")]
+        startup_delay: Option<u64>,
+        /// Run at this niceness (-20 to 19; lower is favored) to cut input latency jitter.
+        ///
+        /// Going below 0 needs root or CAP_SYS_NICE; without them a warning is logged and the
+        /// daemon runs at normal priority.
+        #[arg(
+            long,
+            value_name = "N",
+            allow_negative_numbers = true,
+            value_parser = clap::value_parser!(i32).range(-20..=19),
+            conflicts_with = "rt_priority"
+        )]
+        nice: Option<i32>,
+        /// Run with real-time (SCHED_FIFO) priority N (1 to 99), ahead of every normal process.
+        ///
+        /// Needs root, CAP_SYS_NICE or an RLIMIT_RTPRIO allowance; without them a warning is
+        /// logged and the daemon runs at normal priority.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..=99))]
+        rt_priority: Option<i32>,
+        /// Don't watch the config file; by default, saved changes to it take effect right away.
+        #[arg(long)]
+        no_reload: bool,
+        /// Log the actions that would fire, with the keys they'd send, instead of running them.
+        ///
+        /// Actions create no virtual devices and launch nothing, so this checks that buttons are
+        /// seen without needing `/dev/uinput` access (`--grab` still needs it for passthrough).
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
+/// Flags shared by every `run` backend.
+#[derive(Debug, Clone)]
+struct RunOptions {
+    grab: bool,
+    drop_privileges: bool,
+    /// Keys that actions are allowed to inject.
+    keys: KeyPolicy,
+    /// Only devices whose name matches are used when enumerating.
+    device_name: Option<Regex>,
+    /// How long to keep retrying at startup when no device can be opened.
+    wait_for_device: Duration,
+    /// What the status socket reports.
+    status: Arc<status::Status>,
+    /// The backend in use; [`Backend::Wayland`] sends keys through the compositor.
+    backend: Backend,
+    /// Log actions instead of running them.
+    dry_run: bool,
+}
+
+impl RunOptions {
+    /// Called once all devices are open, right before the event loop starts.
+    fn enter_event_loop(&self) -> Result<(), AppError> {
+        if self.drop_privileges {
+            privs::drop_privileges()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Auto,
+    X11,
+    Evdev,
+    /// evdev input, with keys sent through the compositor's virtual keyboard protocol instead
+    /// of uinput.
+    Wayland,
+}
+
+/// A config format for `export-config` and `import-config`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Json,
+}
+
+impl From<Format> for ConfigFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Toml => Self::Toml,
+            Format::Json => Self::Json,
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 enum AppError {
     #[error(transparent)]
@@ -67,319 +384,1697 @@ enum AppError {
     X11Connection(#[from] x11rb::errors::ConnectionError),
     #[error("x11 reply error: {0}")]
     X11Reply(#[from] x11rb::errors::ReplyError),
+    #[error("no profile named {0:?} in the config")]
+    UnknownProfile(String),
+    #[error("no bindings configured in {} \u{2014} nothing to do", .0.display())]
+    NoBindings(PathBuf),
+    #[error("{} has {count} problem(s); see above, or run `validate`", .path.display())]
+    InvalidConfig { path: PathBuf, count: usize },
+    #[error("no {0}")]
+    MissingVirtualDevice(String),
+    #[error("can't tell the format of {} from its name; pass --format toml or --format json", .0.display())]
+    UnknownConfigFormat(PathBuf),
+    #[error("{} already exists; pass --force to replace it", .0.display())]
+    ConfigExists(PathBuf),
+    #[error("no X11 pointer is {0:?}, or more than one matches (see `list-devices --x11`)")]
+    UnknownX11Device(String),
+    #[error("recording: {0}")]
+    Recording(#[from] recording::RecordingError),
+    #[error("invalid device name regex {pattern:?}: {source}")]
+    DeviceNameRegex {
+        pattern: String,
+        source: regex::Error,
+    },
 }
 
-fn main() -> Result<(), AppError> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+fn device_name_regex(pattern: &str) -> Result<Regex, AppError> {
+    Regex::new(pattern).map_err(|source| AppError::DeviceNameRegex {
+        pattern: pattern.to_owned(),
+        source,
+    })
+}
 
+fn main() -> Result<(), AppError> {
     let cli = Cli::parse();
 
+    // A level given on the command line replaces RUST_LOG entirely, so `-v` always means the
+    // same thing, whatever the service's environment says.
+    let level = cli.requested_log_level();
+    let filter = match level {
+        Some(level) => EnvFilter::new(level.to_string()),
+        None => EnvFilter::from_default_env(),
+    };
+    let initial_filter = filter.to_string();
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_filter_reloading();
+    let reload = subscriber.reload_handle();
+    subscriber.init();
+    // So a `set_log_level` action can change it while running.
+    log_level::install(
+        Box::new(move |filter| reload.reload(filter).map_err(|err| err.to_string())),
+        initial_filter,
+    );
+    if level.is_some() && std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        info!("ignoring RUST_LOG in favor of the log level given on the command line");
+    }
+    panic_cleanup::install();
+
     match cli.command {
         Command::ConfigPath => {
             println!("{}", default_config_path()?.display());
         }
-        Command::WriteDefaultConfig { path, force } => {
+        Command::WriteDefaultConfig {
+            path,
+            force,
+            compact,
+            template,
+        } => {
             let path = path.unwrap_or(default_config_path()?);
             if path.exists() && !force {
                 warn!("config already exists: {}", path.display());
                 return Ok(());
             }
-            save_config(&path, &Config::default())?;
+            let style = if compact {
+                TomlStyle::Compact
+            } else {
+                TomlStyle::Tables
+            };
+            let config = Config::template(&template).expect("clap only accepts known templates");
+            save_config_with_style(&path, &config, style)?;
             info!("wrote config: {}", path.display());
         }
-        Command::ListDevices => {
+        Command::ListDevices { x11: false } => {
             list_devices()?;
         }
+        Command::ListDevices { x11: true } => {
+            list_x11_pointers()?;
+        }
+        Command::Detect => {
+            print_detection();
+        }
+        Command::Recent => {
+            print_recent_launches()?;
+        }
+        Command::Validate { config, mut paths } => {
+            if let Some(config) = config {
+                paths.insert(0, config);
+            }
+            if paths.is_empty() {
+                paths.push(default_config_path()?);
+            }
+            if let [path] = &paths[..] {
+                if !path.is_dir() {
+                    std::process::exit(validate_config_file(path, false));
+                }
+            }
+            std::process::exit(validate_config_files(&paths)?);
+        }
+        Command::DumpConfig {
+            config,
+            backend,
+            profile,
+            device,
+            device_name,
+        } => {
+            let config_path = config.unwrap_or(default_config_path()?);
+            print!(
+                "{}",
+                dump_config(
+                    &config_path,
+                    backend,
+                    profile.as_deref(),
+                    device,
+                    device_name
+                )?
+            );
+        }
+        Command::ExportScript { config, backend } => {
+            let config_path = config.unwrap_or(default_config_path()?);
+            let config = resolve_config(load_config(&config_path)?, &config_path)?;
+            let tool = match choose_backend(backend, false, false).0 {
+                Backend::X11 => export_script::Tool::Xdotool,
+                _ => export_script::Tool::Ydotool,
+            };
+            print!("{}", export_script::render(&config, &config_path, tool));
+        }
+        Command::ExportConfig {
+            config,
+            format,
+            out,
+        } => {
+            let config = load_config(&config.unwrap_or(default_config_path()?))?;
+            let raw = match format {
+                Format::Toml => config.to_toml_string()?,
+                Format::Json => config.to_json_string()?,
+            };
+            match out {
+                Some(out) => {
+                    fs::write(&out, raw)?;
+                    info!("wrote config: {}", out.display());
+                }
+                None => print!("{raw}"),
+            }
+        }
+        Command::ImportConfig {
+            input,
+            format,
+            config,
+            force,
+        } => {
+            let config_path = config.unwrap_or(default_config_path()?);
+            import_config(&input, format, &config_path, force)?;
+            info!("wrote config: {}", config_path.display());
+        }
+        Command::Record {
+            file,
+            device,
+            device_name,
+        } => {
+            let device_name = device_name
+                .map(|pattern| device_name_regex(&pattern))
+                .transpose()?;
+            record(&file, device, device_name.as_ref())?;
+        }
+        Command::Capture {
+            device,
+            out,
+            seconds,
+        } => capture(&device, &out, seconds.map(Duration::from_secs))?,
+        Command::Replay {
+            file,
+            config,
+            backend,
+        } => {
+            let config_path = config.unwrap_or(default_config_path()?);
+            replay(&file, &config_path, backend)?;
+        }
         Command::Run {
             device,
+            device_name,
+            x11_device,
             config,
+            backend,
             grab,
+            drop_privileges,
+            wait_for_device,
+            startup_delay,
+            nice,
+            rt_priority,
+            no_reload,
+            dry_run,
         } => {
+            // On the thread that reads input, and before dropping privileges.
+            let priority = rt_priority
+                .map(priority::Priority::RealTime)
+                .or(nice.map(priority::Priority::Nice));
+            if let Some(priority) = priority {
+                priority::apply(priority);
+            }
             let config_path = config.unwrap_or(default_config_path()?);
-            let config = if config_path.exists() {
-                load_config(&config_path)?
-            } else {
+            if !config_path.exists() {
                 warn!(
                     "config not found (creating default): {}",
                     config_path.display()
                 );
-                let cfg = Config::default();
-                save_config(&config_path, &cfg)?;
-                cfg
-            };
-            if let Some(device_path) =
-                device.or_else(|| config.device_by_path.as_ref().map(PathBuf::from))
-            {
-                run_device(&device_path, &config)?;
-            } else if is_x11_session() {
-                run_x11(&config, grab)?;
+                save_config(&config_path, &Config::default())?;
+            }
+            // `main` only prints the error's debug form.
+            let config = load_run_config(&config_path).inspect_err(|err| error!("{err}"))?;
+            let system_policy = load_system_policy(Path::new(SYSTEM_POLICY_PATH))?;
+            let mut reloader = if no_reload {
+                None
             } else {
-                run_all_devices(&config)?;
+                match Reloader::watch(&config_path, system_policy.clone()) {
+                    Ok(reloader) => Some(reloader),
+                    Err(err) => {
+                        warn!("not watching {} for changes: {err}", config_path.display());
+                        None
+                    }
+                }
+            };
+            // No button could ever do anything. Wait for one to be bound, or, when the config
+            // isn't watched, say so rather than sit there (X11) or exit over "no matching
+            // devices" (evdev).
+            let config = match reloader.as_mut() {
+                _ if config.all_bindings().next().is_some() => config,
+                Some(reloader) => {
+                    info!(
+                        "no bindings configured in {}; waiting for the config to change",
+                        config_path.display()
+                    );
+                    loop {
+                        let reload = reloader.wait();
+                        if reload.config.all_bindings().next().is_some() {
+                            break reload.config;
+                        }
+                    }
+                }
+                None => {
+                    let err = AppError::NoBindings(config_path);
+                    error!("{err}");
+                    return Err(err);
+                }
+            };
+            let device_name = device_name
+                .or_else(|| config.device_name.clone())
+                .map(|pattern| device_name_regex(&pattern))
+                .transpose()?;
+            let device_path = device.or_else(|| config.device_by_path.as_ref().map(PathBuf::from));
+            let (backend, reason) =
+                choose_backend(backend, device_path.is_some(), device_name.is_some());
+            let options = RunOptions {
+                grab,
+                drop_privileges,
+                keys: KeyPolicy::new(&config, &system_policy),
+                device_name,
+                wait_for_device: Duration::from_secs(wait_for_device),
+                status: Arc::new(status::Status::new()),
+                backend,
+                dry_run,
+            };
+            if dry_run {
+                info!("dry run: actions are logged, not run");
             }
-        }
-    }
-
-    Ok(())
-}
-
-fn is_x11_session() -> bool {
-    match std::env::var("XDG_SESSION_TYPE") {
-        Ok(t) if t == "x11" => return true,
-        Ok(t) if t == "wayland" => return false,
-        _ => {}
-    }
-    std::env::var_os("DISPLAY").is_some() && std::env::var_os("WAYLAND_DISPLAY").is_none()
-}
-
-fn list_devices() -> Result<(), AppError> {
-    let mut entries: Vec<PathBuf> = Vec::new();
-    for entry in fs::read_dir("/dev/input")? {
-        let path = entry?.path();
-        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            if name.starts_with("event") {
-                entries.push(path);
+            // Removes the socket file when `run` returns.
+            let _status_socket = status::serve(Arc::clone(&options.status));
+            if options.keys.is_restricted() {
+                info!("key injection limited to an allowlist");
             }
-        }
-    }
-    entries.sort();
-
-    for path in entries {
-        match evdev::Device::open(&path) {
-            Ok(dev) => {
-                let name = dev.name().unwrap_or("<unknown>");
-                println!("{}  {}", path.display(), name);
+            let startup_delay = startup_delay.or(config.startup_delay_ms).unwrap_or(0);
+            if startup_delay > 0 {
+                info!("waiting {startup_delay}ms for the session to settle before starting");
+                std::thread::sleep(Duration::from_millis(startup_delay));
             }
-            Err(err) => {
-                println!("{}  <unreadable: {}>", path.display(), err);
+            if let Some(reason) = reason {
+                info!("{reason}");
             }
-        }
-    }
-    Ok(())
-}
-
-fn run_device(device_path: &Path, config: &Config) -> Result<(), AppError> {
-    info!("opening device: {}", device_path.display());
-    let mut dev = evdev::Device::open(device_path)?;
-    info!("device name: {}", dev.name().unwrap_or("<unknown>"));
-    dev.set_nonblocking(false)?;
-
-    let mut executor = ActionExecutor::new(config)?;
-
-    loop {
-        for ev in dev.fetch_events()? {
-            if let evdev::EventSummary::Key(_event, keycode, value) = ev.destructure() {
-                if value == 1 {
-                    let code = keycode.code();
-                    if let Some(binding) = config
-                        .bindings
-                        .iter()
-                        .find(|b| b.button.linux_key_code() == Some(code))
-                    {
-                        executor.execute_action(&binding.action);
+            options.status.set_backend(match backend {
+                Backend::X11 => "x11",
+                Backend::Wayland => "wayland",
+                _ => "evdev",
+            });
+            match (backend, device_path) {
+                (Backend::X11, device_path) => {
+                    if let Some(path) = device_path {
+                        warn!(
+                            "ignoring device {} (the X11 backend listens to all pointers)",
+                            path.display()
+                        );
                     }
+                    if let Some(name) = &options.device_name {
+                        warn!("ignoring device name filter {name} (the X11 backend listens to all pointers)");
+                    }
+                    run_x11(&config, &options, x11_device.as_deref(), reloader)?;
                 }
-            }
-            if let evdev::EventSummary::RelativeAxis(_event, axis, value) = ev.destructure() {
-                if let Some(tilt) = wheel_tilt_from_relative_axis(axis, value) {
-                    let button = match tilt {
-                        WheelTilt::Left => MouseButton::WheelTiltLeft,
-                        WheelTilt::Right => MouseButton::WheelTiltRight,
-                    };
-                    if let Some(binding) = config.bindings.iter().find(|b| b.button == button) {
-                        executor.execute_action(&binding.action);
+                (_, device_path) if x11_device.is_some() => {
+                    warn!("ignoring --x11-device (only the X11 backend uses it)");
+                    match device_path {
+                        Some(path) => run_device(&path, &config, &options, reloader)?,
+                        None => run_all_devices(&config, &options, reloader)?,
+                    }
+                }
+                (_, Some(device_path)) => {
+                    if let Some(name) = &options.device_name {
+                        warn!("ignoring device name filter {name} (a single device was given)");
                     }
+                    run_device(&device_path, &config, &options, reloader)?
                 }
+                (_, None) => run_all_devices(&config, &options, reloader)?,
             }
         }
     }
+
+    Ok(())
 }
 
-fn run_all_devices(config: &Config) -> Result<(), AppError> {
-    let key_binding_codes: Vec<evdev::KeyCode> = config
-        .bindings
-        .iter()
-        .filter_map(|b| b.button.linux_key_code().map(evdev::KeyCode::new))
-        .collect();
-    let wants_wheel_tilt = config.bindings.iter().any(|b| {
-        matches!(
-            b.button,
-            MouseButton::WheelTiltLeft | MouseButton::WheelTiltRight
-        )
-    });
+fn print_recent_launches() -> Result<(), AppError> {
+    let launches = launches::read_recent(&launches::state_file()?)?;
+    if launches.is_empty() {
+        println!("no commands launched yet");
+        return Ok(());
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    for launch in launches.iter().rev() {
+        let state = if launches::is_running(launch.pid) {
+            "running"
+        } else {
+            "exited"
+        };
+        println!(
+            "{:>7}  {state:<7}  {:>5}s ago  {:?}",
+            launch.pid,
+            now.saturating_sub(launch.started),
+            launch.argv
+        );
+    }
+    Ok(())
+}
 
-    let mut devices: Vec<(PathBuf, evdev::Device)> = evdev::enumerate()
-        .filter_map(|(path, dev)| {
-            let keys_match = dev.supported_keys().map_or(false, |keys| {
-                key_binding_codes.iter().any(|c| keys.contains(*c))
-            });
-            let rel_match = wants_wheel_tilt
-                && dev.supported_relative_axes().map_or(false, |axes| {
-                    axes.contains(evdev::RelativeAxisCode::REL_HWHEEL)
-                        || axes.contains(evdev::RelativeAxisCode::REL_HWHEEL_HI_RES)
-                });
-            if !keys_match && !rel_match {
-                return None;
-            }
-            if let Err(err) = dev.set_nonblocking(true) {
-                warn!("failed to set nonblocking for {}: {err}", path.display());
+fn record(
+    path: &Path,
+    device: Option<PathBuf>,
+    device_name: Option<&Regex>,
+) -> Result<(), AppError> {
+    let devices: Vec<(PathBuf, evdev::Device)> = match device {
+        Some(device) => {
+            let dev = evdev::Device::open(&device)?;
+            vec![(device, dev)]
+        }
+        None => {
+            if let Some(problem) = missing_input_devices() {
+                warn!("{problem}");
+                return Ok(());
             }
-            Some((path, dev))
-        })
-        .collect();
-
+            evdev::enumerate()
+                .filter(|(_, dev)| {
+                    device_name.is_none_or(|pattern| pattern.is_match(dev.name().unwrap_or("")))
+                })
+                .filter(|(_, dev)| is_pointer(dev))
+                .collect()
+        }
+    };
     if devices.is_empty() {
-        warn!("no input devices matched current bindings; try `list-devices` or pass `--device`");
+        warn!("no mouse found to record from; try `list-devices` or pass `--device`");
         return Ok(());
     }
 
-    info!("listening on {} device(s)", devices.len());
-    for (path, dev) in &devices {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    for (device, mut dev) in devices {
         info!(
-            "device: {} ({})",
-            path.display(),
+            "recording {} ({})",
+            device.display(),
             dev.name().unwrap_or("<unknown>")
         );
-    }
-
-    let mut executor = ActionExecutor::new(config)?;
-
-    loop {
-        let mut saw_any = false;
-        let mut i = 0;
-        while i < devices.len() {
-            let path_for_log = devices[i].0.clone();
-            let mut remove = false;
-            let mut remove_reason: Option<std::io::Error> = None;
-
-            {
-                let (_path, dev) = &mut devices[i];
+        let tx = tx.clone();
+        std::thread::Builder::new()
+            .name("record".into())
+            .spawn(move || loop {
                 match dev.fetch_events() {
                     Ok(events) => {
                         for ev in events {
-                            if let evdev::EventSummary::Key(_event, keycode, value) =
-                                ev.destructure()
-                            {
-                                if value == 1 {
-                                    saw_any = true;
-                                    let code = keycode.code();
-                                    if let Some(binding) = config
-                                        .bindings
-                                        .iter()
-                                        .find(|b| b.button.linux_key_code() == Some(code))
-                                    {
-                                        executor.execute_action(&binding.action);
-                                    }
-                                }
-                            }
-                            if let evdev::EventSummary::RelativeAxis(_event, axis, value) =
-                                ev.destructure()
-                            {
-                                if let Some(tilt) = wheel_tilt_from_relative_axis(axis, value) {
-                                    saw_any = true;
-                                    let button = match tilt {
-                                        WheelTilt::Left => MouseButton::WheelTiltLeft,
-                                        WheelTilt::Right => MouseButton::WheelTiltRight,
-                                    };
-                                    if let Some(binding) =
-                                        config.bindings.iter().find(|b| b.button == button)
-                                    {
-                                        executor.execute_action(&binding.action);
-                                    }
-                                }
+                            if tx.send(ev).is_err() {
+                                return;
                             }
                         }
                     }
-                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
                     Err(err) => {
-                        remove = true;
-                        remove_reason = Some(err);
+                        warn!("stopped reading {}: {err}", device.display());
+                        return;
                     }
                 }
-            }
-
-            if remove {
-                let err = remove_reason.expect("remove implies error");
-                warn!(
-                    "dropping device {} due to error: {err}",
-                    path_for_log.display()
-                );
-                devices.remove(i);
-            } else {
-                i += 1;
-            }
-        }
-
-        if devices.is_empty() {
-            warn!("no devices left to read; exiting");
-            return Ok(());
-        }
+            })?;
+    }
+    drop(tx);
 
-        if !saw_any {
-            std::thread::sleep(Duration::from_millis(5));
+    let mut recorder = recording::Recorder::create(path)?;
+    println!("recording to {}; press Ctrl-C to stop", path.display());
+    for ev in rx {
+        if let Some((button, value)) = recording::recordable(&ev) {
+            recorder.record(button, value)?;
         }
     }
+    Ok(())
 }
 
-fn run_x11(config: &Config, grab: bool) -> Result<(), AppError> {
-    let (conn, screen_num) = x11rb::connect(None)?;
-    let root = conn.setup().roots[screen_num].root;
-
-    conn.xtest_get_version(2, 2)?.reply()?;
+fn capture(device: &Path, out: &Path, duration: Option<Duration>) -> Result<(), AppError> {
+    let mut dev = evdev::Device::open(device)?;
+    let mut capture = capture::Capture::start(
+        std::io::BufWriter::new(std::fs::File::create(out)?),
+        device,
+        &dev,
+    )?;
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let source = device.to_path_buf();
+    std::thread::Builder::new()
+        .name("capture".into())
+        .spawn(move || loop {
+            match dev.fetch_events() {
+                Ok(events) => {
+                    if events.into_iter().try_for_each(|ev| tx.send(ev)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    warn!("stopped reading {}: {err}", source.display());
+                    return;
+                }
+            }
+        })?;
+
+    match duration {
+        Some(duration) => println!(
+            "capturing {} to {} for {}s",
+            device.display(),
+            out.display(),
+            duration.as_secs()
+        ),
+        None => println!(
+            "capturing {} to {}; press Ctrl-C to stop",
+            device.display(),
+            out.display()
+        ),
+    }
+    let deadline = duration.map(|duration| Instant::now() + duration);
+    let mut count = 0_u64;
+    while let Some(ev) = match deadline {
+        Some(deadline) => rx.recv_deadline(deadline).ok(),
+        None => rx.recv().ok(),
+    } {
+        capture.event(&ev)?;
+        count += 1;
+    }
+    println!("captured {count} event(s)");
+    Ok(())
+}
 
-    if grab {
-        grab_x11_buttons(&conn, root, config)?;
+/// `evdev.input_latency_compensation_ms` for devices that look wireless, else nothing.
+fn input_latency(config: &Config, dev: &evdev::Device) -> Duration {
+    let Some(ms) = config.evdev.input_latency_compensation_ms else {
+        return Duration::ZERO;
+    };
+    let bluetooth = dev.input_id().bus_type() == evdev::BusType::BUS_BLUETOOTH
+        || dev
+            .name()
+            .is_some_and(|name| name.to_lowercase().contains("bluetooth"));
+    if bluetooth {
+        Duration::from_millis(ms)
     } else {
-        conn.xinput_xi_query_version(2, 0)?.reply()?;
-        conn.xinput_xi_select_events(
-            root,
-            &[xinput::EventMask {
-                deviceid: 0,
-                mask: vec![xinput::XIEventMask::RAW_BUTTON_PRESS],
-            }],
-        )?;
-        conn.flush()?;
+        Duration::ZERO
     }
+}
 
-    let mut executor = X11Executor::new(conn, root, config)?;
+/// Whether `dev` has any button or wheel tilt a binding could name.
+fn is_pointer(dev: &evdev::Device) -> bool {
+    let has_button = dev.supported_keys().is_some_and(|keys| {
+        MouseButton::ALL
+            .iter()
+            .filter_map(|b| b.linux_key_code())
+            .any(|code| keys.contains(evdev::KeyCode::new(code)))
+    });
+    let has_tilt = dev.supported_relative_axes().is_some_and(|axes| {
+        axes.contains(evdev::RelativeAxisCode::REL_HWHEEL)
+            || axes.contains(evdev::RelativeAxisCode::REL_HWHEEL_HI_RES)
+    });
+    has_button || has_tilt
+}
 
-    loop {
-        match executor.conn.wait_for_event()? {
-            Event::ButtonPress(ev) if grab => executor.on_button_press(u32::from(ev.detail)),
-            Event::XinputRawButtonPress(ev) if !grab => executor.on_button_press(ev.detail),
-            _ => {}
+fn replay(path: &Path, config_path: &Path, backend: Backend) -> Result<(), AppError> {
+    let events = recording::read(path)?;
+    let config = resolve_config(load_config(config_path)?, config_path)?;
+    let keys = KeyPolicy::new(&config, &load_system_policy(Path::new(SYSTEM_POLICY_PATH))?);
+    let (backend, reason) = choose_backend(backend, false, false);
+    if let Some(reason) = reason {
+        info!("{reason}");
+    }
+    info!(
+        "replaying {} event(s) from {}",
+        events.len(),
+        path.display()
+    );
+    match backend {
+        Backend::X11 => {
+            let (conn, screen_num) = x11rb::connect(None)?;
+            let conn = Arc::new(conn);
+            let root = conn.setup().roots[screen_num].root;
+            conn.xtest_get_version(2, 2)?.reply()?;
+            let executor = X11Executor::new(
+                conn,
+                root,
+                keys,
+                config.keymap.as_deref(),
+                config.key_release_order,
+                &config.x11,
+            )?;
+            replay_with(&config, &events, executor)
+        }
+        backend => {
+            let executor = ActionExecutor::new(&config, keys, backend)?;
+            replay_with(&config, &events, executor)
         }
     }
 }
 
-fn grab_x11_buttons(
-    conn: &x11rb::rust_connection::RustConnection,
-    root: xproto::Window,
+fn replay_with<E: Executor + 'static>(
     config: &Config,
+    events: &[recording::RecordedEvent],
+    executor: E,
 ) -> Result<(), AppError> {
-    use x11rb::protocol::xproto::ConnectionExt as _;
+    let (queue, worker) = ActionQueue::spawn_joinable(config.queue, executor)?;
+    let mut dispatcher = Dispatcher::new(config.clone(), queue);
+    recording::replay(events, &mut dispatcher);
+    // Stops repeats still running from buttons the recording never released.
+    drop(dispatcher);
+    // Let the queued actions finish before exiting.
+    let _ = worker.join();
+    Ok(())
+}
 
-    let mut buttons: Vec<u8> = config
-        .bindings
-        .iter()
-        .filter_map(|b| b.button.x11_button_number())
-        .filter(|&n| (6..=9).contains(&n))
-        .map(|n| n as u8)
-        .collect();
-    buttons.sort_unstable();
+/// Applies what `run` does to a loaded config before starting a backend.
+fn resolve_config(config: Config, config_path: &Path) -> Result<Config, AppError> {
+    // Macros, profile inheritance and tilt inversion are resolved once here, so the backends
+    // never see `Action::Macro`, a partial profile or a tilt binding named for the other side.
+    let mut config = config
+        .resolve_inheritance()?
+        .expand_macros()?
+        .resolve_wheel_tilt();
+    if let (Some(keymap), Some(dir)) = (&config.keymap, config_path.parent()) {
+        config.keymap = Some(dir.join(keymap));
+    }
+    Ok(config)
+}
+
+/// Loads the config for `run`, at startup and on reload: checked as written, so the binding
+/// numbers match the file, then resolved. Each problem is logged.
+fn load_run_config(path: &Path) -> Result<Config, AppError> {
+    let config = load_config(path)?;
+    if let Err(errors) = config.validate() {
+        for err in &errors {
+            error!("{}: {err}", path.display());
+        }
+        return Err(AppError::InvalidConfig {
+            path: path.to_path_buf(),
+            count: errors.len(),
+        });
+    }
+    resolve_config(config, path)
+}
+
+/// Reads a config for `import-config` from `input` (standard input for `-`), checks it and
+/// saves it to `config_path` as TOML.
+fn import_config(
+    input: &Path,
+    format: Option<Format>,
+    config_path: &Path,
+    force: bool,
+) -> Result<(), AppError> {
+    if !force && config_path.exists() {
+        return Err(AppError::ConfigExists(config_path.to_path_buf()));
+    }
+    let format = match format {
+        Some(format) => format.into(),
+        None => ConfigFormat::from_path(input)
+            .ok_or_else(|| AppError::UnknownConfigFormat(input.to_path_buf()))?,
+    };
+    let raw = if input == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        fs::read_to_string(input)?
+    };
+    let config = parse_config(&raw, format)?;
+    if let Err(errors) = config.validate() {
+        for err in &errors {
+            error!("{}: {err}", input.display());
+        }
+        return Err(AppError::InvalidConfig {
+            path: input.to_path_buf(),
+            count: errors.len(),
+        });
+    }
+    save_config(config_path, &config)?;
+    Ok(())
+}
+
+/// Resolves `--backend auto`; the reason is only given when the session was inspected.
+fn choose_backend(
+    requested: Backend,
+    device_path: bool,
+    device_name: bool,
+) -> (Backend, Option<String>) {
+    match requested {
+        Backend::Auto => match detect_backend_from_env() {
+            // Only the evdev-based backends can pick devices, by path or by name.
+            (Backend::X11, _) if device_path || device_name => (Backend::Evdev, None),
+            (backend, reason) => (backend, Some(reason)),
+        },
+        backend => (backend, None),
+    }
+}
+
+/// Renders the effective config for `dump-config`, with comments explaining what was applied.
+fn dump_config(
+    config_path: &Path,
+    backend: Backend,
+    profile: Option<&str>,
+    device: Option<PathBuf>,
+    device_name: Option<String>,
+) -> Result<String, AppError> {
+    let mut notes = Vec::new();
+    let config = if config_path.exists() {
+        notes.push(format!("config: {}", config_path.display()));
+        load_config(config_path)?
+    } else {
+        notes.push(format!(
+            "config: {} (not found; `run` would create it with these defaults)",
+            config_path.display()
+        ));
+        Config::default()
+    };
+    if config.profiles.iter().any(|p| p.inherits.is_some()) {
+        notes.push("profiles: inherited bindings merged in".to_owned());
+    }
+    if config.invert_wheel_tilt {
+        notes.push("invert_wheel_tilt: WHEEL_TILT_LEFT and WHEEL_TILT_RIGHT swapped".to_owned());
+    }
+    let mut config = resolve_config(config, config_path)?;
+    if !config.macros.is_empty() {
+        notes.push("macros: expanded into the bindings that use them".to_owned());
+        config.macros.clear();
+    }
+
+    let policy = KeyPolicy::new(&config, &load_system_policy(Path::new(SYSTEM_POLICY_PATH))?);
+    if policy.allowed_keys() != config.allowed_keys {
+        notes.push(format!("allowed_keys: narrowed by {SYSTEM_POLICY_PATH}"));
+        config.allowed_keys = policy.allowed_keys();
+    }
+
+    if let Some(device_name) = device_name {
+        device_name_regex(&device_name)?;
+        config.device_name = Some(device_name);
+    }
+    if let Some(device) = device {
+        config.device_by_path = Some(device.to_string_lossy().into_owned());
+    }
+    let (backend, reason) = choose_backend(
+        backend,
+        config.device_by_path.is_some(),
+        config.device_name.is_some(),
+    );
+    notes.push(format!(
+        "backend: {}",
+        reason.unwrap_or_else(|| format!("{backend:?}").to_lowercase())
+    ));
+    match backend {
+        Backend::X11 => {
+            // The X11 backend listens to every pointer and has no virtual keyboard.
+            config.device_by_path = None;
+            config.device_name = None;
+            config.evdev = Default::default();
+        }
+        _ => {
+            config.x11 = Default::default();
+            config.keymap = None;
+            if config.device_by_path.is_some() {
+                config.device_name = None;
+            }
+        }
+    }
+
+    if let Some(name) = profile {
+        let Some(profile) = config.profile(name) else {
+            return Err(AppError::UnknownProfile(name.to_owned()));
+        };
+        config.bindings = profile.bindings.clone();
+        notes.push(format!("bindings: while profile {name:?} is held"));
+    }
+
+    let mut out: String = notes.iter().map(|note| format!("# {note}\n")).collect();
+    out.push('\n');
+    out.push_str(&config.to_toml_string()?);
+    Ok(out)
+}
+
+/// Validates every file in `paths`, and every `*.toml` under the directories among them, then
+/// prints a summary and returns the worst exit code.
+fn validate_config_files(paths: &[PathBuf]) -> Result<i32, AppError> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            collect_toml_files(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    if files.is_empty() {
+        eprintln!("error: no *.toml files found");
+        return Ok(2);
+    }
+    let mut counts = [0; 3];
+    for file in &files {
+        counts[validate_config_file(file, true) as usize] += 1;
+    }
+    let [ok, warned, failed] = counts;
+    println!(
+        "{} file(s): {ok} ok, {warned} with warnings, {failed} with errors",
+        files.len()
+    );
+    Ok(if failed > 0 {
+        2
+    } else if warned > 0 {
+        1
+    } else {
+        0
+    })
+}
+
+/// Adds the `*.toml` files under `dir` to `files`, recursively and sorted by path.
+fn collect_toml_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_toml_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "toml") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Prints every problem found in the config at `path` and returns the process exit code. With
+/// `name_file`, each message says which file it's about.
+fn validate_config_file(path: &Path, name_file: bool) -> i32 {
+    let config = match load_config(path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}: {err}", path.display());
+            return 2;
+        }
+    };
+    let file = if name_file {
+        format!("{}: ", path.display())
+    } else {
+        String::new()
+    };
+
+    let errors = config.validate().err().unwrap_or_default();
+    let warnings = config.warnings();
+    // Problems with things the config points at, which the core crate can't check.
+    let mut external_errors: Vec<String> = Vec::new();
+    if let Some(Err(err)) = config.device_name.as_deref().map(device_name_regex) {
+        external_errors.push(err.to_string());
+    }
+    if let Some(keymap) = &config.keymap {
+        let keymap = path.parent().unwrap_or(Path::new("")).join(keymap);
+        if let Err(err) = mouse_assist_core::load_keymap(&keymap) {
+            external_errors.push(format!("keymap {}: {err}", keymap.display()));
+        }
+    }
+    let mut patterns = Vec::new();
+    for action in runnable_actions(&config).chain(config.macros.values()) {
+        collect_clipboard_patterns(action, &mut patterns);
+    }
+    for pattern in patterns {
+        if let Err(err) = Regex::new(pattern) {
+            external_errors.push(format!("clipboard_route pattern {pattern:?}: {err}"));
+        }
+    }
+    for err in &errors {
+        eprintln!("error: {file}{err}");
+    }
+    for err in &external_errors {
+        eprintln!("error: {file}{err}");
+    }
+    for warning in &warnings {
+        eprintln!("warning: {file}{warning}");
+    }
+
+    if !errors.is_empty() || !external_errors.is_empty() {
+        2
+    } else if !warnings.is_empty() {
+        1
+    } else {
+        println!("{}: ok", path.display());
+        0
+    }
+}
+
+const SESSION_ENV_VARS: [&str; 3] = ["XDG_SESSION_TYPE", "DISPLAY", "WAYLAND_DISPLAY"];
+
+fn detect_backend_from_env() -> (Backend, String) {
+    let session_type = std::env::var("XDG_SESSION_TYPE").ok();
+    detect_backend(
+        session_type.as_deref(),
+        std::env::var_os("DISPLAY").is_some(),
+        std::env::var_os("WAYLAND_DISPLAY").is_some(),
+    )
+}
+
+/// Picks the backend `run --backend auto` uses (when no device is given) and explains why.
+fn detect_backend(
+    session_type: Option<&str>,
+    display: bool,
+    wayland_display: bool,
+) -> (Backend, String) {
+    let (backend, reason) = match session_type {
+        Some("x11") => (Backend::X11, "XDG_SESSION_TYPE=x11".to_owned()),
+        Some("wayland") => (Backend::Wayland, "XDG_SESSION_TYPE=wayland".to_owned()),
+        other => {
+            let prefix = match other {
+                Some(t) => format!("XDG_SESSION_TYPE={t} is inconclusive; "),
+                None => "XDG_SESSION_TYPE is unset; ".to_owned(),
+            };
+            match (display, wayland_display) {
+                (true, false) => (
+                    Backend::X11,
+                    format!("{prefix}DISPLAY is set without WAYLAND_DISPLAY"),
+                ),
+                (_, true) => (Backend::Wayland, format!("{prefix}WAYLAND_DISPLAY is set")),
+                (false, false) => (
+                    Backend::Evdev,
+                    format!("{prefix}neither DISPLAY nor WAYLAND_DISPLAY is set"),
+                ),
+            }
+        }
+    };
+    let name = match backend {
+        Backend::X11 => "X11",
+        Backend::Wayland => "Wayland",
+        _ => "evdev",
+    };
+    (backend, format!("{reason} \u{2192} using {name} backend"))
+}
+
+fn print_detection() {
+    for var in SESSION_ENV_VARS {
+        match std::env::var(var) {
+            Ok(value) => println!("{var}={value}"),
+            Err(_) => println!("{var} (unset)"),
+        }
+    }
+    let (_, reason) = detect_backend_from_env();
+    println!();
+    println!("{reason}");
+    if let Some(problem) = missing_input_devices() {
+        println!("{problem}");
+    }
+    println!(
+        "On X11, `--device` or `device_by_path` selects evdev; pass `--backend x11|evdev|wayland` to override."
+    );
+}
+
+const INPUT_DIR: &str = "/dev/input";
+
+/// Explains why evdev can't see any devices when `/dev/input` is absent or holds no event
+/// nodes, which is the usual state of containers and WSL.
+fn missing_input_devices() -> Option<String> {
+    let hint = "the x11 backend (`--backend x11`) doesn't need it";
+    match fs::read_dir(INPUT_DIR) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Some(format!(
+            "no {INPUT_DIR} present; are you in a container or on Wayland without access? {hint}"
+        )),
+        Ok(entries) => {
+            let has_events = entries.filter_map(Result::ok).any(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.starts_with("event"))
+            });
+            (!has_events)
+                .then(|| format!("{INPUT_DIR} has no event devices; is this a container? {hint}"))
+        }
+        // Other errors (usually permissions) are reported by whoever opens the devices.
+        Err(_) => None,
+    }
+}
+
+fn list_devices() -> Result<(), AppError> {
+    if let Some(problem) = missing_input_devices() {
+        println!("{problem}");
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(INPUT_DIR)? {
+        let path = entry?.path();
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            if name.starts_with("event") {
+                entries.push(path);
+            }
+        }
+    }
+    entries.sort();
+
+    for path in entries {
+        match evdev::Device::open(&path) {
+            Ok(dev) => {
+                let name = dev.name().unwrap_or("<unknown>");
+                let kind = device_class::classify_device(&dev);
+                println!("{}  {}  [{kind}]", path.display(), name);
+            }
+            Err(err) => {
+                println!("{}  <unreadable: {}>", path.display(), err);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn start_passthrough(
+    path: &Path,
+    dev: &mut evdev::Device,
+    filter: &GrabFilter,
+) -> Option<Passthrough> {
+    if !filter.applies_to(dev) {
+        return None;
+    }
+    match Passthrough::grab(dev, filter.clone()) {
+        Ok(passthrough) => Some(passthrough),
+        Err(err) => {
+            warn!(
+                "--grab: leaving {} ungrabbed (pass-through setup failed: {err})",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+fn run_device(
+    device_path: &Path,
+    config: &Config,
+    options: &RunOptions,
+    mut reloader: Option<Reloader>,
+) -> Result<(), AppError> {
+    info!("opening device: {}", device_path.display());
+    let mut dev = retry::with_backoff(
+        &format!("opening {}", device_path.display()),
+        options.wait_for_device,
+        || evdev::Device::open(device_path),
+    )?;
+    info!("device name: {}", dev.name().unwrap_or("<unknown>"));
+    dev.set_nonblocking(false)?;
+    let mut latency = input_latency(config, &dev);
+    if !latency.is_zero() {
+        info!(
+            "looks wireless; allowing {}ms of input latency",
+            latency.as_millis()
+        );
+    }
+
+    let mut passthrough = if options.grab {
+        start_passthrough(device_path, &mut dev, &GrabFilter::from_config(config))
+    } else {
+        None
+    };
+    let queue = ActionQueue::spawn(
+        config.queue,
+        evdev_executor(config, options.keys.clone(), options)?,
+    )?;
+    warn_window_class_needs_x11(config);
+    let mut dispatcher =
+        Dispatcher::new(config.clone(), queue).with_status(Arc::clone(&options.status));
+    // Profiles' `device` paths are compared by the node they lead to.
+    let node = fs::canonicalize(device_path).unwrap_or_else(|_| device_path.to_path_buf());
+    let name = dev.name().unwrap_or_default().to_owned();
+    dispatcher.set_device(&node, &name);
+    options.status.set_devices(1);
+    options.enter_event_loop()?;
+    dispatcher.run_startup_action();
+
+    loop {
+        if let Some(reload) = reloader.as_mut().and_then(Reloader::poll) {
+            reload_evdev(&mut dispatcher, reload, options);
+            dispatcher.set_device(&node, &name);
+            latency = input_latency(dispatcher.config(), &dev);
+        }
+        // Woken now and then to look for config changes.
+        let timeout = reloader.is_some().then_some(reload::CHECK_INTERVAL);
+        if !reload::wait_readable(dev.as_raw_fd(), timeout)? {
+            continue;
+        }
+        let events: Vec<evdev::InputEvent> = dev.fetch_events()?.collect();
+        // Re-emit pass-through events first so they reach applications before the action runs.
+        if let Some(passthrough) = passthrough.as_mut() {
+            for &ev in &events {
+                passthrough.forward(ev);
+            }
+        }
+        for ev in &events {
+            dispatch_evdev_event(&mut dispatcher, ev, latency);
+        }
+    }
+}
+
+/// The executor for the evdev and Wayland backends: one that only logs with `--dry-run`.
+fn evdev_executor(
+    config: &Config,
+    keys: KeyPolicy,
+    options: &RunOptions,
+) -> Result<ActionExecutor, AppError> {
+    if options.dry_run {
+        Ok(ActionExecutor::dry_run(config, keys))
+    } else {
+        ActionExecutor::new(config, keys, options.backend)
+    }
+}
+
+/// Switches `dispatcher` to a reloaded config, with a new executor (and so a virtual keyboard
+/// that declares the keys the new config uses). If that can't be set up, including any virtual
+/// device the new config needs (after `--drop-privileges`, `/dev/uinput` usually can't be
+/// opened any more), the old one stays.
+fn reload_evdev(dispatcher: &mut Dispatcher, reload: reload::Reload, options: &RunOptions) {
+    let queue = evdev_executor(&reload.config, reload.keys, options)
+        .and_then(|executor| match executor.missing.first() {
+            Some(missing) => Err(AppError::MissingVirtualDevice(missing.clone())),
+            None => Ok(executor),
+        })
+        .and_then(|executor| Ok(ActionQueue::spawn(reload.config.queue, executor)?));
+    match queue {
+        Ok(queue) => {
+            warn_window_class_needs_x11(&reload.config);
+            dispatcher.reload(reload.config, queue);
+        }
+        Err(err) => warn!("can't switch to the reloaded config ({err}); keeping the one in use"),
+    }
+}
+
+/// Feeds one evdev event from a device with the given [`input_latency`] to `dispatcher`.
+fn dispatch_evdev_event(dispatcher: &mut Dispatcher, ev: &evdev::InputEvent, latency: Duration) {
+    match ev.destructure() {
+        evdev::EventSummary::Key(_event, keycode, value) => match value {
+            1 => dispatcher.press(Input::Key(keycode.code())),
+            0 => dispatcher.release(Input::Key(keycode.code())),
+            // Auto-repeat.
+            _ => {}
+        },
+        evdev::EventSummary::RelativeAxis(_event, evdev::RelativeAxisCode::REL_WHEEL, value) => {
+            let notch = if value > 0 {
+                MouseButton::WheelUp
+            } else {
+                MouseButton::WheelDown
+            };
+            // A fast spin can report several notches at once.
+            for _ in 0..value.unsigned_abs() {
+                dispatcher.tap_delayed(Input::Button(notch), latency);
+            }
+        }
+        evdev::EventSummary::RelativeAxis(_event, axis, value) => {
+            let Some(tilt) = wheel_tilt_from_relative_axis(axis, value) else {
+                return;
+            };
+            let button = match tilt {
+                WheelTilt::Left => MouseButton::WheelTiltLeft,
+                WheelTilt::Right => MouseButton::WheelTiltRight,
+            };
+            dispatcher.tap_delayed(Input::Button(button), latency);
+        }
+        _ => {}
+    }
+}
+
+/// `BTN_SIDE, BTN_EXTRA`, or `none`.
+/// The evdev backend can't tell which window is focused, so `window_class` bindings never fire.
+fn warn_window_class_needs_x11(config: &Config) {
+    if config.all_bindings().any(|b| !b.window_class.is_empty()) {
+        warn!("window_class bindings only work with the X11 backend; they will be skipped");
+    }
+}
+
+fn button_names(buttons: &[MouseButton]) -> String {
+    if buttons.is_empty() {
+        return "none".into();
+    }
+    let names: Vec<&str> = buttons.iter().map(|b| b.toml_name()).collect();
+    names.join(", ")
+}
+
+/// Logs a device `run` listens on.
+fn log_device(config: &Config, path: &Path, dev: &evdev::Device) {
+    info!(
+        "device: {} ({})",
+        path.display(),
+        dev.name().unwrap_or("<unknown>")
+    );
+    let latency = input_latency(config, dev);
+    if !latency.is_zero() {
+        info!(
+            "{} looks wireless; allowing {}ms of input latency",
+            path.display(),
+            latency.as_millis()
+        );
+    }
+}
+
+fn coalesce_window(config: &Config) -> Duration {
+    Duration::from_millis(
+        config
+            .evdev
+            .cross_device_coalesce_ms
+            .unwrap_or(DEFAULT_CROSS_DEVICE_COALESCE_MS),
+    )
+}
+
+fn run_all_devices(
+    config: &Config,
+    options: &RunOptions,
+    mut reloader: Option<Reloader>,
+) -> Result<(), AppError> {
+    let bound_buttons: Vec<MouseButton> = MouseButton::ALL
+        .into_iter()
+        .filter(|&button| {
+            button.linux_key_code().is_some()
+                && config
+                    .all_bindings()
+                    .any(|b| b.button == button || b.modifier_button == Some(button))
+        })
+        .collect();
+    let wants_wheel_tilt = config.all_bindings().any(|b| {
+        matches!(
+            b.button,
+            MouseButton::WheelTiltLeft | MouseButton::WheelTiltRight
+        )
+    });
+
+    if let Some(problem) = missing_input_devices() {
+        warn!("{problem}");
+        return Ok(());
+    }
+
+    let grab_filter = GrabFilter::from_config(config);
+
+    // Whether to use a device, at startup or when it's plugged in; counts the ones whose name
+    // matches `--device-name` in `name_matched`.
+    let use_device = |path: PathBuf, mut dev: evdev::Device, name_matched: &mut usize| {
+        let name = dev.name().unwrap_or("<unknown>").to_owned();
+        if name == SCROLL_DEVICE_NAME {
+            // Our own scrolling, which mustn't fire wheel tilt bindings.
+            return None;
+        }
+        if let Some(pattern) = &options.device_name {
+            if !pattern.is_match(&name) {
+                debug!(
+                    "{} ({name}): skipped, name doesn't match {pattern}",
+                    path.display()
+                );
+                return None;
+            }
+            info!("{} ({name}) matches device name {pattern}", path.display());
+            *name_matched += 1;
+        }
+        let kind = device_class::classify_device(&dev);
+        let kind_allowed = match kind {
+            DeviceKind::Mouse => true,
+            DeviceKind::Keyboard => config.evdev.include_keyboards,
+            DeviceKind::Touchpad => config.evdev.include_touchpads,
+            DeviceKind::Other => false,
+        };
+        if !kind_allowed {
+            debug!(
+                "{} ({name}): skipped, looks like a {kind} rather than a mouse",
+                path.display()
+            );
+            return None;
+        }
+        let (has, lacks): (Vec<MouseButton>, Vec<MouseButton>) =
+            bound_buttons.iter().partition(|button| {
+                dev.supported_keys().is_some_and(|keys| {
+                    button
+                        .linux_key_code()
+                        .is_some_and(|code| keys.contains(evdev::KeyCode::new(code)))
+                })
+            });
+        let has_tilt = dev.supported_relative_axes().is_some_and(|axes| {
+            axes.contains(evdev::RelativeAxisCode::REL_HWHEEL)
+                || axes.contains(evdev::RelativeAxisCode::REL_HWHEEL_HI_RES)
+        });
+        let tilt = match (wants_wheel_tilt, has_tilt) {
+            (false, _) => "not bound",
+            (true, true) => "bound and present",
+            (true, false) => "bound but missing",
+        };
+        let keys_match = !has.is_empty();
+        let rel_match = wants_wheel_tilt && has_tilt;
+        debug!(
+            "{} ({name}): has bound buttons: {}; lacks: {}; wheel tilt: {tilt}; {}",
+            path.display(),
+            button_names(&has),
+            button_names(&lacks),
+            if keys_match || rel_match {
+                "using it"
+            } else {
+                "skipped"
+            }
+        );
+        if !keys_match && !rel_match {
+            return None;
+        }
+        if let Err(err) = dev.set_nonblocking(true) {
+            warn!("failed to set nonblocking for {}: {err}", path.display());
+        }
+        let passthrough = if options.grab {
+            start_passthrough(&path, &mut dev, &grab_filter)
+        } else {
+            None
+        };
+        Some((path, dev, passthrough))
+    };
+    let find_devices = || {
+        let mut name_matched = 0;
+        let mut enumerated: Vec<PathBuf> = Vec::new();
+        let devices: Vec<(PathBuf, evdev::Device, Option<Passthrough>)> = evdev::enumerate()
+            .filter_map(|(path, dev)| {
+                enumerated.push(path.clone());
+                use_device(path, dev, &mut name_matched)
+            })
+            .collect();
+
+        // evdev::enumerate() quietly leaves out nodes it can't open, usually for lack of permission.
+        if let Ok(entries) = fs::read_dir(INPUT_DIR) {
+            for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+                let is_event = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("event"));
+                if is_event && !enumerated.contains(&path) {
+                    if let Err(err) = evdev::Device::open(&path) {
+                        debug!("{}: skipped, can't open it: {err}", path.display());
+                    }
+                }
+            }
+        }
+
+        if devices.is_empty() {
+            return Err(match &options.device_name {
+                Some(pattern) if name_matched == 0 => {
+                    format!("no input device name matches {pattern}; see `list-devices`")
+                }
+                Some(pattern) => format!(
+                    "{name_matched} device(s) match {pattern}, but none has a bound button or wheel tilt"
+                ),
+                None => "no input devices matched current bindings; try `list-devices` or pass `--device` (-vv explains each device)".to_owned(),
+            });
+        }
+        Ok(devices)
+    };
+    // Started first, so that a mouse plugged in while the others are found isn't missed.
+    let watch = match DeviceWatch::watch(Path::new(INPUT_DIR)) {
+        Ok(watch) => Some(watch),
+        Err(err) => {
+            warn!("can't watch {INPUT_DIR} ({err}); mice plugged in from now on won't be used");
+            None
+        }
+    };
+    let mut devices = match retry::with_backoff(
+        "finding input devices",
+        options.wait_for_device,
+        find_devices,
+    ) {
+        Ok(devices) => devices,
+        Err(problem) => {
+            warn!("{problem}");
+            return Ok(());
+        }
+    };
+
+    info!("listening on {} device(s)", devices.len());
+    for (path, dev, _) in &devices {
+        log_device(config, path, dev);
+    }
+
+    let queue = ActionQueue::spawn(
+        config.queue,
+        evdev_executor(config, options.keys.clone(), options)?,
+    )?;
+    warn_window_class_needs_x11(config);
+    let mut dispatcher =
+        Dispatcher::new(config.clone(), queue).with_status(Arc::clone(&options.status));
+    let mut coalescer = Coalescer::new(coalesce_window(config));
+    let epoll = Epoll::new()?;
+    for (_, dev, _) in &devices {
+        epoll.add(dev.as_raw_fd())?;
+    }
+    options.status.set_devices(devices.len());
+    options.enter_event_loop()?;
+    dispatcher.run_startup_action();
+
+    let mut had_devices = true;
+    loop {
+        if let Some(reload) = reloader.as_mut().and_then(Reloader::poll) {
+            coalescer = Coalescer::new(coalesce_window(&reload.config));
+            reload_evdev(&mut dispatcher, reload, options);
+        }
+        for change in watch.iter().flat_map(DeviceWatch::poll) {
+            match change {
+                Change::Appeared(path) => {
+                    if devices.iter().any(|(known, ..)| *known == path) {
+                        continue;
+                    }
+                    // Until udev has set its permissions, a new node may not open yet; that
+                    // change is reported too.
+                    let dev = match evdev::Device::open(&path) {
+                        Ok(dev) => dev,
+                        Err(err) => {
+                            debug!("{}: can't open it yet: {err}", path.display());
+                            continue;
+                        }
+                    };
+                    if let Some(device) = use_device(path, dev, &mut 0) {
+                        if let Err(err) = epoll.add(device.1.as_raw_fd()) {
+                            warn!("can't listen to {}: {err}", device.0.display());
+                            continue;
+                        }
+                        info!("new device plugged in");
+                        log_device(dispatcher.config(), &device.0, &device.1);
+                        devices.push(device);
+                        options.status.set_devices(devices.len());
+                    }
+                }
+                Change::Removed(path) => {
+                    if let Some(i) = devices.iter().position(|(known, ..)| *known == path) {
+                        info!("device {} was unplugged", path.display());
+                        let _ = epoll.remove(devices[i].1.as_raw_fd());
+                        devices.remove(i);
+                        options.status.set_devices(devices.len());
+                    }
+                }
+            }
+        }
+        if devices.is_empty() {
+            if watch.is_none() {
+                warn!("no devices left to read; exiting");
+                return Ok(());
+            }
+            if had_devices {
+                warn!("no devices left to read; waiting for one to be plugged in");
+            }
+        }
+        had_devices = !devices.is_empty();
+
+        // Woken now and then to look for config changes and new devices.
+        let timeout = (reloader.is_some() || watch.is_some()).then_some(reload::CHECK_INTERVAL);
+        let ready = epoll.wait(timeout)?;
+        let count = devices.len();
+        devices.retain_mut(|(path, dev, passthrough)| {
+            let fd = dev.as_raw_fd();
+            if !ready.contains(&fd) {
+                return true;
+            }
+            let latency = input_latency(dispatcher.config(), dev);
+            dispatcher.set_device(path, dev.name().unwrap_or_default());
+            match dev.fetch_events() {
+                Ok(events) => {
+                    let events: Vec<evdev::InputEvent> = events.collect();
+                    // Re-emit pass-through events before any action runs (see run_device).
+                    if let Some(passthrough) = passthrough.as_mut() {
+                        for &ev in &events {
+                            passthrough.forward(ev);
+                        }
+                    }
+                    for ev in &events {
+                        if coalescer.is_duplicate(path, ev) {
+                            debug!(
+                                "{}: ignoring a copy of another device's event",
+                                path.display()
+                            );
+                            continue;
+                        }
+                        dispatch_evdev_event(&mut dispatcher, ev, latency);
+                    }
+                    true
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => true,
+                Err(err) => {
+                    warn!("dropping device {} due to error: {err}", path.display());
+                    let _ = epoll.remove(fd);
+                    false
+                }
+            }
+        });
+        if devices.len() != count {
+            options.status.set_devices(devices.len());
+        }
+    }
+}
+
+/// Prints the X server's pointers for `list-devices --x11`.
+fn list_x11_pointers() -> Result<(), AppError> {
+    let (conn, _) = x11rb::connect(None)?;
+    conn.xinput_xi_query_version(2, 0)?.reply()?;
+    for pointer in x11_devices::pointers(&conn)? {
+        println!("{:>3}  {}  [{}]", pointer.id, pointer.name, pointer.kind);
+    }
+    Ok(())
+}
+
+fn run_x11(
+    config: &Config,
+    options: &RunOptions,
+    x11_device: Option<&str>,
+    mut reloader: Option<Reloader>,
+) -> Result<(), AppError> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let conn = Arc::new(conn);
+    let root = conn.setup().roots[screen_num].root;
+
+    conn.xtest_get_version(2, 2)?.reply()?;
+
+    let tilt_from_valuators = config.x11.tilt_source == TiltSource::Valuators;
+    // Grabbed buttons arrive as core events from the grab; every other button (including
+    // `passthrough` ones under --grab) is seen through raw events. While a grabbed button is
+    // held, the grab also delivers every other button (and wheel notch) as a core event.
+    let mut held_grabbed: Vec<u32> = Vec::new();
+    let mut grabbed = if options.grab {
+        grab_x11_buttons(&conn, root, config)?
+    } else {
+        Vec::new()
+    };
+    let mut raw_events =
+        xinput::XIEventMask::RAW_BUTTON_PRESS | xinput::XIEventMask::RAW_BUTTON_RELEASE;
+    if tilt_from_valuators {
+        raw_events |= xinput::XIEventMask::RAW_MOTION;
+    }
+    // Scroll classes were added in XInput 2.1.
+    let minor = if tilt_from_valuators { 1 } else { 0 };
+    conn.xinput_xi_query_version(2, minor)?.reply()?;
+    let deviceid = match x11_device {
+        Some(spec) => {
+            let pointers = x11_devices::pointers(&conn)?;
+            let Some(pointer) = x11_devices::find(&pointers, spec) else {
+                error!("no X11 pointer matches {spec:?}; the pointers are:");
+                for pointer in &pointers {
+                    error!("  {:>3}  {}  [{}]", pointer.id, pointer.name, pointer.kind);
+                }
+                return Err(AppError::UnknownX11Device(spec.to_owned()));
+            };
+            info!("listening to X11 pointer {} ({})", pointer.id, pointer.name);
+            if options.grab {
+                warn!("--grab takes the grabbed buttons from every pointer, not just {}; their presses on other pointers still run actions", pointer.name);
+            }
+            pointer.id
+        }
+        None => xinput::Device::ALL.into(),
+    };
+    conn.xinput_xi_select_events(
+        root,
+        &[xinput::EventMask {
+            deviceid,
+            mask: vec![raw_events],
+        }],
+    )?;
+    conn.flush()?;
+    let mut horizontal_scroll = if tilt_from_valuators {
+        let scroll = x11_scroll::HorizontalScroll::query(&conn)?;
+        if scroll.is_empty() {
+            warn!("x11.tilt_source = \"valuators\", but no pointer reports a horizontal scroll valuator");
+        }
+        Some(scroll)
+    } else {
+        None
+    };
+    // With valuator tilt, buttons 6/7 are the same motion emulated as clicks.
+    let is_emulated_tilt = |button: u32| tilt_from_valuators && (6..=7).contains(&button);
+    let mut fullscreen = if config.x11.pause_in_fullscreen {
+        let watch = x11_fullscreen::FullscreenWatch::new(&conn, root)?;
+        if watch.is_fullscreen() {
+            info!("focused window is fullscreen; pausing");
+            ungrab_x11_buttons(&conn, root, &mut grabbed)?;
+        }
+        Some(watch)
+    } else {
+        None
+    };
+
+    let queue = ActionQueue::spawn(
+        config.queue,
+        X11Executor::new(
+            Arc::clone(&conn),
+            root,
+            options.keys.clone(),
+            config.keymap.as_deref(),
+            config.key_release_order,
+            &config.x11,
+        )?
+        .with_dry_run(options.dry_run),
+    )?;
+    let focus_conn = Arc::clone(&conn);
+    let mut dispatcher = Dispatcher::new(config.clone(), queue)
+        .with_status(Arc::clone(&options.status))
+        .with_focus(Box::new(move || {
+            x11_windows::focused_class(&focus_conn, root)
+        }));
+    options
+        .status
+        .set_paused(fullscreen.as_ref().is_some_and(|w| w.is_fullscreen()));
+    options.enter_event_loop()?;
+    dispatcher.run_startup_action();
+
+    // Events are read on a thread of their own, so this loop can wake up to look for config
+    // changes. Waiting on the socket here instead would miss events that the executor's and focus
+    // lookup's requests read in while waiting for their replies.
+    let (event_tx, events) = mpsc::channel();
+    let event_conn = Arc::clone(&conn);
+    std::thread::spawn(move || loop {
+        let event = event_conn.wait_for_event();
+        let failed = event.is_err();
+        if event_tx.send(event).is_err() || failed {
+            return;
+        }
+    });
+    let timeout = match reloader {
+        Some(_) => reload::CHECK_INTERVAL,
+        None => Duration::MAX,
+    };
+    loop {
+        let event = match events.recv_timeout(timeout) {
+            Ok(event) => event?,
+            Err(RecvTimeoutError::Disconnected) => {
+                unreachable!("the event thread only stops after sending its error")
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(reload) = reloader.as_mut().and_then(Reloader::poll) {
+                    let executor = X11Executor::new(
+                        Arc::clone(&conn),
+                        root,
+                        reload.keys,
+                        reload.config.keymap.as_deref(),
+                        reload.config.key_release_order,
+                        &reload.config.x11,
+                    )
+                    .map(|executor| executor.with_dry_run(options.dry_run));
+                    match executor
+                        .and_then(|executor| Ok(ActionQueue::spawn(reload.config.queue, executor)?))
+                    {
+                        Ok(queue) => {
+                            if options.grab
+                                && !fullscreen.as_ref().is_some_and(|w| w.is_fullscreen())
+                            {
+                                ungrab_x11_buttons(&conn, root, &mut grabbed)?;
+                                held_grabbed.clear();
+                                grabbed = grab_x11_buttons(&conn, root, &reload.config)?;
+                            }
+                            dispatcher.reload(reload.config, queue);
+                        }
+                        Err(err) => {
+                            warn!("can't switch to the reloaded config ({err}); keeping the one in use")
+                        }
+                    }
+                }
+                conn.flush()?;
+                continue;
+            }
+        };
+        let (button, pressed) = match event {
+            Event::PropertyNotify(ev) => {
+                let Some(watch) = fullscreen.as_mut() else {
+                    continue;
+                };
+                match watch.on_property_notify(&conn, &ev)? {
+                    Some(true) => {
+                        info!("focused window went fullscreen; pausing");
+                        options.status.set_paused(true);
+                        dispatcher.release_all();
+                        held_grabbed.clear();
+                        ungrab_x11_buttons(&conn, root, &mut grabbed)?;
+                    }
+                    Some(false) => {
+                        info!("focused window left fullscreen; resuming");
+                        options.status.set_paused(false);
+                        if options.grab {
+                            grabbed = grab_x11_buttons(&conn, root, dispatcher.config())?;
+                        }
+                    }
+                    None => {}
+                }
+                continue;
+            }
+            _ if fullscreen.as_ref().is_some_and(|w| w.is_fullscreen()) => continue,
+            Event::ButtonPress(ev) => (u32::from(ev.detail), true),
+            Event::ButtonRelease(ev) => (u32::from(ev.detail), false),
+            Event::XinputRawButtonPress(ev)
+                if !grabbed.contains(&ev.detail) && held_grabbed.is_empty() =>
+            {
+                (ev.detail, true)
+            }
+            Event::XinputRawButtonRelease(ev)
+                if !grabbed.contains(&ev.detail) && held_grabbed.is_empty() =>
+            {
+                (ev.detail, false)
+            }
+            Event::XinputRawMotion(ev) => {
+                if let Some(scroll) = horizontal_scroll.as_mut() {
+                    for tilt in scroll.on_raw_motion(&ev) {
+                        dispatcher.tap(Input::Button(tilt));
+                    }
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if is_emulated_tilt(button) {
+            continue;
+        }
+        if grabbed.contains(&button) {
+            held_grabbed.retain(|&b| b != button);
+            if pressed {
+                held_grabbed.push(button);
+            }
+        }
+        if pressed {
+            dispatcher.press(Input::X11Button(button));
+        } else {
+            dispatcher.release(Input::X11Button(button));
+        }
+    }
+}
+
+/// Releases the grabs made by [`grab_x11_buttons`], so applications see those buttons again.
+fn ungrab_x11_buttons(
+    conn: &x11rb::rust_connection::RustConnection,
+    root: xproto::Window,
+    grabbed: &mut Vec<u32>,
+) -> Result<(), AppError> {
+    for button in grabbed.drain(..) {
+        conn.ungrab_button((button as u8).into(), root, xproto::ModMask::ANY)?;
+    }
+    conn.flush()?;
+    Ok(())
+}
+
+/// Grabs bound side/tilt buttons (except `passthrough` ones) and returns the ones grabbed.
+fn grab_x11_buttons(
+    conn: &x11rb::rust_connection::RustConnection,
+    root: xproto::Window,
+    config: &Config,
+) -> Result<Vec<u32>, AppError> {
+    use x11rb::protocol::xproto::ConnectionExt as _;
+
+    let mut buttons: Vec<u8> = config
+        .all_bindings()
+        .filter(|b| !b.passthrough)
+        .flat_map(|b| [Some(b.button), b.modifier_button])
+        .flatten()
+        .filter_map(MouseButton::x11_button_number)
+        .filter(|&n| (6..=9).contains(&n))
+        .map(|n| n as u8)
+        .collect();
+    buttons.sort_unstable();
     buttons.dedup();
 
     if buttons.is_empty() {
         warn!("--grab enabled, but no grab-eligible X11 buttons (6-9) are configured");
-        return Ok(());
+        return Ok(Vec::new());
     }
 
+    let mut grabbed = Vec::with_capacity(buttons.len());
     for button in buttons {
         let cookie = conn.grab_button(
             false,
             root,
-            xproto::EventMask::BUTTON_PRESS,
+            xproto::EventMask::BUTTON_PRESS | xproto::EventMask::BUTTON_RELEASE,
             xproto::GrabMode::ASYNC,
             xproto::GrabMode::ASYNC,
             x11rb::NONE,
@@ -387,14 +2082,15 @@ fn grab_x11_buttons(
             button.into(),
             xproto::ModMask::ANY,
         )?;
-        if let Err(err) = cookie.check() {
-            warn!("failed to grab X11 button {button}: {err}");
+        match cookie.check() {
+            Ok(()) => grabbed.push(u32::from(button)),
+            Err(err) => warn!("failed to grab X11 button {button}: {err}"),
         }
     }
 
     conn.flush()?;
     info!("grabbed X11 button(s) to suppress default actions");
-    Ok(())
+    Ok(grabbed)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -421,110 +2117,732 @@ fn wheel_tilt_from_relative_axis(axis: evdev::RelativeAxisCode, value: i32) -> O
 }
 
 struct ActionExecutor {
-    keyboard: Option<evdev::uinput::VirtualDevice>,
+    /// Declared first so they're dropped before the devices they refer to.
+    _panic_cleanups: Vec<panic_cleanup::Registration>,
+    keyboard: Option<Shared<Keyboard>>,
+    /// Sends `raw_events`; declares exactly the keys and axes those actions use.
+    raw: Option<Shared<evdev::uinput::VirtualDevice>>,
+    /// Turns the wheels for `scroll`; only there when the config scrolls.
+    wheel: Option<Shared<evdev::uinput::VirtualDevice>>,
+    policy: KeyPolicy,
+    syn_per_key: bool,
+    release_order: KeyReleaseOrder,
+    launcher: Launcher,
+    /// Log actions instead of running them; see [`ActionExecutor::dry_run`].
+    dry_run: bool,
+    /// The virtual devices the config needs but that couldn't be created, with why.
+    missing: Vec<String>,
+    /// The button whose binding fired the action being run, for `script`.
+    fired_by: Option<MouseButton>,
+}
+
+/// Where [`ActionExecutor`] sends keys.
+enum Keyboard {
+    Uinput(evdev::uinput::VirtualDevice),
+    /// The compositor's virtual keyboard, for the Wayland backend.
+    Wayland(wayland_keyboard::VirtualKeyboard),
+}
+
+impl Keyboard {
+    /// Sends one input report's key events.
+    fn emit(&mut self, report: &[evdev::InputEvent]) -> std::io::Result<()> {
+        match self {
+            Self::Uinput(dev) => dev.emit(report),
+            Self::Wayland(keyboard) => {
+                for event in report {
+                    keyboard.key(event.code(), event.value() != 0);
+                }
+                keyboard.flush()
+            }
+        }
+    }
 }
 
 impl ActionExecutor {
-    fn new(config: &Config) -> Result<Self, AppError> {
-        let keys = collect_uinput_keys(config);
-        let keyboard = if keys.iter().next().is_none() {
+    fn new(config: &Config, policy: KeyPolicy, backend: Backend) -> Result<Self, AppError> {
+        let mut missing = Vec::new();
+        let keys = collect_uinput_keys(config, &policy);
+        let wayland = if backend == Backend::Wayland && keys.iter().next().is_some() {
+            match wayland_keyboard::VirtualKeyboard::connect(keys.iter()) {
+                Ok(keyboard) => Some(keyboard),
+                Err(err) => {
+                    warn!("no Wayland virtual keyboard ({err}); sending keys through uinput");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let keyboard = if let Some(keyboard) = wayland {
+            Some(Keyboard::Wayland(keyboard))
+        } else if keys.iter().next().is_none() {
             None
         } else {
+            let name = config
+                .evdev
+                .virtual_device_name
+                .as_deref()
+                .unwrap_or("mouse-assist-virtual-keyboard");
             match evdev::uinput::VirtualDevice::builder()
-                .and_then(|b| b.name("mouse-assist-virtual-keyboard").with_keys(&keys))
+                .map(|b| with_virtual_device_ids(b.name(name), config))
+                .and_then(|b| b.with_keys(&keys))
                 .and_then(|b| b.build())
             {
-                Ok(dev) => Some(dev),
+                Ok(dev) => Some(Keyboard::Uinput(dev)),
                 Err(err) => {
                     warn!("failed to initialize uinput keyboard (KeyCombo disabled): {err}");
+                    missing.push(format!("uinput keyboard ({err})"));
+                    None
+                }
+            }
+        };
+
+        let raw = match raw_event_device(config, &policy) {
+            Ok(raw) => raw,
+            Err(err) => {
+                warn!("failed to initialize uinput device for raw_events (disabled): {err}");
+                missing.push(format!("uinput device for raw_events ({err})"));
+                None
+            }
+        };
+
+        let wheel = if runnable_actions(config).any(scrolls) {
+            match scroll_device(config) {
+                Ok(wheel) => Some(wheel),
+                Err(err) => {
+                    warn!("failed to initialize uinput device for scroll (disabled): {err}");
+                    missing.push(format!("uinput device for scroll ({err})"));
                     None
                 }
             }
+        } else {
+            None
         };
 
-        Ok(Self { keyboard })
+        let mut panic_cleanups = Vec::new();
+        // The compositor lets go of a Wayland keyboard's keys when the daemon disconnects.
+        if let Some(Keyboard::Uinput(keyboard)) = &keyboard {
+            panic_cleanups.push(panic_cleanup::release_on_panic(
+                keyboard,
+                keys.iter().map(|key| key.code()),
+            ));
+        }
+        let raw = raw.map(|(raw, raw_keys)| {
+            panic_cleanups.push(panic_cleanup::release_on_panic(
+                &raw,
+                raw_keys.iter().map(|key| key.code()),
+            ));
+            raw
+        });
+
+        Ok(Self {
+            _panic_cleanups: panic_cleanups,
+            keyboard: keyboard.map(Shared::new),
+            raw: raw.map(Shared::new),
+            wheel: wheel.map(Shared::new),
+            policy,
+            syn_per_key: config.evdev.syn_per_key,
+            release_order: config.key_release_order,
+            launcher: Launcher::new(),
+            dry_run: false,
+            missing,
+            fired_by: None,
+        })
     }
 
-    fn execute_action(&mut self, action: &Action) {
+    /// An executor that logs what each action would do, with the key codes it would send,
+    /// and does nothing else; it creates no virtual devices.
+    fn dry_run(config: &Config, policy: KeyPolicy) -> Self {
+        Self {
+            _panic_cleanups: Vec::new(),
+            keyboard: None,
+            raw: None,
+            wheel: None,
+            policy,
+            syn_per_key: config.evdev.syn_per_key,
+            release_order: config.key_release_order,
+            launcher: Launcher::new(),
+            dry_run: true,
+            missing: Vec::new(),
+            fired_by: None,
+        }
+    }
+
+    /// Logs `action` for `--dry-run`; keys show up as the codes the virtual keyboard would send.
+    fn log_dry_run(&self, action: &Action) {
         match action {
-            Action::Command { argv } => self.execute_command(argv),
-            Action::KeyCombo { keys } => self.execute_key_combo(keys),
+            Action::KeyCombo { keys, .. } => {
+                info!("dry run: key_combo {}", self.describe_keys(keys))
+            }
+            Action::KeyChords { keys, .. } => {
+                for chord in keys {
+                    info!("dry run: key_combo {}", self.describe_keys(chord));
+                }
+            }
+            action => log_dry_run(action),
         }
     }
 
-    fn execute_command(&self, argv: &[String]) {
-        if argv.is_empty() {
-            warn!("ignoring empty command argv");
+    fn describe_keys(&self, keys: &[String]) -> String {
+        key_codes(&self.policy, keys)
+            .iter()
+            .map(|key| format!("{key:?} ({})", key.code()))
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    fn execute_key_combo(&mut self, keys: &[String]) {
+        let Some(keyboard) = &self.keyboard else {
+            warn!("key injection unavailable (uinput device not initialized)");
+            return;
+        };
+        let mut keyboard = keyboard.lock();
+
+        let parsed = key_codes(&self.policy, keys);
+        if parsed.is_empty() {
             return;
         }
-        let mut cmd = std::process::Command::new(&argv[0]);
-        if argv.len() > 1 {
-            cmd.args(&argv[1..]);
+
+        let (presses, releases) = key_combo_reports(&parsed, self.syn_per_key, self.release_order);
+        for report in presses {
+            if let Err(err) = keyboard.emit(&report) {
+                error!("failed to inject key press: {err}");
+                return;
+            }
         }
-        match cmd.spawn() {
-            Ok(_) => info!("executed command: {:?}", argv),
-            Err(err) => error!("failed to execute {:?}: {}", argv, err),
+        for report in releases {
+            if let Err(err) = keyboard.emit(&report) {
+                error!("failed to inject key release: {err}");
+                return;
+            }
         }
     }
 
-    fn execute_key_combo(&mut self, keys: &[String]) {
-        let Some(keyboard) = &mut self.keyboard else {
+    /// Presses (`down`) or releases the keys of a `hold`.
+    fn inject_held_keys(&mut self, keys: &[String], down: bool) {
+        let Some(keyboard) = &self.keyboard else {
             warn!("key injection unavailable (uinput device not initialized)");
             return;
         };
+        let mut keyboard = keyboard.lock();
+        let parsed = key_codes(&self.policy, keys);
+        if parsed.is_empty() {
+            return;
+        }
+        let (presses, releases) = key_combo_reports(&parsed, self.syn_per_key, self.release_order);
+        let (reports, what) = if down {
+            (presses, "press")
+        } else {
+            (releases, "release")
+        };
+        for report in reports {
+            if let Err(err) = keyboard.emit(&report) {
+                error!("failed to inject key {what}: {err}");
+                return;
+            }
+        }
+    }
 
-        let parsed: Vec<evdev::KeyCode> = keys
+    fn execute_raw_events(&mut self, events: &[RawEvent]) {
+        let Some(raw) = &self.raw else {
+            warn!("raw_events unavailable (uinput device not initialized)");
+            return;
+        };
+        let events: Vec<evdev::InputEvent> = events
             .iter()
-            .filter_map(|k| match evdev::KeyCode::from_str(k) {
-                Ok(code) => Some(code),
-                Err(_) => {
-                    warn!("unknown key code in config: {}", k);
-                    None
+            .filter(|event| event.is_supported())
+            .filter(|event| {
+                event.event_type != RawEvent::EV_KEY || {
+                    let allowed = raw_key_allowed(&self.policy, event.code);
+                    if !allowed {
+                        warn!(
+                            "raw key code {} is not in the allowed_keys list; skipping it",
+                            event.code
+                        );
+                    }
+                    allowed
                 }
             })
+            .map(|event| evdev::InputEvent::new(event.event_type, event.code, event.value))
             .collect();
+        if let Err(err) = raw.lock().emit(&events) {
+            error!("failed to emit raw events: {err}");
+        }
+    }
+
+    fn execute_scroll(&mut self, axis: ScrollAxis, amount: i32) {
+        let Some(wheel) = &self.wheel else {
+            warn!("scroll unavailable (uinput device not initialized)");
+            return;
+        };
+        let code = match axis {
+            ScrollAxis::Vertical => evdev::RelativeAxisCode::REL_WHEEL,
+            ScrollAxis::Horizontal => evdev::RelativeAxisCode::REL_HWHEEL,
+        };
+        let event = evdev::InputEvent::new(evdev::EventType::RELATIVE.0, code.0, amount);
+        if let Err(err) = wheel.lock().emit(&[event]) {
+            error!("failed to emit scroll: {err}");
+        }
+    }
+}
+
+/// Name of the virtual mouse that `scroll` actions use on evdev.
+const SCROLL_DEVICE_NAME: &str = "mouse-assist-virtual-wheel";
+
+/// The virtual mouse for `scroll` actions. Besides the two wheels it declares pointer motion
+/// and a left button, which it never sends, so that udev and libinput take it for a mouse.
+fn scroll_device(config: &Config) -> std::io::Result<evdev::uinput::VirtualDevice> {
+    let axes = evdev::AttributeSet::from_iter([
+        evdev::RelativeAxisCode::REL_X,
+        evdev::RelativeAxisCode::REL_Y,
+        evdev::RelativeAxisCode::REL_WHEEL,
+        evdev::RelativeAxisCode::REL_HWHEEL,
+    ]);
+    let buttons = evdev::AttributeSet::from_iter([evdev::KeyCode::BTN_LEFT]);
+    with_virtual_device_ids(
+        evdev::uinput::VirtualDevice::builder()?.name(SCROLL_DEVICE_NAME),
+        config,
+    )
+    .with_keys(&buttons)?
+    .with_relative_axes(&axes)?
+    .build()
+}
+
+/// Every action the daemon can run: each binding's `action` and `double_click`, then the
+/// `startup_action`.
+fn runnable_actions(config: &Config) -> impl Iterator<Item = &Action> {
+    config
+        .all_bindings()
+        .flat_map(|b| std::iter::once(&b.action).chain(b.double_click.as_ref()))
+        .chain(config.startup_action.as_ref())
+}
+
+/// Whether `action` is or contains a `scroll`.
+fn scrolls(action: &Action) -> bool {
+    match action {
+        Action::Scroll { .. } => true,
+        Action::Parallel { actions } => actions.iter().any(scrolls),
+        Action::Sequence { steps } => Step::actions(steps).any(scrolls),
+        Action::PerProfile { map, default } => map.values().chain(default.as_deref()).any(scrolls),
+        Action::ClipboardRoute { rules, default, .. } => rules
+            .iter()
+            .map(|rule| &rule.action)
+            .chain(default.as_deref())
+            .any(scrolls),
+        _ => false,
+    }
+}
+
+/// Whether `allowed_keys` lets a raw `EV_KEY` event through; it names keys, so codes without a
+/// name only pass when nothing is restricted.
+fn raw_key_allowed(policy: &KeyPolicy, code: u16) -> bool {
+    !policy.is_restricted() || policy.allows(&format!("{:?}", evdev::KeyCode::new(code)))
+}
+
+/// The virtual device for the config's `raw_events` actions and the keys it declares, if there
+/// are any.
+fn raw_event_device(
+    config: &Config,
+    policy: &KeyPolicy,
+) -> std::io::Result<
+    Option<(
+        evdev::uinput::VirtualDevice,
+        evdev::AttributeSet<evdev::KeyCode>,
+    )>,
+> {
+    let mut events = Vec::new();
+    for action in runnable_actions(config) {
+        collect_raw_events(action, &mut events);
+    }
+    let keys: evdev::AttributeSet<evdev::KeyCode> = events
+        .iter()
+        .filter(|event| event.event_type == RawEvent::EV_KEY && event.is_supported())
+        .filter(|event| raw_key_allowed(policy, event.code))
+        .map(|event| evdev::KeyCode::new(event.code))
+        .collect();
+    let axes: evdev::AttributeSet<evdev::RelativeAxisCode> = events
+        .iter()
+        .filter(|event| event.event_type == RawEvent::EV_REL && event.is_supported())
+        .map(|event| evdev::RelativeAxisCode(event.code))
+        .collect();
+    if keys.iter().next().is_none() && axes.iter().next().is_none() {
+        return Ok(None);
+    }
+    let mut builder = with_virtual_device_ids(
+        evdev::uinput::VirtualDevice::builder()?.name("mouse-assist-raw-events"),
+        config,
+    );
+    if keys.iter().next().is_some() {
+        builder = builder.with_keys(&keys)?;
+    }
+    if axes.iter().next().is_some() {
+        builder = builder.with_relative_axes(&axes)?;
+    }
+    Ok(Some((builder.build()?, keys)))
+}
+
+/// Applies `evdev.virtual_device_ids`, if set, to a virtual device being built.
+fn with_virtual_device_ids<'a>(
+    builder: evdev::uinput::VirtualDeviceBuilder<'a>,
+    config: &Config,
+) -> evdev::uinput::VirtualDeviceBuilder<'a> {
+    match config.evdev.virtual_device_ids {
+        Some(ids) => builder.input_id(evdev::InputId::new(
+            evdev::BusType::BUS_USB,
+            ids.vendor,
+            ids.product,
+            ids.version,
+        )),
+        None => builder,
+    }
+}
+
+fn collect_clipboard_patterns<'a>(action: &'a Action, patterns: &mut Vec<&'a str>) {
+    match action {
+        Action::ClipboardRoute { rules, default, .. } => {
+            for rule in rules {
+                patterns.push(&rule.pattern);
+                collect_clipboard_patterns(&rule.action, patterns);
+            }
+            if let Some(action) = default {
+                collect_clipboard_patterns(action, patterns);
+            }
+        }
+        Action::Parallel { actions } => {
+            for action in actions {
+                collect_clipboard_patterns(action, patterns);
+            }
+        }
+        Action::Sequence { steps } => {
+            for action in Step::actions(steps) {
+                collect_clipboard_patterns(action, patterns);
+            }
+        }
+        Action::PerProfile { map, default } => {
+            for action in map.values().chain(default.as_deref()) {
+                collect_clipboard_patterns(action, patterns);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_raw_events<'a>(action: &'a Action, events: &mut Vec<&'a RawEvent>) {
+    match action {
+        Action::RawEvents { events: raw } => events.extend(raw),
+        Action::Parallel { actions } => {
+            for action in actions {
+                collect_raw_events(action, events);
+            }
+        }
+        Action::Sequence { steps } => {
+            for action in Step::actions(steps) {
+                collect_raw_events(action, events);
+            }
+        }
+        Action::PerProfile { map, default } => {
+            for action in map.values().chain(default.as_deref()) {
+                collect_raw_events(action, events);
+            }
+        }
+        Action::ClipboardRoute { rules, default, .. } => {
+            for action in rules
+                .iter()
+                .map(|rule| &rule.action)
+                .chain(default.as_deref())
+            {
+                collect_raw_events(action, events);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Logs an action `--dry-run` keeps from running, as it's written in the config.
+fn log_dry_run(action: &Action) {
+    match action.inline_toml() {
+        Ok(toml) => info!("dry run: {toml}"),
+        Err(_) => info!("dry run: {}", action.kind()),
+    }
+}
+
+/// Whether a dry run still carries out `action`: it only decides which actions to run, which
+/// are then logged one by one.
+fn runs_when_dry(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::Parallel { .. }
+            | Action::Sequence { .. }
+            | Action::ClipboardRoute { .. }
+            | Action::Script { .. }
+    )
+}
+
+impl Executor for ActionExecutor {
+    fn fork(&self) -> Self {
+        Self {
+            // The devices' panic cleanups stay with the executor that made them.
+            _panic_cleanups: Vec::new(),
+            keyboard: self.keyboard.clone(),
+            raw: self.raw.clone(),
+            wheel: self.wheel.clone(),
+            policy: self.policy.clone(),
+            syn_per_key: self.syn_per_key,
+            release_order: self.release_order,
+            launcher: self.launcher.clone(),
+            dry_run: self.dry_run,
+            missing: Vec::new(),
+            fired_by: self.fired_by,
+        }
+    }
+
+    fn fired_by(&mut self, button: Option<MouseButton>) {
+        self.fired_by = button;
+    }
+
+    fn execute_held_keys(&mut self, keys: &[String], down: bool) {
+        if self.dry_run {
+            let what = if down { "press" } else { "release" };
+            info!("dry run: hold, {what} {}", self.describe_keys(keys));
+            return;
+        }
+        self.inject_held_keys(keys, down);
+    }
+
+    fn execute_action(&mut self, action: &Action) {
+        if self.dry_run && !runs_when_dry(action) {
+            self.log_dry_run(action);
+            return;
+        }
+        match action {
+            Action::Command { argv } => self.launcher.spawn(argv),
+            Action::CommandNotify { argv } => command_notify::run(argv),
+            Action::Script { source } => {
+                let context = script::Context {
+                    button: self.fired_by,
+                    window_class: Vec::new(),
+                };
+                script::run(source, &context, self);
+            }
+            Action::LaunchOrFocus { argv, match_class } => {
+                warn!(
+                    "launch_or_focus can't look for {match_class:?} windows without X11; launching"
+                );
+                self.launcher.spawn(argv);
+            }
+            Action::KeyCombo {
+                target: Some(_), ..
+            }
+            | Action::KeyChords {
+                target: Some(_), ..
+            } => {
+                warn!("sending keys to a target window is only supported by the X11 backend; ignoring");
+            }
+            Action::KeyCombo { keys, target: None } => self.execute_key_combo(keys),
+            Action::KeyChords { keys, target: None } => {
+                for chord in keys {
+                    self.execute_key_combo(chord);
+                }
+            }
+            Action::Hold { .. } => {
+                warn!("hold only works as a binding's own action; ignoring");
+            }
+            Action::Parallel { actions } => worker::run_parallel(self, actions),
+            Action::Sequence { steps } => {
+                worker::run_sequence(steps, |action| self.execute_action(action));
+            }
+            Action::ClipboardRoute {
+                selection,
+                rules,
+                default,
+            } => {
+                if let Some(action) = clipboard::route(*selection, rules, default.as_deref()) {
+                    self.execute_action(&action);
+                }
+            }
+            Action::HoldProfile { name } => {
+                warn!("hold_profile {name:?} only works as a binding's own action; ignoring");
+            }
+            Action::SwitchProfile { .. } => {
+                warn!("switch_profile only works as a binding's own action; ignoring");
+            }
+            Action::ToggleProfile { name } => {
+                warn!("toggle_profile {name:?} only works as a binding's own action; ignoring");
+            }
+            Action::SwitchLayout { .. } => {
+                warn!("switch_layout is only supported by the X11 backend; ignoring");
+            }
+            Action::Screenshot {
+                mode,
+                to_clipboard,
+                save_path,
+            } => screenshot::take(*mode, *to_clipboard, save_path.as_deref()),
+            Action::SetClipboard { text, selection } => clipboard::set(text, *selection),
+            Action::Macro { name } => warn!("macro {name:?} was not expanded; ignoring"),
+            Action::PerProfile { .. } => {
+                warn!("per_profile was not resolved for a profile; ignoring");
+            }
+            Action::RepeatLast => warn!("repeat_last was not replayed by the queue; ignoring"),
+            Action::Webhook {
+                url,
+                method,
+                body,
+                headers,
+            } => webhook::send(url, method.as_deref(), body.as_deref(), headers.as_ref()),
+            Action::CycleWindows { .. } => {
+                warn!("cycle_windows is only supported by the X11 backend; ignoring");
+            }
+            Action::MoveWindowToMonitor { .. } => {
+                warn!("move_window_to_monitor is only supported by the X11 backend; ignoring");
+            }
+            Action::SetCursor { .. } => {
+                warn!("set_cursor is only supported by the X11 backend; ignoring");
+            }
+            Action::RawEvents { events } => self.execute_raw_events(events),
+            Action::Scroll { axis, amount } => self.execute_scroll(*axis, *amount),
+            Action::System { op } => logind::run(*op),
+            Action::SetLogLevel { level } => log_level::set(level),
+            Action::WmCommand { command } => wm_ipc::run(command),
+            Action::ToggleSetting { setting } => desktop_settings::toggle(*setting),
+            Action::Brightness {
+                delta_percent,
+                device,
+            } => backlight::adjust(*delta_percent, device.as_deref()),
+            Action::Open { target } => self.launcher.spawn(&open_argv(target)),
+        }
+    }
+}
+
+/// The command an `open` action runs.
+fn open_argv(target: &str) -> [String; 2] {
+    ["xdg-open".to_owned(), target.trim().to_owned()]
+}
+
+/// Splits a combo into the batches passed to `emit()` (each ends in its own `SYN_REPORT`):
+/// presses in order (see [`modifiers_first`]), then releases in `release_order`, either all at
+/// once or one key per report.
+fn key_combo_reports(
+    codes: &[evdev::KeyCode],
+    syn_per_key: bool,
+    release_order: KeyReleaseOrder,
+) -> (Vec<Vec<evdev::InputEvent>>, Vec<Vec<evdev::InputEvent>>) {
+    let codes = modifiers_first(codes, is_modifier_code);
+    let event = |code: &evdev::KeyCode, value| {
+        evdev::InputEvent::new_now(evdev::EventType::KEY.0, code.0, value)
+    };
+    let presses = codes.iter().map(|code| event(code, 1));
+    let releases = release_order
+        .releases(&codes)
+        .into_iter()
+        .map(|code| event(&code, 0));
+    if syn_per_key {
+        (
+            presses.map(|ev| vec![ev]).collect(),
+            releases.map(|ev| vec![ev]).collect(),
+        )
+    } else {
+        (vec![presses.collect()], vec![releases.collect()])
+    }
+}
+
+/// `keys` in the order to press them: the modifiers first, then the other keys, each as listed.
+/// Whatever order a combo lists its keys in, its modifiers are then down before any other key
+/// and, with the default release order, stay down until every other key is released.
+fn modifiers_first<T: Copy>(keys: &[T], is_modifier: impl Fn(T) -> bool) -> Vec<T> {
+    let (mut modifiers, others): (Vec<T>, Vec<T>) = keys.iter().partition(|&&key| is_modifier(key));
+    modifiers.extend(others);
+    modifiers
+}
 
-        if parsed.is_empty() {
-            return;
+/// Ctrl, Shift, Alt and Meta (Super), either side.
+fn is_modifier_code(code: evdev::KeyCode) -> bool {
+    [
+        evdev::KeyCode::KEY_LEFTCTRL,
+        evdev::KeyCode::KEY_RIGHTCTRL,
+        evdev::KeyCode::KEY_LEFTSHIFT,
+        evdev::KeyCode::KEY_RIGHTSHIFT,
+        evdev::KeyCode::KEY_LEFTALT,
+        evdev::KeyCode::KEY_RIGHTALT,
+        evdev::KeyCode::KEY_LEFTMETA,
+        evdev::KeyCode::KEY_RIGHTMETA,
+    ]
+    .contains(&code)
+}
+
+/// The codes of the keys from `keys` that `policy` lets actions inject, warning about the rest.
+fn key_codes(policy: &KeyPolicy, keys: &[String]) -> Vec<evdev::KeyCode> {
+    allowed_keys(policy, keys)
+        .filter_map(|k| match evdev::KeyCode::from_str(k) {
+            Ok(code) => Some(code),
+            Err(_) => {
+                warn!("unknown key code in config: {}", k);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The keys from `keys` that `policy` lets actions inject, warning about the rest.
+fn allowed_keys<'a>(
+    policy: &'a KeyPolicy,
+    keys: &'a [String],
+) -> impl Iterator<Item = &'a String> + 'a {
+    keys.iter().filter(move |key| {
+        let allowed = policy.allows(key);
+        if !allowed {
+            warn!("key {key} is not in the allowed_keys list; skipping it");
         }
+        allowed
+    })
+}
 
-        let mut events: Vec<evdev::InputEvent> = Vec::with_capacity(parsed.len());
-        for code in &parsed {
-            events.push(evdev::InputEvent::new_now(
-                evdev::EventType::KEY.0,
-                code.0,
-                1,
-            ));
+fn collect_action_keys(action: &Action, policy: &KeyPolicy, keys: &mut Vec<evdev::KeyCode>) {
+    let names: Vec<&String> = match action {
+        Action::KeyCombo { keys, .. } | Action::Hold { keys } => keys.iter().collect(),
+        Action::KeyChords { keys, .. } => keys.iter().flatten().collect(),
+        Action::Parallel { actions } => {
+            for action in actions {
+                collect_action_keys(action, policy, keys);
+            }
+            return;
         }
-        if let Err(err) = keyboard.emit(&events) {
-            error!("failed to inject key press: {err}");
+        Action::Sequence { steps } => {
+            for action in Step::actions(steps) {
+                collect_action_keys(action, policy, keys);
+            }
             return;
         }
-
-        let mut events: Vec<evdev::InputEvent> = Vec::with_capacity(parsed.len());
-        for code in parsed.iter().rev() {
-            events.push(evdev::InputEvent::new_now(
-                evdev::EventType::KEY.0,
-                code.0,
-                0,
-            ));
+        Action::PerProfile { map, default } => {
+            for action in map.values().chain(default.as_deref()) {
+                collect_action_keys(action, policy, keys);
+            }
+            return;
         }
-        if let Err(err) = keyboard.emit(&events) {
-            error!("failed to inject key release: {err}");
+        Action::ClipboardRoute { rules, default, .. } => {
+            for action in rules
+                .iter()
+                .map(|rule| &rule.action)
+                .chain(default.as_deref())
+            {
+                collect_action_keys(action, policy, keys);
+            }
+            return;
         }
-    }
+        _ => return,
+    };
+    keys.extend(
+        names
+            .into_iter()
+            // The virtual keyboard doesn't even get to declare a disallowed key.
+            .filter(|key| policy.allows(key))
+            .filter_map(|key| evdev::KeyCode::from_str(key).ok()),
+    );
 }
 
-fn collect_uinput_keys(config: &Config) -> evdev::AttributeSet<evdev::KeyCode> {
+fn collect_uinput_keys(config: &Config, policy: &KeyPolicy) -> evdev::AttributeSet<evdev::KeyCode> {
     let mut keys: Vec<evdev::KeyCode> = Vec::new();
-    for binding in &config.bindings {
-        if let Action::KeyCombo { keys: combo } = &binding.action {
-            for key in combo {
-                if let Ok(code) = evdev::KeyCode::from_str(key) {
-                    keys.push(code);
-                }
-            }
-        }
+    for action in runnable_actions(config) {
+        collect_action_keys(action, policy, &mut keys);
     }
 
     if keys.is_empty() {
@@ -537,87 +2855,175 @@ fn collect_uinput_keys(config: &Config) -> evdev::AttributeSet<evdev::KeyCode> {
 }
 
 struct X11Executor {
-    conn: x11rb::rust_connection::RustConnection,
+    conn: Arc<x11rb::rust_connection::RustConnection>,
     root: xproto::Window,
     keysym_to_keycode: std::collections::HashMap<xproto::Keysym, xproto::Keycode>,
-    bindings_by_button: std::collections::HashMap<u32, Action>,
+    /// The keycodes of the modifier keysyms (see [`modifier_mask`]), which a combo presses first.
+    modifier_keycodes: Vec<xproto::Keycode>,
+    /// User overrides for [`linux_key_name_to_x11_keysym`], from `keymap`.
+    keymap: std::collections::HashMap<String, xproto::Keysym>,
+    /// Whether the server speaks XKB (needed for `switch_layout`).
+    xkb: bool,
+    policy: KeyPolicy,
+    launcher: Launcher,
+    /// The window `cycle_windows` last activated, to continue from when the focused window
+    /// isn't one it cycles through.
+    cycle_cursor: Option<xproto::Window>,
+    release_order: KeyReleaseOrder,
+    key_method: X11KeyMethod,
+    /// Add the physically held modifiers to keys sent with `SendEvent`.
+    compose_held_modifiers: bool,
+    /// Held while sending keys, so that forks don't mix up their combos.
+    typing: Shared<()>,
+    /// Log actions instead of running them; see [`X11Executor::with_dry_run`].
+    dry_run: bool,
+    /// The button whose binding fired the action being run, for `script`.
+    fired_by: Option<MouseButton>,
 }
 
 impl X11Executor {
     fn new(
-        conn: x11rb::rust_connection::RustConnection,
+        conn: Arc<x11rb::rust_connection::RustConnection>,
         root: xproto::Window,
-        config: &Config,
+        policy: KeyPolicy,
+        keymap_path: Option<&Path>,
+        release_order: KeyReleaseOrder,
+        x11: &X11Config,
     ) -> Result<Self, AppError> {
         let keysym_to_keycode = build_x11_keysym_map(&conn)?;
-        let bindings_by_button = config
-            .bindings
+        let modifier_keycodes = keysym_to_keycode
             .iter()
-            .filter_map(|b| Some((b.button.x11_button_number()?, b.action.clone())))
+            .filter(|&(&keysym, _)| modifier_mask(keysym).is_some())
+            .map(|(_, &keycode)| keycode)
             .collect();
+        let keymap = match keymap_path {
+            Some(path) => x11_keymap::load(path, linux_key_name_to_x11_keysym)?,
+            None => Default::default(),
+        };
+        let xkb = match conn.xkb_use_extension(1, 0)?.reply() {
+            Ok(reply) if reply.supported => true,
+            Ok(reply) => {
+                warn!(
+                    "XKB {}.{} is too old; switch_layout disabled",
+                    reply.server_major, reply.server_minor
+                );
+                false
+            }
+            Err(err) => {
+                warn!("XKB unavailable (switch_layout disabled): {err}");
+                false
+            }
+        };
 
         Ok(Self {
             conn,
             root,
             keysym_to_keycode,
-            bindings_by_button,
+            modifier_keycodes,
+            keymap,
+            xkb,
+            policy,
+            launcher: Launcher::new(),
+            cycle_cursor: None,
+            release_order,
+            key_method: x11.key_method,
+            compose_held_modifiers: x11.compose_held_modifiers,
+            typing: Shared::new(()),
+            dry_run: false,
+            fired_by: None,
         })
     }
 
-    fn on_button_press(&mut self, button_detail: u32) {
-        let action = self.bindings_by_button.get(&button_detail).cloned();
-        if let Some(action) = action {
-            self.execute_action(&action);
-        }
+    /// With `dry_run`, only logs what each action would do, with the keysyms and keycodes it
+    /// would send.
+    fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
     }
 
-    fn execute_action(&mut self, action: &Action) {
+    /// Logs `action` for `--dry-run`; keys show up as the keysyms and keycodes they resolve to.
+    fn log_dry_run(&self, action: &Action) {
         match action {
-            Action::Command { argv } => self.execute_command(argv),
-            Action::KeyCombo { keys } => self.execute_key_combo(keys),
+            Action::KeyCombo { keys, .. } => {
+                info!("dry run: key_combo {}", self.describe_keys(keys))
+            }
+            Action::KeyChords { keys, .. } => {
+                for chord in keys {
+                    info!("dry run: key_combo {}", self.describe_keys(chord));
+                }
+            }
+            action => log_dry_run(action),
         }
     }
 
-    fn execute_command(&self, argv: &[String]) {
-        if argv.is_empty() {
-            warn!("ignoring empty command argv");
-            return;
-        }
-        let mut cmd = std::process::Command::new(&argv[0]);
-        if argv.len() > 1 {
-            cmd.args(&argv[1..]);
-        }
-        match cmd.spawn() {
-            Ok(_) => info!("executed command: {:?}", argv),
-            Err(err) => error!("failed to execute {:?}: {}", argv, err),
+    fn describe_keys(&self, keys: &[String]) -> String {
+        allowed_keys(&self.policy, keys)
+            .map(|key| match self.keysym_for(key) {
+                Some(keysym) => match self.keysym_to_keycode.get(&keysym) {
+                    Some(keycode) => format!("{key} (keysym 0x{keysym:x}, keycode {keycode})"),
+                    None => format!("{key} (keysym 0x{keysym:x}, no keycode)"),
+                },
+                None => format!("{key} (unknown)"),
+            })
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    fn switch_layout(&self, requested: Option<u8>) -> Result<(), AppError> {
+        if !self.xkb {
+            warn!("switch_layout needs the XKB extension, which isn't available");
+            return Ok(());
         }
+        let device = x11rb::protocol::xkb::ID::USE_CORE_KBD.into();
+        let current = u8::from(self.conn.xkb_get_state(device)?.reply()?.locked_group);
+        let num_groups = self.conn.xkb_get_controls(device)?.reply()?.num_groups;
+        let Some(group) = target_layout_group(current, num_groups, requested) else {
+            warn!("layout group {requested:?} doesn't exist ({num_groups} configured)");
+            return Ok(());
+        };
+        let no_mods = xproto::ModMask::from(0u16);
+        self.conn.xkb_latch_lock_state(
+            device,
+            no_mods,
+            no_mods,
+            true,
+            group.into(),
+            no_mods,
+            false,
+            0,
+        )?;
+        self.conn.flush()?;
+        info!("switched keyboard layout to group {group}");
+        Ok(())
     }
 
     fn execute_key_combo(&mut self, keys: &[String]) {
-        if keys == ["KEY_BACK"] {
-            if self.inject_key_by_keysym(x11_dl::keysym::XF86XK_Back as u32) {
+        let keys: Vec<String> = allowed_keys(&self.policy, keys).cloned().collect();
+        // The browser-navigation fallbacks below don't apply when the keymap says otherwise.
+        let remapped = keys.iter().any(|key| self.keymap.contains_key(key));
+        if keys == ["KEY_BACK"] && !remapped {
+            if self.inject_key_by_keysym(x11_dl::keysym::XF86XK_Back) {
                 return;
             }
-            self.inject_keysym_combo(&[
-                x11_dl::keysym::XK_Alt_L as u32,
-                x11_dl::keysym::XK_Left as u32,
-            ]);
+            self.inject_keysym_combo(&[x11_dl::keysym::XK_Alt_L, x11_dl::keysym::XK_Left]);
             return;
         }
-        if keys == ["KEY_FORWARD"] {
-            if self.inject_key_by_keysym(x11_dl::keysym::XF86XK_Forward as u32) {
+        if keys == ["KEY_FORWARD"] && !remapped {
+            if self.inject_key_by_keysym(x11_dl::keysym::XF86XK_Forward) {
                 return;
             }
-            self.inject_keysym_combo(&[
-                x11_dl::keysym::XK_Alt_L as u32,
-                x11_dl::keysym::XK_Right as u32,
-            ]);
+            self.inject_keysym_combo(&[x11_dl::keysym::XK_Alt_L, x11_dl::keysym::XK_Right]);
             return;
         }
 
+        let keycodes = self.keycodes_for(&keys);
+        self.inject_keycode_combo(&keycodes);
+    }
+
+    fn keycodes_for(&self, keys: &[String]) -> Vec<xproto::Keycode> {
         let mut keycodes: Vec<xproto::Keycode> = Vec::new();
         for key in keys {
-            let Some(keysym) = linux_key_name_to_x11_keysym(key) else {
+            let Some(keysym) = self.keysym_for(key) else {
                 warn!("unknown key name in config (x11 backend): {key}");
                 continue;
             };
@@ -627,8 +3033,254 @@ impl X11Executor {
             };
             keycodes.push(keycode);
         }
+        keycodes
+    }
 
-        self.inject_keycode_combo(&keycodes);
+    /// Presses (`down`) or releases the keys of a `hold`. Always through XTEST, whatever
+    /// `x11.key_method` says: a key sent with `SendEvent` can't stay down.
+    fn inject_held_keys(&mut self, keys: &[String], down: bool) {
+        let _typing = self.typing.lock();
+        let keys: Vec<String> = allowed_keys(&self.policy, keys).cloned().collect();
+        let keycodes = self.modifiers_first(&self.keycodes_for(&keys));
+        let (event, keycodes) = if down {
+            (xproto::KEY_PRESS_EVENT, keycodes)
+        } else {
+            (
+                xproto::KEY_RELEASE_EVENT,
+                self.release_order.releases(&keycodes),
+            )
+        };
+        for keycode in keycodes {
+            if let Err(err) = self
+                .conn
+                .xtest_fake_input(event, keycode, 0, self.root, 0, 0, 0)
+            {
+                error!("xtest key event failed: {err}");
+                return;
+            }
+        }
+        if let Err(err) = self.conn.flush() {
+            error!("x11 flush failed: {err}");
+        }
+    }
+
+    /// Clicks the wheel button for `axis` and the direction of `amount`, once per notch.
+    fn scroll(&self, axis: ScrollAxis, amount: i32) -> Result<(), x11rb::errors::ConnectionError> {
+        let button = x11_scroll_button(axis, amount);
+        for _ in 0..amount.unsigned_abs() {
+            for event in [xproto::BUTTON_PRESS_EVENT, xproto::BUTTON_RELEASE_EVENT] {
+                self.conn
+                    .xtest_fake_input(event, button, 0, self.root, 0, 0, 0)?;
+            }
+        }
+        self.conn.flush()
+    }
+
+    fn keysym_for(&self, key: &str) -> Option<xproto::Keysym> {
+        self.keymap
+            .get(key)
+            .copied()
+            .or_else(|| linux_key_name_to_x11_keysym(key))
+    }
+
+    /// Focuses the first window of class `match_class`, or runs `argv` when there's none.
+    fn launch_or_focus(&mut self, argv: &[String], match_class: &str) {
+        let target = WindowTarget {
+            class: Some(match_class.to_owned()),
+            title: None,
+        };
+        match x11_windows::find_window(&self.conn, self.root, &target) {
+            Ok(Some(window)) => {
+                if let Err(err) = x11_windows::activate(&self.conn, self.root, window) {
+                    error!("failed to focus window 0x{window:x}: {err}");
+                }
+            }
+            Ok(None) => self.launcher.spawn(argv),
+            Err(err) => {
+                error!("failed to look for {match_class:?} windows: {err}");
+                self.launcher.spawn(argv);
+            }
+        }
+    }
+
+    /// Focuses the window after the focused one (or after the one it focused last).
+    fn cycle_windows(&mut self, same_class: bool) -> Result<(), x11rb::errors::ReplyError> {
+        let mut windows = x11_windows::client_list(&self.conn, self.root)?;
+        let active = x11_windows::active_window(&self.conn, self.root)?;
+        if same_class {
+            let Some(active) = active else {
+                info!("cycle_windows: no focused window to match the class of");
+                return Ok(());
+            };
+            // Compare the class name (the second WM_CLASS string), not the instance.
+            let class_of = |window| {
+                x11_windows::window_class(&self.conn, window)
+                    .ok()
+                    .and_then(|class| class.last().map(|c| c.to_lowercase()))
+            };
+            let Some(class) = class_of(active) else {
+                info!("cycle_windows: the focused window has no WM_CLASS");
+                return Ok(());
+            };
+            windows.retain(|&window| class_of(window).as_ref() == Some(&class));
+        }
+        let current = active
+            .filter(|active| windows.contains(active))
+            .or(self.cycle_cursor);
+        match x11_windows::next_window(&windows, current) {
+            Some(window) => {
+                x11_windows::activate(&self.conn, self.root, window)?;
+                self.cycle_cursor = Some(window);
+            }
+            None => debug!("cycle_windows: no other window to switch to"),
+        }
+        Ok(())
+    }
+
+    /// Sets the root window's cursor to `name` from the current cursor theme (per
+    /// `Xcursor.theme` and `Xcursor.size` in the X resources), or the core cursor font.
+    fn set_cursor(&self, name: &str) -> Result<(), x11rb::errors::ReplyOrIdError> {
+        let screen = self
+            .conn
+            .setup()
+            .roots
+            .iter()
+            .position(|screen| screen.root == self.root)
+            .unwrap_or(0);
+        let resources = x11rb::resource_manager::new_from_default(&*self.conn)?;
+        let handle = x11rb::cursor::Handle::new(&*self.conn, screen, &resources)?.reply()?;
+        let cursor = handle.load_cursor(&*self.conn, name)?;
+        if cursor == x11rb::NONE {
+            warn!(
+                "set_cursor: no cursor named {name:?} in the cursor theme or the core cursor font"
+            );
+            return Ok(());
+        }
+        self.conn.change_window_attributes(
+            self.root,
+            &xproto::ChangeWindowAttributesAux::new().cursor(cursor),
+        )?;
+        // The root window keeps its own reference.
+        self.conn.free_cursor(cursor)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Moves the focused window to the monitor `direction` leads to.
+    fn move_window_to_monitor(
+        &mut self,
+        direction: MonitorDirection,
+    ) -> Result<(), x11rb::errors::ReplyError> {
+        let Some(window) = x11_windows::active_window(&self.conn, self.root)? else {
+            info!("move_window_to_monitor: no focused window");
+            return Ok(());
+        };
+        let monitors = x11_windows::monitors(&self.conn, self.root)?;
+        let rect = x11_windows::window_rect(&self.conn, window)?;
+        let current = x11_windows::monitor_of(&monitors, rect);
+        let Some(target) = x11_windows::target_monitor(monitors.len(), current, direction) else {
+            info!(
+                "move_window_to_monitor: nowhere to go from monitor {current} of {} ({direction:?})",
+                monitors.len()
+            );
+            return Ok(());
+        };
+        let (x, y) = x11_windows::place_on(rect, monitors[current], monitors[target]);
+        debug!("moving window {window:#x} from monitor {current} to {target} at ({x}, {y})");
+        x11_windows::move_window(&self.conn, self.root, window, x, y)
+    }
+
+    /// Presses each chord in turn: into the focused window through XTEST (or `SendEvent`, per
+    /// `x11.key_method`), or, with a `target`, as synthetic events sent straight to the
+    /// matching window or the one under the pointer.
+    fn execute_key_chords(&mut self, chords: &[Vec<String>], target: Option<&KeyTarget>) {
+        let typing = self.typing.clone();
+        let _typing = typing.lock();
+        let found = match (target, self.key_method) {
+            (Some(KeyTarget::Window(target)), _) => {
+                x11_windows::find_window(&self.conn, self.root, target)
+            }
+            (Some(KeyTarget::Named(NamedTarget::UnderCursor)), _) => {
+                x11_windows::window_under_pointer(&self.conn, self.root)
+            }
+            (None, X11KeyMethod::SendEvent) => x11_windows::input_focus(&self.conn, self.root),
+            (None, X11KeyMethod::XTest) => {
+                for chord in chords {
+                    self.execute_key_combo(chord);
+                }
+                return;
+            }
+        };
+        let window = match found {
+            Ok(Some(window)) => window,
+            Ok(None) => {
+                match target {
+                    Some(KeyTarget::Window(target)) => {
+                        warn!("no window matches {target:?}; not sending keys")
+                    }
+                    Some(KeyTarget::Named(NamedTarget::UnderCursor)) => {
+                        warn!("no window under the pointer to send keys to")
+                    }
+                    None => warn!("no focused window to send keys to"),
+                }
+                return;
+            }
+            Err(err) => {
+                error!("failed to look up the window to send keys to: {err}");
+                return;
+            }
+        };
+        for chord in chords {
+            if let Err(err) = self.send_key_chord(window, chord) {
+                error!("failed to send keys to window 0x{window:x}: {err}");
+                return;
+            }
+        }
+    }
+
+    /// Sends a chord as `KeyPress`/`KeyRelease` events via `SendEvent`. Modifier keys aren't
+    /// sent themselves; they become the events' modifier state instead, along with the held
+    /// ones when `x11.compose_held_modifiers` is set.
+    fn send_key_chord(&mut self, window: xproto::Window, keys: &[String]) -> Result<(), AppError> {
+        let mut keysyms = Vec::new();
+        for key in allowed_keys(&self.policy, keys) {
+            match self.keysym_for(key) {
+                Some(keysym) => keysyms.push(keysym),
+                None => warn!("unknown key name in config (x11 backend): {key}"),
+            }
+        }
+        let held = if self.compose_held_modifiers {
+            self.conn.query_pointer(self.root)?.reply()?.mask
+        } else {
+            xproto::KeyButMask::from(0u16)
+        };
+        let (state, keycodes) = chord_keycodes(&keysyms, &self.keysym_to_keycode, held);
+
+        for keycode in keycodes {
+            for (response_type, mask) in [
+                (xproto::KEY_PRESS_EVENT, xproto::EventMask::KEY_PRESS),
+                (xproto::KEY_RELEASE_EVENT, xproto::EventMask::KEY_RELEASE),
+            ] {
+                let event = xproto::KeyPressEvent {
+                    response_type,
+                    detail: keycode,
+                    sequence: 0,
+                    time: x11rb::CURRENT_TIME,
+                    root: self.root,
+                    event: window,
+                    child: x11rb::NONE,
+                    root_x: 0,
+                    root_y: 0,
+                    event_x: 0,
+                    event_y: 0,
+                    state,
+                    same_screen: true,
+                };
+                self.conn.send_event(true, window, mask, event)?;
+            }
+        }
+        self.conn.flush()?;
+        Ok(())
     }
 
     fn inject_key_by_keysym(&mut self, keysym: xproto::Keysym) -> bool {
@@ -651,12 +3303,20 @@ impl X11Executor {
         self.inject_keycode_combo(&keycodes);
     }
 
+    /// See [`modifiers_first`].
+    fn modifiers_first(&self, keycodes: &[xproto::Keycode]) -> Vec<xproto::Keycode> {
+        modifiers_first(keycodes, |keycode| {
+            self.modifier_keycodes.contains(&keycode)
+        })
+    }
+
     fn inject_keycode_combo(&mut self, keycodes: &[xproto::Keycode]) {
         if keycodes.is_empty() {
             return;
         }
 
-        for &keycode in keycodes {
+        let keycodes = self.modifiers_first(keycodes);
+        for &keycode in &keycodes {
             if let Err(err) =
                 self.conn
                     .xtest_fake_input(xproto::KEY_PRESS_EVENT, keycode, 0, self.root, 0, 0, 0)
@@ -670,7 +3330,7 @@ impl X11Executor {
             return;
         }
 
-        for &keycode in keycodes.iter().rev() {
+        for keycode in self.release_order.releases(&keycodes) {
             if let Err(err) = self.conn.xtest_fake_input(
                 xproto::KEY_RELEASE_EVENT,
                 keycode,
@@ -690,6 +3350,210 @@ impl X11Executor {
     }
 }
 
+impl Executor for X11Executor {
+    fn fork(&self) -> Self {
+        Self {
+            conn: Arc::clone(&self.conn),
+            root: self.root,
+            keysym_to_keycode: self.keysym_to_keycode.clone(),
+            modifier_keycodes: self.modifier_keycodes.clone(),
+            keymap: self.keymap.clone(),
+            xkb: self.xkb,
+            policy: self.policy.clone(),
+            launcher: self.launcher.clone(),
+            cycle_cursor: self.cycle_cursor,
+            release_order: self.release_order,
+            key_method: self.key_method,
+            compose_held_modifiers: self.compose_held_modifiers,
+            typing: self.typing.clone(),
+            dry_run: self.dry_run,
+            fired_by: self.fired_by,
+        }
+    }
+
+    fn fired_by(&mut self, button: Option<MouseButton>) {
+        self.fired_by = button;
+    }
+
+    fn execute_held_keys(&mut self, keys: &[String], down: bool) {
+        if self.dry_run {
+            let what = if down { "press" } else { "release" };
+            info!("dry run: hold, {what} {}", self.describe_keys(keys));
+            return;
+        }
+        self.inject_held_keys(keys, down);
+    }
+
+    fn execute_action(&mut self, action: &Action) {
+        if self.dry_run && !runs_when_dry(action) {
+            self.log_dry_run(action);
+            return;
+        }
+        match action {
+            Action::Command { argv } => self.launcher.spawn(argv),
+            Action::CommandNotify { argv } => command_notify::run(argv),
+            Action::Script { source } => {
+                let context = script::Context {
+                    button: self.fired_by,
+                    window_class: x11_windows::focused_class(&self.conn, self.root)
+                        .unwrap_or_default(),
+                };
+                script::run(source, &context, self);
+            }
+            Action::LaunchOrFocus { argv, match_class } => self.launch_or_focus(argv, match_class),
+            Action::KeyCombo { keys, target } => {
+                self.execute_key_chords(std::slice::from_ref(keys), target.as_ref())
+            }
+            Action::KeyChords { keys, target } => self.execute_key_chords(keys, target.as_ref()),
+            Action::Hold { .. } => {
+                warn!("hold only works as a binding's own action; ignoring");
+            }
+            Action::Parallel { actions } => worker::run_parallel(self, actions),
+            Action::Sequence { steps } => {
+                worker::run_sequence(steps, |action| self.execute_action(action));
+            }
+            Action::ClipboardRoute {
+                selection,
+                rules,
+                default,
+            } => {
+                if let Some(action) = clipboard::route(*selection, rules, default.as_deref()) {
+                    self.execute_action(&action);
+                }
+            }
+            Action::HoldProfile { name } => {
+                warn!("hold_profile {name:?} only works as a binding's own action; ignoring");
+            }
+            Action::SwitchProfile { .. } => {
+                warn!("switch_profile only works as a binding's own action; ignoring");
+            }
+            Action::ToggleProfile { name } => {
+                warn!("toggle_profile {name:?} only works as a binding's own action; ignoring");
+            }
+            Action::SwitchLayout { group } => {
+                if let Err(err) = self.switch_layout(*group) {
+                    error!("switch_layout failed: {err}");
+                }
+            }
+            Action::Screenshot {
+                mode,
+                to_clipboard,
+                save_path,
+            } => screenshot::take(*mode, *to_clipboard, save_path.as_deref()),
+            Action::SetClipboard { text, selection } => clipboard::set(text, *selection),
+            Action::Macro { name } => warn!("macro {name:?} was not expanded; ignoring"),
+            Action::PerProfile { .. } => {
+                warn!("per_profile was not resolved for a profile; ignoring");
+            }
+            Action::RepeatLast => warn!("repeat_last was not replayed by the queue; ignoring"),
+            Action::Webhook {
+                url,
+                method,
+                body,
+                headers,
+            } => webhook::send(url, method.as_deref(), body.as_deref(), headers.as_ref()),
+            Action::CycleWindows { same_class } => {
+                if let Err(err) = self.cycle_windows(*same_class) {
+                    error!("cycle_windows failed: {err}");
+                }
+            }
+            Action::MoveWindowToMonitor { direction } => {
+                if let Err(err) = self.move_window_to_monitor(*direction) {
+                    error!("move_window_to_monitor failed: {err}");
+                }
+            }
+            Action::SetCursor { name } => {
+                if let Err(err) = self.set_cursor(name) {
+                    error!("set_cursor {name:?} failed: {err}");
+                }
+            }
+            Action::RawEvents { .. } => {
+                warn!("raw_events is only supported by the evdev backend; ignoring");
+            }
+            Action::Scroll { axis, amount } => {
+                if let Err(err) = self.scroll(*axis, *amount) {
+                    error!("scroll failed: {err}");
+                }
+            }
+            Action::System { op } => logind::run(*op),
+            Action::SetLogLevel { level } => log_level::set(level),
+            Action::WmCommand { command } => wm_ipc::run(command),
+            Action::ToggleSetting { setting } => desktop_settings::toggle(*setting),
+            Action::Brightness {
+                delta_percent,
+                device,
+            } => backlight::adjust(*delta_percent, device.as_deref()),
+            Action::Open { target } => self.launcher.spawn(&open_argv(target)),
+        }
+    }
+}
+
+/// The state and keycodes `SendEvent` sends a chord as: the chord's modifiers, plus the
+/// modifiers in `held` (a pointer or key event's mask, whose button bits are dropped), go in the
+/// state, and the other keys become keycodes.
+fn chord_keycodes(
+    keysyms: &[xproto::Keysym],
+    keysym_to_keycode: &std::collections::HashMap<xproto::Keysym, xproto::Keycode>,
+    held: xproto::KeyButMask,
+) -> (xproto::KeyButMask, Vec<xproto::Keycode>) {
+    let modifiers = xproto::KeyButMask::SHIFT
+        | xproto::KeyButMask::LOCK
+        | xproto::KeyButMask::CONTROL
+        | xproto::KeyButMask::MOD1
+        | xproto::KeyButMask::MOD2
+        | xproto::KeyButMask::MOD3
+        | xproto::KeyButMask::MOD4
+        | xproto::KeyButMask::MOD5;
+    let mut state = held & modifiers;
+    let mut keycodes = Vec::new();
+    for &keysym in keysyms {
+        if let Some(modifier) = modifier_mask(keysym) {
+            state |= modifier;
+        } else if let Some(&keycode) = keysym_to_keycode.get(&keysym) {
+            keycodes.push(keycode);
+        } else {
+            warn!("no X11 keycode found for keysym=0x{keysym:x}");
+        }
+    }
+    (state, keycodes)
+}
+
+/// The modifier state bit a modifier keysym stands for, with the usual X11 modifier mapping.
+fn modifier_mask(keysym: xproto::Keysym) -> Option<xproto::KeyButMask> {
+    use x11_dl::keysym as ks;
+    [
+        (ks::XK_Shift_L, xproto::KeyButMask::SHIFT),
+        (ks::XK_Shift_R, xproto::KeyButMask::SHIFT),
+        (ks::XK_Control_L, xproto::KeyButMask::CONTROL),
+        (ks::XK_Control_R, xproto::KeyButMask::CONTROL),
+        (ks::XK_Alt_L, xproto::KeyButMask::MOD1),
+        (ks::XK_Alt_R, xproto::KeyButMask::MOD1),
+        (ks::XK_Super_L, xproto::KeyButMask::MOD4),
+        (ks::XK_Super_R, xproto::KeyButMask::MOD4),
+    ]
+    .into_iter()
+    .find_map(|(modifier, mask)| (modifier == keysym).then_some(mask))
+}
+
+/// The core button X11 scrolls with: 4/5 for up/down, 6/7 for left/right.
+fn x11_scroll_button(axis: ScrollAxis, amount: i32) -> u8 {
+    match (axis, amount > 0) {
+        (ScrollAxis::Vertical, true) => 4,
+        (ScrollAxis::Vertical, false) => 5,
+        (ScrollAxis::Horizontal, true) => 7,
+        (ScrollAxis::Horizontal, false) => 6,
+    }
+}
+
+/// The XKB group to lock: `requested` if it exists, otherwise the one after `current`, wrapping.
+fn target_layout_group(current: u8, num_groups: u8, requested: Option<u8>) -> Option<u8> {
+    match requested {
+        Some(group) => (group < num_groups).then_some(group),
+        None if num_groups == 0 => None,
+        None => Some((current + 1) % num_groups),
+    }
+}
+
 fn build_x11_keysym_map(
     conn: &x11rb::rust_connection::RustConnection,
 ) -> Result<std::collections::HashMap<xproto::Keysym, xproto::Keycode>, AppError> {
@@ -720,54 +3584,54 @@ fn build_x11_keysym_map(
 
 fn linux_key_name_to_x11_keysym(key: &str) -> Option<xproto::Keysym> {
     match key {
-        "KEY_VOLUMEUP" => Some(x11_dl::keysym::XF86XK_AudioRaiseVolume as u32),
-        "KEY_VOLUMEDOWN" => Some(x11_dl::keysym::XF86XK_AudioLowerVolume as u32),
-        "KEY_MUTE" => Some(x11_dl::keysym::XF86XK_AudioMute as u32),
-        "KEY_BACK" => Some(x11_dl::keysym::XF86XK_Back as u32),
-        "KEY_FORWARD" => Some(x11_dl::keysym::XF86XK_Forward as u32),
-        "KEY_LEFTALT" => Some(x11_dl::keysym::XK_Alt_L as u32),
-        "KEY_RIGHTALT" => Some(x11_dl::keysym::XK_Alt_R as u32),
-        "KEY_LEFTCTRL" => Some(x11_dl::keysym::XK_Control_L as u32),
-        "KEY_RIGHTCTRL" => Some(x11_dl::keysym::XK_Control_R as u32),
-        "KEY_LEFTSHIFT" => Some(x11_dl::keysym::XK_Shift_L as u32),
-        "KEY_RIGHTSHIFT" => Some(x11_dl::keysym::XK_Shift_R as u32),
-        "KEY_LEFTMETA" => Some(x11_dl::keysym::XK_Super_L as u32),
-        "KEY_RIGHTMETA" => Some(x11_dl::keysym::XK_Super_R as u32),
-        "KEY_LEFT" => Some(x11_dl::keysym::XK_Left as u32),
-        "KEY_RIGHT" => Some(x11_dl::keysym::XK_Right as u32),
+        "KEY_VOLUMEUP" => Some(x11_dl::keysym::XF86XK_AudioRaiseVolume),
+        "KEY_VOLUMEDOWN" => Some(x11_dl::keysym::XF86XK_AudioLowerVolume),
+        "KEY_MUTE" => Some(x11_dl::keysym::XF86XK_AudioMute),
+        "KEY_BACK" => Some(x11_dl::keysym::XF86XK_Back),
+        "KEY_FORWARD" => Some(x11_dl::keysym::XF86XK_Forward),
+        "KEY_LEFTALT" => Some(x11_dl::keysym::XK_Alt_L),
+        "KEY_RIGHTALT" => Some(x11_dl::keysym::XK_Alt_R),
+        "KEY_LEFTCTRL" => Some(x11_dl::keysym::XK_Control_L),
+        "KEY_RIGHTCTRL" => Some(x11_dl::keysym::XK_Control_R),
+        "KEY_LEFTSHIFT" => Some(x11_dl::keysym::XK_Shift_L),
+        "KEY_RIGHTSHIFT" => Some(x11_dl::keysym::XK_Shift_R),
+        "KEY_LEFTMETA" => Some(x11_dl::keysym::XK_Super_L),
+        "KEY_RIGHTMETA" => Some(x11_dl::keysym::XK_Super_R),
+        "KEY_LEFT" => Some(x11_dl::keysym::XK_Left),
+        "KEY_RIGHT" => Some(x11_dl::keysym::XK_Right),
         _ => {
             if let Some(letter) = key.strip_prefix("KEY_") {
                 if letter.len() == 1 {
                     let c = letter.as_bytes()[0];
-                    if (b'A'..=b'Z').contains(&c) {
+                    if c.is_ascii_uppercase() {
                         let lower = (c + 32) as char;
                         return Some(match lower {
-                            'a' => x11_dl::keysym::XK_a as u32,
-                            'b' => x11_dl::keysym::XK_b as u32,
-                            'c' => x11_dl::keysym::XK_c as u32,
-                            'd' => x11_dl::keysym::XK_d as u32,
-                            'e' => x11_dl::keysym::XK_e as u32,
-                            'f' => x11_dl::keysym::XK_f as u32,
-                            'g' => x11_dl::keysym::XK_g as u32,
-                            'h' => x11_dl::keysym::XK_h as u32,
-                            'i' => x11_dl::keysym::XK_i as u32,
-                            'j' => x11_dl::keysym::XK_j as u32,
-                            'k' => x11_dl::keysym::XK_k as u32,
-                            'l' => x11_dl::keysym::XK_l as u32,
-                            'm' => x11_dl::keysym::XK_m as u32,
-                            'n' => x11_dl::keysym::XK_n as u32,
-                            'o' => x11_dl::keysym::XK_o as u32,
-                            'p' => x11_dl::keysym::XK_p as u32,
-                            'q' => x11_dl::keysym::XK_q as u32,
-                            'r' => x11_dl::keysym::XK_r as u32,
-                            's' => x11_dl::keysym::XK_s as u32,
-                            't' => x11_dl::keysym::XK_t as u32,
-                            'u' => x11_dl::keysym::XK_u as u32,
-                            'v' => x11_dl::keysym::XK_v as u32,
-                            'w' => x11_dl::keysym::XK_w as u32,
-                            'x' => x11_dl::keysym::XK_x as u32,
-                            'y' => x11_dl::keysym::XK_y as u32,
-                            'z' => x11_dl::keysym::XK_z as u32,
+                            'a' => x11_dl::keysym::XK_a,
+                            'b' => x11_dl::keysym::XK_b,
+                            'c' => x11_dl::keysym::XK_c,
+                            'd' => x11_dl::keysym::XK_d,
+                            'e' => x11_dl::keysym::XK_e,
+                            'f' => x11_dl::keysym::XK_f,
+                            'g' => x11_dl::keysym::XK_g,
+                            'h' => x11_dl::keysym::XK_h,
+                            'i' => x11_dl::keysym::XK_i,
+                            'j' => x11_dl::keysym::XK_j,
+                            'k' => x11_dl::keysym::XK_k,
+                            'l' => x11_dl::keysym::XK_l,
+                            'm' => x11_dl::keysym::XK_m,
+                            'n' => x11_dl::keysym::XK_n,
+                            'o' => x11_dl::keysym::XK_o,
+                            'p' => x11_dl::keysym::XK_p,
+                            'q' => x11_dl::keysym::XK_q,
+                            'r' => x11_dl::keysym::XK_r,
+                            's' => x11_dl::keysym::XK_s,
+                            't' => x11_dl::keysym::XK_t,
+                            'u' => x11_dl::keysym::XK_u,
+                            'v' => x11_dl::keysym::XK_v,
+                            'w' => x11_dl::keysym::XK_w,
+                            'x' => x11_dl::keysym::XK_x,
+                            'y' => x11_dl::keysym::XK_y,
+                            'z' => x11_dl::keysym::XK_z,
                             _ => return None,
                         });
                     }
@@ -777,3 +3641,405 @@ fn linux_key_name_to_x11_keysym(key: &str) -> Option<xproto::Keysym> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dry_run_logs_actions_without_running_them() {
+        let marker =
+            std::env::temp_dir().join(format!("mouse-assist-dry-run-{}", std::process::id()));
+        let touch = Action::Command {
+            argv: vec!["touch".into(), marker.display().to_string()],
+        };
+        let mut executor = ActionExecutor::dry_run(&Config::default(), KeyPolicy::default());
+        executor.execute_action(&Action::Parallel {
+            actions: vec![
+                touch,
+                Action::KeyCombo {
+                    keys: vec!["KEY_LEFTCTRL".into(), "KEY_C".into()],
+                    target: None,
+                },
+            ],
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(executor.keyboard.is_none());
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn reloads_that_cant_create_their_virtual_devices_keep_the_old_config() {
+        if fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/uinput")
+            .is_ok()
+        {
+            // Here the new keyboard would be created, as it is before --drop-privileges.
+            return;
+        }
+        let key_combo = |key: &str| {
+            mouse_assist_core::Binding::new(
+                MouseButton::BtnSide,
+                Action::KeyCombo {
+                    keys: vec![key.into()],
+                    target: None,
+                },
+            )
+        };
+        let config = |key| Config {
+            bindings: vec![key_combo(key)],
+            ..Config::default()
+        };
+        let (queue, _rx) = ActionQueue::new(config("KEY_BACK").queue);
+        let mut dispatcher = Dispatcher::new(config("KEY_BACK"), queue);
+        let options = RunOptions {
+            grab: false,
+            drop_privileges: true,
+            keys: KeyPolicy::default(),
+            device_name: None,
+            wait_for_device: Duration::ZERO,
+            status: Arc::new(status::Status::new()),
+            backend: Backend::Evdev,
+            dry_run: false,
+        };
+        let reload = reload::Reload {
+            config: config("KEY_FORWARD"),
+            keys: KeyPolicy::default(),
+        };
+        reload_evdev(&mut dispatcher, reload, &options);
+        assert_eq!(dispatcher.config().bindings, [key_combo("KEY_BACK")]);
+    }
+
+    #[test]
+    fn detect_backend_explains_its_choice() {
+        let (backend, reason) = detect_backend(Some("wayland"), true, true);
+        assert_eq!(backend, Backend::Wayland);
+        assert_eq!(
+            reason,
+            "XDG_SESSION_TYPE=wayland \u{2192} using Wayland backend"
+        );
+
+        let (backend, reason) = detect_backend(Some("tty"), true, false);
+        assert_eq!(backend, Backend::X11);
+        assert_eq!(
+            reason,
+            "XDG_SESSION_TYPE=tty is inconclusive; DISPLAY is set without WAYLAND_DISPLAY \u{2192} using X11 backend"
+        );
+
+        assert_eq!(detect_backend(None, false, false).0, Backend::Evdev);
+    }
+
+    #[test]
+    fn key_combo_reports_batch_per_combo_or_per_key() {
+        let codes = [evdev::KeyCode::KEY_LEFTCTRL, evdev::KeyCode::KEY_C];
+        let summary = |reports: Vec<Vec<evdev::InputEvent>>| -> Vec<Vec<(u16, i32)>> {
+            reports
+                .iter()
+                .map(|report| report.iter().map(|ev| (ev.code(), ev.value())).collect())
+                .collect()
+        };
+        let (ctrl, c) = (codes[0].code(), codes[1].code());
+
+        let (presses, releases) = key_combo_reports(&codes, false, KeyReleaseOrder::Reverse);
+        assert_eq!(summary(presses), vec![vec![(ctrl, 1), (c, 1)]]);
+        assert_eq!(summary(releases), vec![vec![(c, 0), (ctrl, 0)]]);
+
+        let (presses, releases) = key_combo_reports(&codes, true, KeyReleaseOrder::Reverse);
+        assert_eq!(summary(presses), vec![vec![(ctrl, 1)], vec![(c, 1)]]);
+        assert_eq!(summary(releases), vec![vec![(c, 0)], vec![(ctrl, 0)]]);
+
+        let (presses, releases) = key_combo_reports(&codes, false, KeyReleaseOrder::Forward);
+        assert_eq!(summary(presses), vec![vec![(ctrl, 1), (c, 1)]]);
+        assert_eq!(summary(releases), vec![vec![(ctrl, 0), (c, 0)]]);
+
+        let (_, releases) = key_combo_reports(&codes, true, KeyReleaseOrder::Forward);
+        assert_eq!(summary(releases), vec![vec![(ctrl, 0)], vec![(c, 0)]]);
+
+        // Modifiers go down first and come up last, wherever the combo lists them.
+        let codes = [
+            evdev::KeyCode::KEY_1,
+            evdev::KeyCode::KEY_LEFTMETA,
+            evdev::KeyCode::KEY_LEFTSHIFT,
+        ];
+        let (one, meta, shift) = (codes[0].code(), codes[1].code(), codes[2].code());
+        let (presses, releases) = key_combo_reports(&codes, false, KeyReleaseOrder::Reverse);
+        assert_eq!(
+            summary(presses),
+            vec![vec![(meta, 1), (shift, 1), (one, 1)]]
+        );
+        assert_eq!(
+            summary(releases),
+            vec![vec![(one, 0), (shift, 0), (meta, 0)]]
+        );
+    }
+
+    #[test]
+    fn held_modifiers_compose_with_a_sent_chord() {
+        use x11_dl::keysym as ks;
+        // One key for `a` and `A`, as in a US layout.
+        let map = std::collections::HashMap::from([(ks::XK_a, 38), (ks::XK_A, 38)]);
+        let held = xproto::KeyButMask::SHIFT | xproto::KeyButMask::BUTTON1;
+
+        // Shift held while a bare `a` is sent: a capital A, without the button bit.
+        assert_eq!(
+            chord_keycodes(&[ks::XK_a], &map, held),
+            (xproto::KeyButMask::SHIFT, vec![38])
+        );
+        assert_eq!(
+            chord_keycodes(&[ks::XK_Control_L, ks::XK_a], &map, held),
+            (
+                xproto::KeyButMask::SHIFT | xproto::KeyButMask::CONTROL,
+                vec![38]
+            )
+        );
+        assert_eq!(
+            chord_keycodes(&[ks::XK_a], &map, xproto::KeyButMask::from(0u16)),
+            (xproto::KeyButMask::from(0u16), vec![38])
+        );
+    }
+
+    #[test]
+    fn layout_group_wraps_and_rejects_missing_groups() {
+        assert_eq!(target_layout_group(0, 2, None), Some(1));
+        assert_eq!(target_layout_group(1, 2, None), Some(0));
+        assert_eq!(target_layout_group(0, 3, Some(2)), Some(2));
+        assert_eq!(target_layout_group(0, 2, Some(2)), None);
+    }
+
+    #[test]
+    fn x11_scroll_direction_follows_the_sign() {
+        assert_eq!(x11_scroll_button(ScrollAxis::Vertical, 3), 4);
+        assert_eq!(x11_scroll_button(ScrollAxis::Vertical, -1), 5);
+        assert_eq!(x11_scroll_button(ScrollAxis::Horizontal, -2), 6);
+        assert_eq!(x11_scroll_button(ScrollAxis::Horizontal, 1), 7);
+    }
+
+    #[test]
+    fn log_level_flags_map_to_levels() {
+        let level = |args: &[&str]| {
+            Cli::try_parse_from(["mouse-assist-daemon"].iter().chain(args))
+                .unwrap()
+                .requested_log_level()
+        };
+        assert_eq!(level(&["detect"]), None);
+        assert_eq!(level(&["-v", "detect"]), Some(LevelFilter::INFO));
+        assert_eq!(level(&["detect", "-vv"]), Some(LevelFilter::DEBUG));
+        assert_eq!(level(&["-vvvv", "detect"]), Some(LevelFilter::TRACE));
+        assert_eq!(
+            level(&["--log-level", "warn", "detect"]),
+            Some(LevelFilter::WARN)
+        );
+        assert!(
+            Cli::try_parse_from(["mouse-assist-daemon", "--log-level", "loud", "detect"]).is_err()
+        );
+    }
+
+    #[test]
+    fn validate_checks_every_toml_under_a_directory() {
+        let dir =
+            std::env::temp_dir().join(format!("mouse-assist-validate-{}", std::process::id()));
+        fs::create_dir_all(dir.join("team")).unwrap();
+        fs::write(dir.join("good.toml"), "").unwrap();
+        fs::write(dir.join("notes.txt"), "not a config").unwrap();
+        let mut files = Vec::new();
+        collect_toml_files(&dir, &mut files).unwrap();
+        assert_eq!(files, [dir.join("good.toml")]);
+        assert_eq!(
+            validate_config_files(std::slice::from_ref(&dir)).unwrap(),
+            0
+        );
+
+        fs::write(
+            dir.join("team/broken.toml"),
+            "[[bindings]]\nbutton = \"BTN_SIDE\"\naction = { type = \"command\", argv = [] }\n",
+        )
+        .unwrap();
+        files.clear();
+        collect_toml_files(&dir, &mut files).unwrap();
+        assert_eq!(files, [dir.join("good.toml"), dir.join("team/broken.toml")]);
+        assert_eq!(
+            validate_config_files(std::slice::from_ref(&dir)).unwrap(),
+            2
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn import_config_takes_json_and_writes_toml() {
+        let dir = std::env::temp_dir().join(format!("mouse-assist-import-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+        let exported = Config::default().to_json_string().unwrap();
+        fs::write(dir.join("exported.json"), &exported).unwrap();
+        import_config(&dir.join("exported.json"), None, &config_path, false).unwrap();
+        assert_eq!(load_config(&config_path).unwrap(), Config::default());
+
+        // An existing config is only replaced when asked to.
+        assert!(matches!(
+            import_config(&dir.join("exported.json"), None, &config_path, false),
+            Err(AppError::ConfigExists(_))
+        ));
+
+        // The format comes from --format when the name doesn't say.
+        fs::write(dir.join("exported"), &exported).unwrap();
+        assert!(matches!(
+            import_config(&dir.join("exported"), None, &config_path, true),
+            Err(AppError::UnknownConfigFormat(_))
+        ));
+        import_config(
+            &dir.join("exported"),
+            Some(Format::Json),
+            &config_path,
+            true,
+        )
+        .unwrap();
+
+        // A broken config is reported and the old one kept.
+        fs::write(
+            dir.join("broken.json"),
+            r#"{ "bindings": [{ "button": "BTN_SIDE", "action": { "type": "command", "argv": [] } }] }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            import_config(&dir.join("broken.json"), None, &config_path, true),
+            Err(AppError::InvalidConfig { count: 1, .. })
+        ));
+        assert_eq!(load_config(&config_path).unwrap(), Config::default());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn inverted_wheel_tilt_swaps_which_binding_fires() {
+        let key_combo = |key: &str| Action::KeyCombo {
+            keys: vec![key.into()],
+            target: None,
+        };
+        let mut config = Config {
+            bindings: vec![
+                mouse_assist_core::Binding::new(
+                    MouseButton::WheelTiltLeft,
+                    key_combo("KEY_PREVIOUSSONG"),
+                ),
+                mouse_assist_core::Binding::new(
+                    MouseButton::WheelTiltRight,
+                    key_combo("KEY_NEXTSONG"),
+                ),
+            ],
+            ..Config::default()
+        };
+        let hwheel = |value| {
+            evdev::InputEvent::new(
+                evdev::EventType::RELATIVE.0,
+                evdev::RelativeAxisCode::REL_HWHEEL.0,
+                value,
+            )
+        };
+        let fired = |config: &Config| {
+            let config = resolve_config(config.clone(), Path::new("config.toml")).unwrap();
+            let (queue, rx) = ActionQueue::new(config.queue);
+            let mut dispatcher = Dispatcher::new(config, queue);
+            dispatch_evdev_event(&mut dispatcher, &hwheel(-1), Duration::ZERO);
+            drop(dispatcher);
+            rx.iter().map(|queued| queued.action()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(fired(&config), [key_combo("KEY_PREVIOUSSONG")]);
+        config.invert_wheel_tilt = true;
+        assert_eq!(fired(&config), [key_combo("KEY_NEXTSONG")]);
+    }
+
+    #[test]
+    fn dump_config_shows_inherited_profiles_merged() {
+        let path =
+            std::env::temp_dir().join(format!("mouse-assist-inherits-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+            [[profiles]]
+            name = "base"
+            bindings = [{ button = "BTN_SIDE", action = { type = "key_combo", keys = ["KEY_BACK"] } }]
+
+            [[profiles]]
+            name = "gaming"
+            inherits = "base"
+            bindings = [{ button = "BTN_EXTRA", action = { type = "key_combo", keys = ["KEY_R"] } }]
+            "#,
+        )
+        .unwrap();
+        let dumped = dump_config(&path, Backend::Evdev, Some("gaming"), None, None).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(dumped.contains("# profiles: inherited bindings merged in\n"));
+        assert!(!dumped.contains("inherits ="));
+        // While "gaming" is held, both its own and base's bindings are in effect.
+        let bindings = dumped.split("[[profiles]]").next().unwrap();
+        assert!(bindings.contains("keys = [\"KEY_BACK\"]"));
+        assert!(bindings.contains("keys = [\"KEY_R\"]"));
+    }
+
+    #[test]
+    fn priority_flags_are_range_checked_and_exclusive() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(["mouse-assist-daemon", "run"].iter().chain(args)).map(|cli| {
+                match cli.command {
+                    Command::Run {
+                        nice, rt_priority, ..
+                    } => (nice, rt_priority),
+                    _ => unreachable!(),
+                }
+            })
+        };
+        assert_eq!(parse(&["--nice", "-10"]).unwrap(), (Some(-10), None));
+        assert_eq!(parse(&["--rt-priority", "50"]).unwrap(), (None, Some(50)));
+        assert!(parse(&["--nice", "-21"]).is_err());
+        assert!(parse(&["--rt-priority", "0"]).is_err());
+        assert!(parse(&["--nice", "5", "--rt-priority", "10"]).is_err());
+    }
+
+    #[test]
+    fn raw_keys_follow_the_allowlist_by_name() {
+        let system = mouse_assist_core::SystemPolicy::default();
+        let mut config = Config::default();
+        assert!(raw_key_allowed(&KeyPolicy::new(&config, &system), 0x2fe));
+
+        config.allowed_keys = Some(vec!["KEY_VOLUMEUP".into()]);
+        let policy = KeyPolicy::new(&config, &system);
+        assert!(raw_key_allowed(
+            &policy,
+            evdev::KeyCode::KEY_VOLUMEUP.code()
+        ));
+        assert!(!raw_key_allowed(&policy, evdev::KeyCode::KEY_A.code()));
+        assert!(!raw_key_allowed(&policy, 0x2fe));
+    }
+
+    #[test]
+    fn double_click_keys_are_declared() {
+        let combo = |key: &str| Action::KeyCombo {
+            keys: vec![key.into()],
+            target: None,
+        };
+        let mut config = Config::default();
+        config.bindings.push(mouse_assist_core::Binding {
+            double_click: Some(combo("KEY_F13")),
+            ..mouse_assist_core::Binding::new(MouseButton::BtnSide, combo("KEY_BACK"))
+        });
+        let policy = KeyPolicy::new(&config, &mouse_assist_core::SystemPolicy::default());
+        let keys = collect_uinput_keys(&config, &policy);
+        assert!(keys.contains(evdev::KeyCode::KEY_BACK));
+        assert!(keys.contains(evdev::KeyCode::KEY_F13));
+    }
+
+    #[test]
+    fn startup_only_keys_are_declared() {
+        let config = Config {
+            startup_action: Some(Action::KeyCombo {
+                keys: vec!["KEY_NUMLOCK".into()],
+                target: None,
+            }),
+            ..Config::default()
+        };
+        let policy = KeyPolicy::new(&config, &mouse_assist_core::SystemPolicy::default());
+        let keys = collect_uinput_keys(&config, &policy);
+        assert!(keys.contains(evdev::KeyCode::KEY_NUMLOCK));
+    }
+}
@@ -0,0 +1,178 @@
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, Instant};
+use tracing::error;
+
+/// `EVIOCGRAB`, `_IOW('E', 0x90, int)`.
+const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+/// How long the hook waits for another thread to finish with the registry.
+const LOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Something that can leave input stuck if the daemon dies halfway.
+enum Cleanup {
+    /// A grabbed device, whose events no application sees until it's let go.
+    Ungrab(RawFd),
+    /// A virtual device whose keys (and buttons) may be down.
+    ReleaseKeys { fd: RawFd, keys: Vec<u16> },
+}
+
+static CLEANUPS: Mutex<Vec<(u64, Cleanup)>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps a cleanup registered until dropped, which has to happen before its device is closed.
+pub(crate) struct Registration(u64);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut cleanups = CLEANUPS.lock().unwrap_or_else(|err| err.into_inner());
+        cleanups.retain(|(id, _)| *id != self.0);
+    }
+}
+
+fn register(cleanup: Cleanup) -> Registration {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    CLEANUPS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push((id, cleanup));
+    Registration(id)
+}
+
+/// Lets go of `device`'s grab if the daemon panics.
+pub(crate) fn ungrab_on_panic(device: &impl AsRawFd) -> Registration {
+    register(Cleanup::Ungrab(device.as_raw_fd()))
+}
+
+/// Releases `keys` on the virtual `device` if the daemon panics. Releasing a key that isn't
+/// down is harmless; the kernel drops it.
+pub(crate) fn release_on_panic(
+    device: &impl AsRawFd,
+    keys: impl IntoIterator<Item = u16>,
+) -> Registration {
+    register(Cleanup::ReleaseKeys {
+        fd: device.as_raw_fd(),
+        keys: keys.into_iter().collect(),
+    })
+}
+
+/// Whether any thread has panicked. Pass-through stops forwarding once it has, since the
+/// device it forwards for is no longer grabbed.
+pub(crate) fn panicked() -> bool {
+    PANICKED.load(Ordering::Relaxed)
+}
+
+/// Logs any panic and puts registered devices right before the default hook runs, so a panic
+/// on any thread can't leave the user without a working mouse or with keys stuck down.
+pub(crate) fn install() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        PANICKED.store(true, Ordering::Relaxed);
+        error!("{info}; releasing grabbed devices and held keys");
+        clean_up();
+        default(info);
+    }));
+}
+
+/// Runs every registered cleanup without blocking for long or panicking: the registry is only
+/// waited on for [`LOCK_TIMEOUT`] (the panic may have happened while it was locked), and the
+/// rest is an ioctl and a write to uinput, neither of which waits.
+fn clean_up() {
+    let Some(cleanups) = lock_within(LOCK_TIMEOUT) else {
+        return;
+    };
+    for (_, cleanup) in cleanups.iter() {
+        match cleanup {
+            // SAFETY: EVIOCGRAB takes an int by value; on a descriptor that isn't a grabbed
+            // evdev device it fails without side effects.
+            Cleanup::Ungrab(fd) => unsafe {
+                libc::ioctl(*fd, EVIOCGRAB, 0 as libc::c_int);
+            },
+            Cleanup::ReleaseKeys { fd, keys } => release_keys(*fd, keys),
+        }
+    }
+}
+
+fn lock_within(timeout: Duration) -> Option<MutexGuard<'static, Vec<(u64, Cleanup)>>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match CLEANUPS.try_lock() {
+            Ok(guard) => return Some(guard),
+            Err(TryLockError::Poisoned(err)) => return Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Err(TryLockError::WouldBlock) => return None,
+        }
+    }
+}
+
+/// Writes a release of each key, then a `SYN_REPORT`, straight to the uinput descriptor.
+fn release_keys(fd: RawFd, keys: &[u16]) {
+    let event = |event_type: u16, code: u16| {
+        // SAFETY: input_event is plain data; uinput fills in the timestamp itself.
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = event_type;
+        event.code = code;
+        event
+    };
+    let events: Vec<libc::input_event> = keys
+        .iter()
+        .map(|&code| event(evdev::EventType::KEY.0, code))
+        .chain(std::iter::once(event(
+            evdev::EventType::SYNCHRONIZATION.0,
+            evdev::SynchronizationCode::SYN_REPORT.0,
+        )))
+        .collect();
+    // SAFETY: the buffer is `events`, whole and initialized.
+    unsafe {
+        libc::write(
+            fd,
+            events.as_ptr().cast(),
+            std::mem::size_of_val(events.as_slice()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::fd::FromRawFd;
+
+    #[test]
+    fn registered_devices_get_their_keys_released() {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for both ends.
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        // SAFETY: both descriptors were just opened and are owned here.
+        let (mut reader, writer) = unsafe {
+            (
+                std::fs::File::from_raw_fd(fds[0]),
+                std::fs::File::from_raw_fd(fds[1]),
+            )
+        };
+        let registration = release_on_panic(&writer, [0x110, 0x1d]);
+        clean_up();
+        drop(registration);
+        // Unregistered, so a second cleanup writes nothing more.
+        clean_up();
+        drop(writer);
+
+        let mut written = Vec::new();
+        reader.read_to_end(&mut written).unwrap();
+        let size = std::mem::size_of::<libc::input_event>();
+        assert_eq!(written.len(), 3 * size);
+        let events: Vec<(u16, u16, i32)> = written
+            .chunks(size)
+            .map(|chunk| {
+                // SAFETY: each chunk is one input_event, as written above.
+                let event: libc::input_event =
+                    unsafe { std::ptr::read_unaligned(chunk.as_ptr().cast()) };
+                (event.type_, event.code, event.value)
+            })
+            .collect();
+        assert_eq!(events, [(1, 0x110, 0), (1, 0x1d, 0), (0, 0, 0)]);
+    }
+}
@@ -0,0 +1,94 @@
+use std::io;
+use tracing::{info, warn};
+
+/// How the daemon asks to be scheduled, from `run --nice` / `--rt-priority`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Priority {
+    /// A niceness from -20 (most favored) to 19.
+    Nice(i32),
+    /// A `SCHED_FIFO` priority from 1 to 99.
+    RealTime(i32),
+}
+
+/// Applies `priority` to the calling thread only, which should be the one reading input. It's
+/// set with `SCHED_RESET_ON_FORK`, so threads it spawns later (the action worker, the status
+/// socket) and the programs actions run start at normal priority. Failing (usually for lack of
+/// `CAP_SYS_NICE`) only logs a warning: the daemon works the same at normal priority, just with
+/// more jitter under load.
+pub(crate) fn apply(priority: Priority) {
+    let result = match priority {
+        Priority::Nice(nice) => set_nice(nice),
+        Priority::RealTime(level) => set_real_time(level),
+    };
+    match result {
+        Ok(()) => info!("scheduling priority set: {}", describe(priority)),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => warn!(
+            "can't set {}: {err}; raising priority needs root, CAP_SYS_NICE or a matching \
+             RLIMIT_NICE/RLIMIT_RTPRIO limit. Continuing at normal priority",
+            describe(priority)
+        ),
+        Err(err) => warn!(
+            "can't set {}: {err}; continuing at normal priority",
+            describe(priority)
+        ),
+    }
+}
+
+fn describe(priority: Priority) -> String {
+    match priority {
+        Priority::Nice(nice) => format!("nice {nice}"),
+        Priority::RealTime(level) => format!("SCHED_FIFO priority {level}"),
+    }
+}
+
+fn set_nice(nice: i32) -> io::Result<()> {
+    // A negative niceness is dropped on fork only once the policy carries the flag.
+    set_scheduler(libc::SCHED_OTHER, 0)?;
+    // SAFETY: no pointers involved; `who = 0` means the calling thread.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn set_real_time(level: i32) -> io::Result<()> {
+    set_scheduler(libc::SCHED_FIFO, level)
+}
+
+fn set_scheduler(policy: i32, level: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: level,
+    };
+    // SAFETY: `param` is a valid sched_param that outlives the call; `pid = 0` means the
+    // calling thread.
+    if unsafe { libc::sched_setscheduler(0, policy | libc::SCHED_RESET_ON_FORK, &param) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler() -> i32 {
+        // SAFETY: no pointers involved.
+        unsafe { libc::sched_getscheduler(0) }
+    }
+
+    #[test]
+    fn threads_spawned_later_run_at_normal_priority() {
+        // On a thread of its own, so the test harness keeps its priority.
+        std::thread::spawn(|| {
+            if set_real_time(1).is_err() {
+                eprintln!("skipping: can't raise priority here");
+                return;
+            }
+            assert_eq!(scheduler(), libc::SCHED_FIFO | libc::SCHED_RESET_ON_FORK);
+            let spawned = std::thread::spawn(scheduler).join().unwrap();
+            assert_eq!(spawned, libc::SCHED_OTHER);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// `_LINUX_CAPABILITY_VERSION_3`: 64-bit capability sets, two data words.
+const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Gives up whatever made opening `/dev/input` and `/dev/uinput` possible, keeping every file
+/// descriptor that's already open.
+///
+/// - Running as root (`sudo`, `pkexec`, or a setuid binary): switch to the invoking user.
+/// - Running as a user with file capabilities (e.g. `setcap cap_dac_override+ep`): clear them.
+/// - Otherwise there's nothing to drop.
+pub(crate) fn drop_privileges() -> io::Result<()> {
+    // SAFETY: these getters have no preconditions and can't fail.
+    let (euid, uid, gid) = unsafe { (libc::geteuid(), libc::getuid(), libc::getgid()) };
+    if euid == 0 {
+        let env = |name: &str| std::env::var(name).ok();
+        let Some((uid, gid)) = invoking_user(env, uid, gid) else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "running as root but can't tell which user to drop to \
+                 (start via sudo/pkexec or as a setuid binary)",
+            ));
+        };
+        switch_user(uid, gid)?;
+        info!("dropped privileges to uid={uid} gid={gid}");
+    } else if has_capabilities()? {
+        let threads = clear_capabilities_everywhere()?;
+        info!("dropped all capabilities on {threads} thread(s)");
+    } else {
+        info!("not running privileged; nothing to drop");
+    }
+    Ok(())
+}
+
+/// The unprivileged user who started us, from the launcher's environment or the real uid.
+fn invoking_user(
+    env: impl Fn(&str) -> Option<String>,
+    real_uid: libc::uid_t,
+    real_gid: libc::gid_t,
+) -> Option<(libc::uid_t, libc::gid_t)> {
+    let parse = |name: &str| env(name).and_then(|v| v.parse().ok());
+    let (uid, gid) = if let Some(uid) = parse("SUDO_UID") {
+        (uid, parse("SUDO_GID").or_else(|| primary_gid(uid))?)
+    } else if let Some(uid) = parse("PKEXEC_UID") {
+        (uid, primary_gid(uid)?)
+    } else {
+        // A setuid-root binary started by a regular user.
+        (real_uid, real_gid)
+    };
+    (uid != 0).then_some((uid, gid))
+}
+
+fn primary_gid(uid: libc::uid_t) -> Option<libc::gid_t> {
+    // SAFETY: getpwuid returns null or a pointer to a static passwd entry, read immediately.
+    let entry = unsafe { libc::getpwuid(uid) };
+    (!entry.is_null()).then(|| unsafe { (*entry).pw_gid })
+}
+
+fn switch_user(uid: libc::uid_t, gid: libc::gid_t) -> io::Result<()> {
+    // Order matters: supplementary groups and gid can only be changed while still root.
+    // SAFETY: plain syscalls on integer arguments.
+    unsafe {
+        if libc::setgroups(1, &gid) != 0 || libc::setgid(gid) != 0 || libc::setuid(uid) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::setuid(0) == 0 {
+            return Err(io::Error::other(
+                "privileges were not dropped: setuid(0) succeeded",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn has_capabilities() -> io::Result<bool> {
+    let mut header = CapHeader {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapData::default(); 2];
+    // SAFETY: header and data match the kernel's v3 layout.
+    let rc = unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(data.iter().any(|d| d.permitted != 0 || d.effective != 0))
+}
+
+/// Clears the calling thread's capabilities; async-signal-safe.
+fn clear_capabilities() -> io::Result<()> {
+    let mut header = CapHeader {
+        version: CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let data = [CapData::default(); 2];
+    // SAFETY: header and data match the kernel's v3 layout.
+    let rc = unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The most threads [`clear_capabilities_everywhere`] keeps track of.
+const MAX_THREADS: usize = 256;
+
+/// The threads that have run [`clear_on_signal`], by thread id, and how many of them failed.
+static CLEARED: [AtomicI32; MAX_THREADS] = [const { AtomicI32::new(0) }; MAX_THREADS];
+static CLEARED_COUNT: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn clear_on_signal(_: libc::c_int) {
+    // SAFETY: errno is thread-local; restoring it keeps the interrupted code's value.
+    let errno = unsafe { *libc::__errno_location() };
+    if clear_capabilities().is_err() {
+        FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+    // SAFETY: gettid has no preconditions.
+    let tid = unsafe { libc::gettid() };
+    let slot = CLEARED_COUNT.fetch_add(1, Ordering::SeqCst);
+    if let Some(cleared) = CLEARED.get(slot) {
+        cleared.store(tid, Ordering::SeqCst);
+    }
+    // SAFETY: as above.
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// Clears the capabilities of every thread in the process and returns how many there were.
+///
+/// `capset` only changes the calling thread, and threads started before the drop (the action
+/// worker, the status socket, file watchers) would keep theirs. So, as libcap's psx does, each
+/// of them is sent a signal whose handler clears its own.
+fn clear_capabilities_everywhere() -> io::Result<usize> {
+    let signal = libc::SIGRTMIN();
+    // SAFETY: an all-zero sigaction is valid, and the handler only makes async-signal-safe calls.
+    let previous = unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = clear_on_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(signal, &action, &mut previous) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        previous
+    };
+    let signalled = signal_other_threads(signal);
+    // SAFETY: `previous` came from sigaction.
+    unsafe { libc::sigaction(signal, &previous, std::ptr::null_mut()) };
+    let signalled = signalled?;
+    let failed = FAILED.load(Ordering::SeqCst);
+    if failed > 0 {
+        return Err(io::Error::other(format!(
+            "{failed} thread(s) couldn't drop their capabilities"
+        )));
+    }
+    clear_capabilities()?;
+    Ok(signalled + 1)
+}
+
+/// Signals every thread but the calling one, waiting for each to handle it or exit, and
+/// returns how many were signalled.
+fn signal_other_threads(signal: libc::c_int) -> io::Result<usize> {
+    // SAFETY: these getters have no preconditions and can't fail.
+    let (pid, me) = unsafe { (libc::getpid(), libc::gettid()) };
+    let mut signalled = BTreeSet::new();
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        // A thread may start another before it has dropped, so look again until there's no
+        // thread left that hasn't been signalled.
+        let new: Vec<libc::pid_t> = std::fs::read_dir("/proc/self/task")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .filter(|tid| *tid != me && !signalled.contains(tid))
+            .collect();
+        if new.is_empty() {
+            return Ok(signalled.len());
+        }
+        for tid in new {
+            // SAFETY: tgkill only sends the signal; a thread that has exited gives ESRCH.
+            if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, signal) } == 0 {
+                signalled.insert(tid);
+            }
+        }
+        loop {
+            let cleared = CLEARED_COUNT.load(Ordering::SeqCst).min(MAX_THREADS);
+            let cleared: BTreeSet<libc::pid_t> = CLEARED[..cleared]
+                .iter()
+                .map(|tid| tid.load(Ordering::SeqCst))
+                .collect();
+            let waiting = signalled.iter().any(|tid| {
+                !cleared.contains(tid) && Path::new(&format!("/proc/self/task/{tid}")).exists()
+            });
+            if !waiting {
+                break;
+            }
+            if signalled.len() > MAX_THREADS || Instant::now() > deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "not every thread dropped its capabilities",
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invoking_user_prefers_sudo_then_real_uid_and_never_root() {
+        let sudo = |name: &str| match name {
+            "SUDO_UID" => Some("1000".to_owned()),
+            "SUDO_GID" => Some("1001".to_owned()),
+            _ => None,
+        };
+        assert_eq!(invoking_user(sudo, 0, 0), Some((1000, 1001)));
+
+        let none = |_: &str| None;
+        assert_eq!(invoking_user(none, 1000, 100), Some((1000, 100)));
+        assert_eq!(invoking_user(none, 0, 0), None);
+    }
+
+    /// The `CapEff` line of the calling thread's status.
+    fn effective_capabilities() -> String {
+        let status = std::fs::read_to_string("/proc/thread-self/status").unwrap();
+        let line = status.lines().find(|line| line.starts_with("CapEff:"));
+        line.unwrap()
+            .trim_start_matches("CapEff:")
+            .trim()
+            .to_owned()
+    }
+
+    #[test]
+    fn capabilities_are_dropped_on_threads_started_before() {
+        const CHILD: &str = "MOUSE_ASSIST_TEST_CAPABILITIES";
+        // Dropping is for the whole process, so it happens in a process of its own.
+        if std::env::var_os(CHILD).is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "--exact",
+                    "privs::tests::capabilities_are_dropped_on_threads_started_before",
+                ])
+                .env(CHILD, "1")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+        if !has_capabilities().unwrap() {
+            // Nothing to drop without any.
+            return;
+        }
+        struct Report(std::sync::mpsc::Sender<String>);
+        impl crate::worker::Executor for Report {
+            fn fork(&self) -> Self {
+                Self(self.0.clone())
+            }
+
+            fn execute_action(&mut self, _: &mouse_assist_core::Action) {
+                self.0.send(effective_capabilities()).unwrap();
+            }
+
+            fn execute_held_keys(&mut self, _: &[String], _: bool) {}
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        let queue = crate::worker::ActionQueue::spawn(Default::default(), Report(tx)).unwrap();
+        let action = mouse_assist_core::Action::Command {
+            argv: vec!["true".into()],
+        };
+        queue.dispatch(&action);
+        assert_ne!(rx.recv().unwrap(), "0000000000000000");
+
+        assert!(clear_capabilities_everywhere().unwrap() >= 2);
+        assert_eq!(effective_capabilities(), "0000000000000000");
+        // Not the same action again, or `min_action_interval_ms` could drop it.
+        queue.dispatch(&mouse_assist_core::Action::Command {
+            argv: vec!["false".into()],
+        });
+        assert_eq!(rx.recv().unwrap(), "0000000000000000");
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+fn state_file() -> Result<PathBuf, mouse_assist_core::ConfigError> {
+    Ok(mouse_assist_core::state_dir()?.join("active-profile"))
+}
+
+/// The profile saved by the last [`save`], if any.
+pub(crate) fn load() -> Option<String> {
+    let path = state_file().ok()?;
+    match fs::read_to_string(&path) {
+        Ok(name) => Some(name.trim_end().to_owned()).filter(|name| !name.is_empty()),
+        Err(err) => {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                warn!("can't read remembered profile {}: {err}", path.display());
+            }
+            None
+        }
+    }
+}
+
+/// Remembers the active profile; `None` (the top-level bindings) forgets it.
+pub(crate) fn save(profile: Option<&str>) {
+    let path = match state_file() {
+        Ok(path) => path,
+        Err(err) => {
+            debug!("not remembering the profile: {err}");
+            return;
+        }
+    };
+    let saved = match profile {
+        Some(name) => path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, format!("{name}\n"))),
+        None => match fs::remove_file(&path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        },
+    };
+    if let Err(err) = saved {
+        warn!("can't remember profile in {}: {err}", path.display());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::dispatch::{Dispatcher, Input};
+use crate::{wheel_tilt_from_relative_axis, WheelTilt};
+use mouse_assist_core::MouseButton;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// One line of a recording.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RecordedEvent {
+    pub(crate) button: MouseButton,
+    /// 1 for a press, 0 for a release. Wheel tilts have no release; each one is a 1.
+    pub(crate) value: i32,
+    /// Milliseconds since the recording started.
+    pub(crate) timestamp: u64,
+}
+
+impl RecordedEvent {
+    fn is_tilt(&self) -> bool {
+        self.button.is_wheel()
+    }
+}
+
+/// The recordable part of an evdev event: mouse buttons and wheel tilts, with the value a
+/// [`RecordedEvent`] stores.
+pub(crate) fn recordable(ev: &evdev::InputEvent) -> Option<(MouseButton, i32)> {
+    match ev.destructure() {
+        evdev::EventSummary::Key(_event, keycode, value @ (0 | 1)) => {
+            let button = MouseButton::ALL
+                .into_iter()
+                .find(|b| b.linux_key_code() == Some(keycode.code()))?;
+            Some((button, value))
+        }
+        evdev::EventSummary::RelativeAxis(_event, axis, value) => {
+            match wheel_tilt_from_relative_axis(axis, value)? {
+                WheelTilt::Left => Some((MouseButton::WheelTiltLeft, 1)),
+                WheelTilt::Right => Some((MouseButton::WheelTiltRight, 1)),
+            }
+        }
+        _ => None,
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum RecordingError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("line {line}: events must be in timestamp order")]
+    OutOfOrder { line: usize },
+}
+
+/// Writes events as JSON lines, flushing each one so an interrupted recording keeps
+/// everything up to the interruption.
+pub(crate) struct Recorder {
+    out: BufWriter<File>,
+    /// When the first event arrived; timestamps count from there, so a replay starts right away.
+    start: Option<Instant>,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            start: None,
+        })
+    }
+
+    /// Records an event that happened just now.
+    pub(crate) fn record(&mut self, button: MouseButton, value: i32) -> Result<(), RecordingError> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        self.write(&RecordedEvent {
+            button,
+            value,
+            timestamp: start.elapsed().as_millis() as u64,
+        })
+    }
+
+    fn write(&mut self, event: &RecordedEvent) -> Result<(), RecordingError> {
+        serde_json::to_writer(&mut self.out, event).map_err(std::io::Error::from)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads a recording, skipping blank lines.
+pub(crate) fn read(path: &Path) -> Result<Vec<RecordedEvent>, RecordingError> {
+    let mut events: Vec<RecordedEvent> = Vec::new();
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: RecordedEvent =
+            serde_json::from_str(&line).map_err(|source| RecordingError::Parse {
+                line: index + 1,
+                source,
+            })?;
+        if events
+            .last()
+            .is_some_and(|last| last.timestamp > event.timestamp)
+        {
+            return Err(RecordingError::OutOfOrder { line: index + 1 });
+        }
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Feeds `events` to `dispatcher` with their recorded spacing, as if they came from a device.
+pub(crate) fn replay(events: &[RecordedEvent], dispatcher: &mut Dispatcher) {
+    let start = Instant::now();
+    for event in events {
+        let due = start + Duration::from_millis(event.timestamp);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+        let input = Input::Button(event.button);
+        if event.is_tilt() {
+            dispatcher.tap(input);
+        } else if event.value == 0 {
+            dispatcher.release(input);
+        } else {
+            dispatcher.press(input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::ActionQueue;
+    use mouse_assist_core::{Action, Config, QueueConfig};
+
+    #[test]
+    fn recordings_round_trip_and_replay_through_the_bindings() {
+        let dir = std::env::temp_dir().join(format!("mouse-assist-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+
+        let recorded = [
+            RecordedEvent {
+                button: MouseButton::BtnSide,
+                value: 1,
+                timestamp: 0,
+            },
+            RecordedEvent {
+                button: MouseButton::BtnSide,
+                value: 0,
+                timestamp: 10,
+            },
+            RecordedEvent {
+                button: MouseButton::BtnExtra,
+                value: 1,
+                timestamp: 20,
+            },
+        ];
+        let mut recorder = Recorder::create(&path).unwrap();
+        for event in &recorded {
+            recorder.write(event).unwrap();
+        }
+        drop(recorder);
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(raw.starts_with(concat!(
+            r#"{"button":"BTN_SIDE","value":1,"timestamp":0}"#,
+            "\n"
+        )));
+        let events = read(&path).unwrap();
+        assert_eq!(events, recorded);
+
+        let config = Config::default();
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let mut dispatcher = Dispatcher::new(config.clone(), queue);
+        replay(&events, &mut dispatcher);
+        drop(dispatcher);
+        // BTN_SIDE and BTN_EXTRA are the first two default bindings.
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(
+            queued,
+            [
+                config.bindings[0].action.clone(),
+                config.bindings[1].action.clone()
+            ]
+        );
+
+        std::fs::write(
+            &path,
+            concat!(
+                r#"{"button":"BTN_SIDE","value":1,"timestamp":5}"#,
+                "\n\n",
+                r#"{"button":"BTN_SIDE","value":0,"timestamp":1}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+        assert!(matches!(
+            read(&path),
+            Err(RecordingError::OutOfOrder { line: 3 })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use crate::load_run_config;
+use mouse_assist_core::{Config, KeyPolicy, SystemPolicy};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Editors (and the config app) write a file in more than one step; changes this close together
+/// count as one.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// How often a blocked event loop wakes up to check for a changed config.
+pub(crate) const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches the config file for `run` and loads it again when it changes.
+pub(crate) struct Reloader {
+    path: PathBuf,
+    system_policy: SystemPolicy,
+    changes: Receiver<()>,
+    /// Watching stops when it's dropped.
+    _watcher: RecommendedWatcher,
+}
+
+/// A config loaded after the file changed, checked and resolved like the one `run` started with.
+pub(crate) struct Reload {
+    pub(crate) config: Config,
+    pub(crate) keys: KeyPolicy,
+}
+
+impl Reloader {
+    /// Watches the directory holding `path` rather than the file itself, since editors often
+    /// replace a file instead of writing to it.
+    pub(crate) fn watch(path: &Path, system_policy: SystemPolicy) -> notify::Result<Self> {
+        let name = path.file_name().map(ToOwned::to_owned);
+        let (tx, changes) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if matches!(event.kind, EventKind::Access(_)) {
+                    return;
+                }
+                if event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == name.as_deref())
+                {
+                    let _ = tx.send(());
+                }
+            })?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            system_policy,
+            changes,
+            _watcher: watcher,
+        })
+    }
+
+    /// The new config, if the file has changed since the last call and still holds a usable
+    /// one. Problems with it are logged, and the caller keeps the config it has.
+    pub(crate) fn poll(&mut self) -> Option<Reload> {
+        self.changes.try_recv().ok()?;
+        self.settle();
+        self.load()
+    }
+
+    /// Blocks until the file changes into a usable config.
+    pub(crate) fn wait(&mut self) -> Reload {
+        loop {
+            // The sender lives in the watcher, which outlives this loop.
+            let _ = self.changes.recv();
+            self.settle();
+            if let Some(reload) = self.load() {
+                return reload;
+            }
+        }
+    }
+
+    fn settle(&self) {
+        loop {
+            std::thread::sleep(SETTLE);
+            if self.changes.try_iter().count() == 0 {
+                return;
+            }
+        }
+    }
+
+    fn load(&self) -> Option<Reload> {
+        match load_run_config(&self.path) {
+            Ok(config) => {
+                info!("reloaded {}", self.path.display());
+                let keys = KeyPolicy::new(&config, &self.system_policy);
+                Some(Reload { config, keys })
+            }
+            Err(err) => {
+                warn!("{err}; keeping the config already in use");
+                None
+            }
+        }
+    }
+}
+
+/// Waits until `fd` has something to read or `timeout` has passed, and says which; without a
+/// timeout it waits for as long as it takes.
+pub(crate) fn wait_readable(fd: RawFd, timeout: Option<Duration>) -> std::io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.map_or(-1, |timeout| {
+        i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX)
+    });
+    loop {
+        // SAFETY: `pollfd` is a valid, exclusively borrowed array of one entry.
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+        if ready >= 0 {
+            return Ok(ready > 0);
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn edits_are_picked_up_and_broken_ones_skipped() {
+        let dir = std::env::temp_dir().join(format!("mouse-assist-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let write = |keys: &str| {
+            fs::write(
+                &path,
+                format!(
+                    "[[bindings]]\nbutton = \"BTN_SIDE\"\naction = {{ type = \"key_combo\", keys = [{keys}] }}\n"
+                ),
+            )
+            .unwrap()
+        };
+        write("\"KEY_BACK\"");
+        let mut reloader = Reloader::watch(&path, SystemPolicy::default()).unwrap();
+        assert!(reloader.poll().is_none(), "nothing changed yet");
+
+        // Unknown key: invalid, so nothing to switch to.
+        write("\"KEY_BAKC\"");
+        std::thread::sleep(SETTLE);
+        assert!(reloader.poll().is_none());
+
+        write("\"KEY_FORWARD\"");
+        std::thread::sleep(SETTLE);
+        let reload = reloader.poll().expect("a valid edit reloads");
+        assert_eq!(
+            reload.config.bindings[0].action,
+            mouse_assist_core::Action::KeyCombo {
+                keys: vec!["KEY_FORWARD".into()],
+                target: None,
+            }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt::Display;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// The first wait between attempts; each later one doubles, up to [`MAX_DELAY`].
+const FIRST_DELAY: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Runs `attempt` until it succeeds or `timeout` worth of waiting has passed, backing off
+/// between attempts. A zero timeout makes a single attempt.
+///
+/// Meant for startup, where a device node may exist before udev has made it readable.
+pub(crate) fn with_backoff<T, E: Display>(
+    what: &str,
+    timeout: Duration,
+    attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    retry(what, timeout, attempt, std::thread::sleep)
+}
+
+fn retry<T, E: Display>(
+    what: &str,
+    timeout: Duration,
+    mut attempt: impl FnMut() -> Result<T, E>,
+    mut sleep: impl FnMut(Duration),
+) -> Result<T, E> {
+    let mut waited = Duration::ZERO;
+    let mut delay = FIRST_DELAY;
+    for tries in 1.. {
+        let err = match attempt() {
+            Ok(value) => {
+                if tries > 1 {
+                    info!("{what}: succeeded on attempt {tries}");
+                }
+                return Ok(value);
+            }
+            Err(err) => err,
+        };
+        let left = timeout.saturating_sub(waited);
+        if left.is_zero() {
+            if tries > 1 {
+                warn!(
+                    "{what}: giving up after {tries} attempts over {:.1}s",
+                    waited.as_secs_f32()
+                );
+            }
+            return Err(err);
+        }
+        let wait = delay.min(left);
+        info!(
+            "{what}: {err}; retrying in {}ms (attempt {tries}, {:.1}s left)",
+            wait.as_millis(),
+            left.as_secs_f32()
+        );
+        sleep(wait);
+        waited += wait;
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+    unreachable!("the attempt loop only ends by returning")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_until_the_timeout_is_spent() {
+        let mut waits = Vec::new();
+        let result: Result<(), &str> = retry(
+            "open",
+            Duration::from_secs(3),
+            || Err("not yet"),
+            |wait| waits.push(wait.as_millis()),
+        );
+        assert_eq!(result, Err("not yet"));
+        assert_eq!(waits, [250, 500, 1000, 1250]);
+
+        let mut tries = 0;
+        let mut waits = Vec::new();
+        let result = retry(
+            "open",
+            Duration::from_secs(3),
+            || {
+                tries += 1;
+                if tries < 3 {
+                    Err("not yet")
+                } else {
+                    Ok(tries)
+                }
+            },
+            |wait| waits.push(wait.as_millis()),
+        );
+        assert_eq!(result, Ok(3));
+        assert_eq!(waits, [250, 500]);
+
+        let mut tries = 0;
+        let result: Result<(), &str> = retry(
+            "open",
+            Duration::ZERO,
+            || {
+                tries += 1;
+                Err("not yet")
+            },
+            |_| panic!("a zero timeout never waits"),
+        );
+        assert_eq!((result, tries), (Err("not yet"), 1));
+    }
+}
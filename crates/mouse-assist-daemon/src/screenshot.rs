@@ -0,0 +1,265 @@
+use mouse_assist_core::ScreenshotMode;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+/// Stands in for the selection (a geometry or window id) in a capture command.
+const SELECTION: &str = "{selection}";
+/// Stands in for the output file in a capture command.
+const OUTPUT: &str = "{output}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Session {
+    Wayland,
+    X11,
+}
+
+impl Session {
+    pub(crate) fn detect() -> Option<Self> {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            Some(Self::Wayland)
+        } else if std::env::var_os("DISPLAY").is_some() {
+            Some(Self::X11)
+        } else {
+            None
+        }
+    }
+}
+
+/// The commands that take one screenshot.
+#[derive(Debug, PartialEq, Eq)]
+struct Plan {
+    /// Run first; its trimmed output replaces [`SELECTION`] in `capture`.
+    select: Option<Vec<String>>,
+    capture: Vec<String>,
+    /// Reads the PNG on stdin and puts it on the clipboard.
+    copy: Option<Vec<String>>,
+}
+
+pub(crate) fn argv(args: &[&str]) -> Vec<String> {
+    args.iter().map(|&arg| arg.to_owned()).collect()
+}
+
+/// Picks tools for `mode` from the ones `installed` reports.
+fn plan(
+    session: Session,
+    mode: ScreenshotMode,
+    to_clipboard: bool,
+    installed: impl Fn(&str) -> bool,
+) -> Result<Plan, String> {
+    let (select, capture) = match session {
+        Session::Wayland => {
+            if !installed("grim") {
+                return Err("no screenshot tool found (install grim)".into());
+            }
+            match mode {
+                ScreenshotMode::Full => (None, argv(&["grim", OUTPUT])),
+                ScreenshotMode::Region | ScreenshotMode::Window => {
+                    if mode == ScreenshotMode::Window {
+                        warn!("there's no portable way to find the focused window on Wayland; pick it as a region");
+                    }
+                    if !installed("slurp") {
+                        return Err("region screenshots need slurp".into());
+                    }
+                    (
+                        Some(argv(&["slurp"])),
+                        argv(&["grim", "-g", SELECTION, OUTPUT]),
+                    )
+                }
+            }
+        }
+        Session::X11 => match mode {
+            ScreenshotMode::Full if installed("maim") => (None, argv(&["maim", OUTPUT])),
+            ScreenshotMode::Region if installed("maim") => (None, argv(&["maim", "-s", OUTPUT])),
+            ScreenshotMode::Window if installed("maim") && installed("xdotool") => (
+                Some(argv(&["xdotool", "getactivewindow"])),
+                argv(&["maim", "-i", SELECTION, OUTPUT]),
+            ),
+            ScreenshotMode::Full if installed("scrot") => (None, argv(&["scrot", "-o", OUTPUT])),
+            ScreenshotMode::Region if installed("scrot") => {
+                (None, argv(&["scrot", "-o", "-s", OUTPUT]))
+            }
+            ScreenshotMode::Window if installed("scrot") => {
+                (None, argv(&["scrot", "-o", "-u", OUTPUT]))
+            }
+            _ => return Err("no screenshot tool found (install maim or scrot)".into()),
+        },
+    };
+
+    let copy = if !to_clipboard {
+        None
+    } else {
+        let (tool, args) = match session {
+            Session::Wayland => ("wl-copy", argv(&["wl-copy", "--type", "image/png"])),
+            Session::X11 => (
+                "xclip",
+                argv(&["xclip", "-selection", "clipboard", "-t", "image/png"]),
+            ),
+        };
+        if installed(tool) {
+            Some(args)
+        } else {
+            warn!("{tool} isn't installed; the screenshot won't be copied to the clipboard");
+            None
+        }
+    };
+
+    Ok(Plan {
+        select,
+        capture,
+        copy,
+    })
+}
+
+pub(crate) fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+fn timestamped_name() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    format!("screenshot-{secs}.png")
+}
+
+/// Where the image is written, and whether it's a temporary file to delete afterwards.
+fn output_path(save_path: Option<&Path>, to_clipboard: bool) -> (PathBuf, bool) {
+    match save_path {
+        Some(path) if path.is_dir() => (path.join(timestamped_name()), false),
+        Some(path) => (path.to_owned(), false),
+        None if to_clipboard => (
+            std::env::temp_dir().join(format!("mouse-assist-{}", timestamped_name())),
+            true,
+        ),
+        None => {
+            let pictures = std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join("Pictures"))
+                .filter(|dir| dir.is_dir())
+                .unwrap_or_else(|| PathBuf::from("."));
+            (pictures.join(timestamped_name()), false)
+        }
+    }
+}
+
+/// Takes a screenshot in the background; region selection can take as long as the user likes.
+pub(crate) fn take(mode: ScreenshotMode, to_clipboard: bool, save_path: Option<&Path>) {
+    let Some(session) = Session::detect() else {
+        warn!("screenshot: neither WAYLAND_DISPLAY nor DISPLAY is set");
+        return;
+    };
+    let plan = match plan(session, mode, to_clipboard, in_path) {
+        Ok(plan) => plan,
+        Err(err) => {
+            warn!("screenshot: {err}");
+            return;
+        }
+    };
+    let (output, temporary) = output_path(save_path, to_clipboard);
+    let spawned = std::thread::Builder::new()
+        .name("screenshot".into())
+        .spawn(move || {
+            if let Err(err) = run(&plan, &output) {
+                error!("screenshot failed: {err}");
+            } else if !temporary {
+                info!("saved screenshot: {}", output.display());
+            }
+            if temporary {
+                let _ = std::fs::remove_file(&output);
+            }
+        });
+    if let Err(err) = spawned {
+        error!("failed to start screenshot thread: {err}");
+    }
+}
+
+fn run(plan: &Plan, output: &Path) -> Result<(), String> {
+    let selection = match &plan.select {
+        Some(select) => {
+            let out = Command::new(&select[0])
+                .args(&select[1..])
+                .stderr(Stdio::inherit())
+                .output()
+                .map_err(|err| format!("{}: {err}", select[0]))?;
+            if !out.status.success() {
+                // Usually the selection was cancelled.
+                return Err(format!("{} exited with {}", select[0], out.status));
+            }
+            String::from_utf8_lossy(&out.stdout).trim().to_owned()
+        }
+        None => String::new(),
+    };
+    let output_arg = output.to_string_lossy();
+    let capture: Vec<&str> = plan
+        .capture
+        .iter()
+        .map(|arg| match arg.as_str() {
+            SELECTION => selection.as_str(),
+            OUTPUT => &output_arg,
+            arg => arg,
+        })
+        .collect();
+    let status = Command::new(capture[0])
+        .args(&capture[1..])
+        .status()
+        .map_err(|err| format!("{}: {err}", capture[0]))?;
+    if !status.success() {
+        return Err(format!("{} exited with {status}", capture[0]));
+    }
+
+    if let Some(copy) = &plan.copy {
+        let image = File::open(output).map_err(|err| format!("{}: {err}", output.display()))?;
+        let status = Command::new(&copy[0])
+            .args(&copy[1..])
+            .stdin(image)
+            .status()
+            .map_err(|err| format!("{}: {err}", copy[0]))?;
+        if !status.success() {
+            return Err(format!("{} exited with {status}", copy[0]));
+        }
+        info!("copied screenshot to the clipboard");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_prefers_maim_and_falls_back_to_scrot() {
+        let only = |tools: &'static [&'static str]| move |tool: &str| tools.contains(&tool);
+
+        let maim = plan(
+            Session::X11,
+            ScreenshotMode::Window,
+            true,
+            only(&["maim", "scrot", "xdotool", "xclip"]),
+        )
+        .unwrap();
+        assert_eq!(maim.select, Some(argv(&["xdotool", "getactivewindow"])));
+        assert_eq!(maim.capture, argv(&["maim", "-i", SELECTION, OUTPUT]));
+        assert!(maim.copy.is_some());
+
+        // Without xdotool, maim can't find the window; scrot can.
+        let scrot = plan(
+            Session::X11,
+            ScreenshotMode::Window,
+            true,
+            only(&["maim", "scrot"]),
+        )
+        .unwrap();
+        assert_eq!(scrot.capture, argv(&["scrot", "-o", "-u", OUTPUT]));
+        assert_eq!(scrot.copy, None);
+
+        assert!(plan(
+            Session::Wayland,
+            ScreenshotMode::Region,
+            false,
+            only(&["grim"])
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::command_notify;
+use crate::worker::Executor;
+use mouse_assist_core::{Action, MouseButton};
+use tracing::{error, info};
+
+/// How long a script may run before it's stopped, e.g. when it's stuck in a loop.
+#[cfg(feature = "script")]
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// What a script can see.
+#[cfg_attr(not(feature = "script"), allow(dead_code))]
+pub(crate) struct Context {
+    /// The button whose binding fired the script.
+    pub(crate) button: Option<MouseButton>,
+    /// The focused window's `WM_CLASS` (instance, class); empty when it isn't known.
+    pub(crate) window_class: Vec<String>,
+}
+
+/// Something a script asked for. Nothing happens while the script runs: these are carried
+/// out once it has finished, and not at all if it fails.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(not(feature = "script"), allow(dead_code))]
+enum Effect {
+    Action(Action),
+    Notify { summary: String, body: String },
+}
+
+/// Runs a `script` action, then does what it asked for through `executor`.
+pub(crate) fn run<E: Executor + ?Sized>(source: &str, context: &Context, executor: &mut E) {
+    let effects = match evaluate(source, context) {
+        Ok(effects) => effects,
+        Err(err) => {
+            error!("script failed: {err}");
+            return;
+        }
+    };
+    for effect in effects {
+        match effect {
+            Effect::Action(action) => executor.execute_action(&action),
+            Effect::Notify { summary, body } => {
+                info!("script notification: {summary}");
+                if let Err(err) = command_notify::notify(&summary, &body) {
+                    error!("failed to show the script's notification: {err}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "script"))]
+fn evaluate(_source: &str, _context: &Context) -> Result<Vec<Effect>, String> {
+    Err("this build has no scripting support (enable the `script` feature)".to_owned())
+}
+
+#[cfg(feature = "script")]
+fn evaluate(source: &str, context: &Context) -> Result<Vec<Effect>, Box<rhai::EvalAltResult>> {
+    sandboxed::evaluate(source, context, TIMEOUT)
+}
+
+#[cfg(feature = "script")]
+mod sandboxed {
+    use super::{Context, Effect};
+    use mouse_assist_core::{parse_key_name, Action};
+    use rhai::module_resolvers::DummyModuleResolver;
+    use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+    use tracing::{debug, info};
+
+    type Effects = Rc<RefCell<Vec<Effect>>>;
+
+    /// Runs `source` in an engine that can only compute and ask for effects: no modules, no
+    /// `eval`, bounded memory and call depth, and stopped after `timeout`.
+    pub(super) fn evaluate(
+        source: &str,
+        context: &Context,
+        timeout: Duration,
+    ) -> Result<Vec<Effect>, Box<EvalAltResult>> {
+        let effects = Effects::default();
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .set_max_string_size(64 * 1024)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32);
+        let started = Instant::now();
+        engine.on_progress(move |_| {
+            (started.elapsed() > timeout).then(|| format!("timed out after {timeout:?}").into())
+        });
+        engine.on_print(|text| info!("script: {text}"));
+        engine.on_debug(|text, _, position| debug!("script ({position}): {text}"));
+        register_api(&mut engine, &effects);
+
+        let class = |index: usize| context.window_class.get(index).cloned().unwrap_or_default();
+        let mut scope = Scope::new();
+        scope.push_constant(
+            "button",
+            context
+                .button
+                .map(|button| button.toml_name().to_owned())
+                .unwrap_or_default(),
+        );
+        scope.push_constant("window_instance", class(0));
+        scope.push_constant("window_class", class(1));
+        engine.run_with_scope(&mut scope, source)?;
+        Ok(effects.take())
+    }
+
+    fn register_api(engine: &mut Engine, effects: &Effects) {
+        let sink = Rc::clone(effects);
+        engine.register_fn("send_keys", move |keys: &str| {
+            sink.borrow_mut().push(Effect::Action(key_combo(
+                keys.split('+').map(str::to_owned),
+            )));
+        });
+        let sink = Rc::clone(effects);
+        engine.register_fn(
+            "send_keys",
+            move |keys: Array| -> Result<(), Box<EvalAltResult>> {
+                let keys = strings(keys, "send_keys")?;
+                sink.borrow_mut()
+                    .push(Effect::Action(key_combo(keys.into_iter())));
+                Ok(())
+            },
+        );
+        let sink = Rc::clone(effects);
+        engine.register_fn(
+            "run",
+            move |argv: Array| -> Result<(), Box<EvalAltResult>> {
+                let argv = strings(argv, "run")?;
+                if argv.is_empty() {
+                    return Err("run needs at least a program".into());
+                }
+                sink.borrow_mut()
+                    .push(Effect::Action(Action::Command { argv }));
+                Ok(())
+            },
+        );
+        let sink = Rc::clone(effects);
+        engine.register_fn("notify", move |summary: &str| {
+            sink.borrow_mut().push(Effect::Notify {
+                summary: summary.to_owned(),
+                body: String::new(),
+            });
+        });
+        let sink = Rc::clone(effects);
+        engine.register_fn("notify", move |summary: &str, body: &str| {
+            sink.borrow_mut().push(Effect::Notify {
+                summary: summary.to_owned(),
+                body: body.to_owned(),
+            });
+        });
+    }
+
+    fn key_combo(keys: impl Iterator<Item = String>) -> Action {
+        Action::KeyCombo {
+            keys: keys.map(|key| parse_key_name(&key)).collect(),
+            target: None,
+        }
+    }
+
+    fn strings(values: Array, function: &str) -> Result<Vec<String>, Box<EvalAltResult>> {
+        values
+            .into_iter()
+            .map(Dynamic::into_string)
+            .collect::<Result<_, _>>()
+            .map_err(|kind| format!("{function} takes strings, not {kind}").into())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use mouse_assist_core::MouseButton;
+
+        fn context() -> Context {
+            Context {
+                button: Some(MouseButton::BtnSide),
+                window_class: vec!["navigator".into(), "firefox".into()],
+            }
+        }
+
+        fn eval(source: &str) -> Result<Vec<Effect>, Box<EvalAltResult>> {
+            evaluate(source, &context(), Duration::from_millis(200))
+        }
+
+        #[test]
+        fn scripts_see_the_context_and_ask_for_effects() {
+            let effects = eval(
+                r#"
+                if window_class == "firefox" && button == "BTN_SIDE" {
+                    send_keys("ctrl+w");
+                } else {
+                    send_keys(["alt", "f4"]);
+                }
+                run(["notify-send", `class: ${window_instance}`]);
+                notify("done");
+                "#,
+            )
+            .unwrap();
+            assert_eq!(
+                effects,
+                vec![
+                    Effect::Action(Action::KeyCombo {
+                        keys: vec!["KEY_LEFTCTRL".into(), "KEY_W".into()],
+                        target: None,
+                    }),
+                    Effect::Action(Action::Command {
+                        argv: vec!["notify-send".into(), "class: navigator".into()],
+                    }),
+                    Effect::Notify {
+                        summary: "done".into(),
+                        body: String::new(),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn scripts_are_sandboxed_and_stopped_when_they_hang() {
+            let err = eval("loop { }").unwrap_err();
+            assert!(matches!(*err, EvalAltResult::ErrorTerminated(..)), "{err}");
+            assert!(eval(r#"import "os" as os;"#).is_err());
+            assert!(eval(r#"eval("1 + 1")"#).is_err());
+            assert!(eval("run([])").is_err());
+            assert!(eval("run([1, 2])").is_err());
+        }
+    }
+}
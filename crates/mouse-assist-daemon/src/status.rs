@@ -0,0 +1,201 @@
+use std::ffi::CString;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+/// What the status socket reports, updated by the event loop and the dispatcher.
+#[derive(Debug)]
+pub(crate) struct Status {
+    started: Instant,
+    snapshot: Mutex<Snapshot>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Snapshot {
+    backend: &'static str,
+    /// `None` where the backend doesn't pick devices (X11 listens to every pointer).
+    devices: Option<usize>,
+    profile: Option<String>,
+    paused: bool,
+}
+
+impl Status {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            snapshot: Mutex::new(Snapshot::default()),
+        }
+    }
+
+    pub(crate) fn set_backend(&self, backend: &'static str) {
+        self.lock().backend = backend;
+    }
+
+    pub(crate) fn set_devices(&self, devices: usize) {
+        self.lock().devices = Some(devices);
+    }
+
+    pub(crate) fn set_profile(&self, profile: Option<&str>) {
+        self.lock().profile = profile.map(str::to_owned);
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.lock().paused = paused;
+    }
+
+    /// `backend=evdev devices=2 uptime=93s profile="media" paused=false`; `-` stands for "none".
+    pub(crate) fn line(&self) -> String {
+        let snapshot = self.lock().clone();
+        format!(
+            "backend={} devices={} uptime={}s profile={} paused={}",
+            if snapshot.backend.is_empty() {
+                "-"
+            } else {
+                snapshot.backend
+            },
+            snapshot
+                .devices
+                .map_or_else(|| "-".to_owned(), |n| n.to_string()),
+            self.started.elapsed().as_secs(),
+            snapshot
+                .profile
+                .map_or_else(|| "-".to_owned(), |name| format!("{name:?}")),
+            snapshot.paused,
+        )
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Snapshot> {
+        // A panic elsewhere can't leave the snapshot half-written in a way that matters here.
+        self.snapshot.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The socket's path, for the signal handler to remove.
+static SOCKET_PATH: OnceLock<CString> = OnceLock::new();
+
+/// Removes the socket file when dropped.
+pub(crate) struct StatusSocket {
+    path: PathBuf,
+}
+
+impl Drop for StatusSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/mouse-assist/status.sock`.
+pub(crate) fn socket_path() -> Result<PathBuf, mouse_assist_core::ConfigError> {
+    Ok(mouse_assist_core::runtime_dir()?.join("status.sock"))
+}
+
+/// Starts answering every connection to [`socket_path`] with [`Status::line`] on its own
+/// thread. Returns `None` (after logging why) when there's no socket to serve.
+pub(crate) fn serve(status: Arc<Status>) -> Option<StatusSocket> {
+    let path = match socket_path() {
+        Ok(path) => path,
+        Err(err) => {
+            info!("no status socket: {err}");
+            return None;
+        }
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            warn!("can't create {}: {err}", dir.display());
+            return None;
+        }
+    }
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            warn!(
+                "another daemon is serving {}; no status socket",
+                path.display()
+            );
+            return None;
+        }
+        // Left behind by a daemon that didn't get to clean up.
+        let _ = std::fs::remove_file(&path);
+    }
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!("can't create status socket {}: {err}", path.display());
+            return None;
+        }
+    };
+    if let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) {
+        if SOCKET_PATH.set(c_path).is_ok() {
+            remove_socket_on_termination();
+        }
+    }
+    let spawned = std::thread::Builder::new()
+        .name("mouse-assist-status".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(mut stream) => {
+                        let _ = writeln!(stream, "{}", status.line());
+                    }
+                    Err(err) => debug!("status socket: {err}"),
+                }
+            }
+        });
+    if let Err(err) = spawned {
+        warn!("can't start the status socket thread: {err}");
+        let _ = std::fs::remove_file(&path);
+        return None;
+    }
+    info!("status socket: {}", path.display());
+    Some(StatusSocket { path })
+}
+
+/// SIGTERM and SIGINT end the process without running destructors, so the socket file is
+/// removed from the signal handler before the signal takes its default course.
+fn remove_socket_on_termination() {
+    extern "C" fn on_signal(signal: libc::c_int) {
+        // SAFETY: unlink, signal and raise are async-signal-safe, and the path is a valid
+        // C string that lives until the process exits.
+        unsafe {
+            if let Some(path) = SOCKET_PATH.get() {
+                libc::unlink(path.as_ptr());
+            }
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: the handler only makes async-signal-safe calls.
+        unsafe {
+            libc::signal(
+                signal,
+                on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_line_reports_the_snapshot() {
+        let status = Status::new();
+        assert_eq!(
+            status.line(),
+            "backend=- devices=- uptime=0s profile=- paused=false"
+        );
+        status.set_backend("evdev");
+        status.set_devices(2);
+        status.set_profile(Some("media"));
+        status.set_paused(true);
+        assert_eq!(
+            status.line(),
+            "backend=evdev devices=2 uptime=0s profile=\"media\" paused=true"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::linux_key_name_to_x11_keysym;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+use std::time::Instant;
+use tracing::warn;
+use wayland_client::globals::{registry_queue_init, BindError, GlobalError, GlobalListContents};
+use wayland_client::protocol::{wl_keyboard, wl_registry, wl_seat};
+use wayland_client::{
+    delegate_noop, ConnectError, Connection, Dispatch, DispatchError, EventQueue, QueueHandle,
+};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+    zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+    zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+use x11rb::protocol::xproto;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum WaylandError {
+    #[error("can't connect to the Wayland compositor: {0}")]
+    Connect(#[from] ConnectError),
+    #[error(transparent)]
+    Globals(#[from] GlobalError),
+    #[error("the compositor has no {0}: {1}")]
+    Bind(&'static str, BindError),
+    #[error(transparent)]
+    Dispatch(#[from] DispatchError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A keyboard the compositor adds to the seat through the `zwp_virtual_keyboard_v1` protocol
+/// (wlroots-based compositors such as Sway and Hyprland offer it). Its keys are evdev codes, so
+/// it sends what the uinput keyboard would, without needing access to `/dev/uinput`.
+pub(crate) struct VirtualKeyboard {
+    conn: Connection,
+    keyboard: ZwpVirtualKeyboardV1,
+    /// Owns the objects' event queue, which nothing ever needs to read.
+    _queue: EventQueue<State>,
+    /// Key events carry milliseconds since this.
+    started: Instant,
+}
+
+struct State;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ignore wl_seat::WlSeat);
+delegate_noop!(State: ZwpVirtualKeyboardManagerV1);
+delegate_noop!(State: ZwpVirtualKeyboardV1);
+
+impl VirtualKeyboard {
+    /// Connects to the compositor named by the environment and declares a keyboard with
+    /// `keys`, using the same keysyms as the X11 backend. Keys without one are left out.
+    pub(crate) fn connect(
+        keys: impl IntoIterator<Item = evdev::KeyCode>,
+    ) -> Result<Self, WaylandError> {
+        let conn = Connection::connect_to_env()?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+        let qh = queue.handle();
+        let seat: wl_seat::WlSeat = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|err| WaylandError::Bind("wl_seat", err))?;
+        let manager: ZwpVirtualKeyboardManagerV1 = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|err| WaylandError::Bind("zwp_virtual_keyboard_manager_v1", err))?;
+        let keyboard = manager.create_virtual_keyboard(&seat, &qh, ());
+
+        let keysyms: Vec<(u16, xproto::Keysym)> = keys
+            .into_iter()
+            .filter_map(|key| {
+                let name = format!("{key:?}");
+                let keysym = linux_key_name_to_x11_keysym(&name);
+                if keysym.is_none() {
+                    warn!("no keysym known for {name}; the Wayland keyboard can't send it");
+                }
+                Some((key.code(), keysym?))
+            })
+            .collect();
+        let text = keymap(&keysyms);
+        let file = keymap_file(&text)?;
+        keyboard.keymap(
+            wl_keyboard::KeymapFormat::XkbV1.into(),
+            file.as_fd(),
+            // Including the terminating NUL.
+            text.len() as u32 + 1,
+        );
+        // A compositor that rejects the keyboard says so before this returns.
+        queue.roundtrip(&mut State)?;
+        Ok(Self {
+            conn,
+            keyboard,
+            _queue: queue,
+            started: Instant::now(),
+        })
+    }
+
+    /// Presses (`down`) or releases the key with evdev code `code`. Sent on [`Self::flush`].
+    pub(crate) fn key(&self, code: u16, down: bool) {
+        let state = if down {
+            wl_keyboard::KeyState::Pressed
+        } else {
+            wl_keyboard::KeyState::Released
+        };
+        // Wraps around like every Wayland timestamp.
+        let time = self.started.elapsed().as_millis() as u32;
+        self.keyboard.key(time, code.into(), state.into());
+    }
+
+    pub(crate) fn flush(&self) -> std::io::Result<()> {
+        self.conn.flush().map_err(std::io::Error::other)
+    }
+}
+
+/// An XKB keymap in which each evdev code in `keys` produces its keysym, the way the kernel's
+/// codes map to XKB keycodes (plus 8). Modifier keysyms get their usual modifier, so a held
+/// Ctrl works with the keys after it.
+fn keymap(keys: &[(u16, xproto::Keysym)]) -> String {
+    let maximum = keys
+        .iter()
+        .map(|&(code, _)| u32::from(code) + 8)
+        .max()
+        .unwrap_or(255)
+        .max(255);
+    let mut keycodes = String::new();
+    let mut symbols = String::new();
+    for &(code, keysym) in keys {
+        let _ = writeln!(keycodes, "    <K{code}> = {};", u32::from(code) + 8);
+        let _ = writeln!(symbols, "    key <K{code}> {{ [ 0x{keysym:x} ] }};");
+        if let Some(modifier) = modifier_name(keysym) {
+            let _ = writeln!(symbols, "    modifier_map {modifier} {{ <K{code}> }};");
+        }
+    }
+    format!(
+        "xkb_keymap {{\n\
+         xkb_keycodes \"mouse-assist\" {{\n    minimum = 8;\n    maximum = {maximum};\n{keycodes}}};\n\
+         xkb_types \"mouse-assist\" {{ include \"complete\" }};\n\
+         xkb_compatibility \"mouse-assist\" {{ include \"complete\" }};\n\
+         xkb_symbols \"mouse-assist\" {{\n{symbols}}};\n\
+         }};\n"
+    )
+}
+
+/// The modifier a modifier keysym sets, with the usual mapping.
+fn modifier_name(keysym: xproto::Keysym) -> Option<&'static str> {
+    use x11_dl::keysym as ks;
+    match keysym {
+        ks::XK_Shift_L | ks::XK_Shift_R => Some("Shift"),
+        ks::XK_Caps_Lock => Some("Lock"),
+        ks::XK_Control_L | ks::XK_Control_R => Some("Control"),
+        ks::XK_Alt_L | ks::XK_Alt_R => Some("Mod1"),
+        ks::XK_Super_L | ks::XK_Super_R => Some("Mod4"),
+        _ => None,
+    }
+}
+
+/// The keymap in an anonymous file, as the protocol hands it over: NUL-terminated.
+fn keymap_file(text: &str) -> std::io::Result<std::fs::File> {
+    // SAFETY: the name is a valid C string, and a descriptor returned by `memfd_create` is
+    // owned by nothing else.
+    let file = unsafe {
+        let fd = libc::memfd_create(c"mouse-assist-keymap".as_ptr(), libc::MFD_CLOEXEC);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        std::fs::File::from(OwnedFd::from_raw_fd(fd))
+    };
+    let mut writer = &file;
+    writer.write_all(text.as_bytes())?;
+    writer.write_all(&[0])?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymap_maps_evdev_codes_to_keysyms_and_modifiers() {
+        let text = keymap(&[
+            (29, x11_dl::keysym::XK_Control_L),
+            (46, x11_dl::keysym::XK_c),
+        ]);
+        assert!(text.contains("    <K29> = 37;\n    <K46> = 54;\n"));
+        assert!(text.contains("    key <K46> { [ 0x63 ] };\n"));
+        assert!(text.contains("    modifier_map Control { <K29> };\n"));
+        assert!(!text.contains("modifier_map Control { <K46> }"));
+        assert!(text.contains("maximum = 255;"));
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use ureq::http;
+
+/// How long a webhook may take, end to end, before it's abandoned.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends the request for a `webhook` action and logs the response status.
+pub(crate) fn send(
+    url: &str,
+    method: Option<&str>,
+    body: Option<&str>,
+    headers: Option<&BTreeMap<String, String>>,
+) {
+    if url.starts_with("https://") && !cfg!(feature = "tls") {
+        warn!("webhook {url}: this build has no TLS support (enable the `tls` feature)");
+        return;
+    }
+    let method = method.unwrap_or("POST");
+    let mut request = http::Request::builder().method(method).uri(url);
+    for (name, value) in headers.into_iter().flatten() {
+        request = request.header(name, value);
+    }
+    let request = match request.body(body.unwrap_or("").to_owned()) {
+        Ok(request) => request,
+        Err(err) => {
+            error!("webhook {method} {url}: {err}");
+            return;
+        }
+    };
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    match agent.run(request) {
+        Ok(response) if response.status().is_success() => {
+            info!("webhook {method} {url}: {}", response.status());
+        }
+        Ok(response) => warn!("webhook {method} {url}: {}", response.status()),
+        Err(err) => error!("webhook {method} {url} failed: {err}"),
+    }
+}
@@ -0,0 +1,224 @@
+use crate::screenshot::in_path;
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How long to wait on the window manager before giving up on a command.
+const IPC_TIMEOUT: Duration = Duration::from_secs(2);
+/// Starts every i3 IPC message, both ways.
+const I3_MAGIC: &[u8] = b"i3-ipc";
+/// i3's `RUN_COMMAND` message type, also Sway's.
+const I3_RUN_COMMAND: u32 = 0;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum WmError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("{wm} rejected it: {reason}")]
+    Rejected { wm: &'static str, reason: String },
+    #[error("{0} sent a reply that isn't i3 IPC")]
+    BadReply(&'static str),
+}
+
+/// A window manager whose IPC socket was found.
+#[derive(Debug, PartialEq, Eq)]
+enum Wm {
+    /// Sway or i3, which share i3's binary protocol.
+    I3 { name: &'static str, socket: PathBuf },
+    /// Hyprland's command socket, which takes `hyprctl` commands as plain text.
+    Hyprland { socket: PathBuf },
+}
+
+impl Wm {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::I3 { name, .. } => name,
+            Self::Hyprland { .. } => "Hyprland",
+        }
+    }
+}
+
+/// Sends a `wm_command` to the Sway, i3 or Hyprland instance the session runs, if any.
+pub(crate) fn run(command: &str) {
+    let wm = detect(&|name| std::env::var_os(name)).or_else(i3_from_cli);
+    let Some(wm) = wm else {
+        warn!("wm_command: no Sway, i3 or Hyprland IPC socket found; ignoring {command:?}");
+        return;
+    };
+    match send(&wm, command) {
+        Ok(()) => info!("wm_command {command:?} sent to {}", wm.name()),
+        Err(err) => error!("wm_command {command:?} on {} failed: {err}", wm.name()),
+    }
+}
+
+/// Finds the window manager from the variables it sets for its clients.
+fn detect(env: &dyn Fn(&str) -> Option<OsString>) -> Option<Wm> {
+    if let Some(socket) = env("SWAYSOCK") {
+        return Some(Wm::I3 {
+            name: "Sway",
+            socket: socket.into(),
+        });
+    }
+    if let Some(signature) = env("HYPRLAND_INSTANCE_SIGNATURE") {
+        let runtime_dir = env("XDG_RUNTIME_DIR").map(PathBuf::from);
+        return Some(Wm::Hyprland {
+            socket: hyprland_socket(runtime_dir.as_deref(), &signature),
+        });
+    }
+    env("I3SOCK").map(|socket| Wm::I3 {
+        name: "i3",
+        socket: socket.into(),
+    })
+}
+
+/// Hyprland 0.40 moved its sockets from `/tmp/hypr` to the runtime directory.
+fn hyprland_socket(runtime_dir: Option<&Path>, signature: &OsString) -> PathBuf {
+    let relative = Path::new("hypr").join(signature).join(".socket.sock");
+    runtime_dir
+        .map(|dir| dir.join(&relative))
+        .filter(|socket| socket.exists())
+        .unwrap_or_else(|| Path::new("/tmp").join(relative))
+}
+
+/// i3 only exports `I3SOCK` to processes it starts; anything else has to ask it.
+fn i3_from_cli() -> Option<Wm> {
+    if !in_path("i3") {
+        return None;
+    }
+    let output = Command::new("i3").arg("--get-socketpath").output().ok()?;
+    let socket = String::from_utf8(output.stdout).ok()?;
+    let socket = socket.trim();
+    (output.status.success() && !socket.is_empty()).then(|| Wm::I3 {
+        name: "i3",
+        socket: socket.into(),
+    })
+}
+
+fn send(wm: &Wm, command: &str) -> Result<(), WmError> {
+    let socket = match wm {
+        Wm::I3 { socket, .. } | Wm::Hyprland { socket } => socket,
+    };
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+    match wm {
+        Wm::I3 { name, .. } => i3_run_command(&mut stream, name, command),
+        Wm::Hyprland { .. } => {
+            stream.write_all(command.as_bytes())?;
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply)?;
+            match reply.trim() {
+                "ok" => Ok(()),
+                reason => Err(WmError::Rejected {
+                    wm: "Hyprland",
+                    reason: reason.to_owned(),
+                }),
+            }
+        }
+    }
+}
+
+/// One entry of a `RUN_COMMAND` reply; there's one per command separated by `,` or `;`.
+#[derive(Deserialize)]
+struct CommandResult {
+    success: bool,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// An i3 IPC message: magic, payload length and type in native byte order, then the payload.
+fn i3_message(kind: u32, payload: &[u8]) -> Vec<u8> {
+    let mut message = I3_MAGIC.to_vec();
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+fn i3_run_command(
+    stream: &mut (impl Read + Write),
+    wm: &'static str,
+    command: &str,
+) -> Result<(), WmError> {
+    stream.write_all(&i3_message(I3_RUN_COMMAND, command.as_bytes()))?;
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if !header.starts_with(I3_MAGIC) {
+        return Err(WmError::BadReply(wm));
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().expect("4 bytes"));
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload)?;
+    let results: Vec<CommandResult> =
+        serde_json::from_slice(&payload).map_err(|_| WmError::BadReply(wm))?;
+    match results.into_iter().find(|result| !result.success) {
+        Some(failed) => Err(WmError::Rejected {
+            wm,
+            reason: failed.error.unwrap_or_else(|| "no reason given".into()),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_prefers_sway_then_hyprland_then_i3() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+        assert_eq!(
+            detect(&env(&[
+                ("I3SOCK", "/run/i3"),
+                ("SWAYSOCK", "/run/sway.sock")
+            ])),
+            Some(Wm::I3 {
+                name: "Sway",
+                socket: "/run/sway.sock".into()
+            })
+        );
+        assert_eq!(
+            detect(&env(&[("HYPRLAND_INSTANCE_SIGNATURE", "abc")])),
+            Some(Wm::Hyprland {
+                socket: "/tmp/hypr/abc/.socket.sock".into()
+            })
+        );
+        assert_eq!(detect(&env(&[("WAYLAND_DISPLAY", "wayland-0")])), None);
+    }
+
+    #[test]
+    fn i3_commands_are_framed_and_failures_reported() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        let wm = std::thread::spawn(move || {
+            let mut header = [0; 14];
+            server.read_exact(&mut header).unwrap();
+            let mut payload =
+                vec![0; u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize];
+            server.read_exact(&mut payload).unwrap();
+            let reply = br#"[{"success":true},{"success":false,"error":"No such workspace"}]"#;
+            server
+                .write_all(&i3_message(I3_RUN_COMMAND, reply))
+                .unwrap();
+            (header, payload)
+        });
+
+        let err =
+            i3_run_command(&mut client, "Sway", "workspace next; workspace nope").unwrap_err();
+        assert_eq!(err.to_string(), "Sway rejected it: No such workspace");
+        let (header, payload) = wm.join().unwrap();
+        assert_eq!(&header[..6], I3_MAGIC);
+        assert_eq!(header[10..], I3_RUN_COMMAND.to_ne_bytes());
+        assert_eq!(payload, b"workspace next; workspace nope");
+    }
+}
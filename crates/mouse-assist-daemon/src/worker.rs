@@ -0,0 +1,640 @@
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use mouse_assist_core::{
+    Action, MouseButton, OverflowPolicy, QueueConfig, Step, MAX_SEQUENCE_DELAY_MS,
+};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Something that can carry out an [`Action`] (uinput, X11, ...).
+///
+/// The worker runs queued actions one at a time on its own thread, each to completion, so an
+/// executor may block, but every action queued behind it waits that long. Anything that can
+/// hang (a child process it waits on, a network request, a script) has its own time limit.
+/// The sub-actions of a `parallel` are the exception: see [`run_parallel`].
+pub(crate) trait Executor: Send {
+    fn execute_action(&mut self, action: &Action);
+
+    /// Another executor sending through the same devices, for a `parallel` sub-action to run on
+    /// while this one carries on.
+    fn fork(&self) -> Self
+    where
+        Self: Sized;
+
+    /// Presses (`down`) or releases the keys of a `hold`.
+    fn execute_held_keys(&mut self, keys: &[String], down: bool);
+
+    /// Called before each queued action with the button whose binding fired it, or `None`
+    /// (the startup action, held keys), for the `script` actions in it to see.
+    fn fired_by(&mut self, _button: Option<MouseButton>) {}
+}
+
+/// Something waiting for the worker, and the button whose binding fired it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Queued {
+    pub(crate) job: Job,
+    pub(crate) button: Option<MouseButton>,
+}
+
+/// What the worker runs.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Job {
+    Action(Action),
+    /// The keys of a `hold` going down (`down`) or coming back up.
+    HeldKeys {
+        keys: Vec<String>,
+        down: bool,
+    },
+}
+
+impl Job {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Action(action) => action.kind(),
+            Self::HeldKeys { .. } => "hold",
+        }
+    }
+}
+
+#[cfg(test)]
+impl Queued {
+    /// The queued action, for tests that only expect actions.
+    pub(crate) fn action(self) -> Action {
+        match self.job {
+            Job::Action(action) => action,
+            job => panic!("expected an action, got {job:?}"),
+        }
+    }
+}
+
+/// Hands actions off to a background worker so the input loop never blocks on them.
+///
+/// The queue is bounded by [`QueueConfig::depth`]; what happens when it's full is up to
+/// [`QueueConfig::overflow`].
+#[derive(Clone)]
+pub(crate) struct ActionQueue {
+    tx: Sender<Queued>,
+    limits: QueueConfig,
+    /// A handle on the worker's end, used to evict the oldest action under `DropOldest`.
+    oldest: Option<Receiver<Queued>>,
+    /// The last action dispatched other than `repeat_last`, shared by every clone of the queue.
+    last: Arc<Mutex<Option<Action>>>,
+    /// The last action let through by [`QueueConfig::min_action_interval_ms`], and when.
+    previous: Arc<Mutex<Option<(Action, Instant)>>>,
+}
+
+impl ActionQueue {
+    /// Creates a queue and the receiving end a worker should drain.
+    pub(crate) fn new(limits: QueueConfig) -> (Self, Receiver<Queued>) {
+        let (tx, rx) = crossbeam_channel::bounded(limits.depth);
+        let oldest = (limits.overflow == OverflowPolicy::DropOldest).then(|| rx.clone());
+        (
+            Self {
+                tx,
+                limits,
+                oldest,
+                last: Arc::default(),
+                previous: Arc::default(),
+            },
+            rx,
+        )
+    }
+
+    /// Spawns the worker thread that owns `executor` and runs queued actions in order.
+    pub(crate) fn spawn<E: Executor + 'static>(
+        limits: QueueConfig,
+        executor: E,
+    ) -> std::io::Result<Self> {
+        Self::spawn_joinable(limits, executor).map(|(queue, _)| queue)
+    }
+
+    /// Like [`ActionQueue::spawn`], also returning the worker thread, which finishes once every
+    /// clone of the queue is dropped and the queued actions have run.
+    pub(crate) fn spawn_joinable<E: Executor + 'static>(
+        limits: QueueConfig,
+        mut executor: E,
+    ) -> std::io::Result<(Self, JoinHandle<()>)> {
+        let (queue, rx) = Self::new(limits);
+        let worker = std::thread::Builder::new()
+            .name("action-worker".into())
+            .spawn(move || {
+                for Queued { job, button } in rx {
+                    executor.fired_by(button);
+                    match job {
+                        Job::Action(action) => executor.execute_action(&action),
+                        Job::HeldKeys { keys, down } => executor.execute_held_keys(&keys, down),
+                    }
+                }
+            })?;
+        Ok((queue, worker))
+    }
+
+    /// Queues `action` for execution.
+    ///
+    /// `Parallel` actions take a single place in the queue, with their nested `parallel` and
+    /// `repeat_last` sub-actions spelled out, so a full queue never drops some of them in favour
+    /// of others. The executor then starts them all at once (see [`run_parallel`]).
+    ///
+    /// `Sequence` actions are walked on a thread of their own, which queues each step's action
+    /// in turn and sleeps through the delays, so other buttons' actions don't wait for them.
+    ///
+    /// `RepeatLast` queues the last action dispatched before it, whichever binding that came
+    /// from, leaving out the key presses and releases of a `hold`. The queue remembers it rather
+    /// than the executor so a `parallel` is repeated whole.
+    ///
+    /// With [`QueueConfig::min_action_interval_ms`], an action identical to the previous one
+    /// dispatched is dropped if it comes too soon after it, whichever binding either came from.
+    pub(crate) fn dispatch(&self, action: &Action) {
+        self.dispatch_at(action, None, Instant::now());
+    }
+
+    /// [`Self::dispatch`] for the action of a binding on `button`. A `repeat_last` passes the
+    /// button on to what it replays.
+    pub(crate) fn dispatch_from(&self, button: MouseButton, action: &Action) {
+        self.dispatch_at(action, Some(button), Instant::now());
+    }
+
+    /// Queues pressing (`down`) or releasing the keys of a `hold`. These are never dropped,
+    /// whatever [`QueueConfig::overflow`] and [`QueueConfig::min_action_interval_ms`] say, since
+    /// a lost release would leave the keys stuck down: when the queue is full, this waits for
+    /// room.
+    pub(crate) fn hold_keys(&self, keys: Vec<String>, down: bool) {
+        self.enqueue_held(Queued {
+            job: Job::HeldKeys { keys, down },
+            button: None,
+        });
+    }
+
+    fn dispatch_at(&self, action: &Action, button: Option<MouseButton>, now: Instant) {
+        if self.too_soon(action, now) {
+            debug!(
+                "dropping {} action repeated within min_action_interval_ms",
+                action.kind()
+            );
+            return;
+        }
+        self.fan_out(action, button, true);
+        if !matches!(action, Action::RepeatLast) {
+            *self.last() = Some(action.clone());
+        }
+    }
+
+    /// `repeat` is false while replaying, so a `repeat_last` inside the replayed action can't
+    /// replay again.
+    fn fan_out(&self, action: &Action, button: Option<MouseButton>, repeat: bool) {
+        let mut actions = Vec::new();
+        self.collect(action, button, repeat, &mut actions);
+        let action = if actions.len() > 1 {
+            Action::Parallel { actions }
+        } else if let Some(action) = actions.pop() {
+            action
+        } else {
+            return;
+        };
+        self.enqueue(Queued {
+            job: Job::Action(action),
+            button,
+        });
+    }
+
+    /// Adds the actions `action` puts on the queue to `actions`. Sequences aren't among them:
+    /// they start walking their steps right away instead.
+    fn collect(
+        &self,
+        action: &Action,
+        button: Option<MouseButton>,
+        repeat: bool,
+        actions: &mut Vec<Action>,
+    ) {
+        match action {
+            Action::Parallel { actions: parallel } => {
+                for action in parallel {
+                    self.collect(action, button, repeat, actions);
+                }
+            }
+            Action::RepeatLast if repeat => {
+                let last = self.last().clone();
+                match last {
+                    Some(last) => self.collect(&last, button, false, actions),
+                    None => debug!("repeat_last: nothing has run yet"),
+                }
+            }
+            Action::RepeatLast => {}
+            Action::Sequence { steps } => {
+                let queue = self.clone();
+                let steps = steps.clone();
+                let spawned =
+                    std::thread::Builder::new()
+                        .name("sequence".into())
+                        .spawn(move || {
+                            run_sequence(&steps, |action| queue.fan_out(action, button, repeat))
+                        });
+                if let Err(err) = spawned {
+                    warn!("failed to start a sequence: {err}");
+                }
+            }
+            action => actions.push(action.clone()),
+        }
+    }
+
+    /// Whether `action` repeats the previous one within the minimum interval; if not, it
+    /// becomes the one later actions are compared with. Dropped repeats don't extend the window,
+    /// so a held auto-repeat still gets through once per interval.
+    fn too_soon(&self, action: &Action, now: Instant) -> bool {
+        if self.limits.min_action_interval_ms == 0 {
+            return false;
+        }
+        let window = Duration::from_millis(self.limits.min_action_interval_ms);
+        let mut previous = self.previous.lock().unwrap_or_else(|err| err.into_inner());
+        let repeated = previous
+            .as_ref()
+            .is_some_and(|(last, at)| last == action && now.duration_since(*at) < window);
+        if !repeated {
+            *previous = Some((action.clone(), now));
+        }
+        repeated
+    }
+
+    fn last(&self) -> std::sync::MutexGuard<'_, Option<Action>> {
+        self.last.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Queues held keys, waiting for room if need be.
+    fn enqueue_held(&self, held: Queued) {
+        if self.tx.send(held).is_err() {
+            warn!("action worker is gone; dropping hold action");
+        }
+    }
+
+    fn enqueue(&self, action: Queued) {
+        let result = match (&self.oldest, self.limits.overflow) {
+            (_, OverflowPolicy::Block) => self
+                .tx
+                .send(action)
+                .map_err(|err| TrySendError::Disconnected(err.into_inner())),
+            (Some(oldest), _) => {
+                let mut action = action;
+                loop {
+                    match self.tx.try_send(action) {
+                        Err(TrySendError::Full(rejected)) => match oldest.try_recv() {
+                            // A hold's keys can't be dropped: they go back in line, and the new
+                            // action is dropped instead.
+                            Ok(
+                                held @ Queued {
+                                    job: Job::HeldKeys { .. },
+                                    ..
+                                },
+                            ) => {
+                                self.enqueue_held(held);
+                                break Err(TrySendError::Full(rejected));
+                            }
+                            Ok(dropped) => {
+                                warn!(
+                                    "action queue full (depth {}); dropped oldest {} action",
+                                    self.limits.depth,
+                                    dropped.job.kind()
+                                );
+                                action = rejected;
+                            }
+                            Err(_) => action = rejected,
+                        },
+                        result => break result,
+                    }
+                }
+            }
+            (None, _) => self.tx.try_send(action),
+        };
+        match result {
+            Ok(()) => {}
+            Err(TrySendError::Full(action)) => warn!(
+                "action queue full (depth {}); dropped new {} action",
+                self.limits.depth,
+                action.job.kind()
+            ),
+            Err(TrySendError::Disconnected(action)) => {
+                warn!(
+                    "action worker is gone; dropping {} action",
+                    action.job.kind()
+                );
+            }
+        }
+    }
+}
+
+/// Starts each of `actions` on a thread of its own, with a [fork](Executor::fork) of `executor`,
+/// and returns without waiting for any of them, so a slow one (`command_notify`, `webhook`)
+/// holds up neither the others nor the queue.
+pub(crate) fn run_parallel<E: Executor + 'static>(executor: &E, actions: &[Action]) {
+    for action in actions {
+        let mut fork = executor.fork();
+        let action = action.clone();
+        let spawned = std::thread::Builder::new()
+            .name("parallel".into())
+            .spawn(move || fork.execute_action(&action));
+        if let Err(err) = spawned {
+            warn!("failed to start a parallel sub-action: {err}");
+        }
+    }
+}
+
+/// What an executor shares with its forks, such as a virtual device. Each use holds the lock
+/// throughout, so the key presses of one fork's combo can't land in the middle of another's.
+pub(crate) struct Shared<T>(Arc<Mutex<T>>);
+
+impl<T> Shared<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self(Arc::new(Mutex::new(value)))
+    }
+
+    pub(crate) fn lock(&self) -> std::sync::MutexGuard<'_, T> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+/// Runs `steps` in order with `run`, sleeping through the delays, which are cut short once
+/// they add up to [`MAX_SEQUENCE_DELAY_MS`].
+pub(crate) fn run_sequence(steps: &[Step], mut run: impl FnMut(&Action)) {
+    let mut left = Duration::from_millis(MAX_SEQUENCE_DELAY_MS);
+    for step in steps {
+        match step {
+            Step::Action(action) => run(action),
+            Step::Delay { ms } => {
+                let delay = Duration::from_millis(*ms).min(left);
+                left -= delay;
+                std::thread::sleep(delay);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(name: &str) -> Action {
+        Action::Command {
+            argv: vec![name.into()],
+        }
+    }
+
+    /// Runs each action only once `count` actions have started, then reports it.
+    #[derive(Clone)]
+    struct Rendezvous {
+        count: usize,
+        started: Arc<(Mutex<usize>, std::sync::Condvar)>,
+        tx: crossbeam_channel::Sender<Action>,
+    }
+
+    impl Executor for Rendezvous {
+        fn fork(&self) -> Self {
+            self.clone()
+        }
+
+        fn execute_action(&mut self, action: &Action) {
+            if let Action::Parallel { actions } = action {
+                run_parallel(self, actions);
+                return;
+            }
+            let (started, all_started) = &*self.started;
+            let mut started = started.lock().unwrap();
+            *started += 1;
+            all_started.notify_all();
+            let (started, wait) = all_started
+                .wait_timeout_while(started, Duration::from_secs(5), |started| {
+                    *started < self.count
+                })
+                .unwrap();
+            drop(started);
+            if !wait.timed_out() {
+                let _ = self.tx.send(action.clone());
+            }
+        }
+
+        fn execute_held_keys(&mut self, _keys: &[String], _down: bool) {}
+    }
+
+    #[test]
+    fn parallel_sub_actions_start_without_waiting_for_each_other() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let executor = Rendezvous {
+            count: 4,
+            started: Arc::default(),
+            tx,
+        };
+        let queue = ActionQueue::spawn(QueueConfig::default(), executor).unwrap();
+
+        // Each action blocks until all four have started, so this only finishes if the three
+        // sub-actions, and the action queued after them, all run at once.
+        queue.dispatch(&Action::Parallel {
+            actions: vec![
+                command("a"),
+                Action::Parallel {
+                    actions: vec![command("b"), command("c")],
+                },
+            ],
+        });
+        queue.dispatch(&command("d"));
+
+        let mut ran: Vec<Action> = (0..4)
+            .map(|_| rx.recv_timeout(Duration::from_secs(10)).unwrap())
+            .collect();
+        ran.sort_by_key(|action| format!("{action:?}"));
+        assert_eq!(ran, ["a", "b", "c", "d"].map(command));
+    }
+
+    /// Reports which button fired each action it runs.
+    struct Report {
+        button: Option<MouseButton>,
+        tx: crossbeam_channel::Sender<(Option<MouseButton>, Action)>,
+    }
+
+    impl Executor for Report {
+        fn fork(&self) -> Self {
+            Self {
+                button: self.button,
+                tx: self.tx.clone(),
+            }
+        }
+
+        fn fired_by(&mut self, button: Option<MouseButton>) {
+            self.button = button;
+        }
+
+        fn execute_action(&mut self, action: &Action) {
+            let _ = self.tx.send((self.button, action.clone()));
+        }
+
+        fn execute_held_keys(&mut self, _keys: &[String], _down: bool) {}
+    }
+
+    #[test]
+    fn the_executor_learns_which_button_fired_each_action() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let (queue, worker) =
+            ActionQueue::spawn_joinable(QueueConfig::default(), Report { button: None, tx })
+                .unwrap();
+
+        queue.dispatch_from(MouseButton::BtnSide, &command("a"));
+        queue.dispatch(&command("b"));
+        // What repeat_last replays counts as fired by its own button.
+        queue.dispatch_from(MouseButton::BtnExtra, &Action::RepeatLast);
+        drop(queue);
+        worker.join().unwrap();
+
+        assert_eq!(
+            rx.iter().collect::<Vec<_>>(),
+            [
+                (Some(MouseButton::BtnSide), command("a")),
+                (None, command("b")),
+                (Some(MouseButton::BtnExtra), command("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn sequences_queue_their_steps_in_order_off_the_caller_thread() {
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let started = Instant::now();
+
+        queue.dispatch(&Action::Sequence {
+            steps: vec![
+                Step::Action(command("a")),
+                Step::Delay { ms: 100 },
+                Step::Action(command("b")),
+            ],
+        });
+        queue.dispatch(&command("c"));
+        assert!(started.elapsed() < Duration::from_millis(100));
+        drop(queue);
+
+        // The queue's worker end stays open until the sequence is done with its clone.
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        // `c` may go before or after `a`, but the delay holds `b` back past both.
+        assert_eq!(queued.len(), 3);
+        assert!(queued[..2].contains(&command("a")) && queued[..2].contains(&command("c")));
+        assert_eq!(queued[2], command("b"));
+    }
+
+    #[test]
+    fn repeat_last_replays_the_previous_action_but_not_itself() {
+        let (queue, rx) = ActionQueue::new(QueueConfig::default());
+        let pair = Action::Parallel {
+            actions: vec![command("a"), Action::RepeatLast],
+        };
+
+        queue.dispatch(&Action::RepeatLast);
+        queue.dispatch(&command("z"));
+        queue.dispatch(&pair);
+        queue.clone().dispatch(&Action::RepeatLast);
+        queue.dispatch(&Action::RepeatLast);
+        drop(queue);
+
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        // The pair's own repeat_last replays "z"; replaying the pair skips it.
+        assert_eq!(
+            queued,
+            [
+                command("z"),
+                Action::Parallel {
+                    actions: vec![command("a"), command("z")],
+                },
+                command("a"),
+                command("a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_actions_within_the_minimum_interval_collapse() {
+        let (queue, rx) = ActionQueue::new(QueueConfig {
+            min_action_interval_ms: 100,
+            ..QueueConfig::default()
+        });
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // Chatter: the second identical press 20ms later is dropped.
+        queue.dispatch_at(&command("a"), None, at(0));
+        queue.clone().dispatch_at(&command("a"), None, at(20));
+        // A different action always goes through, and then "a" is new again.
+        queue.dispatch_at(&command("b"), None, at(40));
+        queue.dispatch_at(&command("a"), None, at(50));
+        // Once the window has passed, the same action runs again.
+        queue.dispatch_at(&command("a"), None, at(160));
+        drop(queue);
+
+        let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+        assert_eq!(queued, ["a", "b", "a", "a"].map(command));
+    }
+
+    #[test]
+    fn full_queue_drops_per_overflow_policy() {
+        for (overflow, expected) in [
+            (OverflowPolicy::DropOldest, ["b", "c"]),
+            (OverflowPolicy::DropNewest, ["a", "b"]),
+        ] {
+            let (queue, rx) = ActionQueue::new(QueueConfig {
+                depth: 2,
+                overflow,
+                ..QueueConfig::default()
+            });
+            for name in ["a", "b", "c"] {
+                queue.dispatch(&command(name));
+            }
+            drop(queue);
+
+            let queued: Vec<Action> = rx.iter().map(|queued| queued.action()).collect();
+            assert_eq!(queued, expected.map(command), "{overflow:?}");
+        }
+
+        // A hold's release is never dropped, even with no room left for it.
+        for overflow in [OverflowPolicy::DropOldest, OverflowPolicy::DropNewest] {
+            let (queue, rx) = ActionQueue::new(QueueConfig {
+                depth: 1,
+                overflow,
+                ..QueueConfig::default()
+            });
+            let release = Job::HeldKeys {
+                keys: vec!["KEY_LEFTCTRL".into()],
+                down: false,
+            };
+            queue.dispatch(&command("a"));
+            let releasing = std::thread::spawn({
+                let queue = queue.clone();
+                move || queue.hold_keys(vec!["KEY_LEFTCTRL".into()], false)
+            });
+            assert_eq!(rx.recv().unwrap().action(), command("a"));
+            releasing.join().unwrap();
+            // Nor does a new action evict it.
+            queue.dispatch(&command("b"));
+            drop(queue);
+
+            let queued: Vec<Job> = rx.iter().map(|queued| queued.job).collect();
+            assert_eq!(queued, [release], "{overflow:?}");
+        }
+
+        // A parallel is one action however many sub-actions it has, so it can't evict its own.
+        let (queue, rx) = ActionQueue::new(QueueConfig {
+            depth: 2,
+            overflow: OverflowPolicy::DropOldest,
+            ..QueueConfig::default()
+        });
+        let parallel = Action::Parallel {
+            actions: ["a", "b", "c"].map(command).to_vec(),
+        };
+        queue.dispatch(&parallel);
+        drop(queue);
+        assert_eq!(
+            rx.iter().map(|queued| queued.action()).collect::<Vec<_>>(),
+            [parallel]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use x11rb::protocol::xinput::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+/// A pointer the X server knows, as `xinput list` shows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Pointer {
+    pub(crate) id: xinput::DeviceId,
+    pub(crate) name: String,
+    /// `master`, `slave` (a physical device attached to a master) or `floating`.
+    pub(crate) kind: &'static str,
+}
+
+/// Every master, slave and floating pointer, by id.
+pub(crate) fn pointers(conn: &RustConnection) -> Result<Vec<Pointer>, x11rb::errors::ReplyError> {
+    let reply = conn.xinput_xi_query_device(xinput::Device::ALL)?.reply()?;
+    let mut pointers: Vec<Pointer> = reply
+        .infos
+        .into_iter()
+        .filter_map(|info| {
+            let kind = match info.type_ {
+                xinput::DeviceType::MASTER_POINTER => "master",
+                xinput::DeviceType::SLAVE_POINTER => "slave",
+                xinput::DeviceType::FLOATING_SLAVE => "floating",
+                _ => return None,
+            };
+            Some(Pointer {
+                id: info.deviceid,
+                name: String::from_utf8_lossy(&info.name).into_owned(),
+                kind,
+            })
+        })
+        .collect();
+    pointers.sort_by_key(|pointer| pointer.id);
+    Ok(pointers)
+}
+
+/// The pointer `spec` names: its id, its whole name (case-insensitively), or a part of the
+/// name only one pointer has.
+pub(crate) fn find<'a>(pointers: &'a [Pointer], spec: &str) -> Option<&'a Pointer> {
+    let spec = spec.trim();
+    if let Ok(id) = spec.parse::<xinput::DeviceId>() {
+        return pointers.iter().find(|pointer| pointer.id == id);
+    }
+    if let Some(pointer) = pointers
+        .iter()
+        .find(|pointer| pointer.name.eq_ignore_ascii_case(spec))
+    {
+        return Some(pointer);
+    }
+    let spec = spec.to_lowercase();
+    let mut matches = pointers
+        .iter()
+        .filter(|pointer| pointer.name.to_lowercase().contains(&spec));
+    match (matches.next(), matches.next()) {
+        (Some(pointer), None) => Some(pointer),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointers_are_found_by_id_name_or_unique_part_of_it() {
+        let pointer = |id, name: &str, kind| Pointer {
+            id,
+            name: name.into(),
+            kind,
+        };
+        let pointers = [
+            pointer(2, "Virtual core pointer", "master"),
+            pointer(4, "Virtual core XTEST pointer", "slave"),
+            pointer(11, "Logitech G502 HERO Gaming Mouse", "slave"),
+            pointer(12, "Logitech USB Receiver Mouse", "slave"),
+        ];
+        let id = |spec| find(&pointers, spec).map(|pointer| pointer.id);
+        assert_eq!(id("11"), Some(11));
+        assert_eq!(id("virtual core pointer"), Some(2));
+        assert_eq!(id("G502"), Some(11));
+        // Ambiguous or unknown.
+        assert_eq!(id("Logitech"), None);
+        assert_eq!(id("7"), None);
+    }
+}
@@ -0,0 +1,136 @@
+use tracing::debug;
+use x11rb::connection::Connection as _;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+/// Follows the focused window's EWMH fullscreen state.
+///
+/// Watches `_NET_ACTIVE_WINDOW` on the root window and `_NET_WM_STATE` on whichever window it
+/// points at, so only `PropertyNotify` events have to be fed in.
+pub(crate) struct FullscreenWatch {
+    root: xproto::Window,
+    net_active_window: xproto::Atom,
+    net_wm_state: xproto::Atom,
+    net_wm_state_fullscreen: xproto::Atom,
+    /// The focused window, or `NONE` when the window manager reports none.
+    active: xproto::Window,
+    fullscreen: bool,
+}
+
+impl FullscreenWatch {
+    pub(crate) fn new(conn: &RustConnection, root: xproto::Window) -> Result<Self, ReplyError> {
+        let atom = |name: &[u8]| -> Result<xproto::Atom, ReplyError> {
+            Ok(conn.intern_atom(false, name)?.reply()?.atom)
+        };
+        let mut watch = Self {
+            root,
+            net_active_window: atom(b"_NET_ACTIVE_WINDOW")?,
+            net_wm_state: atom(b"_NET_WM_STATE")?,
+            net_wm_state_fullscreen: atom(b"_NET_WM_STATE_FULLSCREEN")?,
+            active: x11rb::NONE,
+            fullscreen: false,
+        };
+        watch_properties(conn, root, true)?;
+        watch.follow_active_window(conn)?;
+        Ok(watch)
+    }
+
+    pub(crate) fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    /// Returns the new state if `ev` changed whether the focused window is fullscreen.
+    pub(crate) fn on_property_notify(
+        &mut self,
+        conn: &RustConnection,
+        ev: &xproto::PropertyNotifyEvent,
+    ) -> Result<Option<bool>, ReplyError> {
+        let was = self.fullscreen;
+        if ev.window == self.root && ev.atom == self.net_active_window {
+            self.follow_active_window(conn)?;
+        } else if ev.window == self.active && ev.atom == self.net_wm_state {
+            self.fullscreen = self.read_fullscreen(conn)?;
+        }
+        Ok((self.fullscreen != was).then_some(self.fullscreen))
+    }
+
+    fn follow_active_window(&mut self, conn: &RustConnection) -> Result<(), ReplyError> {
+        let active = conn
+            .get_property(
+                false,
+                self.root,
+                self.net_active_window,
+                xproto::AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next())
+            .unwrap_or(x11rb::NONE);
+        if active != self.active {
+            if self.active != x11rb::NONE {
+                // The old window may already be gone.
+                let _ = watch_properties(conn, self.active, false);
+            }
+            self.active = active;
+            if active != x11rb::NONE {
+                if let Err(err) = watch_properties(conn, active, true) {
+                    debug!("can't watch window 0x{active:x}: {err}");
+                    self.active = x11rb::NONE;
+                }
+            }
+        }
+        self.fullscreen = self.read_fullscreen(conn)?;
+        Ok(())
+    }
+
+    fn read_fullscreen(&self, conn: &RustConnection) -> Result<bool, ReplyError> {
+        if self.active == x11rb::NONE {
+            return Ok(false);
+        }
+        let reply = match conn
+            .get_property(
+                false,
+                self.active,
+                self.net_wm_state,
+                xproto::AtomEnum::ATOM,
+                0,
+                64,
+            )?
+            .reply()
+        {
+            Ok(reply) => reply,
+            // Destroyed between the focus change and now; the next focus change will catch up.
+            Err(ReplyError::X11Error(err))
+                if err.error_kind == x11rb::protocol::ErrorKind::Window =>
+            {
+                return Ok(false)
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(reply
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|atom| atom == self.net_wm_state_fullscreen)))
+    }
+}
+
+fn watch_properties(
+    conn: &RustConnection,
+    window: xproto::Window,
+    enable: bool,
+) -> Result<(), ReplyError> {
+    let mask = if enable {
+        xproto::EventMask::PROPERTY_CHANGE
+    } else {
+        xproto::EventMask::NO_EVENT
+    };
+    conn.change_window_attributes(
+        window,
+        &xproto::ChangeWindowAttributesAux::new().event_mask(mask),
+    )?
+    .check()?;
+    conn.flush()?;
+    Ok(())
+}
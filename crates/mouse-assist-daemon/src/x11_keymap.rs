@@ -0,0 +1,67 @@
+use mouse_assist_core::{load_keymap, ConfigError, KeysymSpec};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::path::Path;
+use tracing::{info, warn};
+use x11rb::protocol::xproto;
+
+/// Loads a user keymap for the X11 backend, resolving keysym names and logging which built-in
+/// entries it overrides. Entries that can't be resolved are skipped with a warning.
+pub(crate) fn load(
+    path: &Path,
+    builtin: impl Fn(&str) -> Option<xproto::Keysym>,
+) -> Result<HashMap<String, xproto::Keysym>, ConfigError> {
+    let entries = load_keymap(path)?;
+    let needs_names = entries
+        .values()
+        .any(|spec| matches!(spec, KeysymSpec::Name(_)));
+    let xlib = if needs_names {
+        match x11_dl::xlib::Xlib::open() {
+            Ok(xlib) => Some(xlib),
+            Err(err) => {
+                warn!("can't load libX11 to look up keysym names (only numbers will work): {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut keymap = HashMap::with_capacity(entries.len());
+    for (key, spec) in entries {
+        if !key.starts_with("KEY_") {
+            warn!("keymap {}: {key} isn't a KEY_* name", path.display());
+        }
+        let keysym = match &spec {
+            KeysymSpec::Number(keysym) => Some(*keysym),
+            KeysymSpec::Name(name) => xlib.as_ref().and_then(|xlib| keysym_by_name(xlib, name)),
+        };
+        let Some(keysym) = keysym else {
+            warn!(
+                "keymap {}: unknown keysym {spec:?} for {key}",
+                path.display()
+            );
+            continue;
+        };
+        match builtin(&key) {
+            Some(old) if old != keysym => {
+                info!("keymap overrides {key}: keysym 0x{old:x} -> 0x{keysym:x}")
+            }
+            Some(_) => {}
+            None => info!("keymap adds {key}: keysym 0x{keysym:x}"),
+        }
+        keymap.insert(key, keysym);
+    }
+    Ok(keymap)
+}
+
+/// Looks up a keysym name as Xlib spells it; an `XK_` prefix (as in the C headers) is allowed.
+fn keysym_by_name(xlib: &x11_dl::xlib::Xlib, name: &str) -> Option<xproto::Keysym> {
+    let name = CString::new(name.strip_prefix("XK_").unwrap_or(name)).ok()?;
+    // SAFETY: XStringToKeysym only reads the NUL-terminated string and needs no display.
+    let keysym = unsafe { (xlib.XStringToKeysym)(name.as_ptr()) };
+    // NoSymbol is 0; real keysyms fit in 29 bits.
+    xproto::Keysym::try_from(keysym)
+        .ok()
+        .filter(|&keysym| keysym != 0)
+}
@@ -0,0 +1,111 @@
+use mouse_assist_core::MouseButton;
+use std::collections::HashMap;
+use x11rb::protocol::xinput::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+/// Turns XInput 2.1 horizontal scroll valuator deltas into wheel-tilt "notches".
+///
+/// Raw events carry per-event deltas for scroll valuators; once the accumulated delta on a
+/// device reaches the valuator's increment (one wheel detent), a tilt is reported.
+#[derive(Debug, Default)]
+pub(crate) struct HorizontalScroll {
+    /// `(device, valuator number)` -> scroll increment.
+    axes: HashMap<(xinput::DeviceId, u16), f64>,
+    accumulated: HashMap<xinput::DeviceId, f64>,
+}
+
+fn fp3232_to_f64(value: xinput::Fp3232) -> f64 {
+    f64::from(value.integral) + f64::from(value.frac) / 4_294_967_296.0
+}
+
+impl HorizontalScroll {
+    /// Finds the horizontal scroll valuators of every physical pointer.
+    pub(crate) fn query(conn: &RustConnection) -> Result<Self, x11rb::errors::ReplyError> {
+        let reply = conn.xinput_xi_query_device(xinput::Device::ALL)?.reply()?;
+        let mut scroll = Self::default();
+        for info in reply.infos {
+            // Master devices repeat their slaves' classes; only count the physical device once.
+            if !matches!(
+                info.type_,
+                xinput::DeviceType::SLAVE_POINTER | xinput::DeviceType::FLOATING_SLAVE
+            ) {
+                continue;
+            }
+            for class in &info.classes {
+                let Some(data) = class.data.as_scroll() else {
+                    continue;
+                };
+                let increment = fp3232_to_f64(data.increment);
+                if data.scroll_type == xinput::ScrollType::HORIZONTAL && increment != 0.0 {
+                    scroll.axes.insert((info.deviceid, data.number), increment);
+                }
+            }
+        }
+        Ok(scroll)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.axes.is_empty()
+    }
+
+    pub(crate) fn on_raw_motion(&mut self, ev: &xinput::RawMotionEvent) -> Vec<MouseButton> {
+        let values: Vec<f64> = ev.axisvalues.iter().copied().map(fp3232_to_f64).collect();
+        self.notches(ev.deviceid, &ev.valuator_mask, &values)
+    }
+
+    /// `values` holds one entry per bit set in `mask`, lowest valuator first.
+    fn notches(
+        &mut self,
+        device: xinput::DeviceId,
+        mask: &[u32],
+        values: &[f64],
+    ) -> Vec<MouseButton> {
+        let set_bits = mask.iter().enumerate().flat_map(|(word, bits)| {
+            (0..32u16)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| word as u16 * 32 + bit)
+        });
+
+        let mut tilts = Vec::new();
+        for (number, value) in set_bits.zip(values) {
+            let Some(increment) = self.axes.get(&(device, number)) else {
+                continue;
+            };
+            let acc = self.accumulated.entry(device).or_default();
+            *acc += value / increment;
+            while *acc >= 1.0 {
+                *acc -= 1.0;
+                tilts.push(MouseButton::WheelTiltRight);
+            }
+            while *acc <= -1.0 {
+                *acc += 1.0;
+                tilts.push(MouseButton::WheelTiltLeft);
+            }
+        }
+        tilts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scroll_deltas_accumulate_into_tilt_notches() {
+        let mut scroll = HorizontalScroll::default();
+        scroll.axes.insert((7, 2), 15.0);
+
+        // Valuators 0, 1 (motion) and 2 (horizontal scroll) are set.
+        assert!(scroll.notches(7, &[0b111], &[3.0, 4.0, 7.5]).is_empty());
+        assert_eq!(
+            scroll.notches(7, &[0b100], &[7.5]),
+            vec![MouseButton::WheelTiltRight]
+        );
+        assert_eq!(
+            scroll.notches(7, &[0b100], &[-30.0]),
+            vec![MouseButton::WheelTiltLeft, MouseButton::WheelTiltLeft]
+        );
+        // Other devices' valuator 2 isn't a scroll axis.
+        assert!(scroll.notches(8, &[0b100], &[100.0]).is_empty());
+    }
+}
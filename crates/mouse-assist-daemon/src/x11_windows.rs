@@ -0,0 +1,430 @@
+use mouse_assist_core::{MonitorDirection, WindowTarget};
+use x11rb::connection::Connection as _;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+/// A rectangle in root window coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rect {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+}
+
+impl Rect {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// Finds the first managed window (per the window manager's `_NET_CLIENT_LIST`) that matches
+/// `target`.
+pub(crate) fn find_window(
+    conn: &RustConnection,
+    root: xproto::Window,
+    target: &WindowTarget,
+) -> Result<Option<xproto::Window>, ReplyError> {
+    let net_wm_name = atom(conn, b"_NET_WM_NAME")?;
+    let utf8_string = atom(conn, b"UTF8_STRING")?;
+
+    for window in client_list(conn, root)? {
+        // Windows can disappear while we look; skip those.
+        let Ok(class) = window_class(conn, window) else {
+            continue;
+        };
+        let title = match text_property(conn, window, net_wm_name, utf8_string) {
+            Ok(title) if !title.is_empty() => title,
+            _ => text_property(
+                conn,
+                window,
+                xproto::AtomEnum::WM_NAME.into(),
+                xproto::AtomEnum::STRING.into(),
+            )
+            .unwrap_or_default(),
+        };
+        let class: Vec<&str> = class.iter().map(String::as_str).collect();
+        if target.matches(&class, &title) {
+            return Ok(Some(window));
+        }
+    }
+    Ok(None)
+}
+
+/// The managed windows, oldest first, per the window manager's `_NET_CLIENT_LIST`.
+pub(crate) fn client_list(
+    conn: &RustConnection,
+    root: xproto::Window,
+) -> Result<Vec<xproto::Window>, ReplyError> {
+    let client_list = atom(conn, b"_NET_CLIENT_LIST")?;
+    Ok(conn
+        .get_property(
+            false,
+            root,
+            client_list,
+            xproto::AtomEnum::WINDOW,
+            0,
+            u32::MAX,
+        )?
+        .reply()?
+        .value32()
+        .map(Iterator::collect)
+        .unwrap_or_default())
+}
+
+/// The focused window per `_NET_ACTIVE_WINDOW`, if the window manager says there is one.
+pub(crate) fn active_window(
+    conn: &RustConnection,
+    root: xproto::Window,
+) -> Result<Option<xproto::Window>, ReplyError> {
+    let net_active_window = atom(conn, b"_NET_ACTIVE_WINDOW")?;
+    Ok(conn
+        .get_property(
+            false,
+            root,
+            net_active_window,
+            xproto::AtomEnum::WINDOW,
+            0,
+            1,
+        )?
+        .reply()?
+        .value32()
+        .and_then(|mut values| values.next())
+        .filter(|&window| window != x11rb::NONE))
+}
+
+/// The window with the keyboard focus, falling back to `_NET_ACTIVE_WINDOW` when focus follows
+/// the pointer or nothing has it.
+pub(crate) fn input_focus(
+    conn: &RustConnection,
+    root: xproto::Window,
+) -> Result<Option<xproto::Window>, ReplyError> {
+    let focus = conn.get_input_focus()?.reply()?.focus;
+    if focus == x11rb::NONE || focus == u32::from(xproto::InputFocus::POINTER_ROOT) || focus == root
+    {
+        return active_window(conn, root);
+    }
+    Ok(Some(focus))
+}
+
+/// The managed window under the pointer: the top-level window the pointer is over, followed
+/// down through window manager frames to the client in `_NET_CLIENT_LIST`. Without that list,
+/// the innermost window under the pointer.
+pub(crate) fn window_under_pointer(
+    conn: &RustConnection,
+    root: xproto::Window,
+) -> Result<Option<xproto::Window>, ReplyError> {
+    let clients = client_list(conn, root)?;
+    let mut window = root;
+    loop {
+        let child = conn.query_pointer(window)?.reply()?.child;
+        if child == x11rb::NONE {
+            return Ok((window != root).then_some(window));
+        }
+        if clients.contains(&child) {
+            return Ok(Some(child));
+        }
+        window = child;
+    }
+}
+
+/// A window's `WM_CLASS`: its instance name, then its class name.
+pub(crate) fn window_class(
+    conn: &RustConnection,
+    window: xproto::Window,
+) -> Result<Vec<String>, ReplyError> {
+    let class = text_property(
+        conn,
+        window,
+        xproto::AtomEnum::WM_CLASS.into(),
+        xproto::AtomEnum::STRING.into(),
+    )?;
+    // Two NUL-terminated strings.
+    Ok(class
+        .split('\0')
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// The focused window's `WM_CLASS`; `None` when there's no focused window or it can't be read.
+pub(crate) fn focused_class(conn: &RustConnection, root: xproto::Window) -> Option<Vec<String>> {
+    let window = active_window(conn, root).ok().flatten()?;
+    window_class(conn, window).ok()
+}
+
+/// The window after `current` in `windows`, wrapping around; the first one when `current`
+/// isn't among them. `None` when there's nowhere else to go.
+pub(crate) fn next_window(
+    windows: &[xproto::Window],
+    current: Option<xproto::Window>,
+) -> Option<xproto::Window> {
+    let next = match current.and_then(|current| windows.iter().position(|&w| w == current)) {
+        Some(index) => windows[(index + 1) % windows.len()],
+        None => *windows.first()?,
+    };
+    Some(next).filter(|&next| Some(next) != current)
+}
+
+/// Asks the window manager to raise and focus `window` (switching desktops if needed).
+pub(crate) fn activate(
+    conn: &RustConnection,
+    root: xproto::Window,
+    window: xproto::Window,
+) -> Result<(), ReplyError> {
+    let net_active_window = atom(conn, b"_NET_ACTIVE_WINDOW")?;
+    // Source indication 2 (a pager or similar) tells the WM to honor the request instead of
+    // treating it as focus stealing.
+    let event = xproto::ClientMessageEvent::new(
+        32,
+        window,
+        net_active_window,
+        [2, x11rb::CURRENT_TIME, 0, 0, 0],
+    );
+    conn.send_event(
+        false,
+        root,
+        xproto::EventMask::SUBSTRUCTURE_REDIRECT | xproto::EventMask::SUBSTRUCTURE_NOTIFY,
+        event,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// The active monitors per RandR, left to right (then top to bottom). A server without RandR
+/// 1.5 counts as one monitor covering the root window.
+pub(crate) fn monitors(
+    conn: &RustConnection,
+    root: xproto::Window,
+) -> Result<Vec<Rect>, ReplyError> {
+    let mut monitors: Vec<Rect> = match conn.randr_get_monitors(root, true) {
+        Ok(cookie) => match cookie.reply() {
+            Ok(reply) => reply
+                .monitors
+                .iter()
+                .map(|m| Rect {
+                    x: m.x.into(),
+                    y: m.y.into(),
+                    width: m.width.into(),
+                    height: m.height.into(),
+                })
+                .collect(),
+            // RandR older than 1.5, without GetMonitors.
+            Err(ReplyError::X11Error(_)) => Vec::new(),
+            Err(err) => return Err(err),
+        },
+        Err(ConnectionError::UnsupportedExtension) => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    if monitors.is_empty() {
+        let root = conn.get_geometry(root)?.reply()?;
+        monitors.push(Rect {
+            x: 0,
+            y: 0,
+            width: root.width.into(),
+            height: root.height.into(),
+        });
+    }
+    monitors.sort_by_key(|m| (m.x, m.y));
+    Ok(monitors)
+}
+
+/// Where `window`'s client area is on the root window.
+pub(crate) fn window_rect(
+    conn: &RustConnection,
+    window: xproto::Window,
+) -> Result<Rect, ReplyError> {
+    let geometry = conn.get_geometry(window)?.reply()?;
+    let origin = conn
+        .translate_coordinates(window, geometry.root, 0, 0)?
+        .reply()?;
+    Ok(Rect {
+        x: origin.dst_x.into(),
+        y: origin.dst_y.into(),
+        width: geometry.width.into(),
+        height: geometry.height.into(),
+    })
+}
+
+/// The index into `monitors` of the one holding the middle of `window` (the first one if the
+/// middle is off every screen).
+pub(crate) fn monitor_of(monitors: &[Rect], window: Rect) -> usize {
+    let (x, y) = (window.x + window.width / 2, window.y + window.height / 2);
+    monitors.iter().position(|m| m.contains(x, y)).unwrap_or(0)
+}
+
+/// The index of the monitor `direction` leads to from `current`; `None` if it leads nowhere
+/// (only one monitor, or an index past the last).
+pub(crate) fn target_monitor(
+    count: usize,
+    current: usize,
+    direction: MonitorDirection,
+) -> Option<usize> {
+    let target = match direction {
+        MonitorDirection::Next => (current + 1) % count.max(1),
+        MonitorDirection::Prev => (current + count.max(1) - 1) % count.max(1),
+        MonitorDirection::Index(index) => index,
+    };
+    (target < count && target != current).then_some(target)
+}
+
+/// Where `window`'s top-left corner goes to sit at the same relative spot on `to` as it did
+/// on `from`, kept on `to` as far as it fits.
+pub(crate) fn place_on(window: Rect, from: Rect, to: Rect) -> (i32, i32) {
+    let scale = |offset: i32, from: i32, to: i32| {
+        (i64::from(offset) * i64::from(to) / i64::from(from.max(1))) as i32
+    };
+    let x = to.x + scale(window.x - from.x, from.width, to.width);
+    let y = to.y + scale(window.y - from.y, from.height, to.height);
+    (
+        x.min(to.x + to.width - window.width).max(to.x),
+        y.min(to.y + to.height - window.height).max(to.y),
+    )
+}
+
+/// The `_NET_WM_STATE` flags that pin a window to its monitor's size.
+const SIZE_STATES: [&[u8]; 3] = [
+    b"_NET_WM_STATE_MAXIMIZED_VERT",
+    b"_NET_WM_STATE_MAXIMIZED_HORZ",
+    b"_NET_WM_STATE_FULLSCREEN",
+];
+
+/// Moves `window`'s top-left corner to (`x`, `y`). Maximized and fullscreen windows are
+/// restored first, since window managers won't move them, then put back on the new monitor.
+pub(crate) fn move_window(
+    conn: &RustConnection,
+    root: xproto::Window,
+    window: xproto::Window,
+    x: i32,
+    y: i32,
+) -> Result<(), ReplyError> {
+    let net_wm_state = atom(conn, b"_NET_WM_STATE")?;
+    let state: Vec<xproto::Atom> = conn
+        .get_property(false, window, net_wm_state, xproto::AtomEnum::ATOM, 0, 64)?
+        .reply()?
+        .value32()
+        .map(Iterator::collect)
+        .unwrap_or_default();
+    let mut pinned = Vec::new();
+    for name in SIZE_STATES {
+        let atom = atom(conn, name)?;
+        if state.contains(&atom) {
+            pinned.push(atom);
+        }
+    }
+    // _NET_WM_STATE actions: 0 removes, 1 adds; source 2 as in `activate`.
+    let set_state = |action: u32, property: xproto::Atom| {
+        let event =
+            xproto::ClientMessageEvent::new(32, window, net_wm_state, [action, property, 0, 2, 0]);
+        conn.send_event(
+            false,
+            root,
+            xproto::EventMask::SUBSTRUCTURE_REDIRECT | xproto::EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )
+    };
+    for &property in &pinned {
+        set_state(0, property)?;
+    }
+
+    let net_moveresize_window = atom(conn, b"_NET_MOVERESIZE_WINDOW")?;
+    if supported(conn, root)?.contains(&net_moveresize_window) {
+        // Static gravity (10) so (x, y) is the client area, not the frame; bits 8 and 9 say
+        // x and y are set, bits 12-13 give source 2.
+        let flags = 10 | 1 << 8 | 1 << 9 | 2 << 12;
+        let event = xproto::ClientMessageEvent::new(
+            32,
+            window,
+            net_moveresize_window,
+            [flags, x as u32, y as u32, 0, 0],
+        );
+        conn.send_event(
+            false,
+            root,
+            xproto::EventMask::SUBSTRUCTURE_REDIRECT | xproto::EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+    } else {
+        conn.configure_window(window, &xproto::ConfigureWindowAux::new().x(x).y(y))?;
+    }
+
+    for &property in &pinned {
+        set_state(1, property)?;
+    }
+    conn.flush()?;
+    Ok(())
+}
+
+/// The hints the window manager says it supports (`_NET_SUPPORTED`).
+fn supported(conn: &RustConnection, root: xproto::Window) -> Result<Vec<xproto::Atom>, ReplyError> {
+    let net_supported = atom(conn, b"_NET_SUPPORTED")?;
+    Ok(conn
+        .get_property(false, root, net_supported, xproto::AtomEnum::ATOM, 0, 1024)?
+        .reply()?
+        .value32()
+        .map(Iterator::collect)
+        .unwrap_or_default())
+}
+
+fn atom(conn: &RustConnection, name: &[u8]) -> Result<xproto::Atom, ReplyError> {
+    Ok(conn.intern_atom(false, name)?.reply()?.atom)
+}
+
+fn text_property(
+    conn: &RustConnection,
+    window: xproto::Window,
+    property: xproto::Atom,
+    type_: xproto::Atom,
+) -> Result<String, ReplyError> {
+    let reply = conn
+        .get_property(false, window, property, type_, 0, 1024)?
+        .reply()?;
+    Ok(String::from_utf8_lossy(&reply.value).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_window_wraps_around_and_stays_put_when_alone() {
+        assert_eq!(next_window(&[1, 2, 3], Some(2)), Some(3));
+        assert_eq!(next_window(&[1, 2, 3], Some(3)), Some(1));
+        assert_eq!(next_window(&[1, 2, 3], Some(9)), Some(1));
+        assert_eq!(next_window(&[1, 2, 3], None), Some(1));
+        assert_eq!(next_window(&[1], Some(1)), None);
+        assert_eq!(next_window(&[1], None), Some(1));
+        assert_eq!(next_window(&[], Some(1)), None);
+    }
+
+    #[test]
+    fn windows_keep_their_relative_place_on_the_next_monitor() {
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let left = rect(0, 0, 1920, 1080);
+        let right = rect(1920, 0, 2560, 1440);
+        let monitors = [left, right];
+
+        let window = rect(960, 540, 800, 400);
+        assert_eq!(monitor_of(&monitors, window), 0);
+        assert_eq!(place_on(window, left, right), (1920 + 1280, 720));
+        // Too far right to fit at the same spot, so it's pulled in.
+        assert_eq!(
+            place_on(rect(3700, 100, 800, 400), right, left),
+            (1920 - 800, 75)
+        );
+
+        assert_eq!(target_monitor(2, 0, MonitorDirection::Next), Some(1));
+        assert_eq!(target_monitor(2, 1, MonitorDirection::Next), Some(0));
+        assert_eq!(target_monitor(3, 0, MonitorDirection::Prev), Some(2));
+        assert_eq!(target_monitor(3, 0, MonitorDirection::Index(1)), Some(1));
+        assert_eq!(target_monitor(3, 0, MonitorDirection::Index(3)), None);
+        assert_eq!(target_monitor(1, 0, MonitorDirection::Next), None);
+    }
+}